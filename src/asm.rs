@@ -0,0 +1,284 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    instruction::{self, OpcodeInfo},
+    MAX_ADDR,
+};
+
+/// An operand as written: a value, or a label resolved once every label is placed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Operand {
+    Value(u16),
+    Label(String),
+}
+
+/// A program assembled from source by `assemble`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Assembled {
+    pub words: Vec<u16>,
+    /// The address of every label.
+    pub labels: BTreeMap<String, u16>,
+}
+
+/// Assembles Synacor assembly into words, to be loaded at address 0.
+///
+/// Each line holds an optional label, an optional instruction and an optional comment:
+/// ```text
+/// ; print a greeting
+/// start:  out "Hi!\n"     ; a string operand to `out` prints it a character at a time
+///         set r0 'a'
+///         call print
+///         halt
+/// print:  out r0
+///         ret
+/// table:  dw 1 0x10 start "text"
+/// ```
+/// Operands are written the way the disassembler shows them: registers `r0`-`r7`, numbers,
+/// character literals and, for addresses, labels. `dw` writes data words, and strings write each
+/// of their characters.
+pub fn assemble(source: &str) -> Result<Assembled, AsmError> {
+    let mut assembled = Assembled::default();
+    // where each label is used, to resolve them after every label is placed
+    let mut uses = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let err = |msg: String| AsmError::Syntax(msg, line_no);
+        let mut tokens = tokenize(line).map_err(err)?;
+
+        if let Some(label) = tokens.first().and_then(|token| token.strip_suffix(':')) {
+            if !is_label(label) {
+                return Err(err(format!("invalid label `{label}`")));
+            }
+            let addr = assembled.words.len() as u16;
+            if assembled.labels.insert(label.to_string(), addr).is_some() {
+                return Err(AsmError::DuplicateLabel(label.to_string(), line_no));
+            }
+            tokens.remove(0);
+        }
+        let Some((&mnemonic, operands)) = tokens.split_first() else {
+            continue;
+        };
+
+        let mut words = Vec::new();
+        if mnemonic == "dw" {
+            for operand in operands {
+                words.extend(parse_operands(operand).map_err(err)?);
+            }
+        } else {
+            let info = instruction::by_mnemonic(mnemonic)
+                .ok_or_else(|| err(format!("unknown mnemonic `{mnemonic}`")))?;
+            match operands {
+                [text] if info.mnemonic == "out" && text.starts_with('"') => {
+                    for ch in parse_operands(text).map_err(err)? {
+                        words.push(Operand::Value(info.code));
+                        words.push(ch);
+                    }
+                }
+                _ => {
+                    check_arity(info, operands.len()).map_err(err)?;
+                    words.push(Operand::Value(info.code));
+                    for operand in operands {
+                        words.push(parse_operand(operand).map_err(err)?);
+                    }
+                }
+            }
+        }
+
+        for word in words {
+            let word = match word {
+                Operand::Value(val) => val,
+                Operand::Label(label) => {
+                    uses.push((assembled.words.len(), label, line_no));
+                    0
+                }
+            };
+            assembled.words.push(word);
+        }
+        if assembled.words.len() > MAX_ADDR {
+            return Err(err("the program doesn't fit in memory".to_string()));
+        }
+    }
+
+    for (at, label, line_no) in uses {
+        let &addr = assembled
+            .labels
+            .get(&label)
+            .ok_or(AsmError::UndefinedLabel(label, line_no))?;
+        assembled.words[at] = addr;
+    }
+    Ok(assembled)
+}
+
+fn check_arity(info: &OpcodeInfo, count: usize) -> Result<(), String> {
+    if count != info.arity {
+        return Err(format!(
+            "`{}` takes {} operand(s), got {count}",
+            info.mnemonic, info.arity
+        ));
+    }
+    Ok(())
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_operand(s: &str) -> Result<Operand, String> {
+    let is_register = s
+        .strip_prefix('r')
+        .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+    if is_label(s) && !is_register {
+        return Ok(Operand::Label(s.to_string()));
+    }
+    if s.starts_with('"') {
+        return Err(format!("a string `{s}` can't be a single operand"));
+    }
+    instruction::parse_operand(s).map(Operand::Value)
+}
+
+/// Parses a `dw` operand, which may be a string of several words.
+fn parse_operands(s: &str) -> Result<Vec<Operand>, String> {
+    let Some(text) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) else {
+        return Ok(vec![parse_operand(s)?]);
+    };
+    let mut words = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(c @ ('"' | '\\')) => c,
+                _ => return Err(format!("invalid escape in `{s}`")),
+            },
+            c if c.is_ascii() => c,
+            c => return Err(format!("`{c}` is not ASCII")),
+        };
+        words.push(Operand::Value(c as u16));
+    }
+    Ok(words)
+}
+
+/// Splits a line into whitespace-separated tokens, keeping character and string literals whole
+/// and dropping the comment.
+pub(crate) fn tokenize(line: &str) -> Result<Vec<&str>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == ';' {
+            break;
+        }
+        chars.next();
+        let mut end = start + c.len_utf8();
+        if c == '\'' || c == '"' {
+            let mut escaped = false;
+            let mut closed = false;
+            for (i, next) in chars.by_ref() {
+                end = i + next.len_utf8();
+                match next {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    _ if next == c => {
+                        closed = true;
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            if !closed {
+                return Err(format!("unterminated literal `{}`", &line[start..]));
+            }
+        } else {
+            while let Some(&(i, next)) = chars.peek() {
+                if next.is_whitespace() || next == ';' {
+                    break;
+                }
+                end = i + next.len_utf8();
+                chars.next();
+            }
+        }
+        tokens.push(&line[start..end]);
+    }
+    Ok(tokens)
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum AsmError {
+    #[error("{0} on line {1}")]
+    Syntax(String, usize),
+    #[error("Undefined label `{0}` on line {1}")]
+    UndefinedLabel(String, usize),
+    #[error("Label `{0}` defined again on line {1}")]
+    DuplicateLabel(String, usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::setup, RunOutcome};
+
+    #[test]
+    fn assembles_programs() {
+        let source = "\
+; print a greeting
+start:  out \"Hi\\n\"
+        set r0 'a'      ; comment
+        call print
+        halt
+print:  out r0
+        ret
+table:  dw 1 0x10 start \"; \\\"\"
+";
+        let assembled = assemble(source).unwrap();
+        assert_eq!(
+            assembled.words,
+            [
+                19, 72, 19, 105, 19, 10, 1, 32768, 97, 17, 12, 0, 19, 32768, 18, 1, 16, 0, 59, 32,
+                34
+            ]
+        );
+        assert_eq!(assembled.labels["print"], 12);
+        assert_eq!(assembled.labels["table"], 15);
+
+        let mut machine = setup(assembled.words);
+        machine.push_input(b"");
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.drain_output(), b"Hi\na");
+    }
+
+    #[test]
+    fn reports_errors() {
+        let error = |source: &str| assemble(source).unwrap_err();
+        assert_eq!(
+            error("noop\njmp nowhere"),
+            AsmError::UndefinedLabel("nowhere".to_string(), 2)
+        );
+        assert_eq!(
+            error("a: noop\na: noop"),
+            AsmError::DuplicateLabel("a".to_string(), 2)
+        );
+        for source in [
+            "frobnicate",
+            "set r0",
+            "set r8 1",
+            "out \"open",
+            "set r0 \"ab\"",
+            "1a: noop",
+            "dw '\\q'",
+        ] {
+            assert!(
+                matches!(assemble(source), Err(AsmError::Syntax(_, 1))),
+                "{source}"
+            );
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+
+/// The operations that touch the stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackOp {
+    Push,
+    Pop,
+    Call,
+    Ret,
+}
+
+/// One stack operation: the instruction at `pos` pushed or popped `value`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackEvent {
+    pub op: StackOp,
+    pub pos: u16,
+    pub value: u16,
+}
+
+/// What a stack entry was pushed as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackTag {
+    Data,
+    ReturnAddress,
+}
+
+/// An optional log of every stack operation, which also flags pops that don't match how the value
+/// was pushed: `pop` of a return address pushed by `call`, or `ret` to a value pushed by `push`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StackAudit {
+    pub log: Vec<StackEvent>,
+    pub suspicious: Vec<StackEvent>,
+    /// Mirrors the machine's stack with how each entry was pushed.
+    tags: Vec<StackTag>,
+}
+
+impl StackAudit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a stack operation, returning `true` if it looks suspicious.
+    pub fn record(&mut self, event: StackEvent) -> bool {
+        self.log.push(event);
+
+        let expected = match event.op {
+            StackOp::Push => {
+                self.tags.push(StackTag::Data);
+                return false;
+            }
+            StackOp::Call => {
+                self.tags.push(StackTag::ReturnAddress);
+                return false;
+            }
+            StackOp::Pop => StackTag::Data,
+            StackOp::Ret => StackTag::ReturnAddress,
+        };
+
+        // entries pushed before auditing started are untagged and can't be checked
+        match self.tags.pop() {
+            Some(tag) if tag != expected => {
+                self.suspicious.push(event);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for StackEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.op {
+            StackOp::Push => "push",
+            StackOp::Pop => "pop",
+            StackOp::Call => "call",
+            StackOp::Ret => "ret",
+        };
+        write!(f, "{:>5}: {op:<4} {}", self.pos, self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(op: StackOp, value: u16) -> StackEvent {
+        StackEvent { op, pos: 0, value }
+    }
+
+    #[test]
+    fn balanced_usage() {
+        let mut audit = StackAudit::new();
+        assert!(!audit.record(event(StackOp::Call, 2)));
+        assert!(!audit.record(event(StackOp::Push, 7)));
+        assert!(!audit.record(event(StackOp::Pop, 7)));
+        assert!(!audit.record(event(StackOp::Ret, 2)));
+        assert_eq!(audit.log.len(), 4);
+        assert!(audit.suspicious.is_empty());
+    }
+
+    #[test]
+    fn mismatched_usage() {
+        let mut audit = StackAudit::new();
+        audit.record(event(StackOp::Call, 2));
+        assert!(audit.record(event(StackOp::Pop, 2)));
+        audit.record(event(StackOp::Push, 7));
+        assert!(audit.record(event(StackOp::Ret, 7)));
+        assert_eq!(audit.suspicious.len(), 2);
+    }
+
+    #[test]
+    fn untagged_entries() {
+        let mut audit = StackAudit::new();
+        assert!(!audit.record(event(StackOp::Ret, 2)));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{stack::Stack, MachineState, REGISTER_COUNT};
+
+/// The name of the bank holding the program the machine was created with.
+pub const MAIN_BANK: &str = "main";
+
+/// A program image with its own position, registers and stack.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bank {
+    pub name: String,
+    pub mem: Vec<u16>,
+    pub cur: u16,
+    pub registers: [u16; REGISTER_COUNT],
+    pub stack: Stack,
+    pub halted: bool,
+}
+
+/// Program images loaded into separate banks, to compare a patched binary with the original in
+/// one session. The machine runs the active bank, whose state lives in the machine itself; the
+/// others are parked here.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Banks {
+    pub active: usize,
+    /// Every bank, with a stale entry for the active one.
+    banks: Vec<Bank>,
+}
+
+impl Banks {
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.banks.iter().map(|bank| bank.name.as_str())
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.banks.iter().position(|bank| bank.name == name)
+    }
+}
+
+impl MachineState {
+    /// Loads `mem` into a new bank, starting at 0 with cleared registers and an empty stack, and
+    /// returns its index. The program the machine was created with is bank 0.
+    pub fn load_bank(&mut self, name: impl Into<String>, mem: Vec<u16>) -> usize {
+        let banks = self.banks.get_or_insert_with(|| Banks {
+            active: 0,
+            banks: vec![Bank {
+                name: MAIN_BANK.to_string(),
+                mem: Vec::new(),
+                cur: 0,
+                registers: [0; REGISTER_COUNT],
+                stack: Stack::new(),
+                halted: false,
+            }],
+        });
+        banks.banks.push(Bank {
+            name: name.into(),
+            mem,
+            cur: 0,
+            registers: [0; REGISTER_COUNT],
+            stack: Stack::new(),
+            halted: false,
+        });
+        banks.banks.len() - 1
+    }
+
+    /// Makes `bank` the active bank, parking the current one. With `preserve`, the position,
+    /// registers and stack carry over to the new bank instead of being restored from it.
+    pub fn switch_bank(&mut self, bank: usize, preserve: bool) -> Result<(), BankError> {
+        let Some(banks) = &mut self.banks else {
+            return Err(BankError::NoBank(bank));
+        };
+        let active = banks.active;
+        let incoming = banks.banks.get_mut(bank).ok_or(BankError::NoBank(bank))?;
+        if bank == active {
+            return Ok(());
+        }
+
+        if preserve {
+            incoming.cur = self.cur;
+            incoming.registers = self.registers;
+            incoming.stack = self.stack.clone();
+            incoming.halted = self.halted;
+        }
+        std::mem::swap(&mut self.mem, &mut incoming.mem);
+        std::mem::swap(&mut self.cur, &mut incoming.cur);
+        std::mem::swap(&mut self.registers, &mut incoming.registers);
+        std::mem::swap(&mut self.stack, &mut incoming.stack);
+        std::mem::swap(&mut self.halted, &mut incoming.halted);
+        // the incoming slot now holds the outgoing bank, which goes back to its own slot
+        banks.banks.swap(bank, active);
+        banks.active = bank;
+        Ok(())
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum BankError {
+    #[error("No memory bank `{0}`")]
+    NoBank(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::setup, RunOutcome};
+
+    #[test]
+    fn switches_banks() {
+        // set r0 1, halt
+        let mut machine = setup(vec![1, 32768, 1, 0]);
+        // set r0 2, halt
+        let patched = machine.load_bank("patched", vec![1, 32768, 2, 0]);
+        assert_eq!(machine.switch_bank(2, false), Err(BankError::NoBank(2)));
+
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.registers[0], 1);
+
+        machine.switch_bank(patched, false).unwrap();
+        assert_eq!(machine.registers[0], 0);
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.registers[0], 2);
+
+        machine.switch_bank(0, false).unwrap();
+        assert_eq!(machine.registers[0], 1);
+        assert_eq!(machine.mem[2], 1);
+        let banks = machine.banks.as_ref().unwrap();
+        assert_eq!(banks.names().collect::<Vec<_>>(), vec!["main", "patched"]);
+        assert_eq!(banks.find("patched"), Some(patched));
+    }
+
+    #[test]
+    fn preserves_state() {
+        let mut machine = setup(vec![21, 21, 0]);
+        let other = machine.load_bank("other", vec![21, 0, 0]);
+        machine.exec_next().unwrap();
+        machine.registers[3] = 7;
+        machine.stack.push(5);
+
+        machine.switch_bank(other, true).unwrap();
+        assert_eq!(machine.cur, 1);
+        assert_eq!(machine.registers[3], 7);
+        assert_eq!(machine.stack.peek(), Some(5));
+        // halts at 1, where the original has a `noop`
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.steps, 2);
+    }
+}
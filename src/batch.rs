@@ -0,0 +1,285 @@
+//! Runs many scripts or snapshots at once and tabulates how each run ended, for regression
+//! testing solver scripts.
+//!
+//! A directory holds input scripts `name.in`, which are run from the start of the image, and
+//! snapshots `name.snapshot`, which are resumed and fed `name.in` if there is such a file.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{
+    crash::{CrashDump, SNAPSHOT_EXTENSION},
+    testrom::INPUT_EXTENSION,
+    MachineState, RunLimits, RunOutcome, RunResult,
+};
+
+/// A run to make: a machine and the input to feed it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Job {
+    pub name: String,
+    pub machine: MachineState,
+    pub input: Vec<u8>,
+}
+
+/// Finds the jobs in `dir`, in the order of their names. Scripts are run on `program`.
+pub fn jobs(program: &[u16], dir: impl AsRef<Path>) -> Result<Vec<Job>, String> {
+    let dir = dir.as_ref();
+    let mut paths = std::fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<PathBuf>>>()
+        })
+        .map_err(|err| format!("Could not read `{}`: {err}", dir.display()))?;
+    paths.sort();
+
+    let is = |path: &Path, ext: &str| path.extension().is_some_and(|found| found == ext);
+    let mut jobs = Vec::new();
+    for path in &paths {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let machine = if is(path, SNAPSHOT_EXTENSION) {
+            CrashDump::load(path)?.to_machine()
+        } else if is(path, INPUT_EXTENSION) && !path.with_extension(SNAPSHOT_EXTENSION).exists() {
+            MachineState::new(program.to_vec())
+        } else {
+            continue;
+        };
+        let input = match std::fs::read(path.with_extension(INPUT_EXTENSION)) {
+            Ok(input) => input,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(format!("Could not read the input of `{name}`: {err}")),
+        };
+        jobs.push(Job {
+            name: name.into_owned(),
+            machine,
+            input,
+        });
+    }
+    Ok(jobs)
+}
+
+/// How one job ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JobResult {
+    pub name: String,
+    pub result: RunResult,
+    /// The expected codes the run printed, in the order they were expected.
+    pub codes: Vec<String>,
+    pub steps: u64,
+}
+
+/// The limits every run of a batch is under and what to look for in its output.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Batch {
+    pub fuel: u64,
+    /// How long each run may take.
+    pub timeout: Option<Duration>,
+    pub threads: usize,
+    /// The codes to look for in the output.
+    pub codes: Vec<String>,
+}
+
+impl Batch {
+    /// Runs `jobs` on `threads` workers, returning their results in the order of the jobs.
+    pub fn run(&self, jobs: Vec<Job>) -> Vec<JobResult> {
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(vec![None; jobs.len()]);
+        std::thread::scope(|scope| {
+            for _ in 0..self.threads.max(1) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(job) = jobs.get(i) else {
+                        return;
+                    };
+                    let result = self.run_job(job.clone());
+                    results.lock().unwrap()[i] = Some(result);
+                });
+            }
+        });
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    fn run_job(&self, job: Job) -> JobResult {
+        let mut machine = job.machine;
+        machine.push_input(&job.input);
+        let result = machine.run_with(RunLimits {
+            fuel: Some(self.fuel),
+            deadline: self.timeout.map(|timeout| Instant::now() + timeout),
+        });
+        let output = String::from_utf8_lossy(&machine.drain_output()).into_owned();
+        JobResult {
+            name: job.name,
+            result,
+            codes: self
+                .codes
+                .iter()
+                .filter(|&code| output.contains(code.as_str()))
+                .cloned()
+                .collect(),
+            steps: machine.steps,
+        }
+    }
+}
+
+/// A short name for how a run ended, for the table.
+fn status(result: &RunResult) -> &'static str {
+    match result {
+        Ok(RunOutcome::Halted) => "halted",
+        Ok(RunOutcome::NeedsInput) => "needs input",
+        Ok(RunOutcome::FuelExhausted) => "out of fuel",
+        Ok(RunOutcome::TimedOut) => "timed out",
+        Ok(RunOutcome::Breakpoint(_) | RunOutcome::Watchpoint(_) | RunOutcome::Timer(_)) => {
+            "stopped"
+        }
+        Ok(RunOutcome::ResourceLimit(_)) => "over limit",
+        Ok(RunOutcome::InfiniteLoop(_)) => "looping",
+        Err(_) => "erred",
+    }
+}
+
+/// Tabulates `results`: one row per run with how it ended, how many of the `expected` codes it
+/// found and the instructions it executed, then the errors in full and a summary.
+pub fn report(results: &[JobResult], expected: usize) -> String {
+    let mut rows = vec![["run", "result", "codes", "steps"].map(String::from)];
+    for job in results {
+        rows.push([
+            job.name.clone(),
+            status(&job.result).to_string(),
+            format!("{}/{expected}", job.codes.len()),
+            job.steps.to_string(),
+        ]);
+    }
+    let widths = (0..4)
+        .map(|i| rows.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+    for row in &rows {
+        let cells = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            // text lines up on the left, numbers on the right
+            .map(|(i, (cell, &width))| match i {
+                0 | 1 => format!("{cell:<width$}"),
+                _ => format!("{cell:>width$}"),
+            })
+            .collect::<Vec<_>>();
+        let _ = writeln!(out, "{}", cells.join("  ").trim_end());
+    }
+    for job in results {
+        if let Err(err) = &job.result {
+            let _ = writeln!(out, "\n{}: {err}", job.name);
+        }
+    }
+    let halted = results
+        .iter()
+        .filter(|job| job.result == Ok(RunOutcome::Halted))
+        .count();
+    let erred = results.iter().filter(|job| job.result.is_err()).count();
+    let _ = writeln!(
+        out,
+        "\n{} run(s): {halted} halted, {erred} erred, {} stopped otherwise",
+        results.len(),
+        results.len() - halted - erred
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup;
+
+    // 0: in r0
+    // 2: eq r1 r0 'q'
+    // 6: jt r1 14
+    // 9: out r0
+    // 11: jmp 0
+    // 13: noop
+    // 14: halt
+    fn program() -> Vec<u16> {
+        vec![
+            20, 32768, 4, 32769, 32768, 113, 7, 32769, 14, 19, 32768, 6, 0, 21, 0,
+        ]
+    }
+
+    fn job(name: &str, input: &[u8]) -> Job {
+        Job {
+            name: name.to_string(),
+            machine: setup(program()),
+            input: input.to_vec(),
+        }
+    }
+
+    #[test]
+    fn runs_jobs() {
+        let batch = Batch {
+            fuel: 1000,
+            timeout: None,
+            threads: 2,
+            codes: vec!["abc".to_string(), "xyz".to_string()],
+        };
+        let mut failing = job("failing", b"");
+        failing.machine.mem[0] = 30;
+        let results = batch.run(vec![
+            job("solved", b"abcq"),
+            job("stuck", b"ab"),
+            failing,
+            job("slow", &[b'a'; 1000]),
+        ]);
+
+        assert_eq!(
+            results
+                .iter()
+                .map(|job| (job.name.as_str(), status(&job.result), job.codes.len()))
+                .collect::<Vec<_>>(),
+            [
+                ("solved", "halted", 1),
+                ("stuck", "needs input", 0),
+                ("failing", "erred", 0),
+                ("slow", "out of fuel", 0),
+            ]
+        );
+        assert_eq!(results[0].steps, 19);
+
+        let report = report(&results, batch.codes.len());
+        assert!(report.starts_with(
+            "run      result       codes  steps\nsolved   halted         1/2     19\n"
+        ));
+        assert!(report.contains("\nfailing: "));
+        assert!(report.ends_with("4 run(s): 1 halted, 1 erred, 2 stopped otherwise\n"));
+    }
+
+    #[test]
+    fn finds_jobs() {
+        let dir = std::env::temp_dir().join(format!("synacor-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.in"), "q").unwrap();
+        std::fs::write(dir.join("b.in"), "xq").unwrap();
+        let mut resumed = setup(program());
+        resumed.cur = 9;
+        resumed.registers[0] = b'!' as u16;
+        CrashDump::of(&resumed, "saved")
+            .save(dir.join("b.snapshot"))
+            .unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let jobs = jobs(&program(), &dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            jobs.iter()
+                .map(|job| (job.name.as_str(), job.machine.cur, job.input.as_slice()))
+                .collect::<Vec<_>>(),
+            [("a", 0, &b"q"[..]), ("b", 9, &b"xq"[..])]
+        );
+    }
+}
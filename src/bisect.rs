@@ -0,0 +1,197 @@
+use crate::{
+    crash::CrashDump,
+    project::{parse_number, Project},
+    MachineState,
+};
+
+/// A condition on the machine state that becomes true at some point of a run, and stays true.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Predicate {
+    /// Memory at `addr` holds `value`.
+    Mem { addr: u16, value: u16 },
+    /// The program has printed `text` since the start of the replay.
+    Output(String),
+}
+
+impl Predicate {
+    /// Parses `mem <addr> <value>` or `output <text>`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (kind, rest) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        match kind {
+            "mem" => {
+                let words = rest.split_whitespace().collect::<Vec<_>>();
+                let [addr, value] = words[..] else {
+                    return Err("expected `mem <addr> <value>`".to_string());
+                };
+                Ok(Predicate::Mem {
+                    addr: parse_number(addr)?,
+                    value: parse_number(value)?,
+                })
+            }
+            "output" if !rest.is_empty() => Ok(Predicate::Output(rest.to_string())),
+            "output" => Err("expected `output <text>`".to_string()),
+            other => Err(format!("unknown predicate `{other}`")),
+        }
+    }
+
+    fn holds(&self, machine: &MachineState, output: &str) -> bool {
+        match self {
+            Predicate::Mem { addr, value } => machine.mem.get(*addr as usize) == Some(value),
+            Predicate::Output(text) => output.contains(text.as_str()),
+        }
+    }
+}
+
+/// The first state in which a predicate holds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bisection {
+    /// The number of instructions executed, counted like `MachineState::steps`.
+    pub steps: u64,
+    /// The position of the instruction that made the predicate true.
+    pub pos: Option<u16>,
+    /// How many replays the search took.
+    pub replays: u32,
+}
+
+/// Replays `input` from `start` for `steps` instructions, the total counted from the start of
+/// the original run, and checks `predicate`. Runs that fail or halt early are checked where they
+/// stopped.
+fn replay(
+    start: &CrashDump,
+    input: &[u8],
+    steps: u64,
+    predicate: &Predicate,
+) -> (bool, Option<u16>) {
+    let mut machine = start.to_machine();
+    machine.push_input(input);
+    let _ = machine.run_for(steps - start.steps);
+    let output = String::from_utf8_lossy(&machine.drain_output()).into_owned();
+    let pos = machine.history.iter().next_back();
+    (predicate.holds(&machine, &output), pos)
+}
+
+/// Finds the exact instruction between the snapshots `start` and `end` at which `predicate`
+/// first holds, by replaying `input` from `start` with a binary search over instruction counts.
+/// `input` is what the program reads from `start` on.
+pub fn bisect(
+    start: &CrashDump,
+    end: &CrashDump,
+    input: &[u8],
+    predicate: &Predicate,
+) -> Result<Bisection, BisectError> {
+    if end.steps <= start.steps {
+        return Err(BisectError::Order(start.steps, end.steps));
+    }
+    let mut replays = 1;
+    if replay(start, input, start.steps, predicate).0 {
+        return Err(BisectError::AlreadyTrue(start.steps));
+    }
+    replays += 1;
+    let (holds, mut pos) = replay(start, input, end.steps, predicate);
+    if !holds {
+        return Err(BisectError::NeverTrue(end.steps));
+    }
+
+    // the predicate is false after `lo` instructions and true after `hi`
+    let (mut lo, mut hi) = (start.steps, end.steps);
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        replays += 1;
+        match replay(start, input, mid, predicate) {
+            (true, mid_pos) => {
+                hi = mid;
+                pos = mid_pos;
+            }
+            (false, _) => lo = mid,
+        }
+    }
+    Ok(Bisection {
+        steps: hi,
+        pos,
+        replays,
+    })
+}
+
+impl Bisection {
+    pub fn describe(&self, project: &Project) -> String {
+        match self.pos {
+            Some(pos) => format!(
+                "first true after {} steps, by the instruction at {} ({} replays)",
+                self.steps,
+                project.describe(pos),
+                self.replays
+            ),
+            None => format!(
+                "first true after {} steps ({} replays)",
+                self.steps, self.replays
+            ),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum BisectError {
+    #[error("The first snapshot, at step `{0}`, must come before the second, at step `{1}`")]
+    Order(u64, u64),
+    #[error("The predicate already holds at step `{0}`")]
+    AlreadyTrue(u64),
+    #[error("The predicate still doesn't hold at step `{0}`")]
+    NeverTrue(u64),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::setup, RunOutcome};
+
+    #[test]
+    fn parses_predicates() {
+        assert_eq!(
+            Predicate::parse("mem 0x10 5"),
+            Ok(Predicate::Mem { addr: 16, value: 5 })
+        );
+        assert_eq!(
+            Predicate::parse("output You win"),
+            Ok(Predicate::Output("You win".to_string()))
+        );
+        assert!(Predicate::parse("mem 1").is_err());
+        assert!(Predicate::parse("output").is_err());
+        assert!(Predicate::parse("regs").is_err());
+    }
+
+    #[test]
+    fn finds_first_instruction() {
+        // 0: in r0
+        // 2: add r1 r1 1
+        // 6: eq r2 r1 5
+        // 10: jf r2 2
+        // 13: wmem 100 7
+        // 16: jmp 16
+        let mut machine = setup(vec![
+            20, 32768, 9, 32769, 32769, 1, 4, 32770, 32769, 5, 8, 32770, 2, 16, 100, 7, 6, 16,
+        ]);
+        machine.push_input(b"x");
+        machine.exec_next().unwrap();
+        let start = CrashDump::of(&machine, "start");
+        assert_eq!(machine.run_for(100), Ok(RunOutcome::FuelExhausted));
+        let end = CrashDump::of(&machine, "end");
+
+        let written = Predicate::Mem {
+            addr: 100,
+            value: 7,
+        };
+        let bisection = bisect(&start, &end, b"", &written).unwrap();
+        // `in`, then 5 iterations of 3 instructions, then `wmem`
+        assert_eq!(bisection.steps, 1 + 5 * 3 + 1);
+        assert_eq!(bisection.pos, Some(13));
+
+        assert_eq!(
+            bisect(&end, &start, b"", &written),
+            Err(BisectError::Order(101, 1))
+        );
+        assert_eq!(
+            bisect(&start, &end, b"", &Predicate::Output("x".to_string())),
+            Err(BisectError::NeverTrue(101))
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::REGISTER_COUNT;
+
+/// A lightweight summary of the machine's state, for live dashboards.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateSummary {
+    pub cur: u16,
+    pub registers: [u16; REGISTER_COUNT],
+    pub steps: u64,
+    /// The most recent output, up to `StatusBroadcaster::OUTPUT_TAIL` bytes.
+    pub last_output: String,
+}
+
+struct Slot<T> {
+    version: u64,
+    value: T,
+}
+
+type Shared<T> = Arc<(Mutex<Slot<T>>, Condvar)>;
+
+/// The sending half of a watch channel, which only ever holds the latest value.
+pub struct WatchSender<T> {
+    shared: Shared<T>,
+}
+
+/// The receiving half of a watch channel. Receivers can be cloned freely.
+pub struct WatchReceiver<T> {
+    shared: Shared<T>,
+    seen: u64,
+}
+
+/// Creates a watch channel holding `initial` until something is sent.
+pub fn watch_channel<T: Clone>(initial: T) -> (WatchSender<T>, WatchReceiver<T>) {
+    let shared = Arc::new((
+        Mutex::new(Slot {
+            version: 0,
+            value: initial,
+        }),
+        Condvar::new(),
+    ));
+    (
+        WatchSender {
+            shared: Arc::clone(&shared),
+        },
+        WatchReceiver { shared, seen: 0 },
+    )
+}
+
+impl<T> WatchSender<T> {
+    /// Replaces the current value and wakes up every waiting receiver.
+    pub fn send(&self, value: T) {
+        let (slot, changed) = &*self.shared;
+        let mut slot = slot.lock().unwrap();
+        slot.version += 1;
+        slot.value = value;
+        changed.notify_all();
+    }
+}
+
+impl<T: Clone> WatchReceiver<T> {
+    /// The latest value, marking it as seen.
+    pub fn latest(&mut self) -> T {
+        let slot = self.shared.0.lock().unwrap();
+        self.seen = slot.version;
+        slot.value.clone()
+    }
+
+    /// Whether a value was sent since the last one seen by this receiver.
+    pub fn has_changed(&self) -> bool {
+        self.shared.0.lock().unwrap().version != self.seen
+    }
+
+    /// Blocks until a value newer than the last seen one is sent, then returns it.
+    pub fn changed(&mut self) -> T {
+        let (slot, changed) = &*self.shared;
+        let slot = changed
+            .wait_while(slot.lock().unwrap(), |slot| slot.version == self.seen)
+            .unwrap();
+        self.seen = slot.version;
+        slot.value.clone()
+    }
+}
+
+impl<T> Clone for WatchReceiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+            seen: self.seen,
+        }
+    }
+}
+
+/// Publishes a `StateSummary` every `interval` instructions while the machine runs.
+#[derive(Clone)]
+pub struct StatusBroadcaster {
+    pub interval: u64,
+    sender: Arc<WatchSender<StateSummary>>,
+    output: VecDeque<u8>,
+}
+
+impl StatusBroadcaster {
+    /// How many bytes of recent output are kept for summaries.
+    pub const OUTPUT_TAIL: usize = 256;
+
+    /// Creates a broadcaster and a receiver for its summaries.
+    pub fn new(interval: u64) -> (Self, WatchReceiver<StateSummary>) {
+        let (sender, receiver) = watch_channel(StateSummary::default());
+        let broadcaster = Self {
+            interval: interval.max(1),
+            sender: Arc::new(sender),
+            output: VecDeque::new(),
+        };
+        (broadcaster, receiver)
+    }
+
+    /// Whether a summary should be published after `steps` instructions.
+    pub fn due(&self, steps: u64) -> bool {
+        steps.is_multiple_of(self.interval)
+    }
+
+    pub fn record_output(&mut self, byte: u8) {
+        if self.output.len() == Self::OUTPUT_TAIL {
+            self.output.pop_front();
+        }
+        self.output.push_back(byte);
+    }
+
+    pub fn publish(&self, cur: u16, registers: [u16; REGISTER_COUNT], steps: u64) {
+        let (front, back) = self.output.as_slices();
+        self.sender.send(StateSummary {
+            cur,
+            registers,
+            steps,
+            last_output: String::from_utf8_lossy(&[front, back].concat()).into_owned(),
+        });
+    }
+}
+
+impl fmt::Debug for StatusBroadcaster {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StatusBroadcaster")
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Broadcasters are equal when they publish to the same channel.
+impl PartialEq for StatusBroadcaster {
+    fn eq(&self, other: &Self) -> bool {
+        self.interval == other.interval && Arc::ptr_eq(&self.sender, &other.sender)
+    }
+}
+
+impl Eq for StatusBroadcaster {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watch_keeps_latest() {
+        let (sender, mut receiver) = watch_channel(0);
+        assert!(!receiver.has_changed());
+        sender.send(1);
+        sender.send(2);
+        assert!(receiver.has_changed());
+        assert_eq!(receiver.changed(), 2);
+        assert!(!receiver.has_changed());
+    }
+
+    #[test]
+    fn output_tail() {
+        let (mut broadcaster, mut receiver) = StatusBroadcaster::new(1);
+        for &byte in [b'a'; StatusBroadcaster::OUTPUT_TAIL].iter().chain(b"bc") {
+            broadcaster.record_output(byte);
+        }
+        broadcaster.publish(1, [0; REGISTER_COUNT], 2);
+        let summary = receiver.latest();
+        assert_eq!(summary.last_output.len(), StatusBroadcaster::OUTPUT_TAIL);
+        assert!(summary.last_output.ends_with("abc"));
+    }
+}
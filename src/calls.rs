@@ -0,0 +1,162 @@
+use std::fmt;
+
+/// The registers treated as arguments of a call: r0 to r3.
+pub const ARGS: usize = 4;
+
+/// A `call` or the `ret` matching it. `steps` is the number of instructions executed when it
+/// happened, and `depth` the number of calls open before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallEvent {
+    Call {
+        pos: u16,
+        target: u16,
+        args: [u16; ARGS],
+        steps: u64,
+        depth: usize,
+    },
+    Return {
+        pos: u16,
+        /// The routine returned from, unless the return address wasn't pushed by a traced call.
+        target: Option<u16>,
+        result: u16,
+        steps: u64,
+        depth: usize,
+    },
+}
+
+/// A call still waiting for its `ret`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Frame {
+    target: u16,
+    return_to: u16,
+}
+
+/// An optional log of every `call` with its conventional arguments, r0 to r3, and every `ret` with
+/// its conventional result, r0.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallTrace {
+    pub log: Vec<CallEvent>,
+    frames: Vec<Frame>,
+}
+
+impl CallTrace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the `call` at `pos` to `target`.
+    pub fn call(&mut self, pos: u16, target: u16, registers: &[u16], steps: u64) {
+        let mut args = [0; ARGS];
+        args.copy_from_slice(&registers[..ARGS]);
+        self.log.push(CallEvent::Call {
+            pos,
+            target,
+            args,
+            steps,
+            depth: self.frames.len(),
+        });
+        self.frames.push(Frame {
+            target,
+            return_to: pos + 2,
+        });
+    }
+
+    /// Records the `ret` at `pos` to `return_to`.
+    pub fn ret(&mut self, pos: u16, return_to: u16, result: u16, steps: u64) {
+        // routines that drop return addresses from the stack leave frames that never return,
+        // so unwind to the call that pushed this one
+        let frame = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.return_to == return_to);
+        let target = frame.map(|i| {
+            let target = self.frames[i].target;
+            self.frames.truncate(i);
+            target
+        });
+        self.log.push(CallEvent::Return {
+            pos,
+            target,
+            result,
+            steps,
+            depth: self.frames.len(),
+        });
+    }
+}
+
+impl fmt::Display for CallEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CallEvent::Call {
+                pos,
+                target,
+                args,
+                steps,
+                depth,
+            } => {
+                let args = args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, val)| format!("r{i}={val}"))
+                    .collect::<Vec<_>>();
+                write!(
+                    f,
+                    "{steps:>10} {:indent$}call {target} from {pos} ({})",
+                    "",
+                    args.join(" "),
+                    indent = depth * 2
+                )
+            }
+            CallEvent::Return {
+                target,
+                result,
+                steps,
+                depth,
+                ..
+            } => {
+                let target = target.map_or("?".to_string(), |target| target.to_string());
+                write!(
+                    f,
+                    "{steps:>10} {:indent$}ret from {target} r0={result}",
+                    "",
+                    indent = depth * 2
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MachineBuilder, RunOutcome};
+
+    #[test]
+    fn traces_calls() {
+        // 0: call 3
+        // 2: halt
+        // 3: set r0 7
+        // 6: ret
+        let mut machine = MachineBuilder::new()
+            .program(&[17, 3, 0, 1, 32768, 7, 18])
+            .register(1, 5)
+            .build();
+        machine.call_trace = Some(CallTrace::new());
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+
+        let log = machine
+            .call_trace
+            .unwrap()
+            .log
+            .iter()
+            .map(|event| event.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            log,
+            vec![
+                "         1 call 3 from 0 (r0=0 r1=5 r2=0 r3=0)",
+                "         3 ret from 3 r0=7",
+            ]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::project::Project;
+
+/// A routine that was called and hasn't returned yet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallFrame {
+    /// The position of the `call`.
+    pub site: u16,
+    /// The routine called.
+    pub target: u16,
+    /// Where its `ret` goes back to.
+    pub return_to: u16,
+    /// The length of the data stack before the call.
+    pub base: usize,
+    /// Where the routine's own entries of the data stack start: above the return address, or at
+    /// `base` if that went to the return stack.
+    pub start: usize,
+}
+
+/// A shadow of the call stack, pairing every `call` with its `ret`, to tell which routines
+/// execution is nested in. Unlike the machine's stack, it holds nothing but return addresses.
+///
+/// It also learns the locals of each routine from its pushes: the challenge's routines keep no
+/// frame pointer, but save registers and temporaries on the stack in the same order every time
+/// they run, so the `n`th slot above a routine's start is named after what the routine pushed to
+/// it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallStack {
+    /// The open calls, the innermost last.
+    pub frames: Vec<CallFrame>,
+    /// The operand each routine pushed to each of its slots, `None` if it pushed different ones.
+    pub locals: BTreeMap<u16, Vec<Option<u16>>>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the `call` at `site` to `target`, which took the data stack from `base` entries
+    /// to `start`.
+    pub fn call(&mut self, site: u16, target: u16, base: usize, start: usize) {
+        self.frames.push(CallFrame {
+            site,
+            target,
+            return_to: site + 2,
+            base,
+            start,
+        });
+    }
+
+    /// Records a `push` of `operand` that left `depth` entries on the data stack, learning the
+    /// slot it went to in the routine the machine is in.
+    pub fn push(&mut self, operand: u16, depth: usize) {
+        let Some(frame) = self.frames.last() else {
+            return;
+        };
+        // a routine that popped its caller's entries pushes back to them
+        let Some(slot) = depth.checked_sub(frame.start + 1) else {
+            return;
+        };
+        let slots = self.locals.entry(frame.target).or_default();
+        if slot < slots.len() {
+            if slots[slot] != Some(operand) {
+                slots[slot] = None;
+            }
+        } else {
+            slots.resize(slot, None);
+            slots.push(Some(operand));
+        }
+    }
+
+    /// The name of the `slot`th local of the routine at `target`.
+    fn local(&self, target: u16, slot: usize, project: &Project) -> String {
+        let operand = self
+            .locals
+            .get(&target)
+            .and_then(|slots| slots.get(slot).copied().flatten());
+        match operand {
+            Some(operand) => format!("pushed {}", project.format_operand(operand)),
+            None => format!("slot {slot}"),
+        }
+    }
+
+    /// What the entry `index` of the data stack is: a local of a routine or the return address of
+    /// a call. `None` for the entries pushed outside of any call.
+    pub fn describe_slot(&self, index: usize, project: &Project) -> Option<String> {
+        let frame = self.frames.iter().rev().find(|frame| frame.base <= index)?;
+        if index < frame.start {
+            return Some(format!("return to {}", project.describe(frame.return_to)));
+        }
+        let local = self.local(frame.target, index - frame.start, project);
+        Some(format!("{local} in {}", project.describe(frame.target)))
+    }
+
+    /// Records a `ret` to `return_to`. Routines that drop return addresses from the stack leave
+    /// frames that never return, so this unwinds to the call that pushed `return_to`, and leaves
+    /// the frames alone if no call did.
+    pub fn ret(&mut self, return_to: u16) {
+        if let Some(i) = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.return_to == return_to)
+        {
+            self.frames.truncate(i);
+        }
+    }
+
+    /// The chain of calls leading to `pos`, innermost first, in the format of the postmortem `bt`:
+    /// each frame with the address it returns to, the routine it is in and the call it came from,
+    /// then the locals of the routine on `stack`, the data stack.
+    pub fn backtrace(&self, pos: u16, stack: &[u16], project: &Project) -> String {
+        let routine = |depth: usize| {
+            let frame = self.frames.len().checked_sub(depth + 1)?;
+            Some(format!(
+                " in {}",
+                project.describe(self.frames[frame].target)
+            ))
+        };
+        let locals = |out: &mut String, depth: usize| {
+            let Some(frame) = self.frames.len().checked_sub(depth + 1) else {
+                return;
+            };
+            let end = match self.frames.get(frame + 1) {
+                Some(callee) => callee.base,
+                None => stack.len(),
+            };
+            let start = self.frames[frame].start;
+            let Some(values) = stack.get(start..end.min(stack.len())) else {
+                return;
+            };
+            let target = self.frames[frame].target;
+            let locals = values
+                .iter()
+                .enumerate()
+                .map(|(slot, value)| format!("{} = {value}", self.local(target, slot, project)))
+                .collect::<Vec<_>>();
+            if !locals.is_empty() {
+                let _ = writeln!(out, "     {}", locals.join(", "));
+            }
+        };
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "#0   {}{}",
+            project.describe(pos),
+            routine(0).unwrap_or_default()
+        );
+        locals(&mut out, 0);
+        for (i, frame) in self.frames.iter().rev().enumerate() {
+            let _ = writeln!(
+                out,
+                "#{:<3} {}{} (called from {})",
+                i + 1,
+                project.describe(frame.return_to),
+                routine(i + 1).unwrap_or_default(),
+                frame.site
+            );
+            locals(&mut out, i + 1);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::setup, RunOutcome};
+
+    #[test]
+    fn pairs_calls_and_returns() {
+        // 0: call 10
+        // 2: halt
+        // 10: call 20
+        // 12: ret
+        // 20: pop r0
+        // 22: ret
+        let mut program = vec![17, 10, 0];
+        program.resize(10, 21);
+        program.extend([17, 20, 18]);
+        program.resize(20, 21);
+        program.extend([3, 32768, 18]);
+        let mut machine = setup(program);
+        machine.call_stack = Some(CallStack::new());
+
+        assert_eq!(machine.run_for(2), Ok(RunOutcome::FuelExhausted));
+        let project = Project::parse("symbol 10 outer\nsymbol 20 inner").unwrap();
+        assert_eq!(
+            machine
+                .call_stack
+                .as_ref()
+                .unwrap()
+                .backtrace(20, machine.stack.as_slice(), &project),
+            "#0   20 <inner> in 20 <inner>\n\
+             #1   12 <outer+2> in 10 <outer> (called from 10)\n\
+             #2   2 (called from 0)\n"
+        );
+
+        // the inner routine dropped its return address, so the outer one returns for both
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.call_stack.unwrap().frames, []);
+    }
+
+    #[test]
+    fn infers_locals() {
+        // 0: call 10
+        // 2: halt
+        // 10: push r0
+        // 12: push 7
+        // 14: call 30
+        // 16: pop r1
+        // 18: pop r0
+        // 20: ret
+        // 30: push r2
+        // 32: pop r2
+        // 34: ret
+        let mut program = vec![17, 10, 0];
+        program.resize(10, 21);
+        program.extend([2, 32768, 2, 7, 17, 30, 3, 32769, 3, 32768, 18]);
+        program.resize(30, 21);
+        program.extend([2, 32770, 3, 32770, 18]);
+        let mut machine = setup(program);
+        machine.registers[0] = 4;
+        machine.call_stack = Some(CallStack::new());
+
+        assert_eq!(machine.run_for(5), Ok(RunOutcome::FuelExhausted));
+        let project = Project::parse("symbol 10 outer\nsymbol 30 inner").unwrap();
+        let calls = machine.call_stack.as_ref().unwrap();
+        assert_eq!(
+            calls.backtrace(32, machine.stack.as_slice(), &project),
+            "#0   32 <inner+2> in 30 <inner>\n     \
+             pushed r2 = 0\n\
+             #1   16 <outer+6> in 10 <outer> (called from 14)\n     \
+             pushed r0 = 4, pushed 7 = 7\n\
+             #2   2 (called from 0)\n"
+        );
+        let slot = |index| calls.describe_slot(index, &project);
+        assert_eq!(slot(0).unwrap(), "return to 2");
+        assert_eq!(slot(1).unwrap(), "pushed r0 in 10 <outer>");
+        assert_eq!(slot(3).unwrap(), "return to 16 <outer+6>");
+
+        // another push to the same slot leaves it unnamed
+        let mut calls = calls.clone();
+        calls.push(32769, 5);
+        assert_eq!(calls.locals[&30], [None]);
+        assert_eq!(calls.locals[&10], [Some(32768), Some(7)]);
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+    }
+}
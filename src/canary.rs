@@ -0,0 +1,131 @@
+use crate::{audit::StackOp, ExecutionError, MachineState, OpcodeResult};
+
+/// How an entry of the stack got there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pushed {
+    pub value: u16,
+    /// The position of the instruction that pushed it.
+    pub pos: u16,
+    pub by_call: bool,
+}
+
+/// Tags the return addresses `call` pushes, so that a `ret` to anything else fails right where it
+/// happens instead of sending execution off somewhere: a value pushed by `push`, which is a bug of
+/// the program, or a value other than the one pushed, which is a bug of the VM.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StackCanary {
+    /// Mirrors the stack. Entries pushed while it wasn't looking are `None`, and aren't checked.
+    entries: Vec<Option<Pushed>>,
+}
+
+impl StackCanary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MachineState {
+    /// Called after the instruction at `pos` pushed or popped `value`, failing if it was a `ret`
+    /// that didn't pop a return address.
+    pub(crate) fn check_canary(&mut self, op: StackOp, pos: u16, value: u16) -> OpcodeResult {
+        let depth = self.stack.len();
+        // with a return stack of their own, return addresses can't be overwritten by data
+        let Some(canary) = self
+            .stack_canary
+            .as_mut()
+            .filter(|_| self.return_stack.is_none())
+        else {
+            return Ok(());
+        };
+        if matches!(op, StackOp::Push | StackOp::Call) {
+            canary.entries.resize(depth - 1, None);
+            canary.entries.push(Some(Pushed {
+                value,
+                pos,
+                by_call: op == StackOp::Call,
+            }));
+            return Ok(());
+        }
+
+        canary.entries.resize(depth + 1, None);
+        let pushed = canary.entries.pop().flatten();
+        if op == StackOp::Pop {
+            return Ok(());
+        }
+        let provenance = match pushed {
+            Some(pushed) if !pushed.by_call => {
+                format!("it was pushed by the `push` at index `{}`", pushed.pos)
+            }
+            Some(pushed) if pushed.value != value => format!(
+                "the `call` at index `{}` pushed `{}` there",
+                pushed.pos, pushed.value
+            ),
+            _ => return Ok(()),
+        };
+        Err(ExecutionError::ReturnAddressCorrupted(
+            value, pos, provenance,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::setup, RunOutcome};
+
+    fn guarded(program: Vec<u16>) -> MachineState {
+        let mut machine = setup(program);
+        machine.stack_canary = Some(StackCanary::new());
+        machine
+    }
+
+    #[test]
+    fn allows_calls() {
+        // 0: call 4
+        // 2: halt
+        // 4: push 7
+        // 6: pop r0
+        // 8: ret
+        let mut machine = guarded(vec![17, 4, 0, 0, 2, 7, 3, 32768, 18]);
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.registers[0], 7);
+    }
+
+    #[test]
+    fn catches_returns_to_data() {
+        // 0: call 4
+        // 2: halt
+        // 4: pop r0
+        // 6: push 2
+        // 8: ret
+        let mut machine = guarded(vec![17, 4, 0, 0, 3, 32768, 2, 2, 18]);
+        assert_eq!(
+            machine.run(),
+            Err(ExecutionError::ReturnAddressCorrupted(
+                2,
+                8,
+                "it was pushed by the `push` at index `6`".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn catches_changed_return_addresses() {
+        // 0: call 3
+        // 2: halt
+        // 3: ret
+        let mut machine = guarded(vec![17, 3, 0, 18]);
+        machine.exec_next().unwrap();
+        // as a VM bug overwriting the stack would
+        machine.stack.pop();
+        machine.stack.push(0);
+        assert_eq!(
+            machine.run(),
+            Err(ExecutionError::ReturnAddressCorrupted(
+                0,
+                3,
+                "the `call` at index `0` pushed `2` there".to_string()
+            ))
+        );
+    }
+}
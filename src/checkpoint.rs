@@ -0,0 +1,135 @@
+use crate::{crash::CrashDump, slots, ExecutionError, MachineState};
+
+/// Starts a line of input naming a checkpoint, e.g. `@checkpoint after_ruins`.
+pub const DIRECTIVE: &str = "@checkpoint";
+
+/// Snapshots named by the `@checkpoint <name>` lines of a walkthrough script, so play can be
+/// resumed from any milestone of it. Those lines are taken out of the input instead of being read
+/// by the program, and each saves the machine as it is waiting for the line after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoints {
+    /// Whether the next input byte starts a line.
+    at_line_start: bool,
+    pub saved: Vec<(String, CrashDump)>,
+}
+
+impl Checkpoints {
+    pub fn new() -> Self {
+        Self {
+            at_line_start: true,
+            saved: Vec::new(),
+        }
+    }
+}
+
+impl Default for Checkpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses the name out of a checkpoint line, which names the slot it is saved to.
+pub fn parse_directive(line: &str) -> Result<String, String> {
+    let name = line
+        .strip_prefix(DIRECTIVE)
+        .filter(|rest| rest.starts_with(' '))
+        .map(str::trim)
+        .ok_or_else(|| format!("unknown directive `{line}`, expected `{DIRECTIVE} <name>`"))?;
+    slots::check_name(name)?;
+    Ok(name.to_string())
+}
+
+impl MachineState {
+    /// Reads the next input byte for the `in` at `pos`, the input injected by timers first, then
+    /// that of the script, saving the checkpoints before it.
+    pub(crate) fn read_input(&mut self, pos: u16) -> Result<Option<u8>, ExecutionError> {
+        let read_byte =
+            |machine: &mut Self| match machine.timer_input().or_else(|| machine.script_input()) {
+                Some(byte) => Ok(Some(byte)),
+                None => machine
+                    .io
+                    .read_byte()
+                    .map_err(|err| ExecutionError::ReadError(format!("{:?}", err), pos)),
+            };
+        loop {
+            let read = read_byte(self)?;
+            let Some(checkpoints) = &mut self.checkpoints else {
+                return Ok(read);
+            };
+            let at_line_start = checkpoints.at_line_start;
+            if let Some(byte) = read {
+                checkpoints.at_line_start = byte == b'\n';
+            }
+            if !at_line_start || read != Some(b'@') {
+                return Ok(read);
+            }
+
+            let mut line = vec![b'@'];
+            while let Some(byte) = read_byte(self)? {
+                if byte == b'\n' {
+                    break;
+                }
+                line.push(byte);
+            }
+            let line = String::from_utf8_lossy(&line);
+            let name = parse_directive(line.trim_end_matches('\r'))
+                .map_err(|err| ExecutionError::ReadError(err, pos))?;
+            let dump = CrashDump::before(self, pos, format!("checkpoint `{name}`"));
+            if let Some(checkpoints) = &mut self.checkpoints {
+                checkpoints.at_line_start = true;
+                checkpoints.saved.push((name, dump));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MachineBuilder, RunOutcome};
+
+    // 0: in r0
+    // 2: out r0
+    // 4: jmp 0
+    fn echo(input: &[u8]) -> MachineState {
+        let mut machine = MachineBuilder::new()
+            .program(&[20, 32768, 19, 32768, 6, 0])
+            .input(input)
+            .build();
+        machine.checkpoints = Some(Checkpoints::new());
+        machine
+    }
+
+    #[test]
+    fn saves_checkpoints() {
+        let mut machine = echo(b"go\n@checkpoint first\n@checkpoint second\nx@y\n");
+        assert_eq!(machine.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(machine.drain_output(), b"go\nx@y\n");
+
+        let saved = machine.checkpoints.unwrap().saved;
+        let names = saved
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["first", "second"]);
+        let dump = &saved[0].1;
+        assert_eq!((dump.cur, dump.steps), (0, 9));
+        assert_eq!(dump.history.last(), Some(&4));
+
+        // resuming from a checkpoint with the rest of the script gives the same output
+        let mut resumed = dump.to_machine();
+        resumed.push_input(b"x@y\n");
+        assert_eq!(resumed.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(resumed.drain_output(), b"x@y\n");
+    }
+
+    #[test]
+    fn rejects_malformed_directives() {
+        let mut machine = echo(b"@save here\n");
+        assert!(machine.run().is_err());
+        assert!(parse_directive("@checkpoint ../up").is_err());
+        assert!(parse_directive("@checkpoint").is_err());
+        assert!(parse_directive("@checkpointed x").is_err());
+        assert_eq!(parse_directive("@checkpoint a_1 "), Ok("a_1".to_string()));
+    }
+}
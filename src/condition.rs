@@ -0,0 +1,385 @@
+use std::fmt;
+
+use crate::{MachineState, REGISTER_COUNT};
+
+/// A binary operator, from the loosest binding to the tightest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Op {
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Or => 0,
+            Op::And => 1,
+            Op::Eq | Op::Ne | Op::Lt | Op::Le | Op::Gt | Op::Ge => 2,
+            Op::Add | Op::Sub => 3,
+            Op::Mul | Op::Div | Op::Rem => 4,
+        }
+    }
+
+    /// Applies the operator, or returns `None` if the result is undefined.
+    fn apply(self, a: i64, b: i64) -> Option<i64> {
+        let truth = |holds: bool| Some(holds as i64);
+        match self {
+            Op::Or => truth(a != 0 || b != 0),
+            Op::And => truth(a != 0 && b != 0),
+            Op::Eq => truth(a == b),
+            Op::Ne => truth(a != b),
+            Op::Lt => truth(a < b),
+            Op::Le => truth(a <= b),
+            Op::Gt => truth(a > b),
+            Op::Ge => truth(a >= b),
+            Op::Add => a.checked_add(b),
+            Op::Sub => a.checked_sub(b),
+            Op::Mul => a.checked_mul(b),
+            Op::Div => a.checked_div(b),
+            Op::Rem => a.checked_rem(b),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Number(i64),
+    Register(usize),
+    /// The word at an address in memory.
+    Mem(Box<Expr>),
+    /// The number of values on the stack.
+    Depth,
+    /// The number of instructions executed so far.
+    Steps,
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, machine: &MachineState) -> Option<i64> {
+        match self {
+            Expr::Number(n) => Some(*n),
+            Expr::Register(reg) => Some(machine.registers[*reg] as i64),
+            Expr::Mem(addr) => {
+                let addr = usize::try_from(addr.eval(machine)?).ok()?;
+                machine.mem.get(addr).map(|&word| word as i64)
+            }
+            Expr::Depth => Some(machine.stack.len() as i64),
+            Expr::Steps => i64::try_from(machine.steps).ok(),
+            Expr::Not(expr) => Some((expr.eval(machine)? == 0) as i64),
+            Expr::Binary(op, a, b) => op.apply(a.eval(machine)?, b.eval(machine)?),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Op(Op),
+    Not,
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+    Dot,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c.is_ascii_alphanumeric() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let word = &text[start..end];
+            tokens.push(if c.is_ascii_digit() {
+                Token::Number(parse_literal(word)?)
+            } else {
+                Token::Ident(word.to_string())
+            });
+            continue;
+        }
+
+        chars.next();
+        if c == '\'' {
+            // a character literal such as 'a', which stands for its code
+            match (chars.next(), chars.next()) {
+                (Some((_, c)), Some((_, '\''))) => tokens.push(Token::Number(c as i64)),
+                _ => return Err("unterminated character in condition".to_string()),
+            }
+            continue;
+        }
+        let next = chars.peek().map(|&(_, c)| c);
+        let (token, pair) = match (c, next) {
+            ('|', Some('|')) => (Token::Op(Op::Or), true),
+            ('&', Some('&')) => (Token::Op(Op::And), true),
+            ('=', Some('=')) => (Token::Op(Op::Eq), true),
+            ('!', Some('=')) => (Token::Op(Op::Ne), true),
+            ('<', Some('=')) => (Token::Op(Op::Le), true),
+            ('>', Some('=')) => (Token::Op(Op::Ge), true),
+            ('<', _) => (Token::Op(Op::Lt), false),
+            ('>', _) => (Token::Op(Op::Gt), false),
+            ('+', _) => (Token::Op(Op::Add), false),
+            ('-', _) => (Token::Op(Op::Sub), false),
+            ('*', _) => (Token::Op(Op::Mul), false),
+            ('/', _) => (Token::Op(Op::Div), false),
+            ('%', _) => (Token::Op(Op::Rem), false),
+            ('!', _) => (Token::Not, false),
+            ('(', _) => (Token::Open, false),
+            (')', _) => (Token::Close, false),
+            ('[', _) => (Token::OpenBracket, false),
+            (']', _) => (Token::CloseBracket, false),
+            ('.', _) => (Token::Dot, false),
+            _ => return Err(format!("unexpected `{c}` in condition")),
+        };
+        if pair {
+            chars.next();
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Parses a decimal or `0x` hexadecimal number. Unlike addresses and values, numbers in
+/// conditions may be compared with the step counter, so they aren't limited to 15 bits.
+fn parse_literal(word: &str) -> Result<i64, String> {
+    let parsed = match word.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => word.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number `{word}`"))
+}
+
+/// A recursive descent parser over the tokens of a condition.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            _ => Err(format!("expected `{what}` in condition")),
+        }
+    }
+
+    /// Parses operators binding at least as tightly as `min`, by precedence climbing.
+    fn binary(&mut self, min: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(&Token::Op(op)) = self.tokens.get(self.pos) {
+            if op.precedence() < min {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expr = self.binary(0)?;
+                self.expect(Token::Close, ")")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "depth" => Ok(Expr::Depth),
+                // the same, spelled like a method
+                "stack" => {
+                    self.expect(Token::Dot, ".")?;
+                    self.expect(Token::Ident("len".to_string()), "len")?;
+                    self.expect(Token::Open, "(")?;
+                    self.expect(Token::Close, ")")?;
+                    Ok(Expr::Depth)
+                }
+                "steps" => Ok(Expr::Steps),
+                "mem" => {
+                    self.expect(Token::OpenBracket, "[")?;
+                    let addr = self.binary(0)?;
+                    self.expect(Token::CloseBracket, "]")?;
+                    Ok(Expr::Mem(Box::new(addr)))
+                }
+                _ => match name.strip_prefix('r').map(str::parse::<usize>) {
+                    Some(Ok(reg)) if reg < REGISTER_COUNT => Ok(Expr::Register(reg)),
+                    _ => Err(format!("unknown name `{name}` in condition")),
+                },
+            },
+            _ => Err("expected a value in condition".to_string()),
+        }
+    }
+}
+
+/// A condition on the state of the machine, such as `r0 == 4 && mem[r1] > 100`. Values are
+/// numbers, registers `r0`-`r7`, words of memory `mem[addr]`, the stack `depth` (or
+/// `stack.len()`) and the `steps` executed. Comparisons, `&&`, `||` and `!` give 1 or 0, and any value other than 0 holds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    /// The condition as written.
+    pub text: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            pos: 0,
+        };
+        let expr = parser.binary(0)?;
+        if parser.pos < parser.tokens.len() {
+            return Err(format!("unexpected input at the end of `{text}`"));
+        }
+        Ok(Self {
+            text: text.trim().to_string(),
+            expr,
+        })
+    }
+
+    /// Whether the condition holds for `machine`. One that can't be evaluated, e.g. because it
+    /// divides by zero or reads outside memory, holds, so that the breakpoint isn't missed.
+    pub fn holds(&self, machine: &MachineState) -> bool {
+        self.eval(machine).is_none_or(|value| value != 0)
+    }
+
+    /// The value of the condition for `machine`, or `None` if it can't be evaluated.
+    pub fn eval(&self, machine: &MachineState) -> Option<i64> {
+        self.expr.eval(machine)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Splits a breakpoint such as `6027 if r0 == 4` into its address and condition.
+pub fn parse_breakpoint(spec: &str) -> Result<(&str, Option<Condition>), String> {
+    match spec.trim().split_once(" if ") {
+        Some((addr, condition)) => Ok((addr.trim(), Some(Condition::parse(condition)?))),
+        None => Ok((spec.trim(), None)),
+    }
+}
+
+impl MachineState {
+    /// Whether the machine is at a breakpoint whose condition, if any, holds.
+    pub(crate) fn at_breakpoint(&self) -> bool {
+        match self.breakpoints.get(&self.cur) {
+            None => false,
+            Some(None) => true,
+            Some(Some(condition)) => condition.holds(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{setup, MachineBuilder},
+        RunOutcome,
+    };
+
+    fn holds(text: &str, machine: &MachineState) -> bool {
+        Condition::parse(text).unwrap().holds(machine)
+    }
+
+    #[test]
+    fn evaluates_conditions() {
+        let machine = MachineBuilder::new()
+            .program(&[21, 7, 0x10])
+            .register(0, 4)
+            .register(1, 101)
+            .stack(&[1, 2])
+            .build();
+        assert!(holds("r0 == 4 && r1 > 100", &machine));
+        assert!(!holds("r0 == 4 && r1 > 101", &machine));
+        assert!(holds("r0 != 4 || depth == 2", &machine));
+        assert!(holds("mem[r0 - 3] * 2 + 1 == 15", &machine));
+        assert!(holds("mem[2] == 0x10 && !(steps > 0)", &machine));
+        assert!(holds("(r1 - r0) % 10 == 7", &machine));
+        assert!(holds("1 + 2 * 3 == 7", &machine));
+        assert!(holds("r0 - 5 < 0", &machine));
+        assert!(holds("steps < 100000 && 0x10000 > 65535", &machine));
+        assert!(holds("r1 == 'e'", &machine));
+        assert!(holds("stack.len() == depth", &machine));
+        // undefined values don't hide the breakpoint
+        assert!(holds("r0 / 0", &machine));
+        assert!(holds("mem[40000] == 1", &machine));
+    }
+
+    #[test]
+    fn evaluates_values() {
+        let machine = MachineBuilder::new().register(7, 25734).stack(&[9]).build();
+        let eval = |text| Condition::parse(text).unwrap().eval(&machine);
+        assert_eq!(eval("r7"), Some(25734));
+        assert_eq!(eval("r7 > 1 && depth"), Some(1));
+        assert_eq!(eval("stack.len() * 3"), Some(3));
+        assert_eq!(eval("mem[r7 * 2]"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_conditions() {
+        for text in [
+            "",
+            "r8 == 1",
+            "foo",
+            "r0 ==",
+            "(r0",
+            "mem 1",
+            "r0 = 1",
+            "r0 1",
+            "r0 == 'a",
+            "stack",
+            "stack.len",
+        ] {
+            assert!(Condition::parse(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn stops_when_the_condition_holds() {
+        // 0: add r0 r0 1
+        // 4: jmp 0
+        let mut machine = setup(vec![9, 32768, 32768, 1, 6, 0]);
+        let (addr, condition) = parse_breakpoint("4 if r0 == 3").unwrap();
+        assert_eq!(addr, "4");
+        machine.breakpoints.insert(4, condition);
+        assert_eq!(machine.run(), Ok(RunOutcome::Breakpoint(4)));
+        assert_eq!(machine.registers[0], 3);
+        assert_eq!(parse_breakpoint(" 12 ").unwrap(), ("12", None));
+    }
+}
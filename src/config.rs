@@ -0,0 +1,355 @@
+//! The TUI's config file, written in the subset of TOML read by `toml`:
+//! ```toml
+//! # the layout to start in
+//! layout = "debug"
+//!
+//! # panes by name, each with its share of the column's height after a `:`
+//! [layout.reversing]
+//! columns = ["disassembly:3 watches", "memory:2 registers"]
+//! widths = [1, 1]   # the columns' shares of the width
+//!
+//! [theme]
+//! base = "16"         # `default` for 256 colors, `16`, or `none` for no colors
+//! cursor = "30;43"    # the SGR parameters of a style, replacing the base's
+//!
+//! [keys]
+//! step = ["n", "F10"]   # replacing the keys of an action, or unbinding them with `[]`
+//! ```
+//! The layouts `debug`, `play` and `split` are built in, and can be replaced. Without a base, the
+//! theme is the one that suits the terminal. A key can only do one thing, and `command` and `quit`
+//! need keys.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::{
+    layout::Layout,
+    terminal::Key,
+    theme::{Base, Style, Theme},
+    toml::{self, escape, Item, Value},
+    tui::{Action, KEYS},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The layout the TUI starts in.
+    pub layout: String,
+    pub layouts: BTreeMap<String, Layout>,
+    /// The palette of the theme, `None` to choose one from the terminal.
+    pub base: Option<Base>,
+    /// The styles replacing those of the palette.
+    pub styles: BTreeMap<Style, String>,
+    /// The keys of each action, in the order of `Action::ALL`.
+    pub keys: Vec<(Key, Action)>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            layout: "debug".to_string(),
+            layouts: BTreeMap::from([
+                ("debug".to_string(), Layout::debug()),
+                ("play".to_string(), Layout::play()),
+                ("split".to_string(), Layout::split()),
+            ]),
+            base: None,
+            styles: BTreeMap::new(),
+            keys: bind(&BTreeMap::new()),
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let items = toml::parse(text).map_err(|(msg, line)| ConfigError::Parse(msg, line))?;
+        let mut config = Self::default();
+        // the columns and widths of each layout, and the line its table starts on
+        let mut layouts: Vec<(String, usize, Vec<String>, Vec<usize>)> = Vec::new();
+        let mut table = None;
+        // the keys given to actions, and the lines they were given on
+        let mut bindings = BTreeMap::new();
+
+        for (line_no, item) in items {
+            let err = |msg: String| ConfigError::Parse(msg, line_no);
+            let (key, value) = match item {
+                Item::Table(name) if name == "theme" || name == "keys" => {
+                    table = Some(name);
+                    continue;
+                }
+                Item::Table(name) => match name.strip_prefix("layout.") {
+                    Some(name) => {
+                        table = None;
+                        layouts.push((name.to_string(), line_no, Vec::new(), Vec::new()));
+                        continue;
+                    }
+                    None => return Err(err(format!("unknown table `{name}`"))),
+                },
+                Item::ArrayTable(name) => return Err(err(format!("unknown table `{name}`"))),
+                Item::Entry(key, value) => (key, value),
+            };
+            if table.as_deref() == Some("keys") {
+                let action = Action::parse(&key).map_err(err)?;
+                let keys = match value {
+                    Value::Str(key) => vec![key],
+                    Value::Array(items) => items
+                        .into_iter()
+                        .map(|item| match item {
+                            Value::Str(key) => Ok(key),
+                            _ => Err(err("keys must be strings".to_string())),
+                        })
+                        .collect::<Result<_, _>>()?,
+                    value => return Err(err(format!("invalid entry `{key}` = {value:?}"))),
+                };
+                let keys = keys
+                    .iter()
+                    .map(|key| key.parse::<Key>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(err)?;
+                bindings.insert(action, (keys, line_no));
+                continue;
+            }
+            if table.as_deref() == Some("theme") {
+                match (key.as_str(), value) {
+                    ("base", Value::Str(name)) => {
+                        config.base = Some(Base::parse(&name).map_err(err)?)
+                    }
+                    (name, Value::Str(params)) => {
+                        let style = Style::parse(name).map_err(err)?;
+                        Theme::check_params(&params).map_err(err)?;
+                        config.styles.insert(style, params);
+                    }
+                    (key, value) => return Err(err(format!("invalid entry `{key}` = {value:?}"))),
+                }
+                continue;
+            }
+            match (layouts.last_mut(), key.as_str(), value) {
+                (None, "layout", Value::Str(name)) => config.layout = name,
+                (Some((_, _, columns, _)), "columns", Value::Array(items)) => {
+                    for item in items {
+                        match item {
+                            Value::Str(column) => columns.push(column),
+                            _ => return Err(err("`columns` must contain strings".to_string())),
+                        }
+                    }
+                }
+                (Some((_, _, _, widths)), "widths", Value::Array(items)) => {
+                    for item in items {
+                        match item {
+                            Value::Int(width) => widths.push(width as usize),
+                            _ => return Err(err("`widths` must contain numbers".to_string())),
+                        }
+                    }
+                }
+                (_, key, value) => return Err(err(format!("invalid entry `{key}` = {value:?}"))),
+            }
+        }
+
+        for (name, line_no, columns, widths) in layouts {
+            let columns = columns.iter().map(String::as_str).collect::<Vec<_>>();
+            let layout = Layout::parse(&columns, &widths)
+                .map_err(|msg| ConfigError::Parse(format!("{msg} in layout `{name}`"), line_no))?;
+            config.layouts.insert(name, layout);
+        }
+        if !config.layouts.contains_key(&config.layout) {
+            return Err(ConfigError::UnknownLayout(config.layout));
+        }
+
+        let keys = bindings
+            .iter()
+            .map(|(&action, (keys, _))| (action, keys.clone()))
+            .collect();
+        config.keys = bind(&keys);
+        // where the problems with a key are, if anywhere
+        let line_of = |action| bindings.get(&action).map_or(0, |&(_, line)| line);
+        for (i, &(key, action)) in config.keys.iter().enumerate() {
+            if let Some(&(_, first)) = config.keys[..i].iter().find(|&&(bound, _)| bound == key) {
+                if first != action {
+                    let msg = format!(
+                        "`{key}` is bound to both `{}` and `{}`",
+                        first.name(),
+                        action.name()
+                    );
+                    return Err(ConfigError::Parse(msg, line_of(action).max(line_of(first))));
+                }
+            }
+        }
+        for action in [Action::Command, Action::Quit] {
+            if !config.keys.iter().any(|&(_, bound)| bound == action) {
+                let msg = format!("`{}` needs a key", action.name());
+                return Err(ConfigError::Parse(msg, line_of(action)));
+            }
+        }
+        Ok(config)
+    }
+
+    /// The theme to draw with on a terminal of type `term`, where `no_color` asks for no
+    /// colors unless the config's base has them.
+    pub fn theme(&self, term: Option<&str>, no_color: bool) -> Theme {
+        Theme {
+            base: match self.base {
+                Some(base) => base,
+                None => Theme::for_terminal(term, no_color).base,
+            },
+            styles: self.styles.clone(),
+        }
+    }
+
+    /// Serializes the config into the format read by `parse`, leaving out the built-in layouts
+    /// it doesn't change.
+    pub fn to_toml(&self) -> String {
+        let defaults = Self::default();
+        let list = |items: Vec<String>| items.join(", ");
+
+        let mut out = String::new();
+        let _ = writeln!(out, "layout = \"{}\"", escape(&self.layout));
+        for (name, layout) in &self.layouts {
+            if defaults.layouts.get(name) == Some(layout) {
+                continue;
+            }
+            let (columns, widths) = layout.to_columns();
+            let columns = columns
+                .iter()
+                .map(|column| format!("\"{}\"", escape(column)))
+                .collect();
+            let widths = widths.iter().map(|width| width.to_string()).collect();
+            let _ = writeln!(out, "\n[layout.{name}]");
+            let _ = writeln!(out, "columns = [{}]", list(columns));
+            let _ = writeln!(out, "widths = [{}]", list(widths));
+        }
+        let defaults = bind(&BTreeMap::new());
+        let keys_of = |keys: &[(Key, Action)], action| {
+            keys.iter()
+                .filter(|&&(_, bound)| bound == action)
+                .map(|&(key, _)| key)
+                .collect::<Vec<_>>()
+        };
+        let changed = Action::ALL
+            .into_iter()
+            .filter(|&action| keys_of(&self.keys, action) != keys_of(&defaults, action))
+            .collect::<Vec<_>>();
+        if !changed.is_empty() {
+            let _ = writeln!(out, "\n[keys]");
+            for action in changed {
+                let keys = keys_of(&self.keys, action)
+                    .iter()
+                    .map(|key| format!("\"{}\"", escape(&key.to_string())))
+                    .collect();
+                let _ = writeln!(out, "{} = [{}]", action.name(), list(keys));
+            }
+        }
+        if self.base.is_some() || !self.styles.is_empty() {
+            let _ = writeln!(out, "\n[theme]");
+            if let Some(base) = self.base {
+                let _ = writeln!(out, "base = \"{}\"", base.name());
+            }
+            for (style, params) in &self.styles {
+                let _ = writeln!(out, "{} = \"{}\"", style.name(), escape(params));
+            }
+        }
+        out
+    }
+}
+
+/// The keys of each action: those in `keys`, or the default ones.
+fn bind(keys: &BTreeMap<Action, Vec<Key>>) -> Vec<(Key, Action)> {
+    let mut bindings = Vec::new();
+    for action in Action::ALL {
+        match keys.get(&action) {
+            Some(keys) => bindings.extend(keys.iter().map(|&key| (key, action))),
+            None => bindings.extend(KEYS.iter().filter(|&&(_, bound)| bound == action)),
+        }
+    }
+    bindings
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error("Invalid config: {0} on line `{1}`")]
+    Parse(String, usize),
+    #[error("The config starts in layout `{0}`, which it doesn't define")]
+    UnknownLayout(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::Pane;
+
+    #[test]
+    fn parses_layouts() {
+        let config = Config::parse(
+            r#"
+            layout = "reversing"
+            [layout.reversing]
+            columns = ["disassembly:3 watches", "memory:2 registers"]
+            widths = [2, 1]
+            [layout.play]
+            columns = ["output log"]
+            [theme]
+            base = "none"
+            breakpoint = "1;4"
+            [keys]
+            step = ["n", "F10"]
+            play = []
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.layout, "reversing");
+        assert_eq!(
+            config.layouts.keys().collect::<Vec<_>>(),
+            ["debug", "play", "reversing", "split"]
+        );
+        assert_eq!(config.layouts["play"].column_of(Pane::Log), Some(0));
+        let theme = config.theme(Some("xterm-256color"), false);
+        assert_eq!(theme.base, Base::NoColor);
+        assert_eq!(theme.sgr(Style::Breakpoint), "\x1b[1;4m");
+        assert!(config.keys.contains(&(Key::F(10), Action::Step)));
+        assert!(!config
+            .keys
+            .iter()
+            .any(|&(_, action)| action == Action::Play));
+        assert!(config.keys.contains(&(Key::Char('c'), Action::Continue)));
+        assert_eq!(Config::parse(&config.to_toml()), Ok(config));
+        assert_eq!(Config::parse(""), Ok(Config::default()));
+    }
+
+    #[test]
+    fn rejects_bad_configs() {
+        assert_eq!(
+            Config::parse("layout = \"nowhere\""),
+            Err(ConfigError::UnknownLayout("nowhere".to_string()))
+        );
+        assert_eq!(
+            Config::parse("[layout.x]\ncolumns = [\"registers\"]\n\n[layout.y]\ncolumns = []"),
+            Err(ConfigError::Parse(
+                "a layout needs a column in layout `y`".to_string(),
+                4
+            ))
+        );
+        assert!(Config::parse("[colours]").is_err());
+        assert!(Config::parse("[layout.x]\ncolumns = [1]").is_err());
+        assert!(Config::parse("columns = [\"memory\"]").is_err());
+        assert!(Config::parse("[theme]\nbase = \"sepia\"").is_err());
+        assert!(Config::parse("[theme]\ncursor = \"blue\"").is_err());
+        assert!(Config::parse("[theme]\npurple = \"35\"").is_err());
+        assert_eq!(
+            Config::parse("[keys]\n\nstep = \"c\""),
+            Err(ConfigError::Parse(
+                "`c` is bound to both `step` and `continue`".to_string(),
+                3
+            ))
+        );
+        assert_eq!(
+            Config::parse("[keys]\nquit = []"),
+            Err(ConfigError::Parse("`quit` needs a key".to_string(), 2))
+        );
+        // the key can move once the other action lets go of it
+        assert!(Config::parse("[keys]\nstep = \"c\"\ncontinue = \"C\"").is_ok());
+        // a bracket key doesn't open an array
+        let config = Config::parse("[keys]\nstep = \"[\"").unwrap();
+        assert!(config.keys.contains(&(Key::Char('['), Action::Step)));
+        assert_eq!(Config::parse(&config.to_toml()), Ok(config));
+        assert!(Config::parse("[keys]\nstep = \"Ctrl-S\"").is_err());
+        assert!(Config::parse("[keys]\nfly = \"f\"").is_err());
+    }
+}
@@ -0,0 +1,91 @@
+use crate::{MachineState, MAX_ADDR};
+
+pub const WIDTH: usize = 80;
+pub const HEIGHT: usize = 25;
+/// The number of words the screen takes up in memory.
+pub const CELLS: usize = WIDTH * HEIGHT;
+
+/// A virtual 80x25 character screen mapped onto memory, for programs that draw instead of
+/// printing line by line with `out`. The cell at column `x` and row `y` is the word at
+/// `base + y * 80 + x`, whose low byte is the ASCII character shown there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Console {
+    pub base: u16,
+    /// Whether the screen was written since it was last rendered.
+    pub changed: bool,
+}
+
+impl Console {
+    /// Maps the screen at `base`, failing if it doesn't fit in memory.
+    pub fn new(base: u16) -> Result<Self, String> {
+        if base as usize + CELLS > MAX_ADDR {
+            return Err(format!(
+                "a console at `{base}` doesn't fit in memory, it takes {CELLS} words"
+            ));
+        }
+        Ok(Self {
+            base,
+            changed: false,
+        })
+    }
+
+    pub fn contains(&self, addr: u16) -> bool {
+        (self.base as usize..self.base as usize + CELLS).contains(&(addr as usize))
+    }
+
+    /// Draws the screen held in `mem` as 25 lines of text, showing characters that can't be
+    /// printed as blanks, and clears `changed`.
+    pub fn render(&mut self, mem: &[u16]) -> String {
+        self.changed = false;
+        let mut out = String::with_capacity(CELLS + HEIGHT);
+        for row in 0..HEIGHT {
+            let start = self.base as usize + row * WIDTH;
+            let line = (start..start + WIDTH)
+                .map(|addr| match mem.get(addr).map(|&word| word as u8) {
+                    Some(ch) if ch.is_ascii_graphic() => ch as char,
+                    _ => ' ',
+                })
+                .collect::<String>();
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl MachineState {
+    /// Called after `addr` was written.
+    pub(crate) fn console_write(&mut self, addr: u16) {
+        if let Some(console) = &mut self.console {
+            if console.contains(addr) {
+                console.changed = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::setup, RunOutcome};
+
+    #[test]
+    fn draws_the_screen() {
+        // 0: wmem 1000 'H'
+        // 3: wmem 1001 'i'
+        // 6: wmem 1081 '!'
+        // 9: halt
+        let mut machine = setup(vec![16, 1000, 72, 16, 1001, 105, 16, 1081, 33, 0]);
+        machine.console = Some(Console::new(1000).unwrap());
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+
+        let mut console = machine.console.unwrap();
+        assert!(console.changed);
+        let screen = console.render(&machine.mem);
+        assert!(!console.changed);
+        assert_eq!(screen.lines().count(), HEIGHT);
+        assert!(screen.starts_with("Hi\n !\n\n"));
+        assert!(Console::new((MAX_ADDR - CELLS) as u16).is_ok());
+        assert!(Console::new((MAX_ADDR - CELLS + 1) as u16).is_err());
+    }
+}
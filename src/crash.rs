@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::{history::History, stack::Stack, MachineState, REGISTER_COUNT};
+
+/// The extension used for crash files, which are written next to the binary that crashed.
+pub const CRASH_EXTENSION: &str = "crash";
+/// The extension used for snapshots of runs that are still going, which are crash files too.
+pub const SNAPSHOT_EXTENSION: &str = "snapshot";
+
+const HEADER: &str = "synacor-crash 1";
+const WORDS_PER_LINE: usize = 16;
+
+static LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+/// Installs a panic hook that remembers the panic message and location for `last_panic`, before
+/// running the previously installed hook.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Ok(mut last) = LAST_PANIC.lock() {
+            *last = Some(info.to_string());
+        }
+        previous(info);
+    }));
+}
+
+/// The message of the most recent panic seen by the hook from `install_panic_hook`.
+pub fn last_panic() -> Option<String> {
+    LAST_PANIC.lock().ok().and_then(|last| last.clone())
+}
+
+/// Everything needed to diagnose a crash: the machine's state, the positions of the instructions
+/// executed right before it, and why it crashed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrashDump {
+    pub reason: String,
+    pub mem: Vec<u16>,
+    pub cur: u16,
+    pub registers: [u16; REGISTER_COUNT],
+    pub stack: Vec<u16>,
+    /// The return stack, in dual-stack mode.
+    pub return_stack: Option<Vec<u16>>,
+    pub steps: u64,
+    pub history: Vec<u16>,
+    /// The last instructions that wrote to each address, most recent first, if provenance was tracked.
+    pub writers: BTreeMap<u16, Vec<u16>>,
+    /// The save slot the machine was restored from.
+    pub parent: Option<String>,
+}
+
+impl CrashDump {
+    pub fn of(machine: &MachineState, reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+            mem: machine.mem.clone(),
+            cur: machine.cur,
+            registers: machine.registers,
+            stack: machine.stack.as_slice().to_vec(),
+            return_stack: machine
+                .return_stack
+                .as_ref()
+                .map(|stack| stack.as_slice().to_vec()),
+            steps: machine.steps,
+            history: machine.history.iter().collect(),
+            writers: machine
+                .provenance
+                .iter()
+                .flat_map(|provenance| provenance.iter())
+                .map(|(addr, writers)| (addr, writers.iter().rev().copied().collect()))
+                .collect(),
+            parent: machine.parent.clone(),
+        }
+    }
+
+    /// A dump of `machine` as it was before the `in` at `pos` it is executing, which resumes by
+    /// executing that `in` again.
+    pub(crate) fn before(machine: &MachineState, pos: u16, reason: impl Into<String>) -> Self {
+        let mut dump = Self::of(machine, reason);
+        dump.cur = pos;
+        dump.steps -= 1;
+        dump.history.pop();
+        dump
+    }
+
+    /// Rebuilds a machine in the dumped state.
+    pub fn to_machine(&self) -> MachineState {
+        let mut machine = MachineState::new(self.mem.clone());
+        machine.cur = self.cur;
+        machine.registers = self.registers;
+        machine.stack = self.stack.iter().copied().collect::<Stack>();
+        machine.return_stack = self
+            .return_stack
+            .as_ref()
+            .map(|stack| stack.iter().copied().collect::<Stack>());
+        machine.steps = self.steps;
+        machine.history = self.history.iter().copied().collect::<History>();
+        machine
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path.as_ref())
+            .map_err(|err| format!("{}: {err}", path.as_ref().display()))?;
+        Self::parse(&text)
+    }
+
+    pub fn to_text(&self) -> String {
+        let list = |words: &mut dyn Iterator<Item = u16>| {
+            words.map(|w| w.to_string()).collect::<Vec<_>>().join(" ")
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(out, "{HEADER}");
+        let _ = writeln!(out, "reason {}", self.reason.replace('\n', " "));
+        if let Some(parent) = &self.parent {
+            let _ = writeln!(out, "parent {parent}");
+        }
+        let _ = writeln!(out, "cur {}", self.cur);
+        let _ = writeln!(out, "steps {}", self.steps);
+        let _ = writeln!(
+            out,
+            "registers {}",
+            list(&mut self.registers.iter().copied())
+        );
+        let _ = writeln!(out, "stack {}", list(&mut self.stack.iter().copied()));
+        if let Some(stack) = &self.return_stack {
+            let _ = writeln!(out, "return-stack {}", list(&mut stack.iter().copied()));
+        }
+        let _ = writeln!(out, "history {}", list(&mut self.history.iter().copied()));
+        for (addr, writers) in &self.writers {
+            let _ = writeln!(out, "writers {addr} {}", list(&mut writers.iter().copied()));
+        }
+        let _ = writeln!(out, "mem");
+        for line in self.mem.chunks(WORDS_PER_LINE) {
+            let words = line.iter().map(|w| format!("{w:04x}")).collect::<Vec<_>>();
+            let _ = writeln!(out, "{}", words.join(" "));
+        }
+        out
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err("not a crash file".to_string());
+        }
+
+        let mut dump = CrashDump {
+            reason: String::new(),
+            mem: Vec::new(),
+            cur: 0,
+            registers: [0; REGISTER_COUNT],
+            stack: Vec::new(),
+            return_stack: None,
+            steps: 0,
+            history: Vec::new(),
+            writers: BTreeMap::new(),
+            parent: None,
+        };
+        let words = |s: &str| {
+            s.split_whitespace()
+                .map(|w| w.parse::<u16>().map_err(|_| format!("invalid word `{w}`")))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        for line in lines.by_ref() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "reason" => dump.reason = value.to_string(),
+                "parent" => dump.parent = Some(value.to_string()),
+                "cur" => dump.cur = value.parse().map_err(|_| "invalid `cur`")?,
+                "steps" => dump.steps = value.parse().map_err(|_| "invalid `steps`")?,
+                "registers" => {
+                    dump.registers = words(value)?
+                        .try_into()
+                        .map_err(|_| "expected 8 registers".to_string())?
+                }
+                "stack" => dump.stack = words(value)?,
+                "return-stack" => dump.return_stack = Some(words(value)?),
+                "history" => dump.history = words(value)?,
+                "writers" => {
+                    let mut words = words(value)?.into_iter();
+                    let addr = words.next().ok_or("expected an address")?;
+                    dump.writers.insert(addr, words.collect());
+                }
+                "mem" => break,
+                other => return Err(format!("unknown entry `{other}`")),
+            }
+        }
+
+        for line in lines {
+            for word in line.split_whitespace() {
+                let word =
+                    u16::from_str_radix(word, 16).map_err(|_| format!("invalid word `{word}`"))?;
+                dump.mem.push(word);
+            }
+        }
+        Ok(dump)
+    }
+}
+
+impl MachineState {
+    /// Saves the memory, registers, stack and position as a snapshot, to stop playing and resume
+    /// later with `load`.
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        CrashDump::of(self, "saved").save(path)
+    }
+
+    /// Loads a machine from a snapshot written by `save`, or any other snapshot or crash file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        Ok(CrashDump::load(path)?.to_machine())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let mut machine = MachineState::new(vec![9, 32768, 1, 2, 0]);
+        machine.provenance = Some(crate::provenance::WriteProvenance::default());
+        machine.push_input(b"");
+        machine.exec_next().unwrap();
+        machine.write(4, 0, 0).unwrap();
+        machine.stack.push(7);
+
+        let dump = CrashDump::of(&machine, "panicked at 'oops'\nsomewhere");
+        let parsed = CrashDump::parse(&dump.to_text()).unwrap();
+        assert_eq!(parsed.reason, "panicked at 'oops' somewhere");
+        assert_eq!(parsed.mem, machine.mem);
+        assert_eq!(parsed.history, vec![0]);
+        assert_eq!(parsed.writers, BTreeMap::from([(4, vec![0])]));
+
+        let restored = parsed.to_machine();
+        assert_eq!(restored.cur, 4);
+        assert_eq!(restored.registers[0], 3);
+        assert_eq!(restored.stack, machine.stack);
+        assert_eq!(restored.return_stack, None);
+
+        machine.return_stack = Some(Stack::from_iter([2, 9]));
+        let parsed = CrashDump::parse(&CrashDump::of(&machine, "").to_text()).unwrap();
+        assert_eq!(parsed.to_machine().return_stack, machine.return_stack);
+    }
+
+    #[test]
+    fn saves_and_loads_machines() {
+        // 0: in r0
+        // 2: out r0
+        // 4: halt
+        let mut machine = MachineState::new(vec![20, 32768, 19, 32768, 0]);
+        machine.push_input(b"");
+        assert_eq!(machine.run(), Ok(crate::RunOutcome::NeedsInput));
+        machine.registers[1] = 5;
+
+        let path = std::env::temp_dir().join(format!("synacor-save-{}", std::process::id()));
+        machine.save(&path).unwrap();
+        let mut loaded = MachineState::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((loaded.cur, loaded.registers[1]), (0, 5));
+
+        // it picks up at the `in` it was waiting on
+        loaded.push_input(b"a");
+        assert_eq!(loaded.run(), Ok(crate::RunOutcome::Halted));
+        assert_eq!(loaded.drain_output(), b"a");
+        assert!(MachineState::load(&path).is_err());
+    }
+}
@@ -0,0 +1,527 @@
+use std::io::{BufRead, Write};
+
+use crate::{
+    callstack::CallStack,
+    condition::{self, Condition},
+    crash::CrashDump,
+    instruction::disassemble_with,
+    journal::Journal,
+    notify::{self, Notifier},
+    postmortem::Postmortem,
+    project::{self, Project},
+    slots::Slots,
+    watch::{self, WatchAction, Watchpoints},
+    MachineState, RunOutcome, RunResult,
+};
+
+const HELP: &str = "\
+commands:
+  step [count]           execute instructions, one by default
+  back [count]           undo instructions, one by default
+  continue               run until the program halts, fails, waits for input or hits a watch/breakpoint
+  break [addr|symbol] [if <condition>]
+                         stop when execution reaches addr and the condition, e.g.
+                         `r0 == 4 && mem[r1] > depth + steps`, holds, or list the breakpoints
+  delete <addr|symbol>   remove a breakpoint
+  watch [addr|reg]       stop when addr or a register like r7 is written, or list the watchpoints
+  unwatch <addr|reg>     remove a watchpoint
+  eval <expression>      the value of an expression written like a condition, e.g. `mem[r1] + 1`
+  bt                     show the calls leading to the current instruction, paired with their returns,
+                         and the locals each routine pushed
+  console                draw the screen mapped with `--console`
+  input <text>           queue a line of input for the program
+  save <slot>            save the machine to a slot of the image
+  tree                   show the slots, each under the slot it was saved from
+  jump <slot>            restore the machine from a slot
+  quit
+and every command of the postmortem debugger, on the current state:";
+
+const NO_SLOTS: &str = "there are no save slots without an image";
+
+/// An interactive debugger, running a machine an instruction or a run at a time and inspecting it
+/// in between with the commands of `Postmortem`.
+pub struct Debugger {
+    pub machine: MachineState,
+    pub project: Project,
+    /// The save slots of the image, for `save`, `tree` and `jump`.
+    pub slots: Option<Slots>,
+    /// Tells the user when a run stops at a breakpoint or a watchpoint.
+    pub notify: Option<Notifier>,
+}
+
+impl Debugger {
+    /// Switches `machine` to buffered I/O, so the program's output doesn't mix with the debugger's,
+    /// keeps a journal so `back` can undo instructions, and tracks calls for `bt`. Only the calls
+    /// made from then on are known, unless `machine` already tracked them.
+    pub fn new(mut machine: MachineState, project: Project) -> Self {
+        machine.push_input(b"");
+        machine.journal.get_or_insert_with(Journal::default);
+        machine.call_stack.get_or_insert_with(CallStack::new);
+        Self {
+            machine,
+            project,
+            slots: None,
+            notify: None,
+        }
+    }
+
+    /// Reads commands from `input` until it ends or `quit` is entered.
+    pub fn repl(&mut self, mut input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+        write!(output, "{}", self.position())?;
+        loop {
+            write!(output, "(debug) ")?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 || line.trim() == "quit" {
+                return Ok(());
+            }
+            match self.execute(line.trim_end_matches(['\r', '\n'])) {
+                Ok(text) => write!(output, "{text}")?,
+                Err(err) => writeln!(output, "error: {err}")?,
+            }
+        }
+    }
+
+    /// Runs a single command, returning its output, including whatever the program printed.
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let (command, rest) = line
+            .trim_start()
+            .split_once(' ')
+            .unwrap_or((line.trim(), ""));
+        let stopped = match command {
+            "help" => {
+                let postmortem =
+                    Postmortem::new(CrashDump::of(&self.machine, ""), Project::default());
+                return Ok(format!("{HELP}\n{}", postmortem.execute("help")?));
+            }
+            "step" | "s" => {
+                let count = match rest.trim() {
+                    "" => 1,
+                    count => count
+                        .parse()
+                        .map_err(|_| format!("invalid count `{count}`"))?,
+                };
+                self.step(count)
+            }
+            "back" | "rs" => {
+                let count = match rest.trim() {
+                    "" => 1,
+                    count => count
+                        .parse()
+                        .map_err(|_| format!("invalid count `{count}`"))?,
+                };
+                let undone = (0..count).take_while(|_| self.machine.step_back()).count();
+                if undone == 0 {
+                    return Err("there is nothing left to undo".to_string());
+                }
+                let mut out = String::new();
+                if undone < count {
+                    out =
+                        format!("undid only {undone} instruction(s), the journal holds no more\n");
+                }
+                out.push_str(&self.position());
+                return Ok(out);
+            }
+            "continue" | "c" => self.run().map(Some).map_err(|err| err.to_string()),
+            "break" | "b" => return self.set_breakpoint(rest.trim()),
+            "delete" => {
+                let addr = self.address(rest.trim())?;
+                if self.machine.breakpoints.remove(&addr).is_none() {
+                    return Err(format!("no breakpoint at `{addr}`"));
+                }
+                return Ok(String::new());
+            }
+            "watch" => return self.set_watchpoint(rest.trim()),
+            "unwatch" => {
+                let location = self.location(rest.trim())?;
+                let watchpoints = self
+                    .machine
+                    .watchpoints
+                    .get_or_insert_with(Watchpoints::new);
+                if watchpoints.watched.remove(&location).is_none() {
+                    return Err(format!("no watchpoint on `{}`", rest.trim()));
+                }
+                return Ok(String::new());
+            }
+            "eval" => {
+                let expr = Condition::parse(rest)?;
+                return match expr.eval(&self.machine) {
+                    Some(value) => Ok(format!("{expr} = {value}\n")),
+                    None => Err(format!(
+                        "`{expr}` has no value, it divides by zero or reads outside memory"
+                    )),
+                };
+            }
+            "bt" | "backtrace" => {
+                let stack = self.machine.call_stack.get_or_insert_with(CallStack::new);
+                let cur = self.machine.cur;
+                return Ok(stack.backtrace(cur, self.machine.stack.as_slice(), &self.project));
+            }
+            "console" => {
+                let Some(console) = &mut self.machine.console else {
+                    return Err("no console is mapped".to_string());
+                };
+                return Ok(console.render(&self.machine.mem));
+            }
+            "input" => {
+                self.machine.push_input(format!("{rest}\n").as_bytes());
+                return Ok(String::new());
+            }
+            "save" => {
+                let slots = self.slots.as_ref().ok_or(NO_SLOTS)?;
+                let path = slots.save(rest.trim(), &mut self.machine)?;
+                return Ok(format!("saved to `{}`\n", path.display()));
+            }
+            "tree" => return self.slots.as_ref().ok_or(NO_SLOTS)?.tree(),
+            "jump" => return self.jump(rest.trim()),
+            _ => {
+                let dump = CrashDump::of(&self.machine, "paused in the debugger");
+                return Postmortem::new(dump, self.project.clone()).execute(line);
+            }
+        };
+
+        let mut out = String::from_utf8_lossy(&self.machine.drain_output()).into_owned();
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        match stopped {
+            Ok(None) => {}
+            Ok(Some(outcome)) => out.push_str(&format!("{}\n", self.describe_outcome(outcome))),
+            Err(err) => out.push_str(&format!("error: {err}\n")),
+        }
+        // a halted machine has no next instruction
+        if !self.machine.halted {
+            out.push_str(&self.position());
+        }
+        Ok(out)
+    }
+
+    /// Runs until the machine stops, notifying of a stop at a breakpoint or a watchpoint.
+    pub(crate) fn run(&mut self) -> RunResult {
+        let result = self.machine.run();
+        if let Ok(outcome) = result {
+            self.notify_stop(outcome);
+        }
+        result
+    }
+
+    /// Tells the notifier, if there is one, that a run stopped at a breakpoint or a watchpoint.
+    pub(crate) fn notify_stop(&self, outcome: RunOutcome) {
+        if let (Some(notifier), RunOutcome::Breakpoint(_) | RunOutcome::Watchpoint(_)) =
+            (self.notify, outcome)
+        {
+            notifier.notify(notify::title(&Ok(outcome)), &self.describe_outcome(outcome));
+        }
+    }
+
+    /// Why a run stopped, naming the location and values for a watchpoint.
+    pub(crate) fn describe_outcome(&self, outcome: RunOutcome) -> String {
+        let hit = self
+            .machine
+            .watchpoints
+            .as_ref()
+            .and_then(|watchpoints| watchpoints.log.last());
+        match (outcome, hit) {
+            (RunOutcome::Watchpoint(_), Some(hit)) => hit.describe(&self.project),
+            (outcome, _) => outcome.to_string(),
+        }
+    }
+
+    /// Adds the breakpoint `spec`, an address with an optional condition, or lists the
+    /// breakpoints without one.
+    fn set_breakpoint(&mut self, spec: &str) -> Result<String, String> {
+        if spec.is_empty() {
+            return Ok(self
+                .machine
+                .breakpoints
+                .iter()
+                .map(|(&addr, condition)| self.describe_breakpoint(addr, condition.as_ref()))
+                .collect());
+        }
+        let (addr, condition) = condition::parse_breakpoint(spec)?;
+        let addr = self.address(addr)?;
+        let text = self.describe_breakpoint(addr, condition.as_ref());
+        self.machine.breakpoints.insert(addr, condition);
+        Ok(text)
+    }
+
+    fn describe_breakpoint(&self, addr: u16, condition: Option<&Condition>) -> String {
+        match condition {
+            Some(condition) => format!("break at {} if {condition}\n", self.project.describe(addr)),
+            None => format!("break at {}\n", self.project.describe(addr)),
+        }
+    }
+
+    /// Adds a watchpoint on `location`, or lists the watchpoints without one.
+    fn set_watchpoint(&mut self, location: &str) -> Result<String, String> {
+        if location.is_empty() {
+            let watchpoints = self.machine.watchpoints.iter();
+            return Ok(watchpoints
+                .flat_map(|watchpoints| watchpoints.watched.keys())
+                .map(|&location| format!("watch {}\n", self.project.format_operand(location)))
+                .collect());
+        }
+        let location = self.location(location)?;
+        let watchpoints = self
+            .machine
+            .watchpoints
+            .get_or_insert_with(Watchpoints::new);
+        watchpoints.watched.insert(location, WatchAction::Pause);
+        Ok(format!("watch {}\n", self.project.format_operand(location)))
+    }
+
+    /// Parses a memory location given as an address, a register, or the name of a symbol or a
+    /// register.
+    pub(crate) fn location(&self, location: &str) -> Result<u16, String> {
+        let symbol = self
+            .project
+            .symbols
+            .iter()
+            .find(|(_, name)| *name == location)
+            .map(|(&addr, _)| addr);
+        match symbol.or_else(|| self.project.register(location)) {
+            Some(location) => Ok(location),
+            None => watch::parse_location(location),
+        }
+    }
+
+    /// Parses an address given as a number or the name of a symbol.
+    pub(crate) fn address(&self, addr: &str) -> Result<u16, String> {
+        match self.project.symbols.iter().find(|(_, name)| *name == addr) {
+            Some((&addr, _)) => Ok(addr),
+            None => project::parse_number(addr),
+        }
+    }
+
+    /// Executes up to `count` instructions, returning why it stopped early, if it did.
+    pub(crate) fn step(&mut self, count: u64) -> Result<Option<RunOutcome>, String> {
+        match self.machine.run_for(count).map_err(|err| err.to_string())? {
+            RunOutcome::FuelExhausted => Ok(None),
+            outcome => Ok(Some(outcome)),
+        }
+    }
+
+    /// Restores the machine from the slot `name`, keeping the breakpoints, watchpoints and the
+    /// rest of the session. What was undone or called before can't be undone or traced back.
+    fn jump(&mut self, name: &str) -> Result<String, String> {
+        let loaded = self.slots.as_ref().ok_or(NO_SLOTS)?.load(name)?;
+        let machine = &mut self.machine;
+        machine.mem = loaded.mem;
+        machine.cur = loaded.cur;
+        machine.registers = loaded.registers;
+        machine.stack = loaded.stack;
+        machine.return_stack = loaded.return_stack;
+        machine.steps = loaded.steps;
+        machine.history = loaded.history;
+        machine.parent = loaded.parent;
+        machine.halted = false;
+        machine.journal = Some(Journal::default());
+        machine.call_stack = Some(CallStack::new());
+        Ok(self.position())
+    }
+
+    /// The next instruction to execute.
+    fn position(&self) -> String {
+        let (text, _) =
+            disassemble_with(&self.machine.mem, self.machine.cur as usize, &self.project);
+        format!("=> {}: {text}\n", self.project.describe(self.machine.cur))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugger() -> Debugger {
+        // 0: in r0
+        // 2: out r0
+        // 4: set r1 7
+        // 7: halt
+        let mut mem = vec![20, 32768, 19, 32768, 1, 32769, 7, 0];
+        mem.resize(100, 0);
+        let project = Project::parse("symbol 4 later").unwrap();
+        Debugger::new(MachineState::new(mem), project)
+    }
+
+    #[test]
+    fn steps() {
+        let mut debugger = debugger();
+        assert_eq!(
+            debugger.execute("step").unwrap(),
+            "The machine is waiting for input.\n=> 0: in r0\n"
+        );
+        assert_eq!(debugger.execute("input hi").unwrap(), "");
+        assert_eq!(
+            debugger.execute("step 2").unwrap(),
+            "h\n=> 4 <later>: set r1 7\n"
+        );
+        assert!(debugger
+            .execute("regs")
+            .unwrap()
+            .starts_with("r0 = 104\nr1 = 0\n"));
+        assert_eq!(
+            debugger.execute("eval r0 * 2 + depth").unwrap(),
+            "r0 * 2 + depth = 208\n"
+        );
+        assert!(debugger.execute("eval r0 / 0").is_err());
+        assert_eq!(
+            debugger.execute("continue").unwrap(),
+            "The machine halted.\n"
+        );
+        assert_eq!(debugger.machine.registers[1], 7);
+        assert!(debugger.execute("frobnicate").is_err());
+        assert!(debugger.execute("step many").is_err());
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger = debugger();
+        debugger.execute("input hi").unwrap();
+        assert_eq!(
+            debugger.execute("break later").unwrap(),
+            "break at 4 <later>\n"
+        );
+        assert_eq!(debugger.execute("b 0x2").unwrap(), "break at 2\n");
+        assert_eq!(
+            debugger.execute("break").unwrap(),
+            "break at 2\nbreak at 4 <later>\n"
+        );
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "Hit a breakpoint at `2`.\n=> 2: out r0\n"
+        );
+        assert_eq!(debugger.execute("delete 2").unwrap(), "");
+        assert!(debugger.execute("delete 2").is_err());
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "h\nHit a breakpoint at `4`.\n=> 4 <later>: set r1 7\n"
+        );
+        assert!(debugger.execute("break nowhere").is_err());
+    }
+
+    #[test]
+    fn conditional_breakpoints() {
+        let mut debugger = debugger();
+        debugger.execute("input hi").unwrap();
+        assert_eq!(
+            debugger.execute("break 2 if r0 == 105").unwrap(),
+            "break at 2 if r0 == 105\n"
+        );
+        assert_eq!(
+            debugger.execute("break later if  r0 == 104").unwrap(),
+            "break at 4 <later> if r0 == 104\n"
+        );
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "h\nHit a breakpoint at `4`.\n=> 4 <later>: set r1 7\n"
+        );
+        assert!(debugger.execute("break 2 if r0 ==").is_err());
+        assert_eq!(
+            debugger.execute("break").unwrap(),
+            "break at 2 if r0 == 105\nbreak at 4 <later> if r0 == 104\n"
+        );
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut debugger = debugger();
+        debugger.execute("input hi").unwrap();
+        assert_eq!(debugger.execute("watch r1").unwrap(), "watch r1\n");
+        assert_eq!(debugger.execute("watch 0x10").unwrap(), "watch 16\n");
+        assert_eq!(debugger.execute("watch").unwrap(), "watch 16\nwatch r1\n");
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "h\nr1 changed from 0 to 7 at index `4` (step 3)\n=> 7 <later+3>: halt\n"
+        );
+        assert_eq!(debugger.execute("unwatch 16").unwrap(), "");
+        assert!(debugger.execute("unwatch 16").is_err());
+        assert!(debugger.execute("watch r9").is_err());
+    }
+
+    #[test]
+    fn backtrace() {
+        // 0: call 3
+        // 2: halt
+        // 3: noop
+        // 4: ret
+        let project = Project::parse("symbol 3 callee").unwrap();
+        let mut debugger = Debugger::new(MachineState::new(vec![17, 3, 0, 21, 18]), project);
+        assert_eq!(debugger.execute("bt").unwrap(), "#0   0\n");
+        assert_eq!(debugger.position(), "=> 0: call callee\n");
+        debugger.execute("step").unwrap();
+        assert_eq!(
+            debugger.execute("bt").unwrap(),
+            "#0   3 <callee> in 3 <callee>\n#1   2 (called from 0)\n"
+        );
+        debugger.execute("step 2").unwrap();
+        assert_eq!(debugger.execute("bt").unwrap(), "#0   2\n");
+    }
+
+    #[test]
+    fn saves_and_jumps_between_slots() {
+        let mut debugger = debugger();
+        assert!(debugger.execute("tree").is_err());
+        let dir = std::env::temp_dir().join(format!("synacor-jump-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        debugger.slots = Some(Slots::new(dir.join("game.bin")));
+
+        debugger.execute("input hi").unwrap();
+        debugger.execute("save start").unwrap();
+        debugger.execute("step 2").unwrap();
+        debugger.execute("save later").unwrap();
+        assert_eq!(
+            debugger.execute("tree").unwrap(),
+            "start  0 steps at 0\n  later  2 steps at 4\n"
+        );
+        debugger.execute("break later").unwrap();
+        assert_eq!(debugger.execute("jump start").unwrap(), "=> 0: in r0\n");
+        assert!(debugger.execute("back").is_err());
+        // the breakpoint and the input are kept
+        debugger.execute("c").unwrap();
+        assert_eq!(debugger.machine.cur, 4);
+        assert!(debugger.execute("jump nowhere").is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn draws_the_console() {
+        let mut debugger = debugger();
+        assert!(debugger.execute("console").is_err());
+        debugger.machine.console = Some(crate::console::Console::new(50).unwrap());
+        debugger.machine.mem[51] = b'x' as u16;
+        assert!(debugger.execute("console").unwrap().starts_with(" x\n\n"));
+    }
+
+    #[test]
+    fn steps_back() {
+        let mut debugger = debugger();
+        debugger.execute("input hi").unwrap();
+        debugger.execute("break later").unwrap();
+        debugger.execute("c").unwrap();
+        assert_eq!(debugger.execute("back").unwrap(), "=> 2: out r0\n");
+        assert_eq!(
+            debugger.execute("back 5").unwrap(),
+            "undid only 1 instruction(s), the journal holds no more\n=> 0: in r0\n"
+        );
+        assert!(debugger.execute("back").is_err());
+        // the input read is given back
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "h\nHit a breakpoint at `4`.\n=> 4 <later>: set r1 7\n"
+        );
+    }
+
+    #[test]
+    fn repl() {
+        let mut debugger = debugger();
+        let mut output = Vec::new();
+        debugger
+            .repl(&b"input x\nc\nquit\n"[..], &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "=> 0: in r0\n(debug) (debug) x\nThe machine halted.\n(debug) "
+        );
+    }
+}
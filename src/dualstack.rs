@@ -0,0 +1,44 @@
+use crate::{stack::Stack, MachineState};
+
+impl MachineState {
+    /// The stack `call` pushes return addresses on and `ret` pops them from: `return_stack` in
+    /// dual-stack mode, or else the one stack data shares with them.
+    pub fn returns(&self) -> &Stack {
+        self.return_stack.as_ref().unwrap_or(&self.stack)
+    }
+
+    pub fn returns_mut(&mut self) -> &mut Stack {
+        match &mut self.return_stack {
+            Some(stack) => stack,
+            None => &mut self.stack,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{stack::Stack, testing::setup, ExecutionError, RunOutcome};
+
+    #[test]
+    fn keeps_returns_apart_from_data() {
+        // 0: push 5
+        // 2: call 6
+        // 4: halt
+        // 5: noop
+        // 6: pop r0
+        // 8: ret
+        let program = vec![2, 5, 17, 6, 0, 21, 3, 32768, 18];
+        let mut machine = setup(program.clone());
+        machine.return_stack = Some(Stack::new());
+        machine.run_for(3).unwrap();
+        assert_eq!(machine.return_stack.as_ref().unwrap().as_slice(), &[4]);
+        assert_eq!(machine.stack.as_slice(), &[] as &[u16]);
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.registers[0], 5);
+
+        // sharing the stack, the routine pops its own return address and returns to 5
+        let mut machine = setup(program);
+        assert_eq!(machine.run(), Err(ExecutionError::EmptyStack(6)));
+        assert_eq!(machine.registers[0], 4);
+    }
+}
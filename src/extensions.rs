@@ -0,0 +1,235 @@
+use crate::{
+    crash::CrashDump,
+    instruction::{format_operand, OpcodeInfo},
+    ExecutionError, MachineState, OpcodeResult, MAX_ADDR,
+};
+
+/// Opcodes the spec leaves unused, which act as host services for injected code when extensions
+/// are enabled. Without them the machine is strict and these are invalid opcodes.
+pub const OPCODES: [OpcodeInfo; 4] = [
+    OpcodeInfo {
+        code: 22,
+        mnemonic: "dbg",
+        arity: 1,
+    },
+    OpcodeInfo {
+        code: 23,
+        mnemonic: "dump",
+        arity: 2,
+    },
+    OpcodeInfo {
+        code: 24,
+        mnemonic: "snap",
+        arity: 0,
+    },
+    OpcodeInfo {
+        code: 25,
+        mnemonic: "assert",
+        arity: 2,
+    },
+];
+
+/// The number of words shown per line by `dump`.
+const WORDS_PER_LINE: usize = 8;
+
+/// Looks up an extension opcode by its numeric value.
+pub fn info(code: u16) -> Option<&'static OpcodeInfo> {
+    OPCODES.get((code as usize).checked_sub(OPCODES[0].code as usize)?)
+}
+
+/// The outcome of an `assert` executed by the guest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assertion {
+    pub pos: u16,
+    pub passed: bool,
+    pub message: String,
+}
+
+/// The state of the extension opcodes: what they printed, the snapshots they took and the
+/// assertions they checked.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Extensions {
+    /// Also print every line of `log` to stderr as it is written.
+    pub echo: bool,
+    pub log: Vec<String>,
+    pub snapshots: Vec<CrashDump>,
+    pub assertions: Vec<Assertion>,
+}
+
+impl Extensions {
+    pub fn new(echo: bool) -> Self {
+        Self {
+            echo,
+            ..Self::default()
+        }
+    }
+
+    fn print(&mut self, line: String) {
+        if self.echo {
+            eprintln!("{line}");
+        }
+        self.log.push(line);
+    }
+
+    /// Whether every assertion so far passed.
+    pub fn passed(&self) -> bool {
+        self.assertions.iter().all(|assertion| assertion.passed)
+    }
+}
+
+/// Reads the length-prefixed string at `addr`, the way the challenge stores its own strings.
+fn read_string(mem: &[u16], addr: usize) -> String {
+    let len = mem.get(addr).copied().unwrap_or(0) as usize;
+    mem.iter()
+        .skip(addr + 1)
+        .take(len)
+        .map(|&word| char::from_u32(word as u32).unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+impl MachineState {
+    /// Executes the extension opcode `op` at `pos`.
+    pub fn extension(&mut self, op: u16, pos: u16) -> OpcodeResult {
+        if self.extensions.is_none() {
+            return Err(ExecutionError::InvalidOpcode(op, pos));
+        }
+        match op {
+            22 => self.debug_print(pos),
+            23 => self.dump(pos),
+            24 => self.snap(pos),
+            25 => self.assert(pos),
+            op => Err(ExecutionError::InvalidOpcode(op, pos)),
+        }
+    }
+
+    fn log(&mut self, line: String) {
+        if let Some(extensions) = &mut self.extensions {
+            extensions.print(line);
+        }
+    }
+
+    /// Opcode: 22 a
+    /// print <a> in decimal to the host
+    fn debug_print(&mut self, pos: u16) -> OpcodeResult {
+        let operand = self.operand(pos, 0)?;
+        let a = self.value(pos, 0)?;
+        self.log(format!("dbg at {pos}: {} = {a}", format_operand(operand)));
+        Ok(())
+    }
+
+    /// Opcode: 23 a b
+    /// print <b> words of memory starting at <a> to the host
+    fn dump(&mut self, pos: u16) -> OpcodeResult {
+        let start = self.value(pos, 0)? as usize;
+        let len = self.value(pos, 1)? as usize;
+        let end = (start + len).min(MAX_ADDR);
+        for line_start in (start..end).step_by(WORDS_PER_LINE) {
+            let words = self.mem[line_start..(line_start + WORDS_PER_LINE).min(end)]
+                .iter()
+                .map(|word| format!("{word:>5}"))
+                .collect::<Vec<_>>();
+            self.log(format!(
+                "dump at {pos}: {line_start:>5}: {}",
+                words.join(" ")
+            ));
+        }
+        Ok(())
+    }
+
+    /// Opcode: 24
+    /// save a snapshot of the machine, as it is after this instruction
+    fn snap(&mut self, pos: u16) -> OpcodeResult {
+        let dump = CrashDump::of(self, format!("snapshot taken by the instruction at {pos}"));
+        if let Some(extensions) = &mut self.extensions {
+            extensions.snapshots.push(dump);
+        }
+        Ok(())
+    }
+
+    /// Opcode: 25 a b
+    /// check that <a> is nonzero, reporting the length-prefixed string at <b> as the message
+    fn assert(&mut self, pos: u16) -> OpcodeResult {
+        let passed = self.value(pos, 0)? != 0;
+        let message = read_string(&self.mem, self.value(pos, 1)? as usize);
+        if !passed {
+            self.log(format!("assertion failed at {pos}: {message}"));
+        }
+        if let Some(extensions) = &mut self.extensions {
+            extensions.assertions.push(Assertion {
+                pos,
+                passed,
+                message,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{instruction::parse_instruction, testing::MachineBuilder, RunOutcome};
+
+    #[test]
+    fn table_is_indexed_by_code() {
+        for info in &OPCODES {
+            assert_eq!(super::info(info.code), Some(info));
+        }
+        assert_eq!(super::info(21), None);
+        assert_eq!(super::info(26), None);
+    }
+
+    #[test]
+    fn host_services() {
+        // 0: dbg r1
+        // 2: dump 14 3
+        // 5: snap
+        // 6: assert 1 14
+        // 9: assert r0 14
+        // 12: halt
+        // 13: noop
+        // 14: "ok"
+        let program = [
+            22, 32769, 23, 14, 3, 24, 25, 1, 14, 25, 32768, 14, 0, 21, 2, 111, 107,
+        ];
+        let mut machine = MachineBuilder::new()
+            .program(&program)
+            .register(1, 42)
+            .build();
+        machine.extensions = Some(Extensions::new(false));
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+
+        let extensions = machine.extensions.unwrap();
+        assert_eq!(
+            extensions.log,
+            vec![
+                "dbg at 0: r1 = 42",
+                "dump at 2:    14:     2   111   107",
+                "assertion failed at 9: ok",
+            ]
+        );
+        assert_eq!(extensions.snapshots.len(), 1);
+        assert_eq!(extensions.snapshots[0].cur, 6);
+        assert_eq!(
+            extensions
+                .assertions
+                .iter()
+                .map(|assertion| assertion.passed)
+                .collect::<Vec<_>>(),
+            vec![true, false]
+        );
+        assert!(!extensions.passed());
+    }
+
+    #[test]
+    fn strict_without_extensions() {
+        let mut machine = MachineBuilder::new().program(&[24, 0]).build();
+        assert_eq!(machine.run(), Err(ExecutionError::InvalidOpcode(24, 0)));
+    }
+
+    #[test]
+    fn assembles_extensions() {
+        assert_eq!(parse_instruction("dbg r0"), Ok(vec![22, 32768]));
+        assert_eq!(parse_instruction("snap"), Ok(vec![24]));
+    }
+}
@@ -0,0 +1,332 @@
+//! Fuzzes the game's parser with generated commands, to hunt for easter eggs: commands it
+//! understands that nobody told us about, and commands that crash it.
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+
+use crate::{project::Project, strings, MachineState, RunOutcome, RunResult, MAX_ADDR};
+
+/// A command that stood out when fed to the snapshot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub input: String,
+    pub result: RunResult,
+    /// What the program printed in response.
+    pub output: String,
+    /// Whether no command before got this response, nor the one to nonsense.
+    pub new_output: bool,
+    /// How many instructions it executed that no command before did.
+    pub new_coverage: usize,
+}
+
+/// Generates commands from a vocabulary and runs each on a copy of a snapshot waiting for input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fuzzer {
+    pub vocabulary: Vec<String>,
+    /// The instructions each command may run.
+    pub fuel: u64,
+    pub seed: u64,
+}
+
+/// The words of the strings in `mem`, which include every word the game prints and, in a
+/// snapshot taken after the game decrypted them, most of those it understands.
+pub fn vocabulary(mem: &[u16]) -> Vec<String> {
+    let words = strings::strings(mem, &Project::default())
+        .into_iter()
+        .flat_map(|entry| {
+            entry
+                .text
+                .split(|c: char| !c.is_ascii_alphabetic())
+                .filter(|word| (2..=12).contains(&word.len()))
+                .map(str::to_ascii_lowercase)
+                .collect::<Vec<_>>()
+        })
+        .collect::<BTreeSet<_>>();
+    words.into_iter().collect()
+}
+
+/// A xorshift generator, so a seed reproduces a session.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// The result of a fuzzing session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    pub findings: Vec<Finding>,
+    /// The seeds and every command that reached new code, to mutate in the next session.
+    pub corpus: Vec<String>,
+    /// How many instructions the commands executed in all.
+    pub covered: usize,
+}
+
+impl Fuzzer {
+    /// Runs the `seeds`, then `cases` commands on `base`, returning those that crashed it, got a
+    /// new response or reached new code.
+    ///
+    /// Commands that reach new code are kept in a corpus, and most commands are mutations of it
+    /// rather than new ones, so the search digs deeper wherever the game reacted, as AFL does.
+    pub fn run(&self, base: &MachineState, seeds: &[String], cases: usize) -> Session {
+        let mut rng = Rng(self.seed.max(1));
+        // the response to nonsense is what any command the game doesn't understand gets
+        let (_, nonsense, mut covered) = self.case(base, "qqqq");
+        let mut outputs = BTreeSet::from([nonsense]);
+        let mut corpus = seeds.to_vec();
+
+        let mut findings = Vec::new();
+        for i in 0..seeds.len() + cases {
+            let input = match seeds.get(i) {
+                Some(seed) => seed.clone(),
+                None if !corpus.is_empty() && rng.below(4) != 0 => {
+                    // the newest command got the furthest, so it gets half of the mutations
+                    let parent = match rng.below(2) {
+                        0 => corpus.len() - 1,
+                        _ => rng.below(corpus.len()),
+                    };
+                    self.mutate(&corpus[parent], &mut rng)
+                }
+                None => self.command(&mut rng),
+            };
+            let (result, output, coverage) = self.case(base, &input);
+            let new_coverage = coverage.difference(&covered).count();
+            covered.extend(coverage);
+            if new_coverage > 0 && i >= seeds.len() {
+                corpus.push(input.clone());
+            }
+            let new_output = outputs.insert(output.clone());
+            if result.is_err() || new_output || new_coverage > 0 {
+                findings.push(Finding {
+                    input,
+                    result,
+                    output,
+                    new_output,
+                    new_coverage,
+                });
+            }
+        }
+        Session {
+            findings,
+            corpus,
+            covered: covered.len(),
+        }
+    }
+
+    /// A command of one to three words.
+    fn command(&self, rng: &mut Rng) -> String {
+        let words = (0..1 + rng.below(3))
+            .map(|_| self.word(rng))
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>();
+        words.join(" ")
+    }
+
+    /// A word of the vocabulary, one in four of them mutated into a word the game might not print
+    /// anywhere.
+    fn word(&self, rng: &mut Rng) -> String {
+        let word = match self.vocabulary.len() {
+            0 => String::new(),
+            len => self.vocabulary[rng.below(len)].clone(),
+        };
+        if word.is_empty() || rng.below(4) == 0 {
+            return mutate_word(word, rng);
+        }
+        word
+    }
+
+    /// Changes a command of the corpus a little: adds, replaces or drops a word, or changes a
+    /// letter of one.
+    fn mutate(&self, input: &str, rng: &mut Rng) -> String {
+        let mut words = input
+            .split_whitespace()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let at = rng.below(words.len().max(1));
+        match rng.below(4) {
+            _ if words.is_empty() => words.push(self.word(rng)),
+            0 => words.insert(rng.below(words.len() + 1), self.word(rng)),
+            1 => words[at] = self.word(rng),
+            2 if words.len() > 1 => {
+                words.remove(at);
+            }
+            _ => words[at] = mutate_word(std::mem::take(&mut words[at]), rng),
+        }
+        words.retain(|word| !word.is_empty());
+        words.join(" ")
+    }
+
+    /// Feeds `input` to a copy of `base` until it waits for more, returning how that run ended,
+    /// its output with the command replaced by `{}`, and the instructions it executed.
+    fn case(&self, base: &MachineState, input: &str) -> (RunResult, String, BTreeSet<u16>) {
+        let mut machine = base.clone();
+        machine.push_input(format!("{input}\n").as_bytes());
+        let mut covered = vec![false; MAX_ADDR];
+        let mut result = Ok(RunOutcome::FuelExhausted);
+        for _ in 0..self.fuel {
+            covered[machine.cur as usize % MAX_ADDR] = true;
+            result = machine.run_for(1);
+            if result != Ok(RunOutcome::FuelExhausted) {
+                break;
+            }
+        }
+        let mut output = String::from_utf8_lossy(&machine.drain_output()).into_owned();
+        if !input.is_empty() {
+            output = output.replace(input, "{}");
+        }
+        let covered = (0..MAX_ADDR as u16)
+            .filter(|&addr| covered[addr as usize])
+            .collect();
+        (result, output, covered)
+    }
+}
+
+/// Removes, replaces or inserts a letter.
+fn mutate_word(mut word: String, rng: &mut Rng) -> String {
+    let letter = (b'a' + rng.below(26) as u8) as char;
+    match rng.below(3) {
+        0 if !word.is_empty() => {
+            word.remove(rng.below(word.len()));
+        }
+        1 if !word.is_empty() => {
+            let at = rng.below(word.len());
+            word.replace_range(at..at + 1, &letter.to_string());
+        }
+        _ => word.insert(rng.below(word.len() + 1), letter),
+    }
+    word
+}
+
+/// Lists the findings of `session`, the crashes first, then a summary of the `cases` run.
+pub fn report(session: &Session, cases: usize) -> String {
+    let findings = &session.findings;
+    let mut out = String::new();
+    let (crashes, others): (Vec<_>, Vec<_>) =
+        findings.iter().partition(|finding| finding.result.is_err());
+    for finding in &crashes {
+        if let Err(err) = &finding.result {
+            let _ = writeln!(out, "crash     `{}`: {err}", finding.input);
+        }
+    }
+    for finding in &others {
+        let response = finding.output.trim().lines().next().unwrap_or_default();
+        let kind = if finding.new_output {
+            "response "
+        } else {
+            "coverage "
+        };
+        let _ = writeln!(
+            out,
+            "{kind} `{}` (+{} instructions): {response}",
+            finding.input, finding.new_coverage
+        );
+    }
+    let responses = findings.iter().filter(|finding| finding.new_output).count();
+    let _ = writeln!(
+        out,
+        "\n{cases} command(s): {} crash(es), {responses} new response(s), {} instructions covered, \
+         {} command(s) in the corpus",
+        crashes.len(),
+        session.covered,
+        session.corpus.len()
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup;
+
+    // a parser that understands `go`, and crashes on `up`:
+    // 0: in r0
+    // 2: eq r1 r0 'g'
+    // 6: jt r1 20
+    // 9: eq r1 r0 'u'
+    // 13: jt r1 30
+    // 16: jmp 0
+    // 20: out '!'
+    // 22: jmp 0
+    // 30: dw 30
+    fn parser() -> MachineState {
+        let mut program = vec![
+            20, 32768, 4, 32769, 32768, 103, 7, 32769, 20, 4, 32769, 32768, 117, 7, 32769, 30, 6, 0,
+        ];
+        program.resize(20, 21);
+        program.extend([19, 33, 6, 0]);
+        program.resize(30, 21);
+        program.push(30);
+        // the strings the vocabulary comes from
+        program.resize(100, 0);
+        program.extend([9, 103, 111, 32, 97, 110, 100, 32, 117, 112]);
+        setup(program)
+    }
+
+    #[test]
+    fn finds_words_in_strings() {
+        assert_eq!(vocabulary(&parser().mem), ["and", "go", "up"]);
+    }
+
+    #[test]
+    fn finds_new_responses_and_crashes() {
+        let base = parser();
+        let fuzzer = Fuzzer {
+            vocabulary: vocabulary(&base.mem),
+            fuel: 1000,
+            seed: 7,
+        };
+        let session = fuzzer.run(&base, &[], 50);
+        let findings = &session.findings;
+        let crash = findings.iter().find(|finding| finding.result.is_err());
+        assert!(crash.is_some_and(|finding| finding.input.contains('u')));
+        let response = findings.iter().find(|finding| finding.new_output);
+        assert!(response.is_some_and(|finding| finding.input.contains('g')));
+        // a seed reproduces a session
+        assert_eq!(fuzzer.run(&base, &[], 50), session);
+
+        let report = report(&session, 50);
+        assert!(report.starts_with("crash     `"));
+        assert!(report.contains("\nresponse  `"));
+    }
+
+    #[test]
+    fn digs_where_coverage_grows() {
+        // prints `!` once it reads `k`, `e` and `y` in a row, which no word of the vocabulary has
+        let source = "\
+start: in r0
+       eq r1 r0 'k'
+       jf r1 start
+       in r0
+       eq r1 r0 'e'
+       jf r1 start
+       in r0
+       eq r1 r0 'y'
+       jf r1 start
+       out '!'
+       jmp start
+";
+        let base = setup(crate::asm::assemble(source).unwrap().words);
+        let fuzzer = Fuzzer {
+            vocabulary: vec!["ab".to_string()],
+            fuel: 1000,
+            seed: 1,
+        };
+        let seeds = ["k".to_string()];
+        let session = fuzzer.run(&base, &seeds, 2000);
+        assert_eq!(session.corpus[0], "k");
+        assert!(session.corpus.iter().any(|input| input.contains("ke")));
+        assert!(session
+            .findings
+            .iter()
+            .any(|finding| finding.output == "!" && finding.input.contains("key")));
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::{instruction, MAX_ADDR, REGISTER_COUNT};
+
+/// Builds an AFL/libFuzzer dictionary of interesting words found in a program image: every opcode,
+/// every register, and the literal jump targets and constants used by its instructions.
+///
+/// The image is decoded with a linear sweep, so data that happens to look like code contributes
+/// entries too, which does no harm in a dictionary.
+pub fn dictionary(mem: &[u16]) -> String {
+    // keyed by value, so every word only appears once, under the first name it was found with
+    let mut entries = BTreeMap::new();
+    for info in &instruction::OPCODES {
+        entries.insert(info.code, format!("op_{}", info.mnemonic));
+    }
+    for reg in 0..REGISTER_COUNT {
+        entries.insert((MAX_ADDR + reg) as u16, format!("reg_r{reg}"));
+    }
+
+    let mut addr = 0;
+    while addr < mem.len() {
+        let Some(info) = instruction::info(mem[addr]) else {
+            addr += 1;
+            continue;
+        };
+        let Some(operands) = mem.get(addr + 1..addr + 1 + info.arity) else {
+            break;
+        };
+
+        let is_jump = matches!(info.mnemonic, "jmp" | "jt" | "jf" | "call");
+        for (i, &operand) in operands.iter().enumerate() {
+            if operand as usize >= MAX_ADDR {
+                continue;
+            }
+            // the last operand of a jump is its target, the others are conditions
+            let name = if is_jump && i == info.arity - 1 {
+                format!("target_{operand}")
+            } else {
+                format!("const_{operand}")
+            };
+            entries.entry(operand).or_insert(name);
+        }
+        addr += 1 + info.arity;
+    }
+
+    let mut out = String::new();
+    for (value, name) in entries {
+        let [lo, hi] = value.to_le_bytes();
+        let _ = writeln!(out, "{name}=\"\\x{lo:02x}\\x{hi:02x}\"");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collects_targets_and_constants() {
+        // set r0 1000, jt r0 1531
+        let dict = dictionary(&[1, 32768, 1000, 7, 32768, 1531]);
+        assert!(dict.contains("op_halt=\"\\x00\\x00\"\n"));
+        assert!(dict.contains("reg_r7=\"\\x07\\x80\"\n"));
+        assert!(dict.contains("const_1000=\"\\xe8\\x03\"\n"));
+        assert!(dict.contains("target_1531=\"\\xfb\\x05\"\n"));
+    }
+}
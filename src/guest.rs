@@ -0,0 +1,142 @@
+use crate::{io::Io, ExecutionError, MachineState, RunOutcome, REGISTER_COUNT};
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum CallError {
+    #[error("A routine takes at most {REGISTER_COUNT} arguments, got `{0}`")]
+    TooManyArgs(usize),
+    #[error("The routine failed: {0}")]
+    Execution(#[from] ExecutionError),
+    #[error("The routine stopped before returning: {0}")]
+    Stopped(RunOutcome),
+}
+
+impl MachineState {
+    /// Calls the guest routine at `addr` the way the challenge calls its own: with `args` in the
+    /// first registers and the return address on the stack. Returns `r0` once the routine returns,
+    /// after restoring the machine to the state it was in before the call, memory included.
+    pub fn call_function(&mut self, addr: u16, args: &[u16]) -> Result<u16, CallError> {
+        self.call_function_with(addr, args, None)
+    }
+
+    /// Like `call_function`, giving up after `fuel` instructions if set.
+    pub fn call_function_with(
+        &mut self,
+        addr: u16,
+        args: &[u16],
+        fuel: Option<u64>,
+    ) -> Result<u16, CallError> {
+        if args.len() > REGISTER_COUNT {
+            return Err(CallError::TooManyArgs(args.len()));
+        }
+        // on a copy, so that traces, journals and the other hooks don't see instructions the
+        // program never ran, and nothing needs undoing afterwards
+        let mut guest = self.bare();
+        std::mem::swap(&mut guest.io, &mut self.io);
+        guest.registers[..args.len()].copy_from_slice(args);
+        // returning to where the machine was left is the sign the routine is done
+        guest.returns_mut().push(self.cur);
+        let depth = guest.returns().len() - 1;
+        guest.cur = addr;
+        let result = loop {
+            if fuel.is_some_and(|fuel| guest.steps >= fuel) {
+                break Err(CallError::Stopped(RunOutcome::FuelExhausted));
+            }
+            if let Err(err) = guest.exec_next() {
+                break Err(err.into());
+            }
+            if guest.returns().len() == depth && guest.cur == self.cur {
+                break Ok(guest.registers[0]);
+            }
+            if guest.halted {
+                break Err(CallError::Stopped(RunOutcome::Halted));
+            }
+            if let Some(outcome) = guest.stop.take() {
+                break Err(CallError::Stopped(outcome));
+            }
+        };
+        std::mem::swap(&mut guest.io, &mut self.io);
+        result
+    }
+
+    /// A copy of the memory, registers, stacks and position of the machine, with none of its hooks
+    /// and I/O, to run code on the side.
+    pub(crate) fn bare(&self) -> MachineState {
+        let mut machine = MachineState::new(self.mem.clone());
+        machine.io = Io::buffered();
+        machine.registers = self.registers;
+        machine.stack = self.stack.clone();
+        machine.return_stack = self.return_stack.clone();
+        machine.cur = self.cur;
+        machine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        journal::Journal,
+        testing::{MachineBuilder, SharedSink},
+        trace::InstructionTrace,
+    };
+
+    // 0: jmp 0
+    // 100: add r0 r0 r1
+    // 104: wmem 0 r0
+    // 107: ret
+    // 108: in r0
+    // 110: ret
+    fn machine() -> MachineState {
+        MachineBuilder::new()
+            .program(&[6, 0])
+            .at(
+                100,
+                &[9, 32768, 32768, 32769, 16, 0, 32768, 18, 20, 32768, 18],
+            )
+            .cur(50)
+            .register(0, 7)
+            .stack(&[1, 2])
+            .input(b"")
+            .build()
+    }
+
+    #[test]
+    fn calls_routines() {
+        let mut machine = machine();
+        let before = machine.clone();
+        assert_eq!(machine.call_function(100, &[2, 3]), Ok(5));
+        assert_eq!(machine, before);
+    }
+
+    #[test]
+    fn hides_calls_from_hooks() {
+        let mut machine = machine();
+        let sink = SharedSink::default();
+        machine.instruction_trace = Some(InstructionTrace::new(sink.clone()));
+        machine.journal = Some(Journal::default());
+        let before = machine.clone();
+        assert_eq!(machine.call_function(100, &[2, 3]), Ok(5));
+        assert_eq!(machine, before);
+        assert!(!machine.step_back());
+        assert_eq!(machine.instruction_trace.unwrap().flush(), Ok(()));
+        assert!(sink.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_failures() {
+        let mut machine = machine();
+        assert_eq!(
+            machine.call_function(108, &[]),
+            Err(CallError::Stopped(RunOutcome::NeedsInput))
+        );
+        assert_eq!(
+            machine.call_function_with(0, &[], Some(10)),
+            Err(CallError::Stopped(RunOutcome::FuelExhausted))
+        );
+        assert_eq!(
+            machine.call_function(0, &[0; 9]),
+            Err(CallError::TooManyArgs(9))
+        );
+        assert_eq!(machine.registers[0], 7);
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+
+use crate::{instruction::disassemble_registers, project::Project};
+
+/// A ring buffer of the positions of the most recently executed instructions, oldest first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct History {
+    capacity: usize,
+    positions: VecDeque<u16>,
+}
+
+impl History {
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            positions: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn record(&mut self, pos: u16) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.positions.len() == self.capacity {
+            self.positions.pop_front();
+        }
+        self.positions.push_back(pos);
+    }
+
+    /// Forgets the most recent position, when its instruction is undone.
+    pub fn pop(&mut self) -> Option<u16> {
+        self.positions.pop_back()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = u16> + '_ {
+        self.positions.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl FromIterator<u16> for History {
+    fn from_iter<I: IntoIterator<Item = u16>>(iter: I) -> Self {
+        let positions = iter.into_iter().collect::<VecDeque<_>>();
+        Self {
+            capacity: positions.len().max(Self::DEFAULT_CAPACITY),
+            positions,
+        }
+    }
+}
+
+/// Disassembles the last `count` of `positions`, oldest first, the way they read in `mem` now.
+pub fn backtrace(positions: &[u16], count: usize, mem: &[u16], project: &Project) -> String {
+    let mut out = String::new();
+    for &pos in &positions[positions.len().saturating_sub(count)..] {
+        let (text, _) = disassemble_registers(mem, pos as usize, project);
+        let _ = writeln!(out, "{:>5}: {text}", project.describe(pos));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_most_recent() {
+        let mut history = History::new(2);
+        for pos in 0..5 {
+            history.record(pos);
+        }
+        assert_eq!(history.iter().collect::<Vec<_>>(), vec![3, 4]);
+    }
+
+    #[test]
+    fn disassembles_backtrace() {
+        // 0: noop, 1: jmp 3, 3: out 'a'
+        let mem = [21, 6, 3, 19, 97];
+        let project = Project::parse("symbol 3 print").unwrap();
+        assert_eq!(
+            backtrace(&[0, 1, 3], 2, &mem, &project),
+            "    1: jmp 3\n3 <print>: out 'a'\n"
+        );
+        assert_eq!(backtrace(&[0], 5, &mem, &project), "    0: noop\n");
+    }
+}
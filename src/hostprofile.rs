@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use crate::{extensions, instruction};
+
+/// A part of the interpreter's work on every instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Looking up the opcode and preparing optional analyses, such as taint, before it runs.
+    Decode,
+    /// Running the handlers of every opcode except `in` and `out`.
+    Dispatch,
+    /// Running the handlers of `in` and `out`.
+    Io,
+    /// Everything done between instructions: loop and cycle detection, status broadcasts and
+    /// applying taint.
+    Hooks,
+}
+
+impl Phase {
+    const ALL: [Phase; 4] = [Phase::Decode, Phase::Dispatch, Phase::Io, Phase::Hooks];
+
+    fn name(self) -> &'static str {
+        match self {
+            Phase::Decode => "decode",
+            Phase::Dispatch => "dispatch",
+            Phase::Io => "i/o",
+            Phase::Hooks => "hooks",
+        }
+    }
+}
+
+/// Measures the host time the interpreter itself spends per opcode handler and per phase, to
+/// find out what to optimize.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostProfile {
+    /// How many times each opcode ran, and how long its handler took in total.
+    opcodes: BTreeMap<u16, (u64, Duration)>,
+    phases: BTreeMap<Phase, Duration>,
+}
+
+impl HostProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_phase(&mut self, phase: Phase, time: Duration) {
+        *self.phases.entry(phase).or_default() += time;
+    }
+
+    /// Records a run of the handler of `op`, which also counts towards its phase.
+    pub fn record_opcode(&mut self, op: u16, time: Duration) {
+        let (count, total) = self.opcodes.entry(op).or_default();
+        *count += 1;
+        *total += time;
+        let phase = match op {
+            19 | 20 => Phase::Io,
+            _ => Phase::Dispatch,
+        };
+        self.record_phase(phase, time);
+    }
+
+    /// Lists the phases, then the opcodes, slowest first.
+    pub fn report(&self) -> String {
+        let total = self
+            .phases
+            .values()
+            .sum::<Duration>()
+            .max(Duration::from_nanos(1));
+        let mut out = String::new();
+        let _ = writeln!(out, "host time per phase:");
+        for phase in Phase::ALL {
+            let time = self.phases.get(&phase).copied().unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "  {:<8} {:>12.3?} {:>5.1}%",
+                phase.name(),
+                time,
+                time.as_secs_f64() / total.as_secs_f64() * 100.0
+            );
+        }
+
+        let mut opcodes = self.opcodes.iter().collect::<Vec<_>>();
+        opcodes.sort_by_key(|(_, &(_, time))| std::cmp::Reverse(time));
+        let _ = writeln!(out, "host time per opcode:");
+        for (&op, &(count, time)) in opcodes {
+            let mnemonic = instruction::info(op)
+                .or_else(|| extensions::info(op))
+                .map_or("?", |info| info.mnemonic);
+            let _ = writeln!(
+                out,
+                "  {mnemonic:<6} {count:>12} runs {time:>12.3?} {:>8.1} ns/run",
+                time.as_nanos() as f64 / count as f64
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MachineBuilder, RunOutcome};
+
+    #[test]
+    fn profiles_handlers() {
+        // out 'a', noop, noop, halt
+        let mut machine = MachineBuilder::new()
+            .program(&[19, 97, 21, 21, 0])
+            .input(b"")
+            .build();
+        machine.host_profile = Some(HostProfile::new());
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+
+        let profile = machine.host_profile.unwrap();
+        assert_eq!(profile.opcodes[&21].0, 2);
+        assert_eq!(profile.opcodes[&19].0, 1);
+        assert!(profile.phases.contains_key(&Phase::Io));
+        let report = profile.report();
+        assert!(report.starts_with("host time per phase:\n  decode "));
+        assert!(report.contains("  noop              2 runs "));
+    }
+}
@@ -0,0 +1,282 @@
+use std::ops::Range;
+
+use crate::{
+    asm, extensions,
+    project::{parse_number, Project},
+    MAX_ADDR, REGISTER_COUNT,
+};
+
+/// Static information about an opcode from the architecture spec.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub code: u16,
+    pub mnemonic: &'static str,
+    /// The number of operand words following the opcode.
+    pub arity: usize,
+}
+
+/// Every opcode of the machine, indexed by its numeric value.
+pub const OPCODES: [OpcodeInfo; 22] = [
+    op(0, "halt", 0),
+    op(1, "set", 2),
+    op(2, "push", 1),
+    op(3, "pop", 1),
+    op(4, "eq", 3),
+    op(5, "gt", 3),
+    op(6, "jmp", 1),
+    op(7, "jt", 2),
+    op(8, "jf", 2),
+    op(9, "add", 3),
+    op(10, "mult", 3),
+    op(11, "mod", 3),
+    op(12, "and", 3),
+    op(13, "or", 3),
+    op(14, "not", 2),
+    op(15, "rmem", 2),
+    op(16, "wmem", 2),
+    op(17, "call", 1),
+    op(18, "ret", 0),
+    op(19, "out", 1),
+    op(20, "in", 1),
+    op(21, "noop", 0),
+];
+
+const fn op(code: u16, mnemonic: &'static str, arity: usize) -> OpcodeInfo {
+    OpcodeInfo {
+        code,
+        mnemonic,
+        arity,
+    }
+}
+
+/// Looks up an opcode by its numeric value.
+pub fn info(code: u16) -> Option<&'static OpcodeInfo> {
+    OPCODES.get(code as usize)
+}
+
+/// Looks up an opcode by its mnemonic.
+/// Extension opcodes are included, so patches can inject code using them.
+pub fn by_mnemonic(mnemonic: &str) -> Option<&'static OpcodeInfo> {
+    OPCODES
+        .iter()
+        .chain(&extensions::OPCODES)
+        .find(|info| info.mnemonic == mnemonic)
+}
+
+/// Parses a single operand: a register (`r0`-`r7`), a number or a character literal (`'a'`).
+pub fn parse_operand(s: &str) -> Result<u16, String> {
+    if let Some(reg) = s.strip_prefix('r') {
+        return match reg.parse::<usize>() {
+            Ok(n) if n < REGISTER_COUNT => Ok((MAX_ADDR + n) as u16),
+            _ => Err(format!("invalid register `{s}`")),
+        };
+    }
+
+    if let Some(ch) = s.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
+        return match ch {
+            "\\n" => Ok(b'\n' as u16),
+            "\\'" => Ok(b'\'' as u16),
+            "\\\\" => Ok(b'\\' as u16),
+            _ if ch.len() == 1 && ch.is_ascii() => Ok(ch.as_bytes()[0] as u16),
+            _ => Err(format!("invalid character literal `{s}`")),
+        };
+    }
+
+    match parse_number(s)? {
+        val if (val as usize) < MAX_ADDR + REGISTER_COUNT => Ok(val),
+        val => Err(format!("operand `{val}` is out of range")),
+    }
+}
+
+/// Encodes a single instruction written as a mnemonic followed by its operands, e.g. `set r0 6`,
+/// split the way the assembler splits them, so `out ' '` is one operand.
+pub fn parse_instruction(line: &str) -> Result<Vec<u16>, String> {
+    let tokens = asm::tokenize(line)?;
+    let (mnemonic, operands) = tokens.split_first().ok_or("empty instruction")?;
+    let info = by_mnemonic(mnemonic).ok_or_else(|| format!("unknown mnemonic `{mnemonic}`"))?;
+
+    let mut words = vec![info.code];
+    for operand in operands {
+        words.push(parse_operand(operand)?);
+    }
+
+    if words.len() - 1 != info.arity {
+        return Err(format!(
+            "`{mnemonic}` takes {} operand(s), got {}",
+            info.arity,
+            words.len() - 1
+        ));
+    }
+    Ok(words)
+}
+
+/// Formats an operand the way `parse_operand` reads it: registers as `r0`-`r7`, others as numbers.
+pub fn format_operand(word: u16) -> String {
+    match word as usize {
+        val if val < MAX_ADDR => val.to_string(),
+        val if val < MAX_ADDR + REGISTER_COUNT => format!("r{}", val - MAX_ADDR),
+        val => format!("<invalid {val}>"),
+    }
+}
+
+/// Disassembles the instruction at `addr`, returning its text and its length in words.
+/// Words that don't start a valid instruction are shown as data with `dw`.
+pub fn disassemble(mem: &[u16], addr: usize) -> (String, usize) {
+    disassemble_with(mem, addr, &Project::default())
+}
+
+/// Disassembles the instruction at `addr` like `disassemble`, naming the targets of jumps and
+/// calls that have a symbol in `project`, and the registers that have a name in it.
+pub fn disassemble_with(mem: &[u16], addr: usize, project: &Project) -> (String, usize) {
+    render(mem, addr, project, true)
+}
+
+/// Disassembles the instruction at `addr` like `disassemble`, naming only the registers that have
+/// a name in `project`, for output that shows addresses as numbers.
+pub fn disassemble_registers(mem: &[u16], addr: usize, project: &Project) -> (String, usize) {
+    render(mem, addr, project, false)
+}
+
+/// The length in words of the instruction at `addr`, as `disassemble` would show it, without
+/// formatting it.
+pub fn length(mem: &[u16], addr: usize) -> usize {
+    let Some(&word) = mem.get(addr) else {
+        return 0;
+    };
+    match info(word).or_else(|| extensions::info(word)) {
+        Some(info) if addr + info.arity < mem.len() => 1 + info.arity,
+        _ => 1,
+    }
+}
+
+fn render(mem: &[u16], addr: usize, project: &Project, name_targets: bool) -> (String, usize) {
+    let Some(&word) = mem.get(addr) else {
+        return (String::new(), 0);
+    };
+    let Some(info) = info(word).or_else(|| extensions::info(word)) else {
+        return (format!("dw {word}"), 1);
+    };
+    let Some(operands) = mem.get(addr + 1..addr + 1 + info.arity) else {
+        return (format!("dw {word}"), 1);
+    };
+
+    let is_jump = name_targets && matches!(info.mnemonic, "jmp" | "jt" | "jf" | "call");
+    let mut text = info.mnemonic.to_string();
+    for (i, &operand) in operands.iter().enumerate() {
+        text.push(' ');
+        match operand {
+            // show printable characters written by `out` as literals
+            32..=126 if info.mnemonic == "out" => {
+                text.push_str(&format!("{:?}", operand as u8 as char))
+            }
+            // the last operand of a jump is its target
+            _ if is_jump && i == info.arity - 1 && project.symbols.contains_key(&operand) => {
+                text.push_str(&project.symbols[&operand])
+            }
+            _ => text.push_str(&project.format_operand(operand)),
+        }
+    }
+    (text, 1 + info.arity)
+}
+
+/// Disassembles the instructions in `range`, one line each with its address, e.g. `  12: set r0 4`.
+/// The last instruction may run past the end of `range`. Symbols from `project` are shown as
+/// labels and jump targets, and comments are appended to their lines.
+pub fn disassemble_range(mem: &[u16], range: Range<usize>, project: &Project) -> String {
+    let mut out = String::new();
+    let mut addr = range.start;
+    while addr < range.end.min(mem.len()) {
+        let (text, len) = disassemble_with(mem, addr, project);
+        if let Some(name) = project.symbols.get(&(addr as u16)) {
+            out.push_str(&format!("{name}:\n"));
+        }
+        out.push_str(&format!("{addr:5}: {text}"));
+        if let Some(comment) = project.comments.get(&(addr as u16)) {
+            out.push_str(&format!("  ; {comment}"));
+        }
+        out.push('\n');
+        addr += len;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_indexed_by_code() {
+        for (i, info) in OPCODES.iter().enumerate() {
+            assert_eq!(info.code as usize, i);
+        }
+    }
+
+    #[test]
+    fn lengths_match_the_disassembly() {
+        // a truncated `set` and an invalid opcode show as one word of data
+        let mem = [7, 32768, 5, 0, 19, 65, 9999, 1, 2];
+        for addr in 0..=mem.len() {
+            assert_eq!(length(&mem, addr), disassemble(&mem, addr).1, "{addr}");
+        }
+    }
+
+    #[test]
+    fn names_targets() {
+        // 0: jt r0 5
+        // 3: call 5
+        // 5: jmp 0
+        let mem = [7, 32768, 5, 17, 5, 6, 0];
+        let mut project = Project::parse("symbol 5 confirm\ncomment 0 start").unwrap();
+        assert_eq!(
+            disassemble_range(&mem, 0..mem.len(), &project),
+            "    0: jt r0 confirm  ; start\n    3: call confirm\nconfirm:\n    5: jmp 0\n"
+        );
+        project.symbols.insert(32768, "not_a_target".to_string());
+        assert_eq!(disassemble_with(&mem, 0, &project).0, "jt r0 confirm");
+
+        project.registers.insert(0, "key".to_string());
+        assert_eq!(disassemble_with(&mem, 0, &project).0, "jt key confirm");
+        assert_eq!(disassemble_registers(&mem, 0, &project).0, "jt key 5");
+    }
+
+    #[test]
+    fn instructions() {
+        assert_eq!(parse_instruction("set r0 6"), Ok(vec![1, 32768, 6]));
+        assert_eq!(parse_instruction("out 'a'"), Ok(vec![19, 97]));
+        assert_eq!(parse_instruction("out ' '"), Ok(vec![19, 32]));
+        assert!(parse_instruction("out '").is_err());
+        assert_eq!(parse_instruction("jmp 0x10"), Ok(vec![6, 16]));
+        assert_eq!(parse_instruction("noop"), Ok(vec![21]));
+        assert!(parse_instruction("set r8 1").is_err());
+        assert!(parse_instruction("ret 1").is_err());
+        assert!(parse_instruction("frobnicate").is_err());
+    }
+
+    #[test]
+    fn disassembly() {
+        let mem = [1, 32768, 6, 19, 97, 19, 10, 9999, 17];
+        assert_eq!(disassemble(&mem, 0), ("set r0 6".to_string(), 3));
+        assert_eq!(disassemble(&mem, 3), ("out 'a'".to_string(), 2));
+        assert_eq!(disassemble(&mem, 5), ("out 10".to_string(), 2));
+        assert_eq!(disassemble(&mem, 7), ("dw 9999".to_string(), 1));
+        // truncated instruction
+        assert_eq!(disassemble(&mem, 8), ("dw 17".to_string(), 1));
+
+        let project = Project::default();
+        assert_eq!(
+            disassemble_range(&mem, 3..8, &project),
+            "    3: out 'a'\n    5: out 10\n    7: dw 9999\n"
+        );
+        assert_eq!(disassemble_range(&mem, 8..20, &project), "    8: dw 17\n");
+
+        // disassembly can be assembled again
+        assert_eq!(
+            parse_instruction(&disassemble(&mem, 0).0),
+            Ok(mem[0..3].to_vec())
+        );
+        assert_eq!(
+            parse_instruction(&disassemble(&mem, 3).0),
+            Ok(mem[3..5].to_vec())
+        );
+    }
+}
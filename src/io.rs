@@ -0,0 +1,298 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Where the `in` and `out` instructions read from and write to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Io {
+    /// Blocks on stdin for input and prints output to stdout through a buffering sink.
+    Stdio(StdoutSink),
+    /// Reads from `input` and appends to `output`, so a host can feed and drain them itself.
+    /// Running out of input stops the machine with `RunOutcome::NeedsInput` instead of blocking.
+    Buffered {
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+    },
+    /// Reads from and writes to devices supplied by the host, such as channels or mocks.
+    Device(Device),
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        Io::Stdio(StdoutSink::default())
+    }
+}
+
+impl Io {
+    pub fn buffered() -> Self {
+        Io::Buffered {
+            input: VecDeque::new(),
+            output: Vec::new(),
+        }
+    }
+
+    pub fn device(
+        input: impl Input + Send + 'static,
+        output: impl Output + Send + 'static,
+    ) -> Self {
+        Io::Device(Device {
+            input: Arc::new(Mutex::new(input)),
+            output: Arc::new(Mutex::new(output)),
+        })
+    }
+
+    pub fn is_stdio(&self) -> bool {
+        matches!(self, Io::Stdio(_))
+    }
+
+    /// Whether input is known to be available without blocking. Stdin might always block.
+    pub fn has_input(&self) -> bool {
+        match self {
+            Io::Stdio(_) | Io::Device(_) => false,
+            Io::Buffered { input, .. } => !input.is_empty(),
+        }
+    }
+
+    /// Reads the next input byte, or `None` if there is no input available.
+    pub fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        match self {
+            Io::Stdio(sink) => {
+                // whatever prompt was printed must be visible while waiting for the player
+                sink.flush();
+                let mut buf = [0; 1];
+                let read = std::io::stdin().read(&mut buf)?;
+                Ok((read == 1).then_some(buf[0]))
+            }
+            Io::Buffered { input, .. } => Ok(input.pop_front()),
+            Io::Device(device) => device.input.lock().unwrap().read_byte(),
+        }
+    }
+
+    /// Puts `byte` back in front of the input, when the instruction that read it is undone. Only
+    /// buffered input can be given back.
+    pub fn unread(&mut self, byte: u8) {
+        if let Io::Buffered { input, .. } = self {
+            input.push_front(byte);
+        }
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        match self {
+            Io::Stdio(sink) => sink.write_byte(byte),
+            Io::Buffered { output, .. } => output.push(byte),
+            Io::Device(device) => device.output.lock().unwrap().write_byte(byte),
+        }
+    }
+
+    /// Writes out everything still held back by the flush policy.
+    pub fn flush(&mut self) {
+        match self {
+            Io::Stdio(sink) => sink.flush(),
+            Io::Buffered { .. } => {}
+            Io::Device(device) => device.output.lock().unwrap().flush(),
+        }
+    }
+}
+
+/// Where a `Device` gets the bytes `in` reads.
+pub trait Input {
+    /// Returns the next byte, or `None` if there is none yet, which stops the machine with
+    /// `RunOutcome::NeedsInput` until there is.
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>>;
+}
+
+/// Where a `Device` puts the bytes `out` writes.
+pub trait Output {
+    fn write_byte(&mut self, byte: u8);
+
+    /// Writes out anything held back, called whenever the machine flushes its output.
+    fn flush(&mut self) {}
+}
+
+impl Input for VecDeque<u8> {
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        Ok(self.pop_front())
+    }
+}
+
+impl Input for Receiver<u8> {
+    /// Never blocks: a channel with nothing in it, or without senders, has no input yet.
+    fn read_byte(&mut self) -> std::io::Result<Option<u8>> {
+        Ok(self.try_recv().ok())
+    }
+}
+
+impl Output for Vec<u8> {
+    fn write_byte(&mut self, byte: u8) {
+        self.push(byte);
+    }
+}
+
+impl Output for Sender<u8> {
+    /// Output nobody receives any more is dropped.
+    fn write_byte(&mut self, byte: u8) {
+        let _ = self.send(byte);
+    }
+}
+
+/// Host-supplied input and output. Clones of a machine share the devices of the original.
+#[derive(Clone)]
+pub struct Device {
+    pub input: Arc<Mutex<dyn Input + Send>>,
+    pub output: Arc<Mutex<dyn Output + Send>>,
+}
+
+impl std::fmt::Debug for Device {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Device").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for Device {
+    /// Devices are equal when they are the same devices.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.input, &other.input) && Arc::ptr_eq(&self.output, &other.output)
+    }
+}
+
+impl Eq for Device {}
+
+/// When output held by a `StdoutSink` is written to stdout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// After every byte.
+    Char,
+    /// After every newline.
+    #[default]
+    Line,
+    /// Only when flushed explicitly, before reading input, when the buffer is full and when the
+    /// sink is dropped.
+    Manual,
+}
+
+impl std::str::FromStr for FlushPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "char" => Ok(FlushPolicy::Char),
+            "line" => Ok(FlushPolicy::Line),
+            "manual" => Ok(FlushPolicy::Manual),
+            other => Err(format!(
+                "unknown flush policy `{other}`, expected `char`, `line` or `manual`"
+            )),
+        }
+    }
+}
+
+/// Buffers output for stdout and writes it out following a `FlushPolicy`. Whatever is left is
+/// written when the sink is dropped, so output printed right before a crash isn't lost.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StdoutSink {
+    pub policy: FlushPolicy,
+    pending: Vec<u8>,
+}
+
+impl StdoutSink {
+    /// How many bytes are held at most, whatever the policy.
+    pub const CAPACITY: usize = 8192;
+
+    pub fn new(policy: FlushPolicy) -> Self {
+        Self {
+            policy,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        self.pending.push(byte);
+        if self.due(byte) {
+            self.flush();
+        }
+    }
+
+    /// Whether writing `byte`, which is pending already, calls for a flush.
+    fn due(&self, byte: u8) -> bool {
+        let policy = match self.policy {
+            FlushPolicy::Char => true,
+            FlushPolicy::Line => byte == b'\n',
+            FlushPolicy::Manual => false,
+        };
+        policy || self.pending.len() >= Self::CAPACITY
+    }
+
+    /// The bytes written but not flushed yet.
+    pub fn pending(&self) -> &[u8] {
+        &self.pending
+    }
+
+    pub fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let mut stdout = std::io::stdout().lock();
+        let _ = stdout.write_all(&self.pending);
+        let _ = stdout.flush();
+        self.pending.clear();
+    }
+}
+
+impl Clone for StdoutSink {
+    /// Clones hold none of the pending output, which only the original writes out, so that
+    /// dropping copies of a machine doesn't print it again.
+    fn clone(&self) -> Self {
+        Self::new(self.policy)
+    }
+}
+
+impl Drop for StdoutSink {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flush_policies() {
+        assert!(StdoutSink::new(FlushPolicy::Char).due(b'a'));
+        assert!(!StdoutSink::new(FlushPolicy::Line).due(b'a'));
+        assert!(StdoutSink::new(FlushPolicy::Line).due(b'\n'));
+
+        let mut sink = StdoutSink::new(FlushPolicy::Manual);
+        assert!(!sink.due(b'\n'));
+        sink.pending = vec![b'a'; StdoutSink::CAPACITY];
+        assert!(sink.due(b'a'));
+        // only the original prints what is pending
+        assert_eq!(sink.clone().pending(), b"");
+        // don't print anything when dropped
+        sink.pending.clear();
+
+        assert_eq!("manual".parse(), Ok(FlushPolicy::Manual));
+        assert!("never".parse::<FlushPolicy>().is_err());
+    }
+
+    #[test]
+    fn devices() {
+        // in r0, out r0, jmp 0
+        let mut machine = crate::MachineState::new(vec![20, 32768, 19, 32768, 6, 0]);
+        let (to_machine, input) = std::sync::mpsc::channel();
+        let (output, from_machine) = std::sync::mpsc::channel();
+        machine.io = Io::device(input, output);
+
+        to_machine.send(b'a').unwrap();
+        to_machine.send(b'b').unwrap();
+        assert_eq!(machine.run(), Ok(crate::RunOutcome::NeedsInput));
+        assert_eq!(from_machine.try_iter().collect::<Vec<_>>(), b"ab");
+        assert_eq!(machine.clone().io, machine.io);
+
+        let mut machine = crate::MachineState::new(vec![20, 32768, 19, 32768, 6, 0]);
+        machine.io = Io::device(VecDeque::from(b"xyz".to_vec()), Vec::new());
+        assert_eq!(machine.run(), Ok(crate::RunOutcome::NeedsInput));
+        // the `in` that found no input doesn't count
+        assert_eq!(machine.steps, 9);
+    }
+}
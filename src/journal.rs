@@ -0,0 +1,231 @@
+use std::collections::VecDeque;
+
+use crate::{callstack::CallFrame, stack::Stack, MachineState, REGISTER_COUNT};
+
+/// What is needed to undo one instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Entry {
+    cur: u16,
+    registers: [u16; REGISTER_COUNT],
+    steps: u64,
+    halted: bool,
+    /// The depth of the stack and its top before the instruction, which pushes or pops at most
+    /// one value.
+    stack_len: usize,
+    stack_top: Option<u16>,
+    /// The same for the return stack, in dual-stack mode.
+    return_stack: Option<(usize, Option<u16>)>,
+    /// The memory it wrote, with the values it overwrote, in the order written.
+    writes: Vec<(u16, u16)>,
+    /// The input byte it consumed.
+    input: Option<u8>,
+    /// The shadow call stack before a `call` or `ret`.
+    call_frames: Option<Vec<CallFrame>>,
+}
+
+/// An undo journal of the most recent instructions, so the machine can step backwards.
+///
+/// It restores the position, registers, stack, memory written by instructions and, with buffered
+/// I/O, the input they consumed. Output can't be taken back, and neither can the state of other
+/// hooks, nor memory replaced wholesale, as switching banks does.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Journal {
+    capacity: usize,
+    entries: VecDeque<Entry>,
+}
+
+impl Journal {
+    pub const DEFAULT_CAPACITY: usize = 10_000;
+
+    /// Keeps enough to undo the last `capacity` instructions.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// How many instructions can be undone.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn begin(&mut self, entry: Entry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub(crate) fn record_write(&mut self, addr: u16, old: u16) {
+        if let Some(entry) = self.entries.back_mut() {
+            entry.writes.push((addr, old));
+        }
+    }
+
+    pub(crate) fn record_input(&mut self, byte: u8) {
+        if let Some(entry) = self.entries.back_mut() {
+            entry.input = Some(byte);
+        }
+    }
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl MachineState {
+    /// Called by `exec_next` before the instruction at `cur` runs.
+    pub(crate) fn journal_begin(&mut self) {
+        let Some(journal) = &mut self.journal else {
+            return;
+        };
+        journal.begin(Entry {
+            cur: self.cur,
+            registers: self.registers,
+            steps: self.steps,
+            halted: self.halted,
+            stack_len: self.stack.len(),
+            stack_top: self.stack.peek(),
+            return_stack: self
+                .return_stack
+                .as_ref()
+                .map(|stack| (stack.len(), stack.peek())),
+            writes: Vec::new(),
+            input: None,
+            call_frames: match self.mem.get(self.cur as usize) {
+                Some(17 | 18) => self.call_stack.as_ref().map(|stack| stack.frames.clone()),
+                _ => None,
+            },
+        });
+    }
+
+    /// Undoes the last instruction executed. Returns `false` if the journal has nothing to undo.
+    /// An `in` that found no input didn't count as executed, so it is undone along with the
+    /// instruction before it.
+    pub fn step_back(&mut self) -> bool {
+        loop {
+            let Some(entry) = self.journal.as_mut().and_then(|j| j.entries.pop_back()) else {
+                return false;
+            };
+            for &(addr, old) in entry.writes.iter().rev() {
+                self.mem[addr as usize] = old;
+            }
+            restore(&mut self.stack, entry.stack_len, entry.stack_top);
+            if let (Some(stack), Some((len, top))) = (&mut self.return_stack, entry.return_stack) {
+                restore(stack, len, top);
+            }
+            if let Some(byte) = entry.input {
+                self.io.unread(byte);
+            }
+            if let (Some(stack), Some(frames)) = (&mut self.call_stack, entry.call_frames) {
+                stack.frames = frames;
+            }
+            self.history.pop();
+            self.cur = entry.cur;
+            self.registers = entry.registers;
+            self.halted = entry.halted;
+            self.stop = None;
+            let counted = self.steps > entry.steps;
+            self.steps = entry.steps;
+            if counted {
+                return true;
+            }
+        }
+    }
+}
+
+/// Takes `stack` back to the depth `len` and top `top` it had before an instruction pushed or
+/// popped one value.
+fn restore(stack: &mut Stack, len: usize, top: Option<u16>) {
+    if stack.len() > len {
+        stack.pop();
+    }
+    if let (true, Some(top)) = (stack.len() < len, top) {
+        stack.push(top);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MachineBuilder, RunOutcome};
+
+    // 0: in r0
+    // 2: push r0
+    // 4: wmem 50 r0
+    // 7: call 20
+    // 9: halt
+    // 20: pop r1
+    // 22: wmem 50 7
+    // 25: ret
+    fn machine() -> MachineState {
+        let mut machine = MachineBuilder::new()
+            .program(&[20, 32768, 2, 32768, 16, 50, 32768, 17, 20, 0])
+            .at(20, &[3, 32769, 16, 50, 7, 18])
+            .input(b"ab")
+            .build();
+        machine.journal = Some(Journal::default());
+        machine.call_stack = Some(crate::callstack::CallStack::new());
+        machine
+    }
+
+    #[test]
+    fn steps_back() {
+        let mut machine = machine();
+        let mut states = vec![machine.clone()];
+        for _ in 0..7 {
+            machine.run_for(1).unwrap();
+            states.push(machine.clone());
+        }
+        assert_eq!(machine.run_for(1), Ok(RunOutcome::Halted));
+        assert_eq!(machine.stack.as_slice(), &[] as &[u16]);
+
+        while let Some(state) = states.pop() {
+            assert!(machine.step_back());
+            assert_eq!(machine.cur, state.cur);
+            assert_eq!(machine.registers, state.registers);
+            assert_eq!(machine.stack, state.stack);
+            assert_eq!(machine.mem, state.mem);
+            assert_eq!(machine.steps, state.steps);
+            assert_eq!(machine.history, state.history);
+            assert_eq!(machine.call_stack, state.call_stack);
+            assert!(!machine.halted);
+        }
+        assert!(!machine.step_back());
+        // the input was given back
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.registers[0], b'a' as u16);
+    }
+
+    #[test]
+    fn forgets_the_oldest() {
+        let mut machine = machine();
+        machine.journal = Some(Journal::new(2));
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert!(machine.step_back());
+        assert!(machine.step_back());
+        assert!(!machine.step_back());
+        assert_eq!(machine.cur, 25);
+    }
+
+    #[test]
+    fn skips_waiting_for_input() {
+        let mut machine = machine();
+        machine.io = crate::io::Io::buffered();
+        machine.journal = Some(Journal::default());
+        assert_eq!(machine.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(machine.steps, 0);
+        assert!(!machine.step_back());
+        assert_eq!(machine.cur, 0);
+    }
+}
@@ -0,0 +1,415 @@
+//! How the panes of the TUI are arranged: in columns from the left, each with its panes from the
+//! top, sized by their shares of the width and the height.
+
+use std::collections::BTreeSet;
+
+/// A rectangle of the screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// The rectangle under the title bar.
+    pub fn inner(self) -> Rect {
+        Rect {
+            y: self.y + 1,
+            height: self.height.saturating_sub(1),
+            ..self
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Pane {
+    Disassembly,
+    Registers,
+    Stack,
+    /// The expressions added with `display`.
+    Watches,
+    Memory,
+    Output,
+    /// The commands entered and their responses.
+    Log,
+}
+
+impl Pane {
+    pub const ALL: [Pane; 7] = [
+        Pane::Disassembly,
+        Pane::Registers,
+        Pane::Stack,
+        Pane::Watches,
+        Pane::Memory,
+        Pane::Output,
+        Pane::Log,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Pane::Disassembly => "Disassembly",
+            Pane::Registers => "Registers",
+            Pane::Stack => "Stack",
+            Pane::Watches => "Watches",
+            Pane::Memory => "Memory",
+            Pane::Output => "Output",
+            Pane::Log => "Log",
+        }
+    }
+
+    /// Parses the name of a pane, its title in lowercase.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Pane::ALL
+            .into_iter()
+            .find(|pane| pane.title().eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown pane `{name}`"))
+    }
+
+    /// Whether the pane can take the focus, to be moved around in.
+    pub fn scrolls(self) -> bool {
+        !matches!(self, Pane::Registers | Pane::Stack | Pane::Watches)
+    }
+}
+
+/// A column of panes, from the top.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Column {
+    /// The column's share of the width.
+    pub width: usize,
+    /// The panes, each with its share of the height.
+    pub panes: Vec<(Pane, usize)>,
+}
+
+/// Columns of panes, from the left.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub columns: Vec<Column>,
+}
+
+impl Layout {
+    /// The disassembly over the output on the left, the state of the machine and the log on the
+    /// right.
+    pub fn debug() -> Self {
+        Self::parse(
+            &[
+                "disassembly:3 output:2",
+                "registers:4 stack:2 watches:2 memory:3 log:3",
+            ],
+            &[3, 2],
+        )
+        .unwrap()
+    }
+
+    /// Only the output, for playing.
+    pub fn play() -> Self {
+        Self::parse(&["output"], &[]).unwrap()
+    }
+
+    /// The output on the left, for playing while the state of the machine is shown on the right.
+    pub fn split() -> Self {
+        Self::parse(
+            &[
+                "output",
+                "disassembly:3 registers:2 stack:2 watches:2 memory:2",
+            ],
+            &[1, 1],
+        )
+        .unwrap()
+    }
+
+    /// Parses columns listing their panes by name, each followed by its share of the height after
+    /// a `:` unless it is 1, such as `disassembly:3 output:2`. `widths` are the columns' shares of
+    /// the width, all 1 if empty.
+    pub fn parse(columns: &[&str], widths: &[usize]) -> Result<Self, String> {
+        if columns.is_empty() {
+            return Err("a layout needs a column".to_string());
+        }
+        if !widths.is_empty() && widths.len() != columns.len() {
+            return Err(format!(
+                "{} widths for {} columns",
+                widths.len(),
+                columns.len()
+            ));
+        }
+        let mut seen = BTreeSet::new();
+        let mut layout = Self {
+            columns: Vec::new(),
+        };
+        for (i, column) in columns.iter().enumerate() {
+            let mut panes = Vec::new();
+            for spec in column.split_whitespace() {
+                let (name, weight) = spec.split_once(':').unwrap_or((spec, "1"));
+                let pane = Pane::parse(name)?;
+                let weight = parse_weight(weight)?;
+                if !seen.insert(pane) {
+                    return Err(format!("`{name}` is in the layout twice"));
+                }
+                panes.push((pane, weight));
+            }
+            if panes.is_empty() {
+                return Err(format!("column {} has no panes", i + 1));
+            }
+            let width = match widths.get(i) {
+                Some(0) => return Err(invalid_size("0")),
+                Some(&width) => width,
+                None => 1,
+            };
+            layout.columns.push(Column { width, panes });
+        }
+        Ok(layout)
+    }
+
+    /// The index of the column showing `pane`.
+    pub fn column_of(&self, pane: Pane) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|column| column.panes.iter().any(|&(shown, _)| shown == pane))
+    }
+
+    /// Adds `pane` to the bottom of the last column, unless it is already in the layout.
+    pub fn add(&mut self, pane: Pane) {
+        if self.column_of(pane).is_none() {
+            if let Some(column) = self.columns.last_mut() {
+                column.panes.push((pane, 1));
+            }
+        }
+    }
+
+    /// Moves `pane` `by` places down its column, or up if negative, as far as it goes.
+    pub fn move_within(&mut self, pane: Pane, by: isize) {
+        let Some(c) = self.column_of(pane) else {
+            return;
+        };
+        let panes = &mut self.columns[c].panes;
+        let i = panes.iter().position(|&(shown, _)| shown == pane).unwrap();
+        let to = i.saturating_add_signed(by).min(panes.len() - 1);
+        let moved = panes.remove(i);
+        panes.insert(to, moved);
+    }
+
+    /// Moves `pane` to the bottom of the next column, or the previous one if `right` is false.
+    /// Past the last column it starts a new one, unless it is alone in its column.
+    pub fn move_across(&mut self, pane: Pane, right: bool) {
+        let Some(c) = self.column_of(pane) else {
+            return;
+        };
+        let i = self.columns[c]
+            .panes
+            .iter()
+            .position(|&(shown, _)| shown == pane)
+            .unwrap();
+        let last = c + 1 == self.columns.len();
+        let edge = if right { last } else { c == 0 };
+        if edge && self.columns[c].panes.len() == 1 {
+            return;
+        }
+        let moved = self.columns[c].panes.remove(i);
+        let new = Column {
+            width: 1,
+            panes: vec![moved],
+        };
+        match right {
+            true if !last => self.columns[c + 1].panes.push(moved),
+            false if c > 0 => self.columns[c - 1].panes.push(moved),
+            true => self.columns.push(new),
+            false => self.columns.insert(0, new),
+        }
+        self.columns.retain(|column| !column.panes.is_empty());
+    }
+
+    /// Changes the share of the height of `pane` by `height` and that of the width of its column
+    /// by `width`, keeping both at least 1.
+    pub fn resize(&mut self, pane: Pane, height: isize, width: isize) {
+        let Some(c) = self.column_of(pane) else {
+            return;
+        };
+        let column = &mut self.columns[c];
+        column.width = column.width.saturating_add_signed(width).max(1);
+        for (shown, weight) in &mut column.panes {
+            if *shown == pane {
+                *weight = weight.saturating_add_signed(height).max(1);
+            }
+        }
+    }
+
+    /// The columns in the form read by `parse`, with their widths.
+    pub fn to_columns(&self) -> (Vec<String>, Vec<usize>) {
+        let columns = self
+            .columns
+            .iter()
+            .map(|column| {
+                let panes = column.panes.iter().map(|&(pane, weight)| {
+                    let name = pane.title().to_lowercase();
+                    match weight {
+                        1 => name,
+                        weight => format!("{name}:{weight}"),
+                    }
+                });
+                panes.collect::<Vec<_>>().join(" ")
+            })
+            .collect();
+        (
+            columns,
+            self.columns.iter().map(|column| column.width).collect(),
+        )
+    }
+
+    /// Where each pane goes in `area`, leaving out the `hidden` panes, and the columns left
+    /// without any. Columns are separated by a column of the screen.
+    pub fn rects(&self, area: Rect, hidden: &BTreeSet<Pane>) -> Vec<(Pane, Rect)> {
+        let columns = self
+            .columns
+            .iter()
+            .map(|column| {
+                let panes = column
+                    .panes
+                    .iter()
+                    .filter(|(pane, _)| !hidden.contains(pane));
+                (column.width, panes.copied().collect::<Vec<_>>())
+            })
+            .filter(|(_, panes)| !panes.is_empty())
+            .collect::<Vec<_>>();
+        let separators = columns.len().saturating_sub(1);
+        let widths = shares(
+            area.width.saturating_sub(separators),
+            columns.iter().map(|&(width, _)| width),
+        );
+
+        let mut rects = Vec::new();
+        let mut x = area.x;
+        for ((_, panes), width) in columns.iter().zip(widths) {
+            let heights = shares(area.height, panes.iter().map(|&(_, height)| height));
+            let mut y = area.y;
+            for (&(pane, _), height) in panes.iter().zip(heights) {
+                rects.push((
+                    pane,
+                    Rect {
+                        x,
+                        y,
+                        width,
+                        height,
+                    },
+                ));
+                y += height;
+            }
+            x += width + 1;
+        }
+        rects
+    }
+}
+
+fn parse_weight(weight: &str) -> Result<usize, String> {
+    match weight.parse() {
+        Ok(weight) if weight > 0 => Ok(weight),
+        _ => Err(invalid_size(weight)),
+    }
+}
+
+fn invalid_size(size: &str) -> String {
+    format!("invalid size `{size}`, sizes are whole numbers from 1")
+}
+
+/// Divides `total` by `weights`, the last part taking what rounding leaves.
+fn shares(total: usize, weights: impl Iterator<Item = usize> + Clone) -> Vec<usize> {
+    let sum = weights.clone().sum::<usize>().max(1);
+    let mut parts = weights
+        .map(|weight| total * weight / sum)
+        .collect::<Vec<_>>();
+    let used = parts.iter().sum::<usize>();
+    if let Some(last) = parts.last_mut() {
+        *last += total - used;
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_panes() {
+        let layout = Layout::parse(&["disassembly:2 output", "registers"], &[2, 1]).unwrap();
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width: 31,
+            height: 10,
+        };
+        let rect = |x, y, width, height| Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(
+            layout.rects(area, &BTreeSet::new()),
+            [
+                (Pane::Disassembly, rect(0, 0, 20, 6)),
+                (Pane::Output, rect(0, 6, 20, 4)),
+                (Pane::Registers, rect(21, 0, 10, 10)),
+            ]
+        );
+        // hiding the only pane of a column removes it
+        assert_eq!(
+            layout.rects(area, &BTreeSet::from([Pane::Registers, Pane::Output])),
+            [(Pane::Disassembly, rect(0, 0, 31, 10))]
+        );
+        assert_eq!(layout.column_of(Pane::Output), Some(0));
+        assert_eq!(layout.column_of(Pane::Log), None);
+    }
+
+    #[test]
+    fn rejects_bad_layouts() {
+        for (columns, widths) in [
+            (&["disassembly frobnicator"][..], &[][..]),
+            (&["disassembly:0"], &[]),
+            (&["disassembly output", "output"], &[]),
+            (&["disassembly", ""], &[]),
+            (&["disassembly"], &[1, 2]),
+            (&["disassembly"], &[0]),
+            (&[], &[]),
+        ] {
+            assert!(Layout::parse(columns, widths).is_err(), "{columns:?}");
+        }
+        Layout::debug();
+        Layout::play();
+        Layout::split();
+    }
+
+    #[test]
+    fn rearranges_panes() {
+        let mut layout = Layout::parse(&["disassembly output:2", "registers"], &[]).unwrap();
+        layout.move_within(Pane::Disassembly, 5);
+        layout.resize(Pane::Disassembly, 2, -3);
+        layout.resize(Pane::Output, -5, 1);
+        layout.add(Pane::Log);
+        layout.add(Pane::Output);
+        assert_eq!(
+            layout.to_columns(),
+            (
+                vec![
+                    "output disassembly:3".to_string(),
+                    "registers log".to_string()
+                ],
+                vec![2, 1]
+            )
+        );
+
+        layout.move_across(Pane::Output, true);
+        layout.move_across(Pane::Registers, false);
+        layout.move_across(Pane::Log, true);
+        assert_eq!(
+            layout.to_columns().0,
+            ["disassembly:3 registers", "output", "log"]
+        );
+        // a pane alone at the edge stays, and the column left empty goes
+        layout.move_across(Pane::Log, true);
+        layout.move_across(Pane::Output, false);
+        let (columns, widths) = layout.to_columns();
+        assert_eq!(columns, ["disassembly:3 registers output", "log"]);
+        let columns = columns.iter().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(Layout::parse(&columns, &widths), Ok(layout));
+    }
+}
@@ -0,0 +1,730 @@
+//! The virtual machine of the Synacor challenge, and the tools built around it, for embedding in
+//! solvers, debuggers and tests:
+//! ```
+//! use synacor_challenge::{MachineState, RunOutcome};
+//!
+//! // out 'a', halt
+//! let mut machine = MachineState::new(vec![19, 97, 0]);
+//! machine.io = synacor_challenge::io::Io::buffered();
+//! assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+//! ```
+
+#![macro_use]
+extern crate thiserror;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::Instant;
+
+use color_eyre::eyre;
+
+pub mod asm;
+pub mod audit;
+pub mod banks;
+pub mod batch;
+pub mod bisect;
+pub mod broadcast;
+pub mod calls;
+pub mod callstack;
+pub mod canary;
+pub mod checkpoint;
+pub mod condition;
+#[cfg(unix)]
+pub mod config;
+pub mod console;
+pub mod crash;
+pub mod debugger;
+pub mod dualstack;
+pub mod extensions;
+pub mod fuzz;
+pub mod fuzzdict;
+pub mod guest;
+pub mod history;
+pub mod hostprofile;
+pub mod instruction;
+pub mod io;
+pub mod journal;
+pub mod layout;
+pub mod listing;
+pub mod loops;
+pub mod memo;
+pub mod mi;
+pub mod minimize;
+pub mod natives;
+pub mod notify;
+pub mod opcodes;
+pub mod oracle;
+pub mod patch;
+pub mod postmortem;
+pub mod profile;
+pub mod project;
+pub mod prompt;
+pub mod provenance;
+pub mod replay;
+pub mod report;
+pub mod sandbox;
+pub mod script;
+pub mod selfmod;
+pub mod shared;
+#[cfg(unix)]
+pub mod signals;
+pub mod slots;
+pub mod stack;
+pub mod strings;
+pub mod tables;
+pub mod taint;
+pub mod teleporter;
+#[cfg(unix)]
+pub mod terminal;
+pub mod testing;
+pub mod testrom;
+pub mod theme;
+pub mod timeline;
+pub mod timer;
+pub mod toggles;
+pub(crate) mod toml;
+pub mod trace;
+pub mod transcript;
+#[cfg(unix)]
+pub mod tui;
+pub mod verbs;
+pub mod verify;
+pub mod watch;
+
+use audit::{StackAudit, StackEvent, StackOp};
+use banks::Banks;
+use broadcast::StatusBroadcaster;
+use calls::CallTrace;
+use callstack::CallStack;
+use canary::StackCanary;
+use checkpoint::Checkpoints;
+use condition::Condition;
+use console::Console;
+use extensions::Extensions;
+use history::History;
+use hostprofile::{HostProfile, Phase};
+use io::Io;
+use journal::Journal;
+use loops::{CycleDetector, RepeatDetector};
+use memo::Memo;
+use natives::Natives;
+use oracle::AccessOracle;
+use prompt::PromptDetector;
+use provenance::WriteProvenance;
+use replay::Recording;
+use sandbox::{Resource, Sandbox};
+use script::Script;
+use slots::Autosave;
+use stack::Stack;
+use taint::Taint;
+use timeline::{Timeline, TimelineEvent};
+use timer::Timers;
+use toggles::Disabled;
+use trace::InstructionTrace;
+use transcript::Transcript;
+use watch::Watchpoints;
+
+/// The binary run when no other is given, whose project file is loaded alongside it.
+pub const BINARY_PATH: &str = "challenge.bin";
+
+/// The maximum number that can be used as an address on this machine.
+pub const MAX_ADDR: usize = 2usize.pow(15);
+pub const REGISTER_COUNT: usize = 8;
+
+/// Represents the state of the machine:
+/// - `mem` is its entire memory (RAM)
+/// - `cur` is the index of the current operation to be executed
+/// - `registers` are the 8 registers specified in the architecture spec.
+/// - `io` is where `in` reads from and `out` writes to
+/// - `steps` is the number of instructions executed so far
+/// - `history` holds the positions of the most recently executed instructions
+/// - `halted` is set once the program halts
+/// - `stop` is set by an instruction that needs the current run to stop, e.g. `in` without input
+/// - `stack_audit` optionally logs every stack operation.
+/// - `stack_canary` optionally fails `ret`s to values that aren't return addresses.
+/// - `repeat_detector` optionally stops `run` when the machine is stuck in an infinite loop.
+/// - `cycle_detector` optionally warns when the machine is probably livelocked.
+/// - `broadcaster` optionally publishes periodic summaries of the state while running.
+/// - `access_oracle` optionally fails instructions that access memory outside their encoding.
+/// - `taint` optionally tracks which values are derived from input.
+/// - `call_trace` optionally logs every call and return.
+/// - `call_stack` optionally tracks the calls that haven't returned yet.
+/// - `provenance` optionally remembers which instructions last wrote to each address.
+/// - `timeline` optionally records every memory write, input and output.
+/// - `extensions` optionally enables the host services of the extension opcodes.
+/// - `banks` optionally holds other program images to switch to.
+/// - `host_profile` optionally measures the host time spent interpreting.
+/// - `disabled` lists opcodes that fail or are skipped instead of running.
+/// - `sandbox` optionally caps the resources the program may use.
+/// - `prompt` optionally publishes an event whenever a prompt is showing.
+/// - `natives` optionally replaces known routines with native implementations.
+/// - `memo` optionally caches the results of pure routines.
+/// - `breakpoints` lists the addresses at which a run stops before executing the instruction there,
+///   if the condition of the breakpoint holds.
+/// - `watchpoints` optionally reports writes to chosen memory cells and registers.
+/// - `instruction_trace` optionally writes a line for every instruction executed.
+/// - `journal` optionally records how to undo the most recent instructions, for `step_back`.
+/// - `console` optionally maps a character screen onto memory.
+/// - `checkpoints` optionally saves the snapshots named by `@checkpoint` lines of the input.
+/// - `timers` optionally fires events after given numbers of instructions.
+/// - `autosave` optionally saves the machine to a snapshot as it runs.
+/// - `script` optionally holds commands to read before any other input.
+/// - `return_stack` optionally keeps the return addresses of calls apart from the data on `stack`.
+/// - `transcript` optionally copies the output, and the input, to a file.
+/// - `recording` optionally records the input and output of the session, to replay it later.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MachineState {
+    pub mem: Vec<u16>,
+    pub cur: u16,
+    pub registers: [u16; REGISTER_COUNT],
+    pub stack: Stack,
+    pub io: Io,
+    pub steps: u64,
+    pub history: History,
+    pub halted: bool,
+    pub stop: Option<RunOutcome>,
+    pub stack_audit: Option<StackAudit>,
+    pub stack_canary: Option<StackCanary>,
+    pub repeat_detector: Option<RepeatDetector>,
+    pub cycle_detector: Option<CycleDetector>,
+    pub broadcaster: Option<StatusBroadcaster>,
+    pub access_oracle: Option<AccessOracle>,
+    pub taint: Option<Taint>,
+    pub call_trace: Option<CallTrace>,
+    pub call_stack: Option<CallStack>,
+    pub provenance: Option<WriteProvenance>,
+    pub timeline: Option<Timeline>,
+    pub extensions: Option<Extensions>,
+    pub banks: Option<Banks>,
+    pub host_profile: Option<HostProfile>,
+    pub disabled: BTreeMap<u16, Disabled>,
+    pub sandbox: Option<Sandbox>,
+    pub prompt: Option<PromptDetector>,
+    pub natives: Option<Natives>,
+    pub memo: Option<Memo>,
+    pub breakpoints: BTreeMap<u16, Option<Condition>>,
+    pub watchpoints: Option<Watchpoints>,
+    pub instruction_trace: Option<InstructionTrace>,
+    pub journal: Option<Journal>,
+    pub console: Option<Console>,
+    pub checkpoints: Option<Checkpoints>,
+    pub timers: Option<Timers>,
+    pub autosave: Option<Autosave>,
+    /// The save slot the machine was restored from, recorded as the parent of what it saves.
+    pub parent: Option<String>,
+    pub script: Option<Script>,
+    pub return_stack: Option<Stack>,
+    pub transcript: Option<Transcript>,
+    pub recording: Option<Recording>,
+}
+impl MachineState {
+    pub fn new(mem: Vec<u16>) -> Self {
+        Self {
+            mem,
+            cur: 0,
+            registers: [0; REGISTER_COUNT],
+            stack: Stack::new(),
+            io: Io::default(),
+            steps: 0,
+            history: History::default(),
+            halted: false,
+            stop: None,
+            stack_audit: None,
+            stack_canary: None,
+            repeat_detector: None,
+            cycle_detector: None,
+            broadcaster: None,
+            access_oracle: None,
+            taint: None,
+            call_trace: None,
+            call_stack: None,
+            provenance: None,
+            timeline: None,
+            extensions: None,
+            banks: None,
+            host_profile: None,
+            disabled: BTreeMap::new(),
+            sandbox: None,
+            prompt: None,
+            natives: None,
+            memo: None,
+            breakpoints: BTreeMap::new(),
+            watchpoints: None,
+            instruction_trace: None,
+            journal: None,
+            console: None,
+            checkpoints: None,
+            timers: None,
+            autosave: None,
+            parent: None,
+            script: None,
+            return_stack: None,
+            transcript: None,
+            recording: None,
+        }
+    }
+
+    /// Runs until the machine halts, fails or needs input.
+    pub fn run(&mut self) -> RunResult {
+        self.run_with(RunLimits::default())
+    }
+
+    /// Runs at most `fuel` instructions.
+    /// Running out of fuel leaves the machine intact, so calling `run_for` again resumes execution.
+    pub fn run_for(&mut self, fuel: u64) -> RunResult {
+        self.run_with(RunLimits {
+            fuel: Some(fuel),
+            ..RunLimits::default()
+        })
+    }
+
+    /// Runs until the machine stops for any reason, including the provided limits.
+    /// Every outcome other than `Halted` leaves the machine ready to be resumed. A run only stops
+    /// at a breakpoint it reaches, so resuming from one executes the instruction there.
+    pub fn run_with(&mut self, limits: RunLimits) -> RunResult {
+        let mut executed = 0u64;
+        loop {
+            if self.halted {
+                return Ok(RunOutcome::Halted);
+            }
+            if limits.fuel.is_some_and(|fuel| executed >= fuel) {
+                return Ok(RunOutcome::FuelExhausted);
+            }
+            if executed.is_multiple_of(RunLimits::DEADLINE_CHECK_INTERVAL)
+                && limits
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Ok(RunOutcome::TimedOut);
+            }
+            if let Some(sandbox) = &mut self.sandbox {
+                let check_time = self
+                    .steps
+                    .is_multiple_of(RunLimits::DEADLINE_CHECK_INTERVAL);
+                if let Some(resource) = sandbox.check(self.steps, self.stack.len(), check_time) {
+                    return Ok(RunOutcome::ResourceLimit(resource));
+                }
+            }
+            if let Some(outcome) = self.fire_timers() {
+                return Ok(outcome);
+            }
+
+            self.exec_next()?;
+            executed += 1;
+            if let Some(outcome) = self.stop.take() {
+                return Ok(outcome);
+            }
+            let timer = self.host_profile.is_some().then(Instant::now);
+            if let Some(outcome) = self.check_repeat() {
+                return Ok(outcome);
+            }
+            self.check_cycle();
+            self.autosave_on_steps();
+            if let Some(broadcaster) = &self.broadcaster {
+                if broadcaster.due(self.steps) {
+                    broadcaster.publish(self.cur, self.registers, self.steps);
+                }
+            }
+            if let (Some(profile), Some(timer)) = (&mut self.host_profile, timer) {
+                profile.record_phase(Phase::Hooks, timer.elapsed());
+            }
+            if !self.halted && self.at_breakpoint() {
+                return Ok(RunOutcome::Breakpoint(self.cur));
+            }
+        }
+    }
+
+    /// Executes up to `budget` instructions without ever blocking, for hosts that drive the
+    /// machine from their own event loop. Switches the machine to buffered I/O, so input must be
+    /// supplied with `push_input`, and output collected with `drain_output`.
+    pub fn poll_step(&mut self, budget: u64) -> RunResult {
+        if self.io.is_stdio() {
+            self.io = Io::buffered();
+        }
+        self.run_for(budget)
+    }
+
+    /// Queues input for buffered I/O, switching to it if necessary.
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        if self.io.is_stdio() {
+            self.io = Io::buffered();
+        }
+        if let Io::Buffered { input, .. } = &mut self.io {
+            input.extend(bytes);
+        }
+    }
+
+    /// Takes everything written since the last call, when using buffered I/O.
+    pub fn drain_output(&mut self) -> Vec<u8> {
+        match &mut self.io {
+            Io::Buffered { output, .. } => std::mem::take(output),
+            Io::Stdio(_) | Io::Device(_) => Vec::new(),
+        }
+    }
+
+    /// Hashes the full state of the machine: memory, registers, stack and the current position.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.mem.hash(&mut hasher);
+        self.cur.hash(&mut hasher);
+        self.registers.hash(&mut hasher);
+        self.stack.hash(&mut hasher);
+        self.return_stack.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Stops with `RunOutcome::InfiniteLoop` if the repeat detector has seen the current state
+    /// before.
+    fn check_repeat(&mut self) -> Option<RunOutcome> {
+        match &self.repeat_detector {
+            Some(detector) if detector.due(self.steps) => {}
+            _ => return None,
+        }
+
+        let hash = self.state_hash();
+        self.repeat_detector
+            .as_mut()
+            .is_some_and(|detector| detector.observe(hash))
+            .then_some(RunOutcome::InfiniteLoop(self.cur))
+    }
+
+    /// Warns once if the cycle detector suspects the machine is livelocked.
+    fn check_cycle(&mut self) {
+        if let Some(detector) = &mut self.cycle_detector {
+            if detector.observe((self.cur, self.registers)) {
+                eprintln!(
+                    "warning: probable livelock at index `{}` after {} steps",
+                    self.cur, self.steps
+                );
+            }
+        }
+    }
+
+    /// Executes the next operation.
+    /// `cur` is moved past the whole instruction before it runs, so jumps simply overwrite it.
+    /// If the instruction fails, `cur` is left on it.
+    pub fn exec_next(&mut self) -> eyre::Result<(), ExecutionError> {
+        let timer = self.host_profile.is_some().then(Instant::now);
+        let pos = self.cur;
+        self.journal_begin();
+        self.steps += 1;
+        self.history.record(pos);
+
+        let op = self.mem[pos as usize];
+        let info = instruction::info(op)
+            .or_else(|| self.extensions.as_ref().and_then(|_| extensions::info(op)))
+            .ok_or(ExecutionError::InvalidOpcode(op, pos))?;
+        if self.instruction_trace.is_some() {
+            let line = trace::line(self, pos, info);
+            if let Some(trace) = &mut self.instruction_trace {
+                trace.record(&line);
+            }
+        }
+        self.cur = pos + 1 + info.arity as u16;
+        match self.disabled.get(&op) {
+            None => {}
+            Some(Disabled::Nop) => return Ok(()),
+            Some(Disabled::Error) => {
+                self.cur = pos;
+                return Err(ExecutionError::DisabledOpcode(op, pos));
+            }
+        }
+        if let Some(oracle) = &mut self.access_oracle {
+            oracle.decode(pos, info.arity);
+        }
+        if let Some(provenance) = &mut self.provenance {
+            provenance.begin(pos);
+        }
+        let effect = self
+            .taint
+            .as_ref()
+            .map(|taint| taint.effect(pos, &self.mem, &self.registers));
+        let timer = timer.map(|timer| {
+            let decoded = Instant::now();
+            if let Some(profile) = &mut self.host_profile {
+                profile.record_phase(Phase::Decode, decoded - timer);
+            }
+            decoded
+        });
+        let result = match opcodes::HANDLERS.get(op as usize) {
+            Some(handler) => handler(self, pos),
+            None => Err(ExecutionError::InvalidOpcode(op, pos)),
+        };
+        let timer = timer.map(|timer| {
+            let dispatched = Instant::now();
+            if let Some(profile) = &mut self.host_profile {
+                profile.record_opcode(op, dispatched - timer);
+            }
+            dispatched
+        });
+        if result.is_err() {
+            self.cur = pos;
+        } else if let (Some(taint), Some(effect)) = (&mut self.taint, effect) {
+            // `in` without input is retried later, so it has no effect yet
+            if self.stop != Some(RunOutcome::NeedsInput) {
+                taint.apply(effect);
+            }
+        }
+        if let (Some(profile), Some(timer)) = (&mut self.host_profile, timer) {
+            profile.record_phase(Phase::Hooks, timer.elapsed());
+        }
+        result
+    }
+
+    /// The raw `n`th operand of the instruction at `pos`.
+    pub fn operand(&self, pos: u16, n: u16) -> eyre::Result<u16, ExecutionError> {
+        let addr = (pos + 1 + n) as usize;
+        if let Some(oracle) = &self.access_oracle {
+            if !oracle.allows_fetch(addr) {
+                return Err(ExecutionError::AccessViolation(addr as u16, pos));
+            }
+        }
+        Ok(self.mem[addr])
+    }
+
+    /// The value of the `n`th operand of the instruction at `pos`: either a literal or the contents
+    /// of a register.
+    pub fn value(&self, pos: u16, n: u16) -> eyre::Result<u16, ExecutionError> {
+        match self.operand(pos, n)? {
+            val if val < MAX_ADDR as u16 => Ok(val),
+            val => self.get_register(val as usize, pos + 1 + n),
+        }
+    }
+
+    /// Writes `val` to the register or memory address named by the first operand of the
+    /// instruction at `pos`.
+    pub fn store(&mut self, pos: u16, val: u16) -> OpcodeResult {
+        self.write(self.operand(pos, 0)?, val, pos + 1)
+    }
+
+    /// Attempts to set a register to the provided value.
+    /// If the provided register number is invalid, returns an `ExecutionError`.
+    pub fn set_register(&mut self, register: usize, val: u16, pos: u16) -> OpcodeResult {
+        let old = self
+            .registers
+            .get_mut(
+                register
+                    .checked_sub(MAX_ADDR)
+                    .ok_or(ExecutionError::InvalidRegister(register, pos))?,
+            )
+            .map(|old| std::mem::replace(old, val))
+            .ok_or(ExecutionError::InvalidRegister(register, pos))?;
+        self.watch_write(register as u16, old, val);
+        Ok(())
+    }
+
+    /// Attempts to read from a register.
+    pub fn get_register(&self, register: usize, pos: u16) -> eyre::Result<u16, ExecutionError> {
+        self.registers
+            .get(
+                register
+                    .checked_sub(MAX_ADDR)
+                    .ok_or(ExecutionError::InvalidRegister(register, pos))?,
+            )
+            .copied()
+            .ok_or(ExecutionError::InvalidRegister(register, pos))
+    }
+
+    /// Records a stack operation if auditing is enabled, warning about suspicious ones as they happen.
+    pub fn audit_stack(&mut self, op: StackOp, pos: u16, value: u16) {
+        if let Some(audit) = &mut self.stack_audit {
+            let event = StackEvent { op, pos, value };
+            if audit.record(event) {
+                eprintln!("suspicious stack usage: {event}");
+            }
+        }
+    }
+
+    /// Attempts to write the provided value to a register or a memory address.
+    pub fn write(&mut self, write_to: u16, val: u16, pos: u16) -> OpcodeResult {
+        if write_to < MAX_ADDR as u16 {
+            if let Some(oracle) = &self.access_oracle {
+                if !oracle.allows_write(write_to as usize) {
+                    return Err(ExecutionError::AccessViolation(
+                        write_to,
+                        oracle.instruction(),
+                    ));
+                }
+            }
+            let old = std::mem::replace(&mut self.mem[write_to as usize], val);
+            self.watch_write(write_to, old, val);
+            self.console_write(write_to);
+            if let Some(journal) = &mut self.journal {
+                journal.record_write(write_to, old);
+            }
+            if let Some(provenance) = &mut self.provenance {
+                provenance.record(write_to);
+            }
+            if let Some(timeline) = &mut self.timeline {
+                let event = TimelineEvent::Write {
+                    addr: write_to,
+                    value: val,
+                    pos: self.history.iter().next_back().unwrap_or(self.cur),
+                };
+                timeline.record(self.steps, event);
+            }
+            Ok(())
+        } else {
+            self.set_register(write_to as usize, val, pos)
+        }
+    }
+
+    /// Attempts to read from a register or a memory address.
+    pub fn read(&self, read_from: u16, pos: u16) -> eyre::Result<u16, ExecutionError> {
+        if read_from < MAX_ADDR as u16 {
+            Ok(self.mem[read_from as usize])
+        } else {
+            self.get_register(read_from as usize, pos)
+        }
+    }
+}
+
+/// Converts the little-endian bytes of a program image into words.
+pub fn words_from_bytes(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .chunks(2)
+        .map(|chunk| u16::from_le_bytes(<[u8; 2]>::try_from(chunk).unwrap()))
+        .collect()
+}
+
+/// Converts words back into a little-endian program image.
+pub fn bytes_from_words(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// Reads a program image from disk, as many words as the file holds, for tools that work on the
+/// file rather than on the memory of a machine.
+pub fn read_image(path: impl AsRef<Path>) -> eyre::Result<Vec<u16>> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path)
+        .map_err(|err| eyre::eyre!("Could not read `{}`: {err}", path.display()))?;
+    if bytes.len() % 2 != 0 {
+        return Err(eyre::eyre!(
+            "`{}` is not a Synacor binary: it has an odd number of bytes",
+            path.display()
+        ));
+    }
+    if bytes.len() > MAX_ADDR * 2 {
+        return Err(eyre::eyre!(
+            "`{}` is not a Synacor binary: it has more than {MAX_ADDR} words",
+            path.display()
+        ));
+    }
+    Ok(words_from_bytes(&bytes))
+}
+
+/// Reads a program image from disk into a full memory, zeroed past the end of the image.
+pub fn load_image(path: impl AsRef<Path>) -> eyre::Result<Vec<u16>> {
+    let mut mem = read_image(path)?;
+    mem.resize(MAX_ADDR, 0);
+    Ok(mem)
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ExecutionError {
+    #[error("Invalid opcode `{0}` at index `{1}`")]
+    InvalidOpcode(u16, u16),
+    #[error("Tried to access invalid register `{0}` at index `{1}`")]
+    InvalidRegister(usize, u16),
+    #[error("Tried to pop from an empty stack at index `{0}`")]
+    EmptyStack(u16),
+    #[error("Tried to access invalid address `{0}` at index `{1}`")]
+    InvalidAddress(u16, u16),
+    #[error("Encountered an error while trying to read from stdin at index `{1}`: {0}")]
+    ReadError(String, u16),
+    #[error("Undeclared access to `{0}` by the instruction at index `{1}`")]
+    AccessViolation(u16, u16),
+    #[error("Disabled opcode `{0}` at index `{1}`")]
+    DisabledOpcode(u16, u16),
+    #[error("Native `{0}` disagrees with the routine it replaces, called at index `{1}`")]
+    NativeMismatch(&'static str, u16),
+    #[error("Return to `{0}` at index `{1}`, which is not a return address: {2}")]
+    ReturnAddressCorrupted(u16, u16, String),
+    #[error("Division by zero at index `{0}`")]
+    DivisionByZero(u16),
+}
+
+pub type OpcodeResult = eyre::Result<(), ExecutionError>;
+
+/// Why a run stopped without failing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// The program halted, either through `halt` or by returning with an empty stack.
+    Halted,
+    /// The program is waiting on `in`, but no input is available.
+    NeedsInput,
+    /// Execution reached a breakpoint at the contained address.
+    Breakpoint(u16),
+    /// A watched location was written to.
+    Watchpoint(u16),
+    /// The instruction budget was used up.
+    FuelExhausted,
+    /// The deadline passed.
+    TimedOut,
+    /// The program went over a cap of its sandbox.
+    ResourceLimit(Resource),
+    /// A timer event stopped the run after the contained number of instructions.
+    Timer(u64),
+    /// The machine came back to a state it was in before, at the contained address, so it will
+    /// repeat itself forever.
+    InfiniteLoop(u16),
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunOutcome::Halted => write!(f, "The machine halted."),
+            RunOutcome::NeedsInput => write!(f, "The machine is waiting for input."),
+            RunOutcome::Breakpoint(addr) => write!(f, "Hit a breakpoint at `{addr}`."),
+            RunOutcome::Watchpoint(addr) => write!(f, "Watched location `{addr}` was written."),
+            RunOutcome::FuelExhausted => write!(f, "The machine ran out of fuel."),
+            RunOutcome::TimedOut => write!(f, "The machine ran out of time."),
+            RunOutcome::ResourceLimit(resource) => {
+                write!(f, "The program exceeded its sandbox limit on {resource}.")
+            }
+            RunOutcome::Timer(steps) => write!(f, "A timer stopped the run after {steps} steps."),
+            RunOutcome::InfiniteLoop(addr) => {
+                write!(f, "The program is stuck in an infinite loop at `{addr}`.")
+            }
+        }
+    }
+}
+
+/// Limits on how long a single call to `MachineState::run_with` may run for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunLimits {
+    /// The maximum number of instructions to execute.
+    pub fuel: Option<u64>,
+    /// The point in time after which execution stops.
+    pub deadline: Option<Instant>,
+}
+
+impl RunLimits {
+    /// How many instructions are executed between checks of the deadline.
+    pub const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+}
+
+pub type RunResult = eyre::Result<RunOutcome, ExecutionError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_short_images_into_full_memory() {
+        let path = std::env::temp_dir().join(format!("synacor-short-{}.bin", std::process::id()));
+        // 0: jmp 100, where memory past the image reads as halts
+        std::fs::write(&path, bytes_from_words(&[6, 100])).unwrap();
+        let mut machine = MachineState::new(load_image(&path).unwrap());
+        assert_eq!(read_image(&path).unwrap(), [6, 100]);
+        assert_eq!(machine.mem.len(), MAX_ADDR);
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.history.iter().next_back(), Some(100));
+
+        std::fs::write(&path, vec![0; MAX_ADDR * 2 + 2]).unwrap();
+        assert!(load_image(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt::Write as _;
+use std::ops::Range;
+
+use crate::{
+    instruction::{self, disassemble_registers},
+    project::Project,
+    timeline::json_string,
+    MAX_ADDR,
+};
+
+/// The widest instruction has an opcode and three operands.
+const MAX_WORDS: usize = 4;
+
+/// An instruction as placed in a listing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Placed {
+    addr: u16,
+    len: usize,
+    /// The line it is on, counting from 1.
+    line: usize,
+}
+
+/// Produces an `objdump -d` style listing of `range`: one line per instruction with its address,
+/// its raw words in hex and its disassembly. Symbols from `project` are shown as labels, and
+/// comments are appended to their lines.
+pub fn listing(mem: &[u16], range: Range<usize>, project: &Project) -> String {
+    render(mem, range, project).0
+}
+
+/// Renders the listing of `range` along with where each instruction ended up.
+fn render(mem: &[u16], range: Range<usize>, project: &Project) -> (String, Vec<Placed>) {
+    let mut out = String::new();
+    let mut placed = Vec::new();
+    let mut line = 1;
+    let mut addr = range.start;
+    while addr < range.end.min(mem.len()) {
+        let (text, len) = disassemble_registers(mem, addr, project);
+        let pos = addr as u16;
+
+        if let Some(name) = project.symbols.get(&pos) {
+            let _ = writeln!(out, "\n{name}:");
+            line += 2;
+        }
+        let raw = mem[addr..addr + len]
+            .iter()
+            .map(|word| format!("{word:04x}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let _ = write!(
+            out,
+            "{addr:5}:  {raw:<width$}  {text}",
+            width = MAX_WORDS * 5 - 1
+        );
+        if let Some(comment) = project.comments.get(&pos) {
+            let _ = write!(out, "  ; {comment}");
+        }
+        out.push('\n');
+        placed.push(Placed {
+            addr: pos,
+            len,
+            line,
+        });
+
+        line += 1;
+        addr += len;
+    }
+    (out, placed)
+}
+
+/// What an instruction does with an address it names.
+fn xref_kind(op: u16, operand: usize) -> Option<&'static str> {
+    match (instruction::info(op)?.mnemonic, operand) {
+        ("call", 0) => Some("call"),
+        ("jmp", 0) | ("jt" | "jf", 1) => Some("jump"),
+        ("rmem", 1) => Some("read"),
+        ("wmem", 0) => Some("write"),
+        _ => None,
+    }
+}
+
+/// Describes the listing of `range` for editor plugins, as JSON of this shape:
+///
+/// ```text
+/// {
+///   "version": 1,
+///   "instructions": [{"addr": 6027, "len": 3, "line": 1204}],
+///   "symbols": [{"addr": 6027, "name": "confirm", "line": 1203}],
+///   "comments": [{"addr": 6027, "text": "the teleporter check"}],
+///   "variables": [{"addr": 2732, "name": "room", "type": "addr"}],
+///   "xrefs": [{"from": 5483, "to": 6027, "kind": "call"}]
+/// }
+/// ```
+///
+/// Lines count from 1 and name the line of the listing an instruction or a symbol's label is on.
+/// Symbols, comments and variables are those of the project in `range`. An xref is an instruction
+/// in `range` naming a literal address: `kind` is `call`, `jump`, `read` (`rmem`) or `write`
+/// (`wmem`).
+pub fn metadata(mem: &[u16], range: Range<usize>, project: &Project) -> String {
+    let (_, placed) = render(mem, range.clone(), project);
+    let in_range = |addr: &u16| range.contains(&(*addr as usize));
+
+    let instructions = placed
+        .iter()
+        .map(|p| {
+            format!(
+                "{{\"addr\":{},\"len\":{},\"line\":{}}}",
+                p.addr, p.len, p.line
+            )
+        })
+        .collect::<Vec<_>>();
+    let symbols = placed
+        .iter()
+        .filter_map(|p| Some((p, project.symbols.get(&p.addr)?)))
+        .map(|(p, name)| {
+            format!(
+                "{{\"addr\":{},\"name\":{},\"line\":{}}}",
+                p.addr,
+                json_string(name),
+                p.line - 1
+            )
+        })
+        .collect::<Vec<_>>();
+    let comments = project
+        .comments
+        .iter()
+        .filter(|(addr, _)| in_range(addr))
+        .map(|(addr, text)| format!("{{\"addr\":{addr},\"text\":{}}}", json_string(text)))
+        .collect::<Vec<_>>();
+    let variables = project
+        .variables
+        .iter()
+        .filter(|(addr, _)| in_range(addr))
+        .map(|(addr, var)| {
+            format!(
+                "{{\"addr\":{addr},\"name\":{},\"type\":\"{}\"}}",
+                json_string(&var.name),
+                var.ty.as_str()
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut xrefs = Vec::new();
+    for p in &placed {
+        let op = mem[p.addr as usize];
+        for operand in 0..p.len - 1 {
+            let target = mem[p.addr as usize + 1 + operand];
+            if let (Some(kind), true) = (xref_kind(op, operand), target < MAX_ADDR as u16) {
+                xrefs.push(format!(
+                    "{{\"from\":{},\"to\":{target},\"kind\":\"{kind}\"}}",
+                    p.addr
+                ));
+            }
+        }
+    }
+
+    let mut out = String::from("{\"version\":1");
+    for (key, items) in [
+        ("instructions", instructions),
+        ("symbols", symbols),
+        ("comments", comments),
+        ("variables", variables),
+        ("xrefs", xrefs),
+    ] {
+        let _ = write!(out, ",\n\"{key}\":[{}]", items.join(","));
+    }
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        let project = Project::parse("symbol 3 print\ncomment 3 prints a").unwrap();
+        let text = listing(&[1, 32768, 6, 19, 97, 0], 0..6, &project);
+        assert_eq!(
+            text,
+            "    0:  0001 8000 0006       set r0 6\n\
+             \n\
+             print:\n    \
+             3:  0013 0061            out 'a'  ; prints a\n    \
+             5:  0000                 halt\n"
+        );
+    }
+
+    #[test]
+    fn exports_metadata() {
+        // 0: call 5
+        // 2: wmem 9 r0
+        // 5: print: jt r0 0
+        // 8: halt
+        let project = Project::parse(
+            "symbol 5 print\ncomment 5 the \"loop\"\nvariable 7 counter u16\ncomment 20 elsewhere",
+        )
+        .unwrap();
+        let mem = [17, 5, 16, 9, 32768, 7, 32768, 0, 0, 0];
+        assert_eq!(
+            metadata(&mem, 0..9, &project),
+            "{\"version\":1,\n\
+             \"instructions\":[{\"addr\":0,\"len\":2,\"line\":1},{\"addr\":2,\"len\":3,\"line\":2},\
+             {\"addr\":5,\"len\":3,\"line\":5},{\"addr\":8,\"len\":1,\"line\":6}],\n\
+             \"symbols\":[{\"addr\":5,\"name\":\"print\",\"line\":4}],\n\
+             \"comments\":[{\"addr\":5,\"text\":\"the \\\"loop\\\"\"}],\n\
+             \"variables\":[{\"addr\":7,\"name\":\"counter\",\"type\":\"u16\"}],\n\
+             \"xrefs\":[{\"from\":0,\"to\":5,\"kind\":\"call\"},{\"from\":2,\"to\":9,\"kind\":\"write\"},\
+             {\"from\":5,\"to\":0,\"kind\":\"jump\"}]}\n"
+        );
+        let text = listing(&mem, 0..9, &project);
+        assert_eq!(
+            text.lines().nth(4).unwrap().trim_start(),
+            "5:  0007 8000 0000       jt r0 0  ; the \"loop\""
+        );
+    }
+}
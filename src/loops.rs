@@ -0,0 +1,146 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::REGISTER_COUNT;
+
+/// Detects exact repeats of the full machine state.
+///
+/// The state is hashed every `interval` instructions. Without input, execution is deterministic,
+/// so seeing the same state twice means the machine will loop forever. Reading input resets the
+/// detector, since the same state can legitimately recur while waiting for different input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RepeatDetector {
+    pub interval: u64,
+    seen: HashSet<u64>,
+}
+
+impl RepeatDetector {
+    pub const DEFAULT_INTERVAL: u64 = 4096;
+
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Whether the state should be sampled after `steps` instructions.
+    pub fn due(&self, steps: u64) -> bool {
+        steps.is_multiple_of(self.interval)
+    }
+
+    /// Records a state hash, returning `true` if it was seen before.
+    pub fn observe(&mut self, hash: u64) -> bool {
+        !self.seen.insert(hash)
+    }
+
+    /// Forgets every recorded state.
+    pub fn reset(&mut self) {
+        self.seen.clear();
+    }
+}
+
+impl Default for RepeatDetector {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_INTERVAL)
+    }
+}
+
+/// What the cycle detector keys on: the current position and the registers.
+pub type CycleKey = (u16, [u16; REGISTER_COUNT]);
+
+/// A cheaper heuristic than `RepeatDetector`, which ignores memory and the stack.
+///
+/// It tracks the last `window` (position, registers) tuples, and reports a probable livelock once
+/// any tuple occurs `threshold` times within the window. Because memory isn't considered, this
+/// can be wrong, so it is only ever used to warn.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CycleDetector {
+    pub window: usize,
+    pub threshold: usize,
+    recent: VecDeque<CycleKey>,
+    counts: HashMap<CycleKey, usize>,
+    reported: bool,
+}
+
+impl CycleDetector {
+    pub const DEFAULT_WINDOW: usize = 1 << 16;
+    pub const DEFAULT_THRESHOLD: usize = 64;
+
+    pub fn new(window: usize, threshold: usize) -> Self {
+        Self {
+            window: window.max(1),
+            threshold: threshold.max(2),
+            recent: VecDeque::new(),
+            counts: HashMap::new(),
+            reported: false,
+        }
+    }
+
+    /// Records the current tuple, returning `true` the first time a probable livelock is seen.
+    pub fn observe(&mut self, key: CycleKey) -> bool {
+        if self.recent.len() == self.window {
+            let old = self.recent.pop_front().unwrap();
+            if let Some(count) = self.counts.get_mut(&old) {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&old);
+                }
+            }
+        }
+
+        self.recent.push_back(key);
+        let count = self.counts.entry(key).or_insert(0);
+        *count += 1;
+
+        if *count >= self.threshold && !self.reported {
+            self.reported = true;
+            return true;
+        }
+        false
+    }
+
+    /// Forgets the window, so a new livelock can be reported.
+    pub fn reset(&mut self) {
+        self.recent.clear();
+        self.counts.clear();
+        self.reported = false;
+    }
+}
+
+impl Default for CycleDetector {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_WINDOW, Self::DEFAULT_THRESHOLD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeats() {
+        let mut detector = RepeatDetector::new(1);
+        assert!(!detector.observe(1));
+        assert!(!detector.observe(2));
+        assert!(detector.observe(1));
+        detector.reset();
+        assert!(!detector.observe(1));
+    }
+
+    #[test]
+    fn cycles() {
+        let mut detector = CycleDetector::new(4, 2);
+        let key = |cur| (cur, [0; REGISTER_COUNT]);
+        assert!(!detector.observe(key(1)));
+        assert!(!detector.observe(key(2)));
+        assert!(detector.observe(key(1)));
+        // only reported once
+        assert!(!detector.observe(key(1)));
+
+        // tuples fall out of the window
+        let mut detector = CycleDetector::new(2, 2);
+        for cur in [1, 2, 3, 1, 2, 3] {
+            assert!(!detector.observe(key(cur)));
+        }
+    }
+}
@@ -1,153 +1,1074 @@
-#![macro_use]
-extern crate thiserror;
-
-use std::collections::VecDeque;
+use std::collections::BTreeMap;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::time::Duration;
 
 use color_eyre::eyre;
 
-mod opcodes;
-
-/// The maximum number that can be used as an address on this machine.
-pub const MAX_ADDR: usize = 2usize.pow(15);
-pub const REGISTER_COUNT: usize = 8;
-
-/// Represents the state of the machine:
-/// - `mem` is its entire memory (RAM)
-/// - `cur` is the index of the current operation to be executed
-/// - `registers` are the 8 registers specified in the architecture spec.
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct MachineState {
-    pub mem: Vec<u16>,
-    pub cur: u16,
-    pub registers: [u16; REGISTER_COUNT],
-    pub stack: VecDeque<u16>,
+use synacor_challenge::{
+    asm,
+    audit::StackAudit,
+    batch, bisect, bytes_from_words,
+    calls::CallTrace,
+    callstack::CallStack,
+    canary::StackCanary,
+    checkpoint::Checkpoints,
+    condition::{self, Condition},
+    console::Console,
+    crash::{self, CrashDump},
+    debugger::Debugger,
+    extensions::Extensions,
+    fuzz, fuzzdict,
+    history::{self, History},
+    hostprofile::HostProfile,
+    instruction,
+    io::{FlushPolicy, Io, StdoutSink},
+    journal::Journal,
+    listing, load_image,
+    loops::{CycleDetector, RepeatDetector},
+    memo::Memo,
+    mi::Mi,
+    minimize,
+    natives::Natives,
+    notify::{self, Notifier},
+    oracle::AccessOracle,
+    patch::PatchScript,
+    postmortem::Postmortem,
+    profile,
+    project::{self, Project},
+    prompt::PromptDetector,
+    provenance::WriteProvenance,
+    read_image,
+    replay::{self, Recording},
+    report,
+    sandbox::Sandbox,
+    script::Script,
+    selfmod,
+    slots::{self, Autosave, Slots},
+    stack::Stack,
+    strings, tables,
+    taint::Taint,
+    teleporter,
+    testing::ScriptExhausted,
+    testrom,
+    timeline::{self, Timeline},
+    timer::Timers,
+    toggles::{self, Disabled},
+    trace::InstructionTrace,
+    transcript::Transcript,
+    verbs, verify,
+    watch::Watchpoints,
+    MachineState, RunOutcome, RunResult, BINARY_PATH,
+};
+#[cfg(unix)]
+use synacor_challenge::{config::Config, signals, tui::Tui};
+
+const USAGE: &str = "\
+usage: synacor [run <image|file.snapshot>] [--notify bell|desktop] [--audit-stack <log>]
+                      [--trace <log>] [--trace-calls <log>] [--history <count>] [--cycle-window <steps>]
+                      [--cycle-threshold <count>] [--stack-canary] [--dual-stack]
+                      [--self-mod-report] [--trace-stack] [--access-oracle] [--taint]
+                      [--provenance] [--timeline <trace.json>] [--extensions]
+                      [--self-profile] [--disable <op>[:nop],...] [--sandbox]
+                      [--flush char|line|manual] [--natives] [--validate-natives]
+                      [--memoize] [--break '<addr> [if <condition>]']...
+                      [--watch <addr|reg>[:log],...] [--console <addr>] [--checkpoints]
+                      [--labels <file>] [--at '<steps> input <text>|stop']...
+                      [--save-on-exit] [--resume <file.snapshot>] [--save-as <slot>]
+                      [--load <slot>] [--autosave prompt|<steps>,...]
+                      [--input <commands.txt>] [--transcript <out.txt>] [--transcript-input]
+                      [--record <session>]
+       synacor asm <source> <out>
+       synacor slots <image> [tree | delete <slot>]
+       synacor patch apply <image> <patch.toml> <out>
+       synacor patch diff <original> <modified>
+       synacor postmortem <file.crash>
+       synacor export-image <file.snapshot|file.crash> <out.bin>
+       synacor debug <image> [--break '<addr> [if <condition>]']...
+                     [--watch <addr|reg>[:log],...] [--console <addr>] [--labels <file>]
+                     [--at '<steps> input <text>|stop']... [--input <commands.txt>]
+                     [--dual-stack] [--notify bell|desktop] [--mi | --tui [--config <file>]]
+       synacor batch <image> <dir> [--codes <file>] [--fuel <steps>] [--threads <count>]
+                     [--timeout <seconds>]
+       synacor bisect <from.snapshot> <to.snapshot> <input> mem <addr> <value> | output <text>
+       synacor minimize <image> <input> [--fuel <steps>]
+       synacor fuzz <file.snapshot> [--cases <count>] [--fuel <steps>] [--seed <n>]
+                    [--words <file>] [--corpus <file>]
+       synacor fuzz-dict <image>
+       synacor disasm <image> [--from <addr>] [--to <addr>] [--labels <file>]
+       synacor listing <image> [--from <addr>] [--to <addr>] [--metadata <out.json>]
+                       [--labels <file>]
+       synacor profile <image> <input> [--fuel <steps>] [--flamegraph <out.svg>]
+       synacor replay <image|file.snapshot> <session> [--fuel <steps>]
+       synacor report <transcript> <input> <codes> <out.html>
+       synacor strings <image> [--search <text>]
+       synacor table <file.snapshot> <region> [--follow]
+       synacor teleporter [--threads <count>] [--checkpoint <file>] [--json <file|->]
+       synacor test-rom <dir> [--fuel <steps>]
+       synacor verbs <image> <input> [--fuel <steps>]
+       synacor verify <bundle> [--fuel <steps>] [--access-oracle] [--record-hashes <interval>]";
+
+/// Options for running the machine, set from command line flags.
+#[derive(Clone, Debug, Default)]
+struct RunOptions {
+    notify: Option<Notifier>,
+    audit_stack: Option<String>,
+    trace: Option<String>,
+    transcript: Option<String>,
+    transcript_input: bool,
+    record: Option<String>,
+    history: Option<usize>,
+    trace_calls: Option<String>,
+    timeline: Option<String>,
+    cycle_detector: Option<CycleDetector>,
+    self_mod_report: bool,
+    trace_stack: bool,
+    stack_canary: bool,
+    dual_stack: bool,
+    access_oracle: bool,
+    taint: bool,
+    provenance: bool,
+    extensions: bool,
+    self_profile: bool,
+    disabled: BTreeMap<u16, Disabled>,
+    sandbox: bool,
+    flush: FlushPolicy,
+    natives: bool,
+    validate_natives: bool,
+    memoize: bool,
+    breakpoints: BTreeMap<u16, Option<Condition>>,
+    watchpoints: Option<Watchpoints>,
+    console: Option<Console>,
+    checkpoints: bool,
+    mi: bool,
+    tui: bool,
+    /// The TUI's config file, which `layout save` creates if it doesn't exist.
+    config: Option<String>,
+    labels: Option<String>,
+    timers: Option<Timers>,
+    save_on_exit: bool,
+    resume: Option<String>,
+    save_as: Option<String>,
+    load: Option<String>,
+    autosave: Option<String>,
+    script: Option<Vec<u8>>,
 }
-impl MachineState {
-    pub fn new(mem: Vec<u16>) -> Self {
-        Self {
-            mem,
-            cur: 0,
-            registers: [0; REGISTER_COUNT],
-            stack: VecDeque::new(),
+
+/// How often `table --follow` checks whether the snapshot changed.
+const TABLE_REFRESH: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// How many of the last instructions executed are shown when a run fails.
+const BACKTRACE_LEN: usize = 20;
+
+/// How many instructions subcommands that run programs repeatedly execute per run by default.
+const DEFAULT_FUEL: u64 = 100_000_000;
+
+/// How many commands `fuzz` tries by default.
+const DEFAULT_FUZZ_CASES: usize = 1000;
+
+/// Removes `--name <value>` from `args`, returning the value.
+fn take_option(args: &mut Vec<String>, name: &str) -> eyre::Result<Option<String>> {
+    match args.iter().position(|arg| arg == name) {
+        Some(i) if i + 1 < args.len() => {
+            args.remove(i);
+            Ok(Some(args.remove(i)))
         }
+        Some(_) => Err(eyre::eyre!("`{name}` expects a value")),
+        None => Ok(None),
     }
+}
+
+/// Reads a file of codes, one per line.
+fn read_codes(path: impl AsRef<Path>) -> eyre::Result<Vec<String>> {
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|code| !code.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Loads the snapshot at `path`, or the program image if it isn't one.
+fn load_machine(path: &Path) -> eyre::Result<MachineState> {
+    if path
+        .extension()
+        .is_some_and(|ext| ext == crash::SNAPSHOT_EXTENSION)
+    {
+        return MachineState::load(path).map_err(|err| eyre::eyre!(err));
+    }
+    Ok(MachineState::new(load_image(path)?))
+}
+
+/// Removes the flag `name` from `args`, returning whether it was given.
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes `--name <addr>` from `args`, returning the address.
+fn take_address(args: &mut Vec<String>, name: &str) -> eyre::Result<Option<u16>> {
+    take_option(args, name)?
+        .map(|addr| project::parse_number(&addr).map_err(|err| eyre::eyre!(err)))
+        .transpose()
+}
+
+fn main() -> eyre::Result<()> {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let options = RunOptions {
+        notify: take_option(&mut args, "--notify")?
+            .map(|kind| kind.parse::<Notifier>().map_err(|err| eyre::eyre!(err)))
+            .transpose()?,
+        audit_stack: take_option(&mut args, "--audit-stack")?,
+        trace: take_option(&mut args, "--trace")?,
+        transcript: take_option(&mut args, "--transcript")?,
+        transcript_input: take_flag(&mut args, "--transcript-input"),
+        record: take_option(&mut args, "--record")?,
+        history: take_option(&mut args, "--history")?
+            .map(|count| count.parse())
+            .transpose()?,
+        trace_calls: take_option(&mut args, "--trace-calls")?,
+        timeline: take_option(&mut args, "--timeline")?,
+        cycle_detector: {
+            let window = take_option(&mut args, "--cycle-window")?;
+            let threshold = take_option(&mut args, "--cycle-threshold")?;
+            match (window, threshold) {
+                (None, None) => None,
+                (window, threshold) => Some(CycleDetector::new(
+                    window.map_or(Ok(CycleDetector::DEFAULT_WINDOW), |n| n.parse())?,
+                    threshold.map_or(Ok(CycleDetector::DEFAULT_THRESHOLD), |n| n.parse())?,
+                )),
+            }
+        },
+        self_mod_report: take_flag(&mut args, "--self-mod-report"),
+        trace_stack: take_flag(&mut args, "--trace-stack"),
+        stack_canary: take_flag(&mut args, "--stack-canary"),
+        dual_stack: take_flag(&mut args, "--dual-stack"),
+        access_oracle: take_flag(&mut args, "--access-oracle"),
+        taint: take_flag(&mut args, "--taint"),
+        provenance: take_flag(&mut args, "--provenance"),
+        extensions: take_flag(&mut args, "--extensions"),
+        self_profile: take_flag(&mut args, "--self-profile"),
+        sandbox: take_flag(&mut args, "--sandbox"),
+        natives: take_flag(&mut args, "--natives"),
+        validate_natives: take_flag(&mut args, "--validate-natives"),
+        memoize: take_flag(&mut args, "--memoize"),
+        checkpoints: take_flag(&mut args, "--checkpoints"),
+        mi: take_flag(&mut args, "--mi"),
+        tui: take_flag(&mut args, "--tui"),
+        config: take_option(&mut args, "--config")?,
+        labels: take_option(&mut args, "--labels")?,
+        save_on_exit: take_flag(&mut args, "--save-on-exit"),
+        resume: take_option(&mut args, "--resume")?,
+        save_as: take_option(&mut args, "--save-as")?,
+        load: take_option(&mut args, "--load")?,
+        autosave: take_option(&mut args, "--autosave")?,
+        script: take_option(&mut args, "--input")?
+            .map(std::fs::read)
+            .transpose()?,
+        breakpoints: {
+            let mut breakpoints = BTreeMap::new();
+            while let Some(spec) = take_option(&mut args, "--break")? {
+                let (addr, condition) =
+                    condition::parse_breakpoint(&spec).map_err(|err| eyre::eyre!(err))?;
+                let addr = project::parse_number(addr).map_err(|err| eyre::eyre!(err))?;
+                breakpoints.insert(addr, condition);
+            }
+            breakpoints
+        },
+        timers: {
+            let mut timers = None;
+            while let Some(spec) = take_option(&mut args, "--at")? {
+                let (steps, event) = Timers::parse(&spec).map_err(|err| eyre::eyre!(err))?;
+                timers.get_or_insert_with(Timers::new).at(steps, event);
+            }
+            timers
+        },
+        watchpoints: take_option(&mut args, "--watch")?
+            .map(|spec| Watchpoints::parse(&spec).map_err(|err| eyre::eyre!(err)))
+            .transpose()?,
+        console: take_option(&mut args, "--console")?
+            .map(|addr| {
+                project::parse_number(&addr)
+                    .and_then(Console::new)
+                    .map_err(|err| eyre::eyre!(err))
+            })
+            .transpose()?,
+        flush: take_option(&mut args, "--flush")?
+            .map(|policy| {
+                policy
+                    .parse::<FlushPolicy>()
+                    .map_err(|err| eyre::eyre!(err))
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        disabled: take_option(&mut args, "--disable")?
+            .map(|spec| toggles::parse(&spec).map_err(|err| eyre::eyre!(err)))
+            .transpose()?
+            .unwrap_or_default(),
+    };
+    let fuel = take_option(&mut args, "--fuel")?.map_or(Ok(DEFAULT_FUEL), |fuel| fuel.parse())?;
+    let from = take_address(&mut args, "--from")?;
+    let to = take_address(&mut args, "--to")?;
+    let search = take_option(&mut args, "--search")?;
+    let flamegraph = take_option(&mut args, "--flamegraph")?;
+    let follow = take_flag(&mut args, "--follow");
+    let threads = take_option(&mut args, "--threads")?
+        .map(|threads| threads.parse::<usize>())
+        .transpose()?;
+    let checkpoint = take_option(&mut args, "--checkpoint")?;
+    let json = take_option(&mut args, "--json")?;
+    let codes = take_option(&mut args, "--codes")?;
+    let metadata = take_option(&mut args, "--metadata")?;
+    let cases = take_option(&mut args, "--cases")?
+        .map_or(Ok(DEFAULT_FUZZ_CASES), |cases| cases.parse::<usize>())?;
+    let seed = take_option(&mut args, "--seed")?
+        .map(|seed| seed.parse::<u64>())
+        .transpose()?;
+    let words = take_option(&mut args, "--words")?;
+    let corpus = take_option(&mut args, "--corpus")?;
+    let timeout = take_option(&mut args, "--timeout")?
+        .map(|secs| secs.parse::<f64>().map(Duration::from_secs_f64))
+        .transpose()?;
+    let record_hashes = take_option(&mut args, "--record-hashes")?
+        .map(|interval| interval.parse::<u64>())
+        .transpose()?;
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
 
-    pub fn run(&mut self) -> OpcodeResult {
-        for _ in 0..MAX_ADDR {
-            if let Err(err) = self.exec_next() {
-                return Err(err);
+    match args.as_slice() {
+        [] => run_image(Path::new(BINARY_PATH), &options),
+        ["run", image] => run_image(Path::new(image), &options),
+        ["patch", "apply", image, script, out] => {
+            let mut mem = read_image(image)?;
+            let script = PatchScript::parse(&std::fs::read_to_string(script)?)?;
+            script.apply(&mut mem)?;
+            std::fs::write(out, bytes_from_words(&mem))?;
+            Ok(())
+        }
+        ["asm", source, out] => {
+            let assembled = asm::assemble(&std::fs::read_to_string(source)?)?;
+            std::fs::write(out, bytes_from_words(&assembled.words))?;
+            if !assembled.labels.is_empty() {
+                // name the labels in the project, to show them when debugging the binary
+                let path = Project::path_for(out);
+                let mut project = if path.exists() {
+                    Project::load(&path)?
+                } else {
+                    Project::default()
+                };
+                for (name, &addr) in &assembled.labels {
+                    project.symbols.insert(addr, name.clone());
+                }
+                project.save(Project::path_for(out))?;
             }
+            Ok(())
         }
-        Ok(())
+        ["patch", "diff", original, modified] => {
+            let script = PatchScript::diff(&read_image(original)?, &read_image(modified)?)?;
+            print!("{}", script.to_toml());
+            Ok(())
+        }
+        ["batch", image, dir] => {
+            let jobs = batch::jobs(&load_image(image)?, dir).map_err(|err| eyre::eyre!(err))?;
+            let batch = batch::Batch {
+                fuel,
+                timeout,
+                threads: match threads {
+                    Some(threads) => threads,
+                    None => std::thread::available_parallelism()?.get(),
+                },
+                codes: codes.map_or(Ok(Vec::new()), read_codes)?,
+            };
+            let results = batch.run(jobs);
+            print!("{}", batch::report(&results, batch.codes.len()));
+            if results.iter().any(|job| job.result.is_err()) {
+                return Err(eyre::eyre!("Some runs failed"));
+            }
+            Ok(())
+        }
+        ["bisect", from, to, input, predicate @ ..] => {
+            let from_dump = CrashDump::load(from).map_err(|err| eyre::eyre!(err))?;
+            let to_dump = CrashDump::load(to).map_err(|err| eyre::eyre!(err))?;
+            let predicate =
+                bisect::Predicate::parse(&predicate.join(" ")).map_err(|err| eyre::eyre!(err))?;
+            let bisection =
+                bisect::bisect(&from_dump, &to_dump, &std::fs::read(input)?, &predicate)?;
+            println!("{}", bisection.describe(&load_project(from, &options)?));
+            Ok(())
+        }
+        ["export-image", path, out] => {
+            let dump = CrashDump::load(path).map_err(|err| eyre::eyre!(err))?;
+            std::fs::write(out, bytes_from_words(&dump.mem))?;
+            eprintln!("Wrote {} words to `{out}`", dump.mem.len());
+            Ok(())
+        }
+        ["postmortem", path] => {
+            let dump = CrashDump::load(path).map_err(|err| eyre::eyre!(err))?;
+            let project = load_project(path, &options)?;
+            Postmortem::new(dump, project).repl(std::io::stdin().lock(), std::io::stdout())?;
+            Ok(())
+        }
+        ["debug", image] => {
+            let mut machine = MachineState::new(load_image(image)?);
+            machine.breakpoints = options.breakpoints.clone();
+            machine.watchpoints = options.watchpoints.clone();
+            machine.console = options.console;
+            machine.timers = options.timers.clone();
+            machine.script = options.script.as_deref().map(Script::new);
+            if options.dual_stack {
+                machine.return_stack = Some(Stack::new());
+            }
+            let mut debugger = Debugger::new(machine, load_project(image, &options)?);
+            debugger.slots = Some(Slots::new(image));
+            debugger.notify = options.notify;
+            if options.mi {
+                Mi::new(debugger).serve(std::io::stdin().lock(), std::io::stdout())?;
+            } else if options.tui {
+                run_tui(debugger, options.config.as_deref())?;
+            } else {
+                debugger.repl(std::io::stdin().lock(), std::io::stdout())?;
+            }
+            Ok(())
+        }
+        ["minimize", image, input] => {
+            let program = load_image(image)?;
+            let reproducer = minimize::minimize(&program, &std::fs::read(input)?, fuel)
+                .ok_or_else(|| eyre::eyre!("The input does not make the program fail"))?;
+
+            let dump_path = Path::new(input).with_extension(crash::CRASH_EXTENSION);
+            reproducer.dump.save(&dump_path)?;
+            eprintln!(
+                "{:?} at step {} with {} byte(s) of input; the state right before it is in `{}`",
+                reproducer.failure,
+                reproducer.steps,
+                reproducer.input.len(),
+                dump_path.display()
+            );
+            std::io::stdout().write_all(&reproducer.input)?;
+            Ok(())
+        }
+        ["fuzz", snapshot] => {
+            let base = CrashDump::load(snapshot)
+                .map_err(|err| eyre::eyre!(err))?
+                .to_machine();
+            let vocabulary = match &words {
+                Some(path) => std::fs::read_to_string(path)?
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect(),
+                None => fuzz::vocabulary(&base.mem),
+            };
+            let seed = seed.unwrap_or_else(|| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(1, |time| time.as_secs())
+            });
+            eprintln!("fuzzing with {} word(s), seed {seed}", vocabulary.len());
+            let fuzzer = fuzz::Fuzzer {
+                vocabulary,
+                fuel,
+                seed,
+            };
+            let seeds = match &corpus {
+                Some(path) if Path::new(path).exists() => std::fs::read_to_string(path)?
+                    .lines()
+                    .map(str::to_string)
+                    .collect(),
+                _ => Vec::new(),
+            };
+            let session = fuzzer.run(&base, &seeds, cases);
+            if let Some(path) = &corpus {
+                let text = session
+                    .corpus
+                    .iter()
+                    .map(|input| format!("{input}\n"))
+                    .collect::<String>();
+                std::fs::write(path, text)?;
+            }
+            print!("{}", fuzz::report(&session, cases));
+            Ok(())
+        }
+        ["fuzz-dict", image] => {
+            print!("{}", fuzzdict::dictionary(&load_image(image)?));
+            Ok(())
+        }
+        ["disasm", image] => {
+            let mem = read_image(image)?;
+            let range = from.unwrap_or(0) as usize..to.map_or(mem.len(), |to| to as usize);
+            let project = load_project(image, &options)?;
+            print!("{}", instruction::disassemble_range(&mem, range, &project));
+            Ok(())
+        }
+        ["listing", image] => {
+            let mem = read_image(image)?;
+            let range = from.unwrap_or(0) as usize..to.map_or(mem.len(), |to| to as usize);
+            let project = load_project(image, &options)?;
+            if let Some(path) = &metadata {
+                std::fs::write(path, listing::metadata(&mem, range.clone(), &project))?;
+            }
+            print!("{}", listing::listing(&mem, range, &project));
+            Ok(())
+        }
+        ["profile", image, input] => {
+            let project = load_project(image, &options)?;
+            let mut machine = MachineState::new(load_image(image)?);
+            machine.call_trace = Some(CallTrace::new());
+            machine.push_input(&std::fs::read(input)?);
+            let result = machine.run_for(fuel);
+
+            let calls = machine
+                .call_trace
+                .as_ref()
+                .map_or(&[][..], |trace| &trace.log);
+            let stacks = profile::folded(calls, machine.steps, &project);
+            match &flamegraph {
+                Some(path) => std::fs::write(path, profile::flamegraph(&stacks))?,
+                None => print!("{}", profile::folded_text(&stacks)),
+            }
+            match result {
+                Ok(outcome) => eprintln!("{outcome} ({} steps)", machine.steps),
+                Err(err) => eprintln!("failed: {err}"),
+            }
+            Ok(())
+        }
+        ["replay", image, session] => {
+            let machine = load_machine(Path::new(image))?;
+            let recording = Recording::load(session).map_err(|err| eyre::eyre!(err))?;
+            let replayed = replay::replay(machine, &recording, fuel);
+            match &replayed.result {
+                Ok(outcome) => eprintln!("{outcome}"),
+                Err(err) => eprintln!("failed: {err}"),
+            }
+            if let Some(mismatch) = &replayed.mismatch {
+                eprintln!("{mismatch}");
+            }
+            if !replayed.passed() {
+                return Err(eyre::eyre!("The replay does not match the recording"));
+            }
+            eprintln!(
+                "The replay matches the recording: {} input bytes, {} output bytes.",
+                recording.inputs.len(),
+                recording.output.len()
+            );
+            Ok(())
+        }
+        ["report", transcript, input, codes, out] => {
+            let codes = read_codes(codes)?;
+            let session = report::Session::parse(
+                &std::fs::read_to_string(transcript)?,
+                &std::fs::read_to_string(input)?,
+                &codes,
+            );
+            std::fs::write(out, report::html(&session))?;
+            eprintln!("Wrote the report to `{out}`.");
+            Ok(())
+        }
+        ["slots", image] => {
+            for slot in Slots::new(image).list().map_err(|err| eyre::eyre!(err))? {
+                println!(
+                    "{:<20} {:>12} steps at {:<6} {}",
+                    slot.name, slot.steps, slot.cur, slot.reason
+                );
+            }
+            Ok(())
+        }
+        ["slots", image, "tree"] => {
+            print!(
+                "{}",
+                Slots::new(image).tree().map_err(|err| eyre::eyre!(err))?
+            );
+            Ok(())
+        }
+        ["slots", image, "delete", name] => {
+            Slots::new(image)
+                .delete(name)
+                .map_err(|err| eyre::eyre!(err))?;
+            eprintln!("Deleted the slot `{name}`.");
+            Ok(())
+        }
+        ["strings", image] => {
+            let mem = load_image(image)?;
+            let project = load_project(image, &options)?;
+            let found = strings::strings(&mem, &project);
+            print!("{}", strings::render(&found, search.as_deref(), &project));
+            Ok(())
+        }
+        ["table", path, region] => {
+            let project = load_project(path, &options)?;
+            let mut modified = None;
+            loop {
+                let current = std::fs::metadata(path)?.modified()?;
+                if modified != Some(current) {
+                    modified = Some(current);
+                    let dump = CrashDump::load(path).map_err(|err| eyre::eyre!(err))?;
+                    let table = tables::render(&project, region, &dump.mem)
+                        .map_err(|err| eyre::eyre!(err))?;
+                    if follow {
+                        // clear the screen, so the table stays in place as it refreshes
+                        println!("\x1b[2J\x1b[H{} steps", dump.steps);
+                    }
+                    print!("{table}");
+                }
+                if !follow {
+                    return Ok(());
+                }
+                std::thread::sleep(TABLE_REFRESH);
+            }
+        }
+        ["teleporter"] => {
+            let threads = match threads {
+                Some(threads) => threads,
+                None => std::thread::available_parallelism()?.get(),
+            };
+            let mut search = teleporter::Search::new(threads, checkpoint.map(Into::into));
+            search.progress = std::io::stderr().is_terminal();
+            let found = search.run()?;
+            match json.as_deref() {
+                Some("-") => print!("{}", teleporter::to_json(found)),
+                Some(path) => std::fs::write(path, teleporter::to_json(found))?,
+                None => {}
+            }
+            let r7 = found.ok_or_else(|| eyre::eyre!("No value of r7 confirms the teleporter"))?;
+            if json.is_none() {
+                println!("r7 = {r7}");
+            }
+            Ok(())
+        }
+        ["test-rom", dir] => {
+            let results = testrom::run_dir(dir, fuel)
+                .map_err(|err| eyre::eyre!("Could not read test ROMs from `{dir}`: {err}"))?;
+            print!("{}", testrom::report(&results));
+            if !results.iter().all(testrom::RomResult::passed) {
+                return Err(eyre::eyre!("Some test ROMs failed"));
+            }
+            Ok(())
+        }
+        ["verbs", image, input] => {
+            let words = verbs::discover(&load_image(image)?, &std::fs::read(input)?, fuel);
+            for (addr, word) in words {
+                println!("{addr:5}: {word}");
+            }
+            Ok(())
+        }
+        ["verify", path] => {
+            if let Some(interval) = record_hashes {
+                let bundle = verify::Bundle::load(path)?;
+                let mut text = std::fs::read_to_string(path)?
+                    .lines()
+                    .filter(|line| !line.trim_start().starts_with("hash "))
+                    .map(|line| format!("{line}\n"))
+                    .collect::<String>();
+                text.push_str(&verify::record_hashes(&bundle, fuel, interval));
+                std::fs::write(path, text)?;
+                eprintln!("Recorded state hashes in `{path}`.");
+                return Ok(());
+            }
+            let verification =
+                verify::verify(&verify::Bundle::load(path)?, fuel, options.access_oracle);
+            match &verification.result {
+                Ok(outcome) => eprintln!("{outcome}"),
+                Err(err) => eprintln!("failed: {err}"),
+            }
+            if let Some(divergence) = &verification.divergence {
+                eprintln!("{divergence}");
+            }
+            for code in &verification.missing {
+                eprintln!("missing code: {code}");
+            }
+            if !verification.passed() {
+                return Err(eyre::eyre!("The solution does not verify"));
+            }
+            eprintln!("The solution verifies.");
+            Ok(())
+        }
+        _ => Err(eyre::eyre!("{USAGE}")),
     }
+}
+
+/// Opens the full-screen debugger on `debugger`, with the config at `config_path` if it exists.
+#[cfg(unix)]
+fn run_tui(debugger: Debugger, config_path: Option<&str>) -> eyre::Result<()> {
+    let config = match config_path {
+        Some(path) if Path::new(path).exists() => Config::parse(&std::fs::read_to_string(path)?)?,
+        _ => Config::default(),
+    };
+    let term = std::env::var("TERM").ok();
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let theme = config.theme(term.as_deref(), no_color);
+    let mut tui = Tui::new(debugger, config, theme);
+    tui.config_path = config_path.map(str::to_string);
+    tui.run()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn run_tui(_debugger: Debugger, _config_path: Option<&str>) -> eyre::Result<()> {
+    Err(eyre::eyre!("The TUI needs a Unix terminal"))
+}
 
-    /// Executes the next operation.
-    pub fn exec_next(&mut self) -> eyre::Result<(), ExecutionError> {
-        self.cur += 1;
-        match self.mem[self.cur as usize - 1] {
-            0 => self.halt(),
-            1 => self.set(),
-            2 => self.push(),
-            3 => self.pop(),
-            4 => self.eq(),
-            5 => self.gt(),
-            6 => self.jmp(),
-            7 => self.jmp_true(),
-            8 => self.jmp_false(),
-            9 => self.add(),
-            10 => self.mult(),
-            11 => self.modulo(),
-            12 => self.and(),
-            13 => self.or(),
-            14 => self.not(),
-            15 => self.rmem(),
-            16 => self.wmem(),
-            17 => self.call(),
-            18 => self.ret(),
-            19 => self.char_out(),
-            21 => self.no_op(),
-            op => Err(ExecutionError::InvalidOpcode(op, self.cur - 1)),
-        }
-    }
-
-    /// Attempts to set a register to the provided value.
-    /// If the provided register number is invalid, returns an `ExecutionError`.
-    pub fn set_register(&mut self, register: usize, val: u16, pos: u16) -> OpcodeResult {
-        self.registers
-            .get_mut(
-                register
-                    .checked_sub(MAX_ADDR)
-                    .ok_or(ExecutionError::InvalidRegister(register, pos))?,
-            )
-            .map(|old| *old = val)
-            .ok_or(ExecutionError::InvalidRegister(register, pos))
-    }
-
-    /// Attempts to read from a register.
-    pub fn get_register(&self, register: usize, pos: u16) -> eyre::Result<u16, ExecutionError> {
-        self.registers
-            .get(
-                register
-                    .checked_sub(MAX_ADDR)
-                    .ok_or(ExecutionError::InvalidRegister(register, pos))?,
-            )
-            .map(|&val| val)
-            .ok_or(ExecutionError::InvalidRegister(register, pos))
-    }
-
-    /// Attempts to write the provided value to a register or a memory address.
-    pub fn write(&mut self, write_to: u16, val: u16, pos: u16) -> OpcodeResult {
-        if write_to < MAX_ADDR as u16 {
-            self.mem[write_to as usize] = val;
-            Ok(())
-        } else {
-            self.set_register(write_to as usize, val, pos)
-        }
-    }
-
-    /// Attempts to read from a register or a memory address.
-    pub fn read(&self, read_from: u16, pos: u16) -> eyre::Result<u16, ExecutionError> {
-        if read_from < MAX_ADDR as u16 {
-            Ok(self.mem[read_from as usize])
-        } else {
-            self.get_register(read_from as usize, pos)
+/// Runs `machine` until it stops. On `SIGUSR1` its state is saved as a snapshot next to the
+/// binary, which `postmortem` can inspect, and execution waits for `SIGUSR2`.
+#[cfg(unix)]
+fn run_pausable(machine: &mut MachineState, project: &Project, image: &Path) -> RunResult {
+    signals::install();
+    loop {
+        match machine.run_for(signals::CHECK_INTERVAL)? {
+            RunOutcome::FuelExhausted => {}
+            outcome => return Ok(outcome),
         }
+        if !signals::take_pause() {
+            continue;
+        }
+
+        machine.io.flush();
+        eprintln!(
+            "\npaused at {} after {} steps",
+            project.describe(machine.cur),
+            machine.steps
+        );
+        let path = image.with_extension(crash::SNAPSHOT_EXTENSION);
+        match CrashDump::of(machine, "paused by SIGUSR1").save(&path) {
+            Ok(()) => eprintln!("state saved to `{}`", path.display()),
+            Err(err) => eprintln!("could not save the state to `{}`: {err}", path.display()),
+        }
+        eprintln!("send SIGUSR2 to resume");
+        signals::wait_for_resume();
+        eprintln!("resumed");
     }
 }
 
-fn main() -> eyre::Result<()> {
-    let data = include_bytes!("../challenge.bin")
-        .chunks(2)
-        .map(|chunk| u16::from_le_bytes(<[u8; 2]>::try_from(chunk).unwrap()))
-        .collect::<Vec<_>>();
+#[cfg(not(unix))]
+fn run_pausable(machine: &mut MachineState, _project: &Project, _image: &Path) -> RunResult {
+    machine.run()
+}
+
+/// Runs the program in `image`, `challenge.bin` by default, or resumes a snapshot, given as the
+/// image, with `--resume` or as a slot with `--load`, writing crash files and snapshots next to the
+/// image.
+fn run_image(image: &Path, options: &RunOptions) -> eyre::Result<()> {
+    let slots = Slots::new(image);
+    let is_snapshot = image
+        .extension()
+        .is_some_and(|ext| ext == crash::SNAPSHOT_EXTENSION);
+    let snapshot = match (&options.resume, &options.load) {
+        (Some(path), _) => Some(Path::new(path).to_path_buf()),
+        (None, Some(name)) => Some(slots.path(name).map_err(|err| eyre::eyre!(err))?),
+        (None, None) => is_snapshot.then(|| image.to_path_buf()),
+    };
+    let mut machine = match &snapshot {
+        Some(path) => MachineState::load(path).map_err(|err| eyre::eyre!(err))?,
+        None => MachineState::new(load_image(image)?),
+    };
+    // what is saved from a slot is recorded as its child
+    machine.parent = snapshot.and_then(|path| slots.name_of(&path));
+    let save_to = match &options.save_as {
+        Some(name) => Some(slots.path(name).map_err(|err| eyre::eyre!(err))?),
+        None => options
+            .save_on_exit
+            .then(|| image.with_extension(crash::SNAPSHOT_EXTENSION)),
+    };
+    if let Some(spec) = &options.autosave {
+        let path = slots
+            .path(slots::AUTOSAVE_SLOT)
+            .map_err(|err| eyre::eyre!(err))?;
+        machine.autosave = Some(Autosave::parse(spec, path).map_err(|err| eyre::eyre!(err))?);
+    }
+
+    let project = load_project(image, options)?;
+    let original = options.self_mod_report.then(|| machine.mem.clone());
+    machine.io = Io::Stdio(StdoutSink::new(options.flush));
+    machine.repeat_detector = Some(RepeatDetector::default());
+    machine.cycle_detector = options.cycle_detector.clone();
+    machine.stack.trace = options.trace_stack;
+    if let Some(count) = options.history {
+        machine.history = History::new(count);
+    }
+    if options.access_oracle {
+        machine.access_oracle = Some(AccessOracle::new());
+    }
+    if options.taint {
+        machine.taint = Some(Taint::new());
+    }
+    if options.provenance {
+        machine.provenance = Some(WriteProvenance::default());
+    }
+    if options.audit_stack.is_some() {
+        machine.stack_audit = Some(StackAudit::new());
+    }
+    if options.stack_canary {
+        machine.stack_canary = Some(StackCanary::new());
+    }
+    // a resumed snapshot is in dual-stack mode already if it was taken in it
+    if options.dual_stack && machine.return_stack.is_none() {
+        machine.return_stack = Some(Stack::new());
+    }
+    if options.trace_calls.is_some() || options.timeline.is_some() {
+        machine.call_trace = Some(CallTrace::new());
+    }
+    if options.timeline.is_some() {
+        machine.timeline = Some(Timeline::new());
+    }
+    if let Some(path) = &options.trace {
+        let mut trace = InstructionTrace::create(path)?;
+        trace.registers = project.registers.clone();
+        machine.instruction_trace = Some(trace);
+    }
+    if let Some(path) = &options.transcript {
+        machine.transcript = Some(Transcript::create(path, options.transcript_input)?);
+    }
+    if options.record.is_some() {
+        machine.recording = Some(Recording::new());
+    }
+    if options.extensions {
+        machine.extensions = Some(Extensions::new(true));
+    }
+    if options.self_profile {
+        machine.host_profile = Some(HostProfile::new());
+    }
+    machine.disabled = options.disabled.clone();
+    machine.breakpoints = options.breakpoints.clone();
+    machine.watchpoints = options.watchpoints.clone();
+    machine.console = options.console;
+    machine.timers = options.timers.clone();
+    if let Some(commands) = &options.script {
+        // the commands show up in the output the way typed ones do on the terminal
+        let mut script = Script::new(commands);
+        script.echo = true;
+        machine.script = Some(script);
+    }
+    if options.checkpoints {
+        machine.checkpoints = Some(Checkpoints::new());
+    }
+    // so errors and the debugger can show the calls leading to where the run stopped
+    machine.call_stack = Some(CallStack::new());
+    if !machine.breakpoints.is_empty() || machine.watchpoints.is_some() {
+        // so the debugger can step back from where the run stops
+        machine.journal = Some(Journal::default());
+    }
+    // to report what the program was asking for if piped input runs out
+    machine.prompt = Some(PromptDetector::new().0);
+    if options.sandbox {
+        machine.sandbox = Some(Sandbox::untrusted());
+    }
+    if options.natives || options.validate_natives {
+        let natives = Natives::from_project(&project, &machine.mem, options.validate_natives)
+            .map_err(|err| eyre::eyre!(err))?;
+        machine.natives = Some(natives);
+    }
+    if options.memoize {
+        machine.memo = Some(Memo::from_project(&project).map_err(|err| eyre::eyre!(err))?);
+    }
 
-    let mut machine = MachineState::new(data);
+    crash::install_panic_hook();
+    let result = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        run_pausable(&mut machine, &project, image)
+    })) {
+        Ok(result) => result,
+        Err(payload) => {
+            // instructions mutate the machine in place, so it still holds the state at the panic
+            let reason = crash::last_panic().unwrap_or_else(|| "unknown panic".to_string());
+            machine.io.flush();
+            write_crash_dump(&machine, reason, image);
+            std::panic::resume_unwind(payload);
+        }
+    };
+    // show everything the program printed before any report
+    machine.io.flush();
+    if let Err(err) = &result {
+        write_crash_dump(&machine, err.to_string(), image);
+    }
+    if let (Some(path), Some(audit)) = (&options.audit_stack, &machine.stack_audit) {
+        let log = audit
+            .log
+            .iter()
+            .map(|event| format!("{event}\n"))
+            .collect::<String>();
+        std::fs::write(path, log)?;
+    }
+    if let (Some(path), Some(trace)) = (&options.trace, &mut machine.instruction_trace) {
+        trace
+            .flush()
+            .map_err(|err| eyre::eyre!("Could not write the trace to `{path}`: {err}"))?;
+    }
+    if let (Some(path), Some(transcript)) = (&options.transcript, &mut machine.transcript) {
+        transcript
+            .flush()
+            .map_err(|err| eyre::eyre!("Could not write the transcript to `{path}`: {err}"))?;
+    }
+    if let (Some(path), Some(recording)) = (&options.record, &machine.recording) {
+        recording
+            .save(path)
+            .map_err(|err| eyre::eyre!("Could not write the recording to `{path}`: {err}"))?;
+        eprintln!("Recorded the session to `{path}`");
+    }
+    if let (Some(path), Some(trace)) = (&options.trace_calls, &machine.call_trace) {
+        let log = trace
+            .log
+            .iter()
+            .map(|event| format!("{event}\n"))
+            .collect::<String>();
+        std::fs::write(path, log)?;
+    }
+    if let (Some(path), Some(calls), Some(timeline)) =
+        (&options.timeline, &machine.call_trace, &machine.timeline)
+    {
+        std::fs::write(path, timeline::chrome_trace(&calls.log, timeline, &project))?;
+    }
+    if let Some(extensions) = &machine.extensions {
+        for (i, snapshot) in extensions.snapshots.iter().enumerate() {
+            let path = image.with_extension(format!("{}.{}", i + 1, crash::SNAPSHOT_EXTENSION));
+            snapshot.save(&path)?;
+            eprintln!("Wrote a snapshot to `{}`", path.display());
+        }
+    }
+    if let Some(checkpoints) = &machine.checkpoints {
+        for (name, snapshot) in &checkpoints.saved {
+            let path = slots.path(name).map_err(|err| eyre::eyre!(err))?;
+            snapshot.save(&path)?;
+            eprintln!("Wrote the checkpoint `{name}` to `{}`", path.display());
+        }
+    }
+    if let Some(path) = save_to.filter(|_| result.is_ok() && !machine.halted) {
+        machine.save(&path)?;
+        eprintln!("Saved the state to `{}`", path.display());
+    }
+    if let Some(autosave) = &machine.autosave {
+        if let Some(err) = &autosave.error {
+            eprintln!("The last autosave failed: {err}");
+        }
+    }
+    if let Some(original) = &original {
+        eprint!("\n{}", selfmod::report(original, &machine.mem));
+    }
+    if let Some(taint) = &machine.taint {
+        eprint!("\n{}", taint.report(&machine.mem, &project));
+    }
+    if let Some(profile) = &machine.host_profile {
+        eprint!("\n{}", profile.report());
+    }
+    if let Some(console) = &mut machine.console {
+        if console.changed {
+            eprint!("\nThe console:\n{}", console.render(&machine.mem));
+        }
+    }
+    if let Some(watchpoints) = &machine.watchpoints {
+        for hit in &watchpoints.log {
+            eprintln!("watch: {}", hit.describe(&project));
+        }
+    }
+    if let Some(notifier) = options.notify {
+        let body = match &result {
+            Ok(outcome) => outcome.to_string(),
+            Err(err) => err.to_string(),
+        };
+        notifier.notify(notify::title(&result), &body);
+    }
 
-    match machine.run() {
-        Ok(()) | Err(ExecutionError::Halt) => {
+    // a stop for the debugger carries on in its REPL
+    let debug = |machine, project| -> eyre::Result<()> {
+        let mut debugger = Debugger::new(machine, project);
+        debugger.slots = Some(slots);
+        debugger.notify = options.notify;
+        debugger.repl(std::io::stdin().lock(), std::io::stdout())?;
+        Ok(())
+    };
+    match result {
+        Ok(RunOutcome::Halted) => {
             println!("\n\n\nMachine exitted normally.");
             Ok(())
         }
-        Err(err) => Err(eyre::eyre!("{:?}", err)),
+        Ok(RunOutcome::Breakpoint(addr)) => {
+            eprintln!(
+                "\nHit a breakpoint at {} after {} steps",
+                project.describe(addr),
+                machine.steps
+            );
+            debug(machine, project)
+        }
+        Ok(RunOutcome::Watchpoint(location)) => {
+            eprintln!(
+                "\nStopped by a watchpoint on {} after {} steps",
+                project.format_operand(location),
+                machine.steps
+            );
+            debug(machine, project)
+        }
+        Ok(RunOutcome::Timer(steps)) => {
+            eprintln!(
+                "\nStopped by a timer at {} after {steps} steps",
+                project.describe(machine.cur)
+            );
+            debug(machine, project)
+        }
+        Ok(RunOutcome::NeedsInput) => {
+            let exhausted = ScriptExhausted::of(&machine);
+            let mut message = format!(
+                "The program is waiting for input, but stdin is closed (stopped at {} after {} steps)",
+                project.describe(exhausted.pos),
+                exhausted.steps
+            );
+            if !exhausted.prompt.trim().is_empty() {
+                message.push_str(&format!(", waiting on:\n{}", exhausted.prompt.trim_end()));
+            }
+            Err(eyre::eyre!(message))
+        }
+        Ok(RunOutcome::InfiniteLoop(addr)) => {
+            let positions = machine.history.iter().collect::<Vec<_>>();
+            eprint!(
+                "\nThe last instructions executed, which repeat forever:\n{}",
+                history::backtrace(&positions, BACKTRACE_LEN, &machine.mem, &project)
+            );
+            Err(eyre::eyre!(
+                "The program is stuck in an infinite loop at {} after {} steps",
+                project.describe(addr),
+                machine.steps
+            ))
+        }
+        Ok(outcome) => Err(eyre::eyre!(
+            "{outcome} (stopped at {} after {} steps)",
+            project.describe(machine.cur),
+            machine.steps
+        )),
+        Err(err) => {
+            let positions = machine.history.iter().collect::<Vec<_>>();
+            eprint!(
+                "\nThe last instructions executed, the failing one last:\n{}",
+                history::backtrace(&positions, BACKTRACE_LEN, &machine.mem, &project)
+            );
+            if let Some(stack) = &machine.call_stack {
+                let failed = positions.last().copied().unwrap_or(machine.cur);
+                eprint!(
+                    "\nThe calls leading to it, innermost first:\n{}",
+                    stack.backtrace(failed, machine.stack.as_slice(), &project)
+                );
+            }
+            Err(eyre::eyre!(
+                "{:?} (stopped at {})",
+                err,
+                project.describe(machine.cur)
+            ))
+        }
     }
 }
 
-#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
-pub enum ExecutionError {
-    #[error("The program halted.")]
-    Halt,
-    #[error("Invalid opcode `{0}` at index `{1}`")]
-    InvalidOpcode(u16, u16),
-    #[error("Tried to access invalid register `{0}` at index `{1}`")]
-    InvalidRegister(usize, u16),
-    #[error("Tried to pop from an empty stack at index `{0}`")]
-    EmptyStack(u16),
-    #[error("Tried to access invalid address `{0}` at index `{1}`")]
-    InvalidAddress(u16, u16),
-    #[error("Tried to read from stdin, which was empty, at index `{0}`")]
-    EmptyStdin(u16),
-    #[error("Encountered an error while trying to read from stdin at index `{1}`: {0}")]
-    ReadError(String, u16),
+/// Loads the project of `binary`, adding the labels of `--labels`.
+fn load_project(binary: impl AsRef<Path>, options: &RunOptions) -> eyre::Result<Project> {
+    let mut project = Project::load_for(binary)?;
+    if let Some(path) = &options.labels {
+        project.load_labels(path)?;
+    }
+    Ok(project)
 }
 
-pub type OpcodeResult = eyre::Result<(), ExecutionError>;
+/// Writes a crash file next to the binary, for inspecting with `synacor postmortem`.
+fn write_crash_dump(machine: &MachineState, reason: String, image: &Path) {
+    let path = image.with_extension(crash::CRASH_EXTENSION);
+    match CrashDump::of(machine, reason).save(&path) {
+        Ok(()) => eprintln!("Wrote the machine state to `{}`", path.display()),
+        Err(err) => eprintln!("Could not write `{}`: {err}", path.display()),
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{instruction, project::Project, MachineState, MAX_ADDR};
+
+/// A call to a memoized routine waiting for its return, so the result can be cached.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Pending {
+    /// The depth of the stack with the return address pushed.
+    depth: usize,
+    ret_to: u16,
+    key: (u16, Vec<u16>),
+}
+
+/// Caches the results of routines designated as pure, by the registers they read. A `call` to one
+/// with arguments seen before sets `r0` to the cached result instead of running the routine, which
+/// turns recursive routines such as the teleporter's confirmation into dynamic programming.
+///
+/// Only the result in `r0` is restored from the cache, so a routine is only pure if it reads
+/// nothing but the designated registers and callers use nothing but `r0` of what it leaves.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Memo {
+    /// The registers read by each memoized routine.
+    pub routines: BTreeMap<u16, Vec<usize>>,
+    pub cache: HashMap<(u16, Vec<u16>), u16>,
+    pending: Vec<Pending>,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Memo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Memoizes the routine at `addr`, which reads `registers`.
+    pub fn memoize(&mut self, addr: u16, registers: &[usize]) {
+        self.routines.insert(addr, registers.to_vec());
+    }
+
+    /// Memoizes the routines the project declares pure with `pure` directives.
+    pub fn from_project(project: &Project) -> Result<Self, String> {
+        let mut memo = Self::new();
+        for (&addr, registers) in &project.pure {
+            let registers = registers
+                .split_whitespace()
+                .map(|reg| match instruction::parse_operand(reg)? as usize {
+                    reg if reg >= MAX_ADDR => Ok(reg - MAX_ADDR),
+                    _ => Err(format!("`{reg}` is not a register")),
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            memo.memoize(addr, &registers);
+        }
+        Ok(memo)
+    }
+}
+
+impl MachineState {
+    /// Called by the `call` to `addr` before it runs. Returns whether the result was cached, in
+    /// which case it has been stored in `r0` and the routine should not run.
+    pub(crate) fn memo_call(&mut self, addr: u16) -> bool {
+        let depth = self.returns().len() + 1;
+        let Some(memo) = &mut self.memo else {
+            return false;
+        };
+        let Some(registers) = memo.routines.get(&addr) else {
+            return false;
+        };
+        let key = (
+            addr,
+            registers.iter().map(|&reg| self.registers[reg]).collect(),
+        );
+        if let Some(&result) = memo.cache.get(&key) {
+            memo.hits += 1;
+            self.registers[0] = result;
+            return true;
+        }
+        memo.misses += 1;
+        memo.pending.push(Pending {
+            depth,
+            ret_to: self.cur,
+            key,
+        });
+        false
+    }
+
+    /// Called by `ret` after it popped `ret_to`, caching the result of the memoized call it
+    /// returns from, if any.
+    pub(crate) fn memo_return(&mut self, ret_to: u16) {
+        let depth = self.returns().len() + 1;
+        let Some(memo) = &mut self.memo else {
+            return;
+        };
+        // calls the program unwound without returning from are forgotten
+        while memo
+            .pending
+            .last()
+            .is_some_and(|pending| pending.depth > depth)
+        {
+            memo.pending.pop();
+        }
+        if let Some(pending) = memo.pending.last() {
+            if pending.depth == depth && pending.ret_to == ret_to {
+                let pending = memo.pending.pop().unwrap();
+                memo.cache.insert(pending.key, self.registers[0]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{teleporter, testing::setup, RunOutcome};
+
+    // 0: set r0 2
+    // 3: set r1 3
+    // 6: set r7 1
+    // 9: call 100
+    // 11: halt
+    // the teleporter's confirmation routine, moved to 100
+    fn machine() -> MachineState {
+        let mut mem = vec![1, 32768, 2, 1, 32769, 3, 1, 32775, 1, 17, 100, 0];
+        mem.resize(100, 0);
+        mem.extend([
+            7, 32768, 108, // jt r0 108
+            9, 32768, 32769, 1,  // add r0 r1 1
+            18, // ret
+            7, 32769, 121, // jt r1 121
+            9, 32768, 32768, 32767, // add r0 r0 32767
+            1, 32769, 32775, // set r1 r7
+            17, 100, // call 100
+            18,  // ret
+            2, 32768, // push r0
+            9, 32769, 32769, 32767, // add r1 r1 32767
+            17, 100, // call 100
+            1, 32769, 32768, // set r1 r0
+            3, 32768, // pop r0
+            9, 32768, 32768, 32767, // add r0 r0 32767
+            17, 100, // call 100
+            18,  // ret
+        ]);
+        setup(mem)
+    }
+
+    #[test]
+    fn memoizes_routines() {
+        let mut plain = machine();
+        assert_eq!(plain.run(), Ok(RunOutcome::Halted));
+        let expected = teleporter::confirm(2, 3, 1);
+        assert_eq!(plain.registers[0], expected);
+
+        let mut machine = machine();
+        let project = Project::parse("pure 100 r0 r1 r7").unwrap();
+        machine.memo = Some(Memo::from_project(&project).unwrap());
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.registers[0], expected);
+        assert!(machine.steps < plain.steps);
+
+        let memo = machine.memo.unwrap();
+        assert!(memo.hits > 0);
+        assert_eq!(memo.cache[&(100, vec![2, 3, 1])], expected);
+        assert!(memo.pending.is_empty());
+
+        let project = Project::parse("pure 100 r0 100").unwrap();
+        assert!(Memo::from_project(&project).is_err());
+    }
+}
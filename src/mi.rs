@@ -0,0 +1,397 @@
+//! A machine interface to the debugger, for editor plugins and other programs to drive over pipes
+//! instead of a person at a terminal. Modelled on GDB/MI, much reduced:
+//! ```text
+//! 1-break-insert 6027 if r7 != 0
+//! 1^done,bkpt={addr="6027",cond="r7 != 0"}
+//! (mi)
+//! 2-exec-continue
+//! @"What do you do?\n"
+//! 2^done,reason="needs-input",cur="2",where="2 <read_line>",steps="1844"
+//! (mi)
+//! ```
+//! Every request is a line holding an optional numeric token, echoed on its response, then a
+//! command. Its response is the program's output since the last one, as `@` lines of escaped
+//! text, then a single `^done` or `^error` line, then the `(mi)` prompt.
+
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+
+use crate::{
+    condition,
+    debugger::Debugger,
+    project,
+    watch::{WatchAction, Watchpoints},
+    RunOutcome,
+};
+
+const PROMPT: &str = "(mi)";
+
+/// How many words `-data-read-memory` reads by default.
+const DEFAULT_READ_LEN: usize = 16;
+
+/// Quotes `s` as an MI string, with C escapes.
+pub fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if ch.is_control() => {
+                let _ = write!(out, "\\x{:02x}", ch as u32);
+            }
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// A list of quoted values.
+fn list(values: impl IntoIterator<Item = String>) -> String {
+    let values = values
+        .into_iter()
+        .map(|value| quote(&value))
+        .collect::<Vec<_>>();
+    format!("[{}]", values.join(","))
+}
+
+/// The reason `outcome` gives for a run stopping, and any details, as MI results.
+fn stop_reason(outcome: Option<RunOutcome>) -> String {
+    match outcome {
+        None => "reason=\"step-done\"".to_string(),
+        Some(RunOutcome::Halted) => "reason=\"halted\"".to_string(),
+        Some(RunOutcome::NeedsInput) => "reason=\"needs-input\"".to_string(),
+        Some(RunOutcome::Breakpoint(addr)) => format!("reason=\"breakpoint\",bkpt=\"{addr}\""),
+        Some(RunOutcome::Watchpoint(location)) => {
+            format!("reason=\"watchpoint\",location=\"{location}\"")
+        }
+        Some(RunOutcome::FuelExhausted) => "reason=\"fuel\"".to_string(),
+        Some(RunOutcome::TimedOut) => "reason=\"timed-out\"".to_string(),
+        Some(RunOutcome::ResourceLimit(resource)) => {
+            format!(
+                "reason=\"resource-limit\",resource={}",
+                quote(&resource.to_string())
+            )
+        }
+        Some(RunOutcome::Timer(steps)) => format!("reason=\"timer\",at=\"{steps}\""),
+        Some(RunOutcome::InfiniteLoop(addr)) => format!("reason=\"infinite-loop\",at=\"{addr}\""),
+    }
+}
+
+/// Serves MI requests with a `Debugger`.
+pub struct Mi {
+    pub debugger: Debugger,
+}
+
+impl Mi {
+    pub fn new(debugger: Debugger) -> Self {
+        Self { debugger }
+    }
+
+    /// Answers requests from `input` until it ends or `-gdb-exit` is requested.
+    pub fn serve(&mut self, input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+        writeln!(output, "{PROMPT}")?;
+        output.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let (response, exit) = self.respond(&line);
+            write!(output, "{response}")?;
+            if exit {
+                return output.flush();
+            }
+            writeln!(output, "{PROMPT}")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Answers a single request, returning the response without the prompt, and whether it asked
+    /// to exit.
+    pub fn respond(&mut self, line: &str) -> (String, bool) {
+        let line = line.trim();
+        let digits = line
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(line.len());
+        let (token, request) = line.split_at(digits);
+        let (command, args) = request
+            .split_once(char::is_whitespace)
+            .unwrap_or((request, ""));
+        let args = args.trim();
+
+        let result = match command {
+            "" => return (String::new(), false),
+            "-gdb-exit" => return (format!("{token}^exit\n"), true),
+            _ => self.execute(command, args),
+        };
+        let mut out = String::new();
+        // the program's output comes first, whatever the command did
+        let printed = self.debugger.machine.drain_output();
+        for line in String::from_utf8_lossy(&printed).split_inclusive('\n') {
+            let _ = writeln!(out, "@{}", quote(line));
+        }
+        match result {
+            Ok(results) if results.is_empty() => {
+                let _ = writeln!(out, "{token}^done");
+            }
+            Ok(results) => {
+                let _ = writeln!(out, "{token}^done,{results}");
+            }
+            Err(err) => {
+                let _ = writeln!(out, "{token}^error,msg={}", quote(&err));
+            }
+        }
+        (out, false)
+    }
+
+    /// Runs `command`, returning its results.
+    fn execute(&mut self, command: &str, args: &str) -> Result<String, String> {
+        let count = |args: &str| match args {
+            "" => Ok(1),
+            count => count
+                .parse::<u64>()
+                .map_err(|_| format!("invalid count `{count}`")),
+        };
+        match command {
+            "-exec-step" => {
+                let outcome = self.debugger.step(count(args)?)?;
+                Ok(self.stopped(outcome))
+            }
+            "-exec-continue" => {
+                let outcome = self.debugger.run().map_err(|err| err.to_string())?;
+                Ok(self.stopped(Some(outcome)))
+            }
+            "-exec-back" => {
+                let count = count(args)?;
+                let undone = (0..count)
+                    .take_while(|_| self.debugger.machine.step_back())
+                    .count();
+                if undone == 0 {
+                    return Err("there is nothing left to undo".to_string());
+                }
+                Ok(format!("undone=\"{undone}\",{}", self.position()))
+            }
+            "-exec-input" => {
+                self.debugger
+                    .machine
+                    .push_input(format!("{args}\n").as_bytes());
+                Ok(String::new())
+            }
+            "-break-insert" => {
+                let (addr, condition) = condition::parse_breakpoint(args)?;
+                let addr = self.debugger.address(addr)?;
+                let results = match &condition {
+                    Some(condition) => {
+                        format!("bkpt={{addr=\"{addr}\",cond={}}}", quote(&condition.text))
+                    }
+                    None => format!("bkpt={{addr=\"{addr}\"}}"),
+                };
+                self.debugger.machine.breakpoints.insert(addr, condition);
+                Ok(results)
+            }
+            "-break-delete" => {
+                let addr = self.debugger.address(args)?;
+                match self.debugger.machine.breakpoints.remove(&addr) {
+                    Some(_) => Ok(String::new()),
+                    None => Err(format!("no breakpoint at `{addr}`")),
+                }
+            }
+            "-break-list" => {
+                let breakpoints = self
+                    .debugger
+                    .machine
+                    .breakpoints
+                    .iter()
+                    .map(|(addr, condition)| match condition {
+                        Some(condition) => {
+                            format!("{{addr=\"{addr}\",cond={}}}", quote(&condition.text))
+                        }
+                        None => format!("{{addr=\"{addr}\"}}"),
+                    })
+                    .collect::<Vec<_>>();
+                Ok(format!("breakpoints=[{}]", breakpoints.join(",")))
+            }
+            "-watch-insert" => {
+                let location = self.debugger.location(args)?;
+                self.debugger
+                    .machine
+                    .watchpoints
+                    .get_or_insert_with(Watchpoints::new)
+                    .watched
+                    .insert(location, WatchAction::Pause);
+                Ok(format!("wpt={{location=\"{location}\"}}"))
+            }
+            "-watch-delete" => {
+                let location = self.debugger.location(args)?;
+                let watchpoints = self.debugger.machine.watchpoints.as_mut();
+                match watchpoints.and_then(|watchpoints| watchpoints.watched.remove(&location)) {
+                    Some(_) => Ok(String::new()),
+                    None => Err(format!("no watchpoint on `{args}`")),
+                }
+            }
+            "-data-list-registers" => {
+                let machine = &self.debugger.machine;
+                let registers = list(machine.registers.iter().map(u16::to_string));
+                Ok(format!("registers={registers},{}", self.position()))
+            }
+            "-data-read-memory" => {
+                let mut words = args.split_whitespace();
+                let addr = self
+                    .debugger
+                    .address(words.next().ok_or("expected an address")?)?;
+                let len = match words.next() {
+                    Some(len) => len.parse().map_err(|_| format!("invalid length `{len}`"))?,
+                    None => DEFAULT_READ_LEN,
+                };
+                let mem = &self.debugger.machine.mem;
+                let start = (addr as usize).min(mem.len());
+                let end = (start + len).min(mem.len());
+                let memory = list(mem[start..end].iter().map(u16::to_string));
+                Ok(format!("addr=\"{addr}\",memory={memory}"))
+            }
+            "-data-write-memory" => {
+                let (addr, value) = args
+                    .split_once(' ')
+                    .ok_or("expected an address and a value")?;
+                let addr = self.debugger.address(addr)?;
+                let value = project::parse_number(value.trim())?;
+                let machine = &mut self.debugger.machine;
+                let cur = machine.cur;
+                machine
+                    .write(addr, value, cur)
+                    .map_err(|err| err.to_string())?;
+                Ok(String::new())
+            }
+            "-stack-list" => {
+                let machine = &self.debugger.machine;
+                let stack = list(machine.stack.iter_top_down().map(|value| value.to_string()));
+                match &machine.return_stack {
+                    Some(returns) => {
+                        let returns = list(returns.iter_top_down().map(|value| value.to_string()));
+                        Ok(format!("stack={stack},returns={returns}"))
+                    }
+                    None => Ok(format!("stack={stack}")),
+                }
+            }
+            // every other command of the debugger, with its output as text
+            "-interpreter-exec" => {
+                let output = self.debugger.execute(args)?;
+                Ok(format!("output={}", quote(&output)))
+            }
+            other => Err(format!("unknown command `{other}`")),
+        }
+    }
+
+    /// Why a run stopped and where.
+    fn stopped(&self, outcome: Option<RunOutcome>) -> String {
+        format!("{},{}", stop_reason(outcome), self.position())
+    }
+
+    fn position(&self) -> String {
+        let machine = &self.debugger.machine;
+        format!(
+            "cur=\"{}\",where={},steps=\"{}\"",
+            machine.cur,
+            quote(&self.debugger.project.describe(machine.cur)),
+            machine.steps
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{project::Project, MachineState};
+
+    fn mi() -> Mi {
+        // 0: in r0
+        // 2: out r0
+        // 4: set r1 7
+        // 7: halt
+        let mut mem = vec![20, 32768, 19, 32768, 1, 32769, 7, 0];
+        mem.resize(100, 0);
+        let project = Project::parse("symbol 4 later").unwrap();
+        Mi::new(Debugger::new(MachineState::new(mem), project))
+    }
+
+    #[test]
+    fn runs_the_program() {
+        let mut mi = mi();
+        assert_eq!(
+            mi.respond("1-exec-step").0,
+            "1^done,reason=\"needs-input\",cur=\"0\",where=\"0\",steps=\"0\"\n"
+        );
+        assert_eq!(mi.respond("-exec-input hi").0, "^done\n");
+        assert_eq!(
+            mi.respond("2-break-insert later if r0 == 104").0,
+            "2^done,bkpt={addr=\"4\",cond=\"r0 == 104\"}\n"
+        );
+        assert_eq!(
+            mi.respond("3-exec-continue").0,
+            "@\"h\"\n3^done,reason=\"breakpoint\",bkpt=\"4\",cur=\"4\",where=\"4 <later>\",steps=\"2\"\n"
+        );
+        assert_eq!(
+            mi.respond("-break-list").0,
+            "^done,breakpoints=[{addr=\"4\",cond=\"r0 == 104\"}]\n"
+        );
+        assert_eq!(
+            mi.respond("-exec-continue").0,
+            "^done,reason=\"halted\",cur=\"8\",where=\"8 <later+4>\",steps=\"4\"\n"
+        );
+        assert!(mi
+            .respond("-data-list-registers")
+            .0
+            .starts_with("^done,registers=[\"104\",\"7\",\"0\""));
+    }
+
+    #[test]
+    fn inspects_and_edits_state() {
+        let mut mi = mi();
+        assert_eq!(
+            mi.respond("-data-read-memory 4 3").0,
+            "^done,addr=\"4\",memory=[\"1\",\"32769\",\"7\"]\n"
+        );
+        assert_eq!(mi.respond("-data-write-memory 6 9").0, "^done\n");
+        assert_eq!(mi.debugger.machine.mem[6], 9);
+        assert_eq!(mi.respond("-stack-list").0, "^done,stack=[]\n");
+        let (response, _) = mi.respond("-interpreter-exec disasm 4 1");
+        assert!(response.starts_with("^done,output=\""), "{response}");
+        assert!(response.contains("set r1 9"), "{response}");
+    }
+
+    #[test]
+    fn reports_errors() {
+        let mut mi = mi();
+        assert_eq!(
+            mi.respond("7-frobnicate").0,
+            "7^error,msg=\"unknown command `-frobnicate`\"\n"
+        );
+        assert_eq!(
+            mi.respond("-break-delete 2").0,
+            "^error,msg=\"no breakpoint at `2`\"\n"
+        );
+        assert_eq!(mi.respond("-gdb-exit"), ("^exit\n".to_string(), true));
+    }
+
+    #[test]
+    fn serves_pipes() {
+        let mut mi = mi();
+        let mut output = Vec::new();
+        mi.serve(
+            &b"-exec-input x\n-exec-continue\n-gdb-exit\n-exec-step\n"[..],
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "(mi)\n^done\n(mi)\n@\"x\"\n^done,reason=\"halted\",cur=\"8\",where=\"8 <later+4>\",steps=\"4\"\n(mi)\n^exit\n"
+        );
+    }
+
+    #[test]
+    fn quotes_strings() {
+        assert_eq!(quote("a \"b\"\n\\\u{1}"), "\"a \\\"b\\\"\\n\\\\\\x01\"");
+    }
+}
@@ -0,0 +1,166 @@
+use std::mem::discriminant;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{crash::CrashDump, ExecutionError, MachineState, RunLimits};
+
+/// How a run failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Failure {
+    Error(ExecutionError),
+    Panic(String),
+}
+
+impl Failure {
+    /// Whether two failures are the same bug: the same kind of error, or a panic with the same message.
+    /// Error positions are ignored, since they can move around as the input shrinks.
+    pub fn matches(&self, other: &Failure) -> bool {
+        match (self, other) {
+            (Failure::Error(a), Failure::Error(b)) => discriminant(a) == discriminant(b),
+            (Failure::Panic(a), Failure::Panic(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// The result of minimizing a failing run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reproducer {
+    pub input: Vec<u8>,
+    pub failure: Failure,
+    /// The number of the instruction that fails, counting from 1.
+    pub steps: u64,
+    /// The state right before the failing instruction, so a single step reproduces the failure.
+    pub dump: CrashDump,
+}
+
+/// Runs `program` on `input` for at most `fuel` instructions, returning the failure, if any,
+/// along with the machine in the state it failed in.
+pub fn run_once(program: &[u16], input: &[u8], fuel: u64) -> (Option<Failure>, MachineState) {
+    let mut machine = MachineState::new(program.to_vec());
+    machine.push_input(input);
+    let limits = RunLimits {
+        fuel: Some(fuel),
+        ..RunLimits::default()
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| machine.run_with(limits)));
+    let failure = match result {
+        Ok(Ok(_)) => None,
+        Ok(Err(err)) => Some(Failure::Error(err)),
+        Err(payload) => Some(Failure::Panic(panic_message(payload.as_ref()))),
+    };
+    (failure, machine)
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Shrinks `input` to a smaller input that still fails the same way, first by whole lines and then
+/// by single bytes. Returns `None` if the original input doesn't fail.
+pub fn minimize(program: &[u16], input: &[u8], fuel: u64) -> Option<Reproducer> {
+    // every attempt that fails would print a panic message otherwise
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let reproducer = minimize_quietly(program, input, fuel);
+    panic::set_hook(hook);
+    reproducer
+}
+
+fn minimize_quietly(program: &[u16], input: &[u8], fuel: u64) -> Option<Reproducer> {
+    let (original, _) = run_once(program, input, fuel);
+    let original = original?;
+    let fails = |candidate: &[u8]| {
+        run_once(program, candidate, fuel)
+            .0
+            .is_some_and(|failure| failure.matches(&original))
+    };
+
+    let lines = input
+        .split_inclusive(|&b| b == b'\n')
+        .map(<[u8]>::to_vec)
+        .collect::<Vec<_>>();
+    let lines = ddmin(lines, |lines| fails(&lines.concat()));
+    let bytes = ddmin(lines.concat(), |bytes| fails(bytes));
+
+    let (failure, machine) = run_once(program, &bytes, fuel);
+    let steps = machine.steps;
+    // execution is deterministic, so replaying one instruction less stops right before the failure
+    let (_, before) = run_once(program, &bytes, steps.saturating_sub(1));
+    Some(Reproducer {
+        input: bytes,
+        failure: failure.expect("minimized input no longer fails"),
+        steps,
+        dump: CrashDump::of(&before, format!("reproducer for a failure at step {steps}")),
+    })
+}
+
+/// Delta debugging: removes ever smaller chunks of `items` while `fails` keeps holding.
+fn ddmin<T: Clone>(mut items: Vec<T>, fails: impl Fn(&[T]) -> bool) -> Vec<T> {
+    let mut chunks = 2;
+    while items.len() >= 2 {
+        let size = items.len().div_ceil(chunks);
+        let mut reduced = false;
+
+        for start in (0..items.len()).step_by(size) {
+            let complement = items[..start]
+                .iter()
+                .chain(items[(start + size).min(items.len())..].iter())
+                .cloned()
+                .collect::<Vec<_>>();
+            if fails(&complement) {
+                items = complement;
+                chunks = (chunks - 1).max(2);
+                reduced = true;
+                break;
+            }
+        }
+
+        if !reduced {
+            if chunks >= items.len() {
+                break;
+            }
+            chunks = (chunks * 2).min(items.len());
+        }
+    }
+
+    if items.len() == 1 && fails(&[]) {
+        items.clear();
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ddmin_finds_minimal_subset() {
+        let items = (0..20).collect::<Vec<_>>();
+        let minimal = ddmin(items, |items| items.contains(&3) && items.contains(&17));
+        assert_eq!(minimal, vec![3, 17]);
+    }
+
+    #[test]
+    fn minimizes_input() {
+        // 0: in r0
+        // 2: eq r1 r0 '!'
+        // 6: jf r1 0
+        // 9: an invalid opcode
+        let mut program = vec![20, 32768, 4, 32769, 32768, b'!' as u16, 8, 32769, 0, 9999];
+        program.resize(crate::MAX_ADDR, 0);
+
+        let reproducer = minimize(&program, b"look\ngo north\nuse !\ninv\n", 10_000).unwrap();
+        assert_eq!(reproducer.input, b"!");
+        assert_eq!(
+            reproducer.failure,
+            Failure::Error(ExecutionError::InvalidOpcode(9999, 9))
+        );
+        assert_eq!(reproducer.steps, 4);
+        assert_eq!(reproducer.dump.cur, 9);
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    instruction, project::Project, teleporter, verify::fnv1a, ExecutionError, MachineState,
+    OpcodeResult, REGISTER_COUNT,
+};
+
+/// How many words a routine may span to be fingerprinted.
+pub const MAX_ROUTINE: usize = 1024;
+
+/// A native reimplementation of a guest routine. Like the routines of the challenge it takes its
+/// arguments in the registers and returns its result in `r0`.
+pub type NativeFn = fn(&[u16; REGISTER_COUNT]) -> u16;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Native {
+    pub name: &'static str,
+    pub run: NativeFn,
+}
+
+impl PartialEq for Native {
+    /// Natives are told apart by name, since function pointers can't be compared reliably.
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Native {}
+
+impl Native {
+    /// Every native implementation available, by name.
+    pub const KNOWN: [Native; 1] = [Native {
+        name: "confirm",
+        run: |registers| teleporter::confirm(registers[0], registers[1], registers[7]),
+    }];
+
+    pub fn by_name(name: &str) -> Option<Native> {
+        Self::KNOWN.into_iter().find(|native| native.name == name)
+    }
+}
+
+/// Identifies the routine at `addr` by a hash of its code, up to and including its first `ret`.
+/// Returns `None` if no `ret` follows within `MAX_ROUTINE` words of valid instructions.
+pub fn fingerprint(mem: &[u16], addr: u16) -> Option<u64> {
+    let ret = instruction::by_mnemonic("ret").unwrap().code;
+    let start = addr as usize;
+    let mut end = start;
+    while end - start < MAX_ROUTINE {
+        let op = *mem.get(end)?;
+        end += 1 + instruction::info(op)?.arity;
+        if op == ret {
+            return Some(fnv1a(mem.get(start..end)?));
+        }
+    }
+    None
+}
+
+/// Native implementations registered by the fingerprint of the routine they replace, which `call`
+/// runs instead of the guest code. Since the fingerprint is taken when the routine is called, a
+/// routine modified since it was registered is interpreted again.
+///
+/// With `validate` set, every native call is checked against a copy of the machine interpreting the
+/// routine, and a different result fails with `ExecutionError::NativeMismatch`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Natives {
+    pub registry: BTreeMap<u64, Native>,
+    pub validate: bool,
+    /// How many calls ran natively.
+    pub calls: u64,
+}
+
+impl Natives {
+    pub fn new(validate: bool) -> Self {
+        Self {
+            validate,
+            ..Self::default()
+        }
+    }
+
+    /// Registers the natives the project binds to routines with `native` directives, using the
+    /// routines in `mem` as they are now.
+    pub fn from_project(project: &Project, mem: &[u16], validate: bool) -> Result<Self, String> {
+        let mut natives = Self::new(validate);
+        for (&addr, name) in &project.natives {
+            let native = Native::by_name(name).ok_or_else(|| format!("no native `{name}`"))?;
+            let fingerprint = fingerprint(mem, addr)
+                .ok_or_else(|| format!("no routine to replace with `{name}` at `{addr}`"))?;
+            natives.registry.insert(fingerprint, native);
+        }
+        Ok(natives)
+    }
+
+    /// The native replacing the routine at `addr`, if any.
+    pub fn resolve(&self, mem: &[u16], addr: u16) -> Option<Native> {
+        if self.registry.is_empty() {
+            return None;
+        }
+        self.registry.get(&fingerprint(mem, addr)?).copied()
+    }
+}
+
+impl MachineState {
+    /// Runs `native` in place of the routine the `call` at `pos` was about to enter.
+    pub(crate) fn call_native(&mut self, native: Native, pos: u16) -> OpcodeResult {
+        let result = (native.run)(&self.registers);
+        if self
+            .natives
+            .as_ref()
+            .is_some_and(|natives| natives.validate)
+            && self.interpret_call(pos)? != Some(result)
+        {
+            return Err(ExecutionError::NativeMismatch(native.name, pos));
+        }
+        self.registers[0] = result;
+        if let Some(natives) = &mut self.natives {
+            natives.calls += 1;
+        }
+        Ok(())
+    }
+
+    /// Interprets the `call` at `pos` on a bare copy of the machine, returning `r0` once it returns,
+    /// or `None` if it halts or stops for input first. The copy has none of the hooks, so traces,
+    /// transcripts, journals and the like don't see the routine run a second time.
+    fn interpret_call(&mut self, pos: u16) -> Result<Option<u16>, ExecutionError> {
+        let mut shadow = self.bare();
+        shadow.cur = pos;
+        let depth = shadow.returns().len();
+        loop {
+            shadow.exec_next()?;
+            if shadow.returns().len() == depth && shadow.cur == self.cur {
+                return Ok(Some(shadow.registers[0]));
+            }
+            if shadow.halted || shadow.stop.is_some() {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{setup, SharedSink},
+        trace::InstructionTrace,
+        RunOutcome,
+    };
+
+    // 0: set r0 1
+    // 3: set r1 2
+    // 6: set r7 1
+    // 9: call 12
+    // 11: halt
+    // the confirmation routine with `a = 1` is `b + r7 + 1`:
+    // 12: add r0 r7 r1
+    // 16: add r0 r0 1
+    // 20: ret
+    fn program() -> Vec<u16> {
+        vec![
+            1, 32768, 1, 1, 32769, 2, 1, 32775, 1, 17, 12, 0, 9, 32768, 32775, 32769, 9, 32768,
+            32768, 1, 18,
+        ]
+    }
+
+    fn natives(validate: bool, mem: &[u16]) -> Natives {
+        let project = Project::parse("native 12 confirm").unwrap();
+        Natives::from_project(&project, mem, validate).unwrap()
+    }
+
+    #[test]
+    fn fingerprints_routines() {
+        let mem = program();
+        assert!(fingerprint(&mem, 12).is_some());
+        assert_ne!(fingerprint(&mem, 12), fingerprint(&mem, 16));
+        assert_eq!(fingerprint(&mem[..20], 12), None);
+    }
+
+    #[test]
+    fn replaces_routines() {
+        let mut machine = setup(program());
+        machine.natives = Some(natives(false, &machine.mem));
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.registers[0], 4);
+        assert_eq!(machine.natives.unwrap().calls, 1);
+        // the routine never ran
+        assert_eq!(machine.steps, 5);
+
+        let mut machine = setup(program());
+        machine.natives = Some(natives(true, &machine.mem));
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.registers[0], 4);
+
+        // a routine that disagrees with the native fails validation
+        let mut program = program();
+        program[19] = 2;
+        let mut machine = setup(program);
+        machine.natives = Some(natives(true, &machine.mem));
+        assert_eq!(
+            machine.run(),
+            Err(ExecutionError::NativeMismatch("confirm", 9))
+        );
+    }
+
+    #[test]
+    fn validates_without_hooks() {
+        let mut machine = setup(program());
+        machine.natives = Some(natives(true, &machine.mem));
+        let sink = SharedSink::default();
+        machine.instruction_trace = Some(InstructionTrace::new(sink.clone()));
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.instruction_trace.unwrap().flush(), Ok(()));
+
+        // only the instructions outside the routine are traced
+        let trace = String::from_utf8(sink.0.lock().unwrap().clone()).unwrap();
+        let traced = trace
+            .lines()
+            .map(|line| line.split(' ').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(traced, ["0", "3", "6", "9", "11"]);
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::Write;
+use std::process::Command;
+
+use crate::{RunOutcome, RunResult};
+
+/// How the user is told that a run stopped, so they can look away during long runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Notifier {
+    /// Rings the terminal bell.
+    Bell,
+    /// Sends a desktop notification through `notify-send`, ringing the bell if that fails.
+    Desktop,
+}
+
+impl Notifier {
+    pub fn notify(self, summary: &str, body: &str) {
+        if self == Notifier::Desktop {
+            let sent = Command::new("notify-send")
+                .args(["--app-name=synacor", summary, body])
+                .status()
+                .map(|status| status.success())
+                .unwrap_or(false);
+            if sent {
+                return;
+            }
+        }
+
+        // the bell goes to stderr so it doesn't end up in captured game output
+        let mut stderr = std::io::stderr();
+        let _ = stderr.write_all(b"\x07");
+        let _ = stderr.flush();
+    }
+}
+
+/// The summary of the notification for a run that ended with `result`.
+pub fn title(result: &RunResult) -> &'static str {
+    match result {
+        Ok(RunOutcome::Halted) => "synacor: the program halted",
+        Ok(RunOutcome::NeedsInput) => "synacor: the program is waiting for input",
+        Ok(RunOutcome::Breakpoint(_)) => "synacor: hit a breakpoint",
+        Ok(RunOutcome::Watchpoint(_)) => "synacor: stopped by a watchpoint",
+        Ok(RunOutcome::Timer(_)) => "synacor: stopped by a timer",
+        Ok(RunOutcome::InfiniteLoop(_)) => "synacor: the program is stuck in a loop",
+        Ok(RunOutcome::FuelExhausted | RunOutcome::TimedOut | RunOutcome::ResourceLimit(_)) => {
+            "synacor: the run hit a limit"
+        }
+        Err(_) => "synacor: the run failed",
+    }
+}
+
+impl std::str::FromStr for Notifier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bell" => Ok(Notifier::Bell),
+            "desktop" => Ok(Notifier::Desktop),
+            other => Err(format!(
+                "unknown notifier `{other}`, expected `bell` or `desktop`"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExecutionError;
+
+    #[test]
+    fn titles_outcomes() {
+        assert_eq!(
+            title(&Ok(RunOutcome::Breakpoint(5))),
+            "synacor: hit a breakpoint"
+        );
+        assert_eq!(
+            title(&Ok(RunOutcome::Halted)),
+            "synacor: the program halted"
+        );
+        assert_eq!(
+            title(&Err(ExecutionError::EmptyStack(0))),
+            "synacor: the run failed"
+        );
+    }
+}
@@ -1,320 +1,260 @@
-use crate::{ExecutionError, MachineState, OpcodeResult, MAX_ADDR};
-
+use crate::{
+    audit::StackOp, timeline::TimelineEvent, ExecutionError, MachineState, OpcodeResult,
+    RunOutcome, MAX_ADDR,
+};
+
+/// An opcode handler, called with the position of the instruction.
+pub type Handler = fn(&mut MachineState, u16) -> OpcodeResult;
+
+/// The handler of every opcode, indexed by its numeric value, followed by the extension opcodes.
+pub const HANDLERS: [Handler; 26] = [
+    |machine, _| machine.halt(),
+    MachineState::set,
+    MachineState::push,
+    MachineState::pop,
+    MachineState::eq,
+    MachineState::gt,
+    MachineState::jmp,
+    MachineState::jmp_true,
+    MachineState::jmp_false,
+    MachineState::add,
+    MachineState::mult,
+    MachineState::modulo,
+    MachineState::and,
+    MachineState::or,
+    MachineState::not,
+    MachineState::rmem,
+    MachineState::wmem,
+    MachineState::call,
+    MachineState::ret,
+    MachineState::char_out,
+    MachineState::char_in,
+    |machine, _| machine.no_op(),
+    |machine, pos| machine.extension(22, pos),
+    |machine, pos| machine.extension(23, pos),
+    |machine, pos| machine.extension(24, pos),
+    |machine, pos| machine.extension(25, pos),
+];
+
+// `exec_next` has already moved `cur` past the instruction when these run; `pos` is where it starts.
 impl MachineState {
     /// Opcode: 0
     /// Stop execution and terminate the program.
     pub fn halt(&mut self) -> OpcodeResult {
-        Err(ExecutionError::Halt)
+        self.halted = true;
+        Ok(())
     }
 
     /// Opcode: 1 a b
     /// set register <a> to the value of <b>
-    pub fn set(&mut self) -> OpcodeResult {
-        let a = self.mem[self.cur as usize] as usize;
-        let b = match self.mem[self.cur as usize + 1] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 1)?,
-        };
-
-        self.cur += 2;
-        self.set_register(a, b, self.cur - 2)
+    pub fn set(&mut self, pos: u16) -> OpcodeResult {
+        let b = self.value(pos, 1)?;
+        self.set_register(self.operand(pos, 0)? as usize, b, pos + 1)
     }
 
     /// Opcode: 2 a
     /// push <a> onto the stack
-    pub fn push(&mut self) -> OpcodeResult {
-        let a = match self.mem[self.cur as usize] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur)?,
-        };
-        self.stack.push_back(a);
-        self.cur += 1;
-        Ok(())
+    pub fn push(&mut self, pos: u16) -> OpcodeResult {
+        let a = self.value(pos, 0)?;
+        self.stack.push(a);
+        let operand = self.operand(pos, 0)?;
+        if let Some(calls) = &mut self.call_stack {
+            calls.push(operand, self.stack.len());
+        }
+        self.audit_stack(StackOp::Push, pos, a);
+        self.check_canary(StackOp::Push, pos, a)
     }
 
     /// Opcode: 3 a
     /// remove the top element from the stack and write it into <a>; empty stack = error
-    pub fn pop(&mut self) -> OpcodeResult {
-        let top = self
-            .stack
-            .pop_back()
-            .ok_or(ExecutionError::EmptyStack(self.cur - 1))?;
-
-        self.cur += 1;
-        self.write(self.mem[self.cur as usize - 1], top, self.cur - 1)
+    pub fn pop(&mut self, pos: u16) -> OpcodeResult {
+        let top = self.stack.pop().ok_or(ExecutionError::EmptyStack(pos))?;
+        self.audit_stack(StackOp::Pop, pos, top);
+        self.check_canary(StackOp::Pop, pos, top)?;
+        self.store(pos, top)
     }
 
     /// Opcode: 4 a b c
     /// set <a> to 1 if <b> is equal to <c>; set it to 0 otherwise
-    pub fn eq(&mut self) -> OpcodeResult {
-        let b = match self.mem[self.cur as usize + 1] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 1)?,
-        };
-        let c = match self.mem[self.cur as usize + 2] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 2)?,
-        };
-
-        self.cur += 3;
-        self.write(
-            self.mem[self.cur as usize - 3],
-            (b == c) as u16,
-            self.cur - 3,
-        )
+    pub fn eq(&mut self, pos: u16) -> OpcodeResult {
+        let b = self.value(pos, 1)?;
+        let c = self.value(pos, 2)?;
+        self.store(pos, (b == c) as u16)
     }
 
     /// Opcode: 5 a b c
     /// set <a> to 1 if <b> is greater than <c>; set it to 0 otherwise
-    pub fn gt(&mut self) -> OpcodeResult {
-        let b = match self.mem[self.cur as usize + 1] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 1)?,
-        };
-        let c = match self.mem[self.cur as usize + 2] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 2)?,
-        };
-
-        self.cur += 3;
-        self.write(
-            self.mem[self.cur as usize - 3],
-            (b > c) as u16,
-            self.cur - 3,
-        )
+    pub fn gt(&mut self, pos: u16) -> OpcodeResult {
+        let b = self.value(pos, 1)?;
+        let c = self.value(pos, 2)?;
+        self.store(pos, (b > c) as u16)
     }
 
     /// Opcode: 6 a
     /// jump to <a>
-    pub fn jmp(&mut self) -> OpcodeResult {
-        let a = match self.mem[self.cur as usize] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur)?,
-        };
-
-        self.cur = if a < MAX_ADDR as u16 {
-            a
-        } else {
-            return Err(ExecutionError::InvalidAddress(a, self.cur));
-        };
-
-        Ok(())
+    pub fn jmp(&mut self, pos: u16) -> OpcodeResult {
+        let a = self.value(pos, 0)?;
+        self.jump_to(a, pos + 1)
     }
 
     /// Opcode: 7 a b
     /// if <a> is nonzero, jump to <b>
-    pub fn jmp_true(&mut self) -> OpcodeResult {
-        let a = match self.mem[self.cur as usize] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur)?,
-        };
-        let b = match self.mem[self.cur as usize + 1] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 1)?,
-        };
-
-        self.cur = if b >= MAX_ADDR as u16 {
-            return Err(ExecutionError::InvalidAddress(b, self.cur + 1));
-        } else if a != 0 {
-            b
-        } else {
-            self.cur + 2
-        };
-
+    pub fn jmp_true(&mut self, pos: u16) -> OpcodeResult {
+        let a = self.value(pos, 0)?;
+        let b = self.value(pos, 1)?;
+        if a != 0 {
+            self.jump_to(b, pos + 2)?;
+        }
         Ok(())
     }
 
     /// Opcode: 8 a b
     /// if <a> is zero, jump to <b>
-    pub fn jmp_false(&mut self) -> OpcodeResult {
-        let a = match self.mem[self.cur as usize] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur)?,
-        };
-        let b = match self.mem[self.cur as usize + 1] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 1)?,
-        };
-
-        self.cur = if b >= MAX_ADDR as u16 {
-            return Err(ExecutionError::InvalidAddress(b, self.cur + 1));
-        } else if a == 0 {
-            b
-        } else {
-            self.cur + 2
-        };
-
+    pub fn jmp_false(&mut self, pos: u16) -> OpcodeResult {
+        let a = self.value(pos, 0)?;
+        let b = self.value(pos, 1)?;
+        if a == 0 {
+            self.jump_to(b, pos + 2)?;
+        }
         Ok(())
     }
 
     /// Opcode: 9 a b c
     /// assign into <a> the sum of <b> and <c> (modulo 32768)
-    pub fn add(&mut self) -> OpcodeResult {
-        let a = self.mem[self.cur as usize];
+    pub fn add(&mut self, pos: u16) -> OpcodeResult {
         // these are usize to avoid overflow
-        let b = match self.mem[self.cur as usize + 1] as usize {
-            val if val < MAX_ADDR => val,
-            val => self.get_register(val, self.cur + 1)? as usize,
-        };
-
-        let c = match self.mem[self.cur as usize + 2] as usize {
-            val if val < MAX_ADDR => val,
-            val => self.get_register(val, self.cur + 2)? as usize,
-        };
-
-        self.cur += 3;
-        self.write(a, ((b + c) % MAX_ADDR) as u16, self.cur - 3)
+        let b = self.value(pos, 1)? as usize;
+        let c = self.value(pos, 2)? as usize;
+        self.store(pos, ((b + c) % MAX_ADDR) as u16)
     }
 
     /// Opcode: 10 a b c
     /// store into <a> the product of <b> and <c> (modulo 32768)
-    pub fn mult(&mut self) -> OpcodeResult {
-        let a = self.mem[self.cur as usize];
+    pub fn mult(&mut self, pos: u16) -> OpcodeResult {
         // these are usize to avoid overflow
-        let b = match self.mem[self.cur as usize + 1] as usize {
-            val if val < MAX_ADDR => val,
-            val => self.get_register(val, self.cur + 1)? as usize,
-        };
-
-        let c = match self.mem[self.cur as usize + 2] as usize {
-            val if val < MAX_ADDR => val,
-            val => self.get_register(val, self.cur + 2)? as usize,
-        };
-
-        self.cur += 3;
-        self.write(a, ((b * c) % MAX_ADDR) as u16, self.cur - 3)
+        let b = self.value(pos, 1)? as usize;
+        let c = self.value(pos, 2)? as usize;
+        self.store(pos, ((b * c) % MAX_ADDR) as u16)
     }
 
     /// Opcode: 11 a b c
     /// store into <a> the remainder of <b> divided by <c>
-    pub fn modulo(&mut self) -> OpcodeResult {
-        let a = self.mem[self.cur as usize];
-        let b = match self.mem[self.cur as usize + 1] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 1)?,
-        };
-        let c = match self.mem[self.cur as usize + 2] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 2)?,
-        };
-
-        self.cur += 3;
-        self.write(a, b % c, self.cur - 3)
+    pub fn modulo(&mut self, pos: u16) -> OpcodeResult {
+        let b = self.value(pos, 1)?;
+        let c = self.value(pos, 2)?;
+        if c == 0 {
+            return Err(ExecutionError::DivisionByZero(pos));
+        }
+        self.store(pos, b % c)
     }
 
     /// Opcode: 12 a b c
     /// stores into <a> the bitwise and of <b> and <c>
-    pub fn and(&mut self) -> OpcodeResult {
-        let a = self.mem[self.cur as usize];
-        let b = match self.mem[self.cur as usize + 1] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 1)?,
-        };
-        let c = match self.mem[self.cur as usize + 2] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 2)?,
-        };
-
-        self.cur += 3;
-        self.write(a, b & c, self.cur - 3)
+    pub fn and(&mut self, pos: u16) -> OpcodeResult {
+        let b = self.value(pos, 1)?;
+        let c = self.value(pos, 2)?;
+        self.store(pos, b & c)
     }
 
     /// Opcode: 13 a b c
     /// stores into <a> the bitwise or of <b> and <c>
-    pub fn or(&mut self) -> OpcodeResult {
-        let a = self.mem[self.cur as usize];
-        let b = match self.mem[self.cur as usize + 1] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 1)?,
-        };
-        let c = match self.mem[self.cur as usize + 2] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 2)?,
-        };
-
-        self.cur += 3;
-        self.write(a, b | c, self.cur - 3)
+    pub fn or(&mut self, pos: u16) -> OpcodeResult {
+        let b = self.value(pos, 1)?;
+        let c = self.value(pos, 2)?;
+        self.store(pos, b | c)
     }
 
     /// Opcode: 14 a b
     /// stores 15-bit bitwise inverse of <b> in <a>
-    pub fn not(&mut self) -> OpcodeResult {
-        let a = self.mem[self.cur as usize];
-        let b = match self.mem[self.cur as usize + 1] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 1)?,
-        };
-
-        self.cur += 2;
-        self.write(a, !b, self.cur - 2)
+    pub fn not(&mut self, pos: u16) -> OpcodeResult {
+        let b = self.value(pos, 1)?;
+        self.store(pos, !b)
     }
 
     /// Opcode: 15 a b
     /// read memory at address <b> and write it to <a>
-    pub fn rmem(&mut self) -> OpcodeResult {
-        let b = match self.mem[self.cur as usize + 1] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 1)?,
-        };
-
-        self.mem[self.cur as usize] = self.read(b, self.cur + 1)?;
-        self.cur += 2;
-        Ok(())
+    pub fn rmem(&mut self, pos: u16) -> OpcodeResult {
+        let b = self.value(pos, 1)?;
+        let val = self.read(b, pos + 2)?;
+        self.store(pos, val)
     }
 
     /// Opcode: 16 a b
     /// write the value from <b> into memory at address <a>
-    pub fn wmem(&mut self) -> OpcodeResult {
-        let a = self.mem[self.cur as usize];
-        let b = match self.mem[self.cur as usize + 1] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur + 1)?,
-        };
-
-        self.cur += 2;
-        self.write(a, dbg!(self.read(b, self.cur - 1)?), self.cur - 2)
+    pub fn wmem(&mut self, pos: u16) -> OpcodeResult {
+        let a = self.value(pos, 0)?;
+        let b = self.value(pos, 1)?;
+        self.write(a, b, pos + 1)
     }
 
     /// Opcode: 17 a
     /// write the address of the next instruction to the stack and jump to <a>
-    pub fn call(&mut self) -> OpcodeResult {
-        let next_instr = self.cur + 1;
-        self.stack.push_back(next_instr);
-
-        let a = match self.mem[self.cur as usize] {
-            val if val < MAX_ADDR as u16 => val,
-            val => return Err(ExecutionError::InvalidAddress(val, self.cur)),
-        };
-        // jump to a
-        self.cur = a;
-
+    pub fn call(&mut self, pos: u16) -> OpcodeResult {
+        let a = self.value(pos, 0)?;
+        if let Some(native) = self.natives.as_ref().and_then(|n| n.resolve(&self.mem, a)) {
+            return self.call_native(native, pos);
+        }
+        if self.memo_call(a) {
+            return Ok(());
+        }
+        let next_instr = self.cur;
+        let base = self.stack.len();
+        self.returns_mut().push(next_instr);
+        self.audit_stack(StackOp::Call, pos, next_instr);
+        self.check_canary(StackOp::Call, pos, next_instr)?;
+        self.jump_to(a, pos + 1)?;
+        if let Some(trace) = &mut self.call_trace {
+            trace.call(pos, a, &self.registers, self.steps);
+        }
+        let start = self.stack.len();
+        if let Some(stack) = &mut self.call_stack {
+            stack.call(pos, a, base, start);
+        }
         Ok(())
     }
 
     /// Opcode: 18
     /// remove the top element from the stack and jump to it; empty stack = halt
-    pub fn ret(&mut self) -> OpcodeResult {
-        let ret_to = self.stack.pop_back().ok_or(ExecutionError::Halt)?;
-        self.cur = if ret_to < MAX_ADDR as u16 {
-            ret_to
-        } else {
-            return Err(ExecutionError::InvalidAddress(ret_to, self.cur));
+    pub fn ret(&mut self, pos: u16) -> OpcodeResult {
+        let Some(ret_to) = self.returns_mut().pop() else {
+            self.halted = true;
+            return Ok(());
         };
-
+        self.audit_stack(StackOp::Ret, pos, ret_to);
+        self.check_canary(StackOp::Ret, pos, ret_to)?;
+        self.jump_to(ret_to, pos)?;
+        self.memo_return(ret_to);
+        if let Some(trace) = &mut self.call_trace {
+            trace.ret(pos, ret_to, self.registers[0], self.steps);
+        }
+        if let Some(stack) = &mut self.call_stack {
+            stack.ret(ret_to);
+        }
         Ok(())
     }
 
     /// Opcode: 19 a
     /// Write the character represented by ascii code <a> to the terminal.
-    pub fn char_out(&mut self) -> OpcodeResult {
-        let ch = match self.mem[self.cur as usize] {
-            val if val < MAX_ADDR as u16 => val,
-            val => self.get_register(val as usize, self.cur)?,
-        } as u8 as char;
-
-        print!("{ch}");
-        // skip past the arg
-        self.cur += 1;
+    pub fn char_out(&mut self, pos: u16) -> OpcodeResult {
+        let ch = self.value(pos, 0)? as u8;
+
+        self.io.write_byte(ch);
+        self.transcribe_output(ch);
+        if let Some(recording) = &mut self.recording {
+            recording.output.push(ch);
+        }
+        if let Some(sandbox) = &mut self.sandbox {
+            sandbox.output += 1;
+        }
+        if let Some(timeline) = &mut self.timeline {
+            timeline.record(self.steps, TimelineEvent::Output(ch));
+        }
+        if let Some(broadcaster) = &mut self.broadcaster {
+            broadcaster.record_output(ch);
+        }
+        if let Some(prompt) = &mut self.prompt {
+            prompt.record_output(ch);
+        }
         Ok(())
     }
 
@@ -322,17 +262,45 @@ impl MachineState {
     /// read a character from the terminal and write its ascii code to <a>
     /// it can be assumed that once input starts, it will continue until a newline is encountered
     /// this means that you can safely read whole lines from the keyboard and trust that they will be fully read
-    pub fn char_in(&mut self) -> OpcodeResult {
-        use std::io::{stdin, Read};
+    pub fn char_in(&mut self, pos: u16) -> OpcodeResult {
+        if let Some(prompt) = &mut self.prompt {
+            if !self.io.has_input() {
+                prompt.waiting(pos, self.steps - 1);
+            }
+        }
+        self.autosave_at_prompt(pos);
+        let read = self.read_input(pos)?;
+        let Some(read) = read else {
+            // stay on this instruction so it is retried once input is available
+            self.cur = pos;
+            self.steps -= 1;
+            self.stop = Some(RunOutcome::NeedsInput);
+            return Ok(());
+        };
+        if let Some(journal) = &mut self.journal {
+            journal.record_input(read);
+        }
+        self.autosave_read(read);
+        self.transcribe_input(read);
+        if let Some(recording) = &mut self.recording {
+            recording.inputs.push((self.steps, read));
+        }
 
-        let read = stdin()
-            .bytes()
-            .next()
-            .ok_or(ExecutionError::EmptyStdin(self.cur - 1))?
-            .map_err(|err| ExecutionError::ReadError(format!("{:?}", err), self.cur - 1))?;
+        // the same state may come up again with different input
+        if let Some(detector) = &mut self.repeat_detector {
+            detector.reset();
+        }
+        if let Some(detector) = &mut self.cycle_detector {
+            detector.reset();
+        }
 
-        self.cur += 1;
-        self.write(self.mem[self.cur as usize], read as u16, self.cur - 1)
+        if let Some(timeline) = &mut self.timeline {
+            timeline.record(self.steps, TimelineEvent::Input(read));
+        }
+        if let Some(prompt) = &mut self.prompt {
+            prompt.record_input();
+        }
+        self.store(pos, read as u16)
     }
 
     /// Opcode: 21
@@ -340,20 +308,29 @@ impl MachineState {
     pub fn no_op(&mut self) -> OpcodeResult {
         Ok(())
     }
+
+    /// Continues execution at `addr`, which was read from `pos`.
+    fn jump_to(&mut self, addr: u16, pos: u16) -> OpcodeResult {
+        if addr >= MAX_ADDR as u16 {
+            return Err(ExecutionError::InvalidAddress(addr, pos));
+        }
+        self.cur = addr;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::MAX_ADDR;
+    use crate::testing::setup;
+    use crate::{extensions, instruction, RunLimits, MAX_ADDR};
 
-    fn setup(overrides: Vec<u16>) -> MachineState {
-        let mut mem = Vec::from([0; MAX_ADDR]);
-        for (i, v) in overrides.into_iter().enumerate() {
-            mem[i] = v;
-        }
-
-        MachineState::new(mem)
+    #[test]
+    fn handler_table_covers_opcodes() {
+        assert_eq!(
+            HANDLERS.len(),
+            instruction::OPCODES.len() + extensions::OPCODES.len()
+        );
     }
 
     #[test]
@@ -377,7 +354,9 @@ mod tests {
     #[test]
     fn halt() {
         let mut machine = setup(vec![]);
-        assert_eq!(machine.exec_next(), Err(ExecutionError::Halt))
+        assert_eq!(machine.exec_next(), Ok(()));
+        assert!(machine.halted);
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
     }
 
     #[test]
@@ -393,13 +372,13 @@ mod tests {
         let mut machine = setup(vec![2, 10]);
         assert_eq!(machine.exec_next(), Ok(()));
         assert_eq!(machine.cur, 2);
-        assert_eq!(machine.stack.back(), Some(&10));
+        assert_eq!(machine.stack.peek(), Some(10));
     }
 
     #[test]
     fn pop() {
         let mut machine = setup(vec![3]);
-        machine.stack.push_back(10);
+        machine.stack.push(10);
         assert_eq!(machine.exec_next(), Ok(()));
         assert_eq!(machine.cur, 2);
         assert_eq!(machine.mem[0], 10);
@@ -520,17 +499,19 @@ mod tests {
 
     #[test]
     fn rmem() {
-        let mut machine = setup(vec![15, 0, 0]);
+        let mut machine = setup(vec![15, 32768, 3, 42]);
         assert_eq!(machine.exec_next(), Ok(()));
-        assert_eq!(machine.mem[1], 15);
+        assert_eq!(machine.registers[0], 42);
+        assert_eq!(machine.mem[..4], [15, 32768, 3, 42]);
         assert_eq!(machine.cur, 3);
     }
 
     #[test]
     fn wmem() {
-        let mut machine = setup(vec![16, 0, 1]);
+        let mut machine = setup(vec![16, 4, 32768, 0, 0]);
+        machine.registers[0] = 7;
         assert_eq!(machine.exec_next(), Ok(()));
-        assert_eq!(machine.mem[0], 0);
+        assert_eq!(machine.mem[4], 7);
         assert_eq!(machine.cur, 3);
     }
 
@@ -538,21 +519,111 @@ mod tests {
     fn call() {
         let mut machine = setup(vec![17, 0]);
         assert_eq!(machine.exec_next(), Ok(()));
-        assert_eq!(machine.stack.back(), Some(&2));
+        assert_eq!(machine.stack.peek(), Some(2));
         assert_eq!(machine.cur, 0);
+
+        // through a register
+        let mut machine = setup(vec![17, 32768]);
+        machine.registers[0] = 10;
+        assert_eq!(machine.exec_next(), Ok(()));
+        assert_eq!(machine.cur, 10);
+    }
+
+    #[test]
+    fn failed_instruction_keeps_cur() {
+        let mut machine = setup(vec![21, 6, 32768]);
+        machine.registers[0] = 40000;
+        assert_eq!(machine.exec_next(), Ok(()));
+        assert_eq!(
+            machine.exec_next(),
+            Err(ExecutionError::InvalidAddress(40000, 2))
+        );
+        assert_eq!(machine.cur, 1);
     }
 
     #[test]
     fn ret() {
         // empty stack
         let mut machine = setup(vec![18, 18]);
-        assert_eq!(machine.exec_next(), Err(ExecutionError::Halt));
+        assert_eq!(machine.exec_next(), Ok(()));
+        assert!(machine.halted);
         // valid case
-        machine.stack.push_back(10);
+        machine.halted = false;
+        machine.stack.push(10);
         assert_eq!(machine.exec_next(), Ok(()));
         assert_eq!(machine.cur, 10);
     }
 
+    #[test]
+    fn stack_audit() {
+        let mut machine = setup(vec![17, 3, 0, 3, 0]);
+        machine.stack_audit = Some(crate::audit::StackAudit::new());
+        assert_eq!(machine.exec_next(), Ok(()));
+        // popping the return address into a plain value is flagged
+        assert_eq!(machine.exec_next(), Ok(()));
+        let audit = machine.stack_audit.unwrap();
+        assert_eq!(audit.log.len(), 2);
+        assert_eq!(audit.suspicious[0].op, StackOp::Pop);
+    }
+
+    #[test]
+    fn access_oracle() {
+        // wmem 1 5 overwrites its own operand
+        let mut machine = setup(vec![21, 16, 2, 5]);
+        machine.access_oracle = Some(crate::oracle::AccessOracle::new());
+        assert_eq!(machine.exec_next(), Ok(()));
+        assert_eq!(
+            machine.exec_next(),
+            Err(ExecutionError::AccessViolation(2, 1))
+        );
+        assert_eq!(machine.mem[2], 2);
+        assert_eq!(
+            machine.operand(1, 2),
+            Err(ExecutionError::AccessViolation(4, 1))
+        );
+    }
+
+    #[test]
+    fn infinite_loop() {
+        let mut machine = setup(vec![21, 6, 0]);
+        machine.repeat_detector = Some(crate::loops::RepeatDetector::new(1));
+        assert_eq!(machine.run(), Ok(RunOutcome::InfiniteLoop(1)));
+    }
+
+    #[test]
+    fn resumable_run() {
+        let mut machine = setup(vec![21, 21, 21, 0]);
+        assert_eq!(machine.run_for(2), Ok(RunOutcome::FuelExhausted));
+        assert_eq!(machine.cur, 2);
+        assert_eq!(machine.run_for(2), Ok(RunOutcome::Halted));
+        assert_eq!(machine.steps, 4);
+    }
+
+    #[test]
+    fn breakpoints() {
+        // 0: noop, 1: noop, 2: jmp 0
+        let mut machine = setup(vec![21, 21, 6, 0]);
+        machine.breakpoints.insert(2, None);
+        assert_eq!(machine.run(), Ok(RunOutcome::Breakpoint(2)));
+        assert_eq!((machine.cur, machine.steps), (2, 2));
+        // resuming runs the instruction at the breakpoint
+        assert_eq!(machine.run(), Ok(RunOutcome::Breakpoint(2)));
+        assert_eq!(machine.steps, 5);
+        // reaching a breakpoint with the last of the fuel still reports the breakpoint
+        assert_eq!(machine.run_for(3), Ok(RunOutcome::Breakpoint(2)));
+        assert_eq!(machine.run_for(2), Ok(RunOutcome::FuelExhausted));
+    }
+
+    #[test]
+    fn timed_out() {
+        let mut machine = setup(vec![6, 0]);
+        let limits = RunLimits {
+            deadline: Some(std::time::Instant::now()),
+            ..RunLimits::default()
+        };
+        assert_eq!(machine.run_with(limits), Ok(RunOutcome::TimedOut));
+    }
+
     #[test]
     fn out() {
         let mut machine = setup(vec![19, b'a' as u16]);
@@ -564,6 +635,33 @@ mod tests {
         assert_eq!(machine.cur, 2);
     }
 
+    #[test]
+    fn poll_step() {
+        // echo one character, then halt
+        let mut machine = setup(vec![20, 32768, 19, 32768, 0]);
+        assert_eq!(machine.poll_step(10), Ok(RunOutcome::NeedsInput));
+        assert_eq!(machine.cur, 0);
+
+        machine.push_input(b"x");
+        assert_eq!(machine.poll_step(10), Ok(RunOutcome::Halted));
+        assert_eq!(machine.drain_output(), b"x");
+        assert_eq!(machine.drain_output(), b"");
+    }
+
+    #[test]
+    fn broadcast() {
+        let mut machine = setup(vec![19, b'a' as u16, 21, 0]);
+        let (broadcaster, mut receiver) = crate::broadcast::StatusBroadcaster::new(2);
+        machine.broadcaster = Some(broadcaster);
+        machine.push_input(b"");
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+
+        let summary = receiver.latest();
+        assert_eq!(summary.steps, 2);
+        assert_eq!(summary.cur, 3);
+        assert_eq!(summary.last_output, "a");
+    }
+
     #[test]
     fn no_op() {
         let initial = setup(vec![21]);
@@ -0,0 +1,54 @@
+use std::ops::Range;
+
+/// Validates the memory accesses of every instruction against its encoding: operands may only be
+/// fetched from the words following the opcode that it declares, and data writes may not land
+/// inside the instruction being executed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessOracle {
+    encoding: Range<usize>,
+}
+
+impl AccessOracle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts checking the instruction at `pos`, which has `arity` operands.
+    pub fn decode(&mut self, pos: u16, arity: usize) {
+        let pos = pos as usize;
+        self.encoding = pos..pos + 1 + arity;
+    }
+
+    /// The position of the instruction being checked.
+    pub fn instruction(&self) -> u16 {
+        self.encoding.start as u16
+    }
+
+    /// Whether fetching an operand from `addr` is allowed.
+    pub fn allows_fetch(&self, addr: usize) -> bool {
+        self.encoding.start < addr && addr < self.encoding.end
+    }
+
+    /// Whether writing data to `addr` is allowed.
+    pub fn allows_write(&self, addr: usize) -> bool {
+        !self.encoding.contains(&addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_encoding() {
+        let mut oracle = AccessOracle::new();
+        oracle.decode(10, 2);
+        assert!(!oracle.allows_fetch(10));
+        assert!(oracle.allows_fetch(11));
+        assert!(oracle.allows_fetch(12));
+        assert!(!oracle.allows_fetch(13));
+
+        assert!(!oracle.allows_write(11));
+        assert!(oracle.allows_write(13));
+    }
+}
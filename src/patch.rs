@@ -0,0 +1,300 @@
+use std::fmt::Write as _;
+
+use crate::{
+    instruction::parse_instruction,
+    toml::{self, escape, Item, Value},
+};
+
+/// A list of patches applied to a memory image in order.
+///
+/// Patch scripts are written in a small subset of TOML:
+/// ```toml
+/// [[patch]]
+/// addr = 0x1571
+/// expect = [17, 6027]   # optional precondition on the current words
+/// words = [21, 21]      # replacement words...
+///
+/// [[patch]]
+/// addr = 5489
+/// asm = ["set r0 6", "noop"]   # ...or replacement instructions
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PatchScript {
+    pub patches: Vec<Patch>,
+}
+
+/// Replaces the words at `addr` with `words`, optionally checking that the old words match `expect`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Patch {
+    pub addr: u16,
+    pub expect: Option<Vec<u16>>,
+    pub words: Vec<u16>,
+    pub description: Option<String>,
+}
+
+impl PatchScript {
+    pub fn parse(text: &str) -> Result<Self, PatchError> {
+        let items = toml::parse(text).map_err(|(msg, line)| PatchError::Parse(msg, line))?;
+        let mut patches: Vec<Patch> = Vec::new();
+        // the line of each `[[patch]]` header, and whether the table set `addr`
+        let mut tables: Vec<(usize, bool)> = Vec::new();
+
+        for (line_no, item) in items {
+            let err = |msg: String| PatchError::Parse(msg, line_no);
+            let (key, value) = match item {
+                Item::ArrayTable(name) if name == "patch" => {
+                    patches.push(Patch::default());
+                    tables.push((line_no, false));
+                    continue;
+                }
+                Item::Table(name) | Item::ArrayTable(name) => {
+                    return Err(err(format!("unknown table `{name}`")))
+                }
+                Item::Entry(key, value) => (key, value),
+            };
+            let patch = patches
+                .last_mut()
+                .ok_or_else(|| err("key outside of a `[[patch]]` table".to_string()))?;
+            match (key.as_str(), value) {
+                ("addr", Value::Int(addr)) => {
+                    patch.addr = addr;
+                    if let Some((_, has_addr)) = tables.last_mut() {
+                        *has_addr = true;
+                    }
+                }
+                ("expect", value) => patch.expect = Some(int_array(value).map_err(err)?),
+                ("words", value) => patch.words.extend(int_array(value).map_err(err)?),
+                ("asm", Value::Str(line)) => {
+                    patch.words.extend(parse_instruction(&line).map_err(err)?)
+                }
+                ("asm", Value::Array(lines)) => {
+                    for line in lines {
+                        match line {
+                            Value::Str(line) => {
+                                patch.words.extend(parse_instruction(&line).map_err(err)?)
+                            }
+                            _ => return Err(err("`asm` must contain strings".to_string())),
+                        }
+                    }
+                }
+                ("description", Value::Str(text)) => patch.description = Some(text),
+                (key, value) => return Err(err(format!("invalid entry `{key}` = {value:?}"))),
+            }
+        }
+
+        for (patch, (line_no, has_addr)) in patches.iter().zip(tables) {
+            if !has_addr {
+                return Err(PatchError::Parse(
+                    "`[[patch]]` needs an `addr`".to_string(),
+                    line_no,
+                ));
+            }
+            if patch.words.is_empty() {
+                return Err(PatchError::Parse(
+                    "`[[patch]]` needs `words` or `asm`".to_string(),
+                    line_no,
+                ));
+            }
+        }
+        Ok(Self { patches })
+    }
+
+    /// Applies every patch to `mem`, checking preconditions before writing anything.
+    pub fn apply(&self, mem: &mut [u16]) -> Result<(), PatchError> {
+        for patch in &self.patches {
+            let range = patch.addr as usize..patch.addr as usize + patch.words.len();
+            if range.end > mem.len() {
+                return Err(PatchError::OutOfBounds(patch.addr));
+            }
+            if let Some(expect) = &patch.expect {
+                let found = mem
+                    .get(patch.addr as usize..patch.addr as usize + expect.len())
+                    .ok_or(PatchError::OutOfBounds(patch.addr))?;
+                if found != expect.as_slice() {
+                    return Err(PatchError::Precondition(
+                        patch.addr,
+                        expect.clone(),
+                        found.to_vec(),
+                    ));
+                }
+            }
+        }
+
+        for patch in &self.patches {
+            let start = patch.addr as usize;
+            mem[start..start + patch.words.len()].copy_from_slice(&patch.words);
+        }
+        Ok(())
+    }
+
+    /// Generates a patch script turning `original` into `modified`, one patch per changed run of words.
+    /// Patches can't change the length of an image, so both must have the same length.
+    pub fn diff(original: &[u16], modified: &[u16]) -> Result<Self, PatchError> {
+        if original.len() != modified.len() {
+            return Err(PatchError::LengthMismatch(original.len(), modified.len()));
+        }
+        let len = original.len();
+
+        let mut patches = Vec::new();
+        let mut i = 0;
+        while i < len {
+            if original[i] == modified[i] {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < len && original[i] != modified[i] {
+                i += 1;
+            }
+            patches.push(Patch {
+                addr: start as u16,
+                expect: Some(original[start..i].to_vec()),
+                words: modified[start..i].to_vec(),
+                description: None,
+            });
+        }
+
+        Ok(Self { patches })
+    }
+
+    /// Serializes the script into the format read by `parse`.
+    pub fn to_toml(&self) -> String {
+        let list = |words: &[u16]| {
+            words
+                .iter()
+                .map(|w| w.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        let mut out = String::new();
+        for patch in &self.patches {
+            let _ = writeln!(out, "[[patch]]");
+            if let Some(description) = &patch.description {
+                let _ = writeln!(out, "description = \"{}\"", escape(description));
+            }
+            let _ = writeln!(out, "addr = {}", patch.addr);
+            if let Some(expect) = &patch.expect {
+                let _ = writeln!(out, "expect = [{}]", list(expect));
+            }
+            let _ = writeln!(out, "words = [{}]\n", list(&patch.words));
+        }
+        out
+    }
+}
+
+fn int_array(value: Value) -> Result<Vec<u16>, String> {
+    match value {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::Int(n) => Ok(n),
+                other => Err(format!("expected a number, got {other:?}")),
+            })
+            .collect(),
+        Value::Int(n) => Ok(vec![n]),
+        other => Err(format!("expected an array of numbers, got {other:?}")),
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    #[error("Invalid patch script: {0} on line `{1}`")]
+    Parse(String, usize),
+    #[error("Patch at `{0}` does not fit in memory")]
+    OutOfBounds(u16),
+    #[error("Patch precondition failed at `{0}`: expected {1:?}, found {2:?}")]
+    Precondition(u16, Vec<u16>, Vec<u16>),
+    #[error("Cannot diff images of different lengths: {0} and {1} words")]
+    LengthMismatch(usize, usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_apply() {
+        let script = PatchScript::parse(
+            r#"
+            # skip the call
+            [[patch]]
+            addr = 0x2
+            expect = [17, 3]
+            words = [21, 21]
+
+            [[patch]]
+            addr = 4
+            asm = [
+                "set r0 6",  # multi-line arrays work
+                "noop",
+                "out ' '",
+            ]
+            "#,
+        )
+        .unwrap();
+
+        let mut mem = vec![0, 0, 17, 3, 0, 0, 0, 0, 0, 0];
+        script.apply(&mut mem).unwrap();
+        assert_eq!(mem, vec![0, 0, 21, 21, 1, 32768, 6, 21, 19, 32]);
+
+        // the precondition no longer holds
+        assert_eq!(
+            script.apply(&mut mem),
+            Err(PatchError::Precondition(2, vec![17, 3], vec![21, 21]))
+        );
+    }
+
+    #[test]
+    fn rejects_incomplete_patches() {
+        assert_eq!(
+            PatchScript::parse("[[patch]]\naddr = 1\nwords = [1]\n\n[[patch]]\nwords = [21]"),
+            Err(PatchError::Parse(
+                "`[[patch]]` needs an `addr`".to_string(),
+                5
+            ))
+        );
+        assert_eq!(
+            PatchScript::parse("[[patch]]\naddr = 1\nexpect = [0]"),
+            Err(PatchError::Parse(
+                "`[[patch]]` needs `words` or `asm`".to_string(),
+                1
+            ))
+        );
+    }
+
+    #[test]
+    fn diff_roundtrip() {
+        let original = vec![1, 2, 3, 4, 5, 6];
+        let modified = vec![1, 9, 9, 4, 5, 7];
+        let script = PatchScript::diff(&original, &modified).unwrap();
+        assert_eq!(script.patches.len(), 2);
+
+        let reparsed = PatchScript::parse(&script.to_toml()).unwrap();
+        assert_eq!(reparsed, script);
+
+        let mut mem = original.clone();
+        reparsed.apply(&mut mem).unwrap();
+        assert_eq!(mem, modified);
+    }
+
+    #[test]
+    fn rejects_diffs_of_different_lengths() {
+        assert_eq!(
+            PatchScript::diff(&[1, 2], &[1, 2, 3]),
+            Err(PatchError::LengthMismatch(2, 3))
+        );
+    }
+
+    #[test]
+    fn escapes_descriptions() {
+        let mut script = PatchScript::diff(&[1], &[2]).unwrap();
+        let description = "skip the \"check\"\n\tat C:\\ # é\u{1}";
+        script.patches[0].description = Some(description.to_string());
+        let reparsed = PatchScript::parse(&script.to_toml()).unwrap();
+        assert_eq!(reparsed, script);
+        assert!(
+            PatchScript::parse("[[patch]]\ndescription = \"\\q\"\naddr = 0\nwords = [1]").is_err()
+        );
+    }
+}
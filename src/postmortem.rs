@@ -0,0 +1,277 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+
+use crate::{
+    bytes_from_words,
+    crash::CrashDump,
+    history,
+    instruction::{self, disassemble_registers},
+    project::{parse_number, Project},
+    strings, tables,
+};
+
+const HELP: &str = "\
+commands:
+  reason                 why the machine crashed
+  regs                   register values
+  stack                  the stack, top first, and the return stack in dual-stack mode
+  bt                     probable return addresses on the stack, or the return stack
+  mem <addr> [len]       memory words
+  disasm [addr] [count]  disassembly, at the crash position by default
+  history [count]        the most recently executed instructions
+  strings [text]         strings in memory containing `text`, with their xrefs
+  who <addr>             the last instructions that wrote to `addr`, if recorded
+  vars                   the variables declared in the project and their values
+  table <region>         a region laid out with the columns declared in the project
+  export-image <out.bin> write memory, with every patch and change made while running, as an image
+  quit";
+
+/// A read-only debugger over a crash file: everything can be inspected, nothing can be executed.
+pub struct Postmortem {
+    pub dump: CrashDump,
+    pub project: Project,
+}
+
+impl Postmortem {
+    pub fn new(dump: CrashDump, project: Project) -> Self {
+        Self { dump, project }
+    }
+
+    /// Reads commands from `input` until it ends or `quit` is entered.
+    pub fn repl(&self, mut input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+        writeln!(output, "{}", self.dump.reason)?;
+        loop {
+            write!(output, "(postmortem) ")?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 || line.trim() == "quit" {
+                return Ok(());
+            }
+            match self.execute(line.trim()) {
+                Ok(text) => write!(output, "{text}")?,
+                Err(err) => writeln!(output, "error: {err}")?,
+            }
+        }
+    }
+
+    /// Runs a single command, returning its output.
+    pub fn execute(&self, line: &str) -> Result<String, String> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let arg =
+            |i: usize, default: u16| words.get(i).map_or(Ok(default), |word| parse_number(word));
+
+        let mut out = String::new();
+        match words.first().copied() {
+            None => {}
+            Some("help") => out = format!("{HELP}\n"),
+            Some("reason") => out = format!("{}\n", self.dump.reason),
+            Some("regs") => {
+                for (i, val) in self.dump.registers.iter().enumerate() {
+                    let reg = match self.project.registers.get(&i) {
+                        Some(name) => format!("r{i} <{name}>"),
+                        None => format!("r{i}"),
+                    };
+                    let _ = writeln!(out, "{reg} = {val}");
+                }
+                let _ = writeln!(out, "cur = {}", self.project.describe(self.dump.cur));
+                let _ = writeln!(out, "steps = {}", self.dump.steps);
+            }
+            Some("stack") => {
+                for (depth, val) in self.dump.stack.iter().rev().enumerate() {
+                    let _ = writeln!(out, "#{depth:<3} {val}");
+                }
+                if let Some(stack) = &self.dump.return_stack {
+                    let _ = writeln!(out, "return stack:");
+                    for (depth, &val) in stack.iter().rev().enumerate() {
+                        let _ = writeln!(out, "#{depth:<3} {}", self.project.describe(val));
+                    }
+                }
+            }
+            Some("bt") => {
+                let _ = writeln!(out, "#0   {}", self.project.describe(self.dump.cur));
+                for (i, &ret) in self.return_addresses().iter().enumerate() {
+                    let _ = writeln!(
+                        out,
+                        "#{:<3} {} (called from {})",
+                        i + 1,
+                        self.project.describe(ret),
+                        ret - 2
+                    );
+                }
+            }
+            Some("mem") => {
+                let addr = arg(1, self.dump.cur)? as usize;
+                let len = arg(2, 16)? as usize;
+                let end = (addr + len).min(self.dump.mem.len());
+                for start in (addr..end).step_by(8) {
+                    let words = self.dump.mem[start..(start + 8).min(end)]
+                        .iter()
+                        .map(|w| format!("{w:5}"))
+                        .collect::<Vec<_>>();
+                    let _ = writeln!(out, "{start:5}: {}", words.join(" "));
+                }
+            }
+            Some("disasm") => {
+                let mut addr = arg(1, self.dump.cur)? as usize;
+                for _ in 0..arg(2, 10)? {
+                    let (text, len) = disassemble_registers(&self.dump.mem, addr, &self.project);
+                    if len == 0 {
+                        break;
+                    }
+                    let marker = if addr == self.dump.cur as usize {
+                        "=>"
+                    } else {
+                        "  "
+                    };
+                    let _ = writeln!(out, "{marker} {:>5}: {text}", addr);
+                    addr += len;
+                }
+            }
+            Some("strings") => {
+                let found = strings::strings(&self.dump.mem, &self.project);
+                let query = line
+                    .split_once(char::is_whitespace)
+                    .map(|(_, query)| query.trim());
+                out = strings::render(&found, query, &self.project);
+            }
+            Some("who") => {
+                let addr = arg(1, self.dump.cur)?;
+                match self.dump.writers.get(&addr) {
+                    Some(writers) => {
+                        for &pos in writers {
+                            let (text, _) =
+                                disassemble_registers(&self.dump.mem, pos as usize, &self.project);
+                            let _ = writeln!(out, "{:>5}: {text}", self.project.describe(pos));
+                        }
+                    }
+                    None if self.dump.writers.is_empty() => {
+                        return Err("no writes were recorded, run with `--provenance`".to_string())
+                    }
+                    None => out = format!("{addr} was never written\n"),
+                }
+            }
+            Some("vars") => {
+                for (&addr, var) in &self.project.variables {
+                    if let Some(&word) = self.dump.mem.get(addr as usize) {
+                        let value = var.ty.value(word);
+                        let _ =
+                            writeln!(out, "{} @ {addr}: {} = {value}", var.name, var.ty.as_str());
+                    }
+                }
+            }
+            Some("table") => {
+                let name = words.get(1).ok_or("usage: table <region>")?;
+                out = tables::render(&self.project, name, &self.dump.mem)?;
+            }
+            Some("export-image") => {
+                let path = words.get(1).ok_or("usage: export-image <out.bin>")?;
+                std::fs::write(path, bytes_from_words(&self.dump.mem))
+                    .map_err(|err| format!("{path}: {err}"))?;
+                out = format!("wrote {} words to `{path}`\n", self.dump.mem.len());
+            }
+            Some("history") => {
+                let count = arg(1, 10)? as usize;
+                out = history::backtrace(&self.dump.history, count, &self.dump.mem, &self.project);
+            }
+            Some(other) => return Err(format!("unknown command `{other}`, try `help`")),
+        }
+        Ok(out)
+    }
+
+    /// Stack values that point right after a `call` instruction, top first, or in dual-stack mode,
+    /// the return stack.
+    pub fn return_addresses(&self) -> Vec<u16> {
+        if let Some(stack) = &self.dump.return_stack {
+            return stack.iter().rev().copied().collect();
+        }
+        let call = instruction::by_mnemonic("call").unwrap().code;
+        self.dump
+            .stack
+            .iter()
+            .rev()
+            .copied()
+            .filter(|&val| val >= 2 && self.dump.mem.get(val as usize - 2) == Some(&call))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MachineState;
+
+    fn postmortem() -> Postmortem {
+        // call 4, halt, noop, then crash at 4
+        let mut machine = MachineState::new(vec![17, 4, 0, 21, 9999]);
+        machine.push_input(b"");
+        machine.exec_next().unwrap();
+        machine.stack.push(1234);
+        let project = Project::parse("symbol 4 broken\nvariable 1 target addr").unwrap();
+        Postmortem::new(CrashDump::of(&machine, "invalid opcode"), project)
+    }
+
+    #[test]
+    fn backtrace() {
+        let postmortem = postmortem();
+        assert_eq!(postmortem.return_addresses(), vec![2]);
+        assert_eq!(
+            postmortem.execute("bt").unwrap(),
+            "#0   4 <broken>\n#1   2 (called from 0)\n"
+        );
+
+        // in dual-stack mode the return stack holds the return addresses, and only them
+        let mut postmortem = postmortem;
+        postmortem.dump.stack = vec![1234];
+        postmortem.dump.return_stack = Some(vec![2]);
+        assert_eq!(postmortem.return_addresses(), vec![2]);
+        assert_eq!(
+            postmortem.execute("stack").unwrap(),
+            "#0   1234\nreturn stack:\n#0   2\n"
+        );
+    }
+
+    #[test]
+    fn commands() {
+        let postmortem = postmortem();
+        assert_eq!(
+            postmortem.execute("disasm 0 2").unwrap(),
+            "       0: call 4\n       2: halt\n"
+        );
+        assert_eq!(postmortem.execute("history").unwrap(), "    0: call 4\n");
+        assert_eq!(
+            postmortem.execute("vars").unwrap(),
+            "target @ 1: addr = 0x0004\n"
+        );
+        assert!(postmortem.execute("step").is_err());
+    }
+
+    #[test]
+    fn exports_the_image() {
+        let mut postmortem = postmortem();
+        // as if the program had decrypted itself
+        postmortem.dump.mem[3] = 19;
+        let path = std::env::temp_dir().join(format!("synacor-export-{}.bin", std::process::id()));
+        let command = format!("export-image {}", path.display());
+        assert_eq!(
+            postmortem.execute(&command).unwrap(),
+            format!("wrote 5 words to `{}`\n", path.display())
+        );
+        assert_eq!(crate::read_image(&path).unwrap(), vec![17, 4, 0, 19, 9999]);
+        std::fs::remove_file(path).unwrap();
+        assert!(postmortem.execute("export-image").is_err());
+    }
+
+    #[test]
+    fn who() {
+        let mut postmortem = postmortem();
+        assert!(postmortem.execute("who 3").is_err());
+
+        postmortem.dump.writers.insert(3, vec![0]);
+        assert_eq!(postmortem.execute("who 3").unwrap(), "    0: call 4\n");
+        assert_eq!(
+            postmortem.execute("who 1").unwrap(),
+            "1 was never written\n"
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::{calls::CallEvent, project::Project};
+
+/// The name of the bottom frame, which holds the time spent outside of any call.
+const ROOT: &str = "program";
+
+const WIDTH: f64 = 1200.0;
+const ROW_HEIGHT: usize = 16;
+/// Frames narrower than this many pixels are left out.
+const MIN_WIDTH: f64 = 0.1;
+/// Roughly how wide a character of the labels is, to cut labels that don't fit.
+const CHAR_WIDTH: f64 = 7.0;
+
+/// Converts a call trace into folded stacks: for every chain of calls, how many instructions were
+/// executed in its innermost routine. `end` is the number of instructions executed when the run
+/// stopped.
+pub fn folded(calls: &[CallEvent], end: u64, project: &Project) -> BTreeMap<String, u64> {
+    let mut stacks = BTreeMap::new();
+    let mut stack = vec![ROOT.to_string()];
+    let mut last = 0;
+
+    let mut attribute = |stack: &[String], until: u64, last: &mut u64| {
+        if until > *last {
+            *stacks.entry(stack.join(";")).or_default() += until - *last;
+        }
+        *last = until;
+    };
+    for event in calls {
+        match *event {
+            CallEvent::Call { target, steps, .. } => {
+                attribute(&stack, steps, &mut last);
+                stack.push(project.describe(target));
+            }
+            CallEvent::Return { steps, depth, .. } => {
+                attribute(&stack, steps, &mut last);
+                stack.truncate(depth + 1);
+            }
+        }
+    }
+    attribute(&stack, end, &mut last);
+    stacks
+}
+
+/// Writes folded stacks in the format read by `flamegraph.pl` and `inferno`.
+pub fn folded_text(stacks: &BTreeMap<String, u64>) -> String {
+    stacks
+        .iter()
+        .map(|(stack, count)| format!("{stack} {count}\n"))
+        .collect()
+}
+
+/// A routine in the flamegraph, with the time spent in it and everything it called.
+#[derive(Default)]
+struct Node {
+    total: u64,
+    children: BTreeMap<String, Node>,
+}
+
+/// Renders folded stacks as an SVG flamegraph. Hovering a frame shows its name and time.
+pub fn flamegraph(stacks: &BTreeMap<String, u64>) -> String {
+    let mut root = Node::default();
+    for (stack, &count) in stacks {
+        let mut frames = stack.split(';');
+        // every stack starts with the root frame
+        frames.next();
+        root.total += count;
+        let mut node = &mut root;
+        for frame in frames {
+            node = node.children.entry(frame.to_string()).or_default();
+            node.total += count;
+        }
+    }
+
+    let height = (depth(&root) + 1) * ROW_HEIGHT;
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{height}\" \
+         font-family=\"monospace\" font-size=\"12\">"
+    );
+    draw(
+        &mut out,
+        ROOT,
+        &root,
+        0.0,
+        height - ROW_HEIGHT,
+        root.total.max(1),
+    );
+    out.push_str("</svg>\n");
+    out
+}
+
+fn depth(node: &Node) -> usize {
+    node.children
+        .values()
+        .map(|child| depth(child) + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Draws `node` at `y`, and its children in the rows above it.
+fn draw(out: &mut String, name: &str, node: &Node, x: f64, y: usize, total: u64) {
+    let width = node.total as f64 / total as f64 * WIDTH;
+    if width < MIN_WIDTH {
+        return;
+    }
+
+    let percent = node.total as f64 / total as f64 * 100.0;
+    let label = if width > 3.0 * CHAR_WIDTH {
+        name.chars()
+            .take((width / CHAR_WIDTH) as usize - 1)
+            .collect::<String>()
+    } else {
+        String::new()
+    };
+    let _ = writeln!(
+        out,
+        "<g><title>{} ({} instructions, {percent:.2}%)</title>\
+         <rect x=\"{x:.2}\" y=\"{y}\" width=\"{width:.2}\" height=\"{ROW_HEIGHT}\" fill=\"{}\" stroke=\"white\"/>\
+         <text x=\"{:.2}\" y=\"{}\">{}</text></g>",
+        xml_escape(name),
+        node.total,
+        color(name),
+        x + 2.0,
+        y + ROW_HEIGHT - 4,
+        xml_escape(&label),
+    );
+
+    let mut child_x = x;
+    for (child_name, child) in &node.children {
+        if let Some(child_y) = y.checked_sub(ROW_HEIGHT) {
+            draw(out, child_name, child, child_x, child_y, total);
+        }
+        child_x += child.total as f64 / total as f64 * WIDTH;
+    }
+}
+
+/// A warm color, always the same for the same routine.
+fn color(name: &str) -> String {
+    let hash = name
+        .bytes()
+        .fold(0u32, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u32));
+    format!(
+        "rgb({},{},{})",
+        205 + hash % 50,
+        (hash / 50) % 180,
+        (hash / 9000) % 55
+    )
+}
+
+pub(crate) fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{calls::CallTrace, testing::MachineBuilder, RunOutcome};
+
+    fn stacks() -> BTreeMap<String, u64> {
+        // 0: call 4
+        // 2: noop
+        // 3: halt
+        // 4: call 7
+        // 6: ret
+        // 7: noop
+        // 8: ret
+        let mut machine = MachineBuilder::new()
+            .program(&[17, 4, 21, 0, 17, 7, 18, 21, 18])
+            .build();
+        machine.call_trace = Some(CallTrace::new());
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+
+        let project = Project::parse("symbol 4 outer\nsymbol 7 inner").unwrap();
+        folded(&machine.call_trace.unwrap().log, machine.steps, &project)
+    }
+
+    #[test]
+    fn folds_stacks() {
+        assert_eq!(
+            folded_text(&stacks()),
+            "program 3\n\
+             program;4 <outer> 2\n\
+             program;4 <outer>;7 <inner> 2\n"
+        );
+    }
+
+    #[test]
+    fn renders_svg() {
+        let svg = flamegraph(&stacks());
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains("<title>program (7 instructions, 100.00%)</title>"));
+        assert!(svg.contains("<title>7 &lt;inner&gt; (2 instructions, 28.57%)</title>"));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+}
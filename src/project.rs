@@ -0,0 +1,556 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::{instruction, MAX_ADDR, REGISTER_COUNT};
+
+/// The extension used for project files, which live next to the binary they annotate.
+pub const PROJECT_EXTENSION: &str = "proj";
+
+/// The extension of label files, a simpler sidecar of names and comments that is read along with
+/// the project file.
+pub const LABELS_EXTENSION: &str = "labels";
+
+/// User annotations for a binary, persisted between sessions:
+/// - `symbols` are names given to addresses
+/// - `comments` are free-form notes attached to addresses
+/// - `regions` are address ranges identified as code, data or strings
+/// - `bookmarks` are addresses worth jumping back to, with a description
+/// - `variables` are named memory words, shown according to their type
+/// - `tables` are the columns of the regions starting at an address, which hold rows of them
+/// - `natives` are the routines to replace with a native implementation, by its name
+/// - `pure` are the routines whose results can be cached, with the registers they read
+/// - `registers` are names given to registers, shown in their place, by register number
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Project {
+    pub symbols: BTreeMap<u16, String>,
+    pub comments: BTreeMap<u16, String>,
+    pub regions: Vec<Region>,
+    pub bookmarks: BTreeMap<u16, String>,
+    pub variables: BTreeMap<u16, Variable>,
+    pub tables: BTreeMap<u16, Vec<Variable>>,
+    pub natives: BTreeMap<u16, String>,
+    pub pure: BTreeMap<u16, String>,
+    pub registers: BTreeMap<usize, String>,
+}
+
+/// An identified range of memory, `start..end`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub start: u16,
+    pub end: u16,
+    pub kind: RegionKind,
+    pub name: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionKind {
+    Code,
+    Data,
+    Strings,
+}
+
+impl RegionKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RegionKind::Code => "code",
+            RegionKind::Data => "data",
+            RegionKind::Strings => "strings",
+        }
+    }
+}
+
+impl std::str::FromStr for RegionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "code" => Ok(RegionKind::Code),
+            "data" => Ok(RegionKind::Data),
+            "strings" => Ok(RegionKind::Strings),
+            other => Err(format!("unknown region kind `{other}`")),
+        }
+    }
+}
+
+/// A named memory word, such as the state of the lantern, or a column of a table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub ty: VarType,
+}
+
+/// How the word behind a `Variable` is interpreted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VarType {
+    #[default]
+    U16,
+    Bool,
+    Char,
+    Addr,
+}
+
+impl VarType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            VarType::U16 => "u16",
+            VarType::Bool => "bool",
+            VarType::Char => "char",
+            VarType::Addr => "addr",
+        }
+    }
+
+    pub fn value(self, word: u16) -> Value {
+        match self {
+            VarType::U16 => Value::U16(word),
+            VarType::Bool => Value::Bool(word != 0),
+            VarType::Char => Value::Char(char::from_u32(word as u32).unwrap_or('\u{fffd}')),
+            VarType::Addr => Value::Addr(word),
+        }
+    }
+}
+
+impl std::str::FromStr for VarType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "u16" => Ok(VarType::U16),
+            "bool" => Ok(VarType::Bool),
+            "char" => Ok(VarType::Char),
+            "addr" => Ok(VarType::Addr),
+            other => Err(format!("unknown variable type `{other}`")),
+        }
+    }
+}
+
+/// The value of a `Variable`, typed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Value {
+    U16(u16),
+    Bool(bool),
+    Char(char),
+    Addr(u16),
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::U16(val) => write!(f, "{val}"),
+            Value::Bool(val) => write!(f, "{val}"),
+            Value::Char(val) => write!(f, "{val:?}"),
+            Value::Addr(val) => write!(f, "{val:#06x}"),
+        }
+    }
+}
+
+impl Project {
+    /// The path of the project file belonging to `binary`.
+    pub fn path_for(binary: impl AsRef<Path>) -> PathBuf {
+        binary.as_ref().with_extension(PROJECT_EXTENSION)
+    }
+
+    /// Loads the project file belonging to `binary`, or an empty project if there is none yet, with
+    /// the labels of its label file if there is one.
+    pub fn load_for(binary: impl AsRef<Path>) -> Result<Self, ProjectError> {
+        let path = Self::path_for(&binary);
+        let mut project = if path.exists() {
+            Self::load(path)?
+        } else {
+            Self::default()
+        };
+        let labels = binary.as_ref().with_extension(LABELS_EXTENSION);
+        if labels.exists() {
+            project.load_labels(labels)?;
+        }
+        Ok(project)
+    }
+
+    pub fn load_labels(&mut self, path: impl AsRef<Path>) -> Result<(), ProjectError> {
+        let text = std::fs::read_to_string(path.as_ref())
+            .map_err(|err| ProjectError::Io(format!("{}: {err}", path.as_ref().display())))?;
+        self.add_labels(&text)
+    }
+
+    /// Adds the names and comments of a label file, which has one address per line with a name, a
+    /// comment or both:
+    /// ```text
+    /// 6027 = confirm_teleporter  # checks r7
+    /// 0x178b # the comment alone
+    /// r7 = teleporter_key
+    /// ```
+    pub fn add_labels(&mut self, text: &str) -> Result<(), ProjectError> {
+        for (i, line) in text.lines().enumerate() {
+            let err = |msg: String| ProjectError::Parse(msg, i + 1);
+            let (line, comment) = match line.split_once('#') {
+                Some((line, comment)) => (line.trim(), Some(comment.trim())),
+                None => (line.trim(), None),
+            };
+            if line.is_empty() {
+                continue;
+            }
+            let (addr, name) = match line.split_once('=') {
+                Some((addr, name)) => (addr.trim(), Some(name.trim())),
+                None => (line, None),
+            };
+            if addr.starts_with('r') {
+                let reg = parse_register(addr).map_err(err)?;
+                let name = name.unwrap_or_default();
+                check_name(name).map_err(err)?;
+                self.registers.insert(reg, name.to_string());
+                continue;
+            }
+            let addr = parse_number(addr).map_err(err)?;
+            match name {
+                Some(name) => {
+                    check_name(name).map_err(err)?;
+                    self.symbols.insert(addr, name.to_string());
+                }
+                None if comment.is_none() => {
+                    return Err(err(format!("expected `{addr} = <name>`")));
+                }
+                None => {}
+            }
+            if let Some(comment) = comment.filter(|comment| !comment.is_empty()) {
+                self.comments.insert(addr, comment.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProjectError> {
+        let text = std::fs::read_to_string(path.as_ref())
+            .map_err(|err| ProjectError::Io(format!("{}: {err}", path.as_ref().display())))?;
+        Self::parse(&text)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ProjectError> {
+        std::fs::write(path.as_ref(), self.to_text())
+            .map_err(|err| ProjectError::Io(format!("{}: {err}", path.as_ref().display())))
+    }
+
+    /// Parses the line-based project format:
+    /// ```text
+    /// # comments start with a hash
+    /// symbol 6027 confirm_teleporter
+    /// comment 0x178b checks r7
+    /// region 0x0f70 0x0f80 data room_table
+    /// bookmark 5489 teleporter call site
+    /// variable 0x0f72 lantern_state u16
+    /// columns 0x0f70 name:addr exits:addr visited:bool
+    /// native 6027 confirm
+    /// pure 6027 r0 r1 r7
+    /// register r7 teleporter_key
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, ProjectError> {
+        let mut project = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let err = |msg: String| ProjectError::Parse(msg, line_no);
+            let (directive, rest) = split_word(line);
+            if directive == "register" {
+                let (reg, name) = split_word(rest);
+                let reg = parse_register(reg).map_err(err)?;
+                check_name(name).map_err(err)?;
+                project.registers.insert(reg, name.to_string());
+                continue;
+            }
+            let (addr, rest) = split_word(rest);
+            let addr = parse_number(addr).map_err(err)?;
+
+            match directive {
+                "symbol" => {
+                    check_name(rest).map_err(err)?;
+                    project.symbols.insert(addr, rest.to_string());
+                }
+                "comment" => {
+                    project.comments.insert(addr, rest.to_string());
+                }
+                "bookmark" => {
+                    project.bookmarks.insert(addr, rest.to_string());
+                }
+                "variable" => {
+                    let (name, ty) = split_word(rest);
+                    if name.is_empty() {
+                        return Err(err("missing variable name".to_string()));
+                    }
+                    let ty = match ty {
+                        "" => VarType::default(),
+                        ty => ty.parse().map_err(err)?,
+                    };
+                    let name = name.to_string();
+                    project.variables.insert(addr, Variable { name, ty });
+                }
+                "region" => {
+                    let (end, rest) = split_word(rest);
+                    let (kind, name) = split_word(rest);
+                    let end = parse_number(end).map_err(err)?;
+                    if end < addr {
+                        return Err(err(format!("region ends before it starts: {addr}..{end}")));
+                    }
+                    project.regions.push(Region {
+                        start: addr,
+                        end,
+                        kind: kind.parse().map_err(err)?,
+                        name: name.to_string(),
+                    });
+                }
+                "native" => {
+                    project.natives.insert(addr, rest.to_string());
+                }
+                "pure" => {
+                    project.pure.insert(addr, rest.to_string());
+                }
+                "columns" => {
+                    let columns = rest
+                        .split_whitespace()
+                        .map(|column| {
+                            let (name, ty) = column.split_once(':').unwrap_or((column, "u16"));
+                            let name = name.to_string();
+                            Ok(Variable {
+                                name,
+                                ty: ty.parse()?,
+                            })
+                        })
+                        .collect::<Result<Vec<_>, String>>()
+                        .map_err(err)?;
+                    if columns.is_empty() {
+                        return Err(err("a table needs at least one column".to_string()));
+                    }
+                    project.tables.insert(addr, columns);
+                }
+                other => return Err(err(format!("unknown directive `{other}`"))),
+            }
+        }
+
+        Ok(project)
+    }
+
+    /// Serializes the project into the format read by `parse`.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for (addr, name) in &self.symbols {
+            let _ = writeln!(out, "symbol {addr} {name}");
+        }
+        for (addr, text) in &self.comments {
+            let _ = writeln!(out, "comment {addr} {text}");
+        }
+        for region in &self.regions {
+            let _ = writeln!(
+                out,
+                "region {} {} {} {}",
+                region.start,
+                region.end,
+                region.kind.as_str(),
+                region.name
+            );
+        }
+        for (addr, text) in &self.bookmarks {
+            let _ = writeln!(out, "bookmark {addr} {text}");
+        }
+        for (addr, var) in &self.variables {
+            let _ = writeln!(out, "variable {addr} {} {}", var.name, var.ty.as_str());
+        }
+        for (addr, columns) in &self.tables {
+            let columns = columns
+                .iter()
+                .map(|column| format!("{}:{}", column.name, column.ty.as_str()))
+                .collect::<Vec<_>>();
+            let _ = writeln!(out, "columns {addr} {}", columns.join(" "));
+        }
+        for (addr, name) in &self.natives {
+            let _ = writeln!(out, "native {addr} {name}");
+        }
+        for (addr, registers) in &self.pure {
+            let _ = writeln!(out, "pure {addr} {registers}");
+        }
+        for (reg, name) in &self.registers {
+            let _ = writeln!(out, "register r{reg} {name}");
+        }
+        out
+    }
+
+    /// The address and variable called `name`, if one is declared.
+    pub fn variable(&self, name: &str) -> Option<(u16, &Variable)> {
+        self.variables
+            .iter()
+            .find(|(_, var)| var.name == name)
+            .map(|(&addr, var)| (addr, var))
+    }
+
+    /// Reads the variable called `name` from `mem`.
+    pub fn watch(&self, name: &str, mem: &[u16]) -> Option<Value> {
+        let (addr, var) = self.variable(name)?;
+        Some(var.ty.value(*mem.get(addr as usize)?))
+    }
+
+    /// Formats an operand like `instruction::format_operand`, showing registers by their name if
+    /// they have one.
+    pub fn format_operand(&self, word: u16) -> String {
+        let reg = (word as usize).wrapping_sub(MAX_ADDR);
+        match self.registers.get(&reg) {
+            Some(name) => name.clone(),
+            None => instruction::format_operand(word),
+        }
+    }
+
+    /// The operand of the register called `name`.
+    pub fn register(&self, name: &str) -> Option<u16> {
+        self.registers
+            .iter()
+            .find(|(_, alias)| *alias == name)
+            .map(|(&reg, _)| (MAX_ADDR + reg) as u16)
+    }
+
+    /// Returns the region containing `addr`, if any.
+    pub fn region_at(&self, addr: u16) -> Option<&Region> {
+        self.regions
+            .iter()
+            .find(|region| region.start <= addr && addr < region.end)
+    }
+
+    /// Describes an address by the variable there or relative to the closest preceding symbol,
+    /// e.g. `confirm+3`.
+    pub fn describe(&self, addr: u16) -> String {
+        match self.symbols.range(..=addr).next_back() {
+            Some((&base, name)) if base == addr => format!("{addr} <{name}>"),
+            _ if self.variables.contains_key(&addr) => {
+                format!("{addr} <{}>", self.variables[&addr].name)
+            }
+            Some((&base, name)) => format!("{addr} <{name}+{}>", addr - base),
+            None => addr.to_string(),
+        }
+    }
+}
+
+/// Parses a decimal or `0x`-prefixed hexadecimal number.
+pub fn parse_number(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number `{s}`"))
+}
+
+/// Parses a register `r0`-`r7` into its number.
+fn parse_register(s: &str) -> Result<usize, String> {
+    match s.strip_prefix('r').map(str::parse::<usize>) {
+        Some(Ok(reg)) if reg < REGISTER_COUNT => Ok(reg),
+        _ => Err(format!("invalid register `{s}`")),
+    }
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(format!("invalid symbol name `{name}`"));
+    }
+    Ok(())
+}
+
+/// Splits off the first word of `s`.
+pub(crate) fn split_word(s: &str) -> (&str, &str) {
+    match s.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim_start()),
+        None => (s, ""),
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ProjectError {
+    #[error("Could not access project file: {0}")]
+    Io(String),
+    #[error("Invalid project file: {0} on line `{1}`")]
+    Parse(String, usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let text = "\
+# my notes
+symbol 6027 confirm
+comment 0x178b checks r7
+region 0x0f70 0x0f80 data room_table
+bookmark 5489 teleporter call
+variable 0x0f72 lantern_state bool
+variable 0x0f73 counter
+columns 0x0f70 name:addr id
+native 6027 confirm
+pure 6027 r0 r1 r7
+register r7 teleporter_key
+";
+        let project = Project::parse(text).unwrap();
+        assert_eq!(project.symbols[&6027], "confirm");
+        assert_eq!(project.comments[&0x178b], "checks r7");
+        assert_eq!(project.region_at(0x0f75).unwrap().kind, RegionKind::Data);
+        assert_eq!(project.bookmarks[&5489], "teleporter call");
+        assert_eq!(project.variables[&0x0f73].ty, VarType::U16);
+        assert_eq!(project.tables[&0x0f70][1].ty, VarType::U16);
+        assert_eq!(project.registers[&7], "teleporter_key");
+        assert_eq!(Project::parse(&project.to_text()), Ok(project));
+    }
+
+    #[test]
+    fn describe() {
+        let project = Project::parse("symbol 10 start").unwrap();
+        assert_eq!(project.describe(5), "5");
+        assert_eq!(project.describe(10), "10 <start>");
+        assert_eq!(project.describe(13), "13 <start+3>");
+    }
+
+    #[test]
+    fn variables() {
+        let project = Project::parse("variable 2 lit bool\nvariable 3 letter char").unwrap();
+        let mem = [0, 0, 1, 97];
+        assert_eq!(project.watch("lit", &mem), Some(Value::Bool(true)));
+        assert_eq!(project.watch("letter", &mem).unwrap().to_string(), "'a'");
+        assert_eq!(project.watch("missing", &mem), None);
+        assert_eq!(project.describe(3), "3 <letter>");
+        assert!(Project::parse("variable 2 lit float").is_err());
+    }
+
+    #[test]
+    fn labels() {
+        let mut project = Project::parse("symbol 1 old").unwrap();
+        project
+            .add_labels("# notes\n6027 = confirm_teleporter  # checks r7\n\n0x10 # data\n1 = new")
+            .unwrap();
+        assert_eq!(project.symbols[&6027], "confirm_teleporter");
+        assert_eq!(project.symbols[&1], "new");
+        assert_eq!(project.comments[&6027], "checks r7");
+        assert_eq!(project.comments[&16], "data");
+        assert!(!project.symbols.contains_key(&16));
+        project.add_labels("r7 = teleporter_key").unwrap();
+        assert_eq!(project.format_operand(32775), "teleporter_key");
+        assert_eq!(project.format_operand(32774), "r6");
+        assert_eq!(project.format_operand(5), "5");
+        assert_eq!(project.register("teleporter_key"), Some(32775));
+        for text in [
+            "6027",
+            "6027 =",
+            "6027 = two words",
+            "x = name",
+            "r8 = a",
+            "r7",
+        ] {
+            assert!(project.add_labels(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn invalid_directive() {
+        assert_eq!(
+            Project::parse("\nlabel 10 start"),
+            Err(ProjectError::Parse("unknown directive `label`".into(), 2))
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::VecDeque;
+
+use crate::broadcast::{watch_channel, WatchReceiver, WatchSender};
+
+/// The program has printed something and is now waiting on `in`: a prompt is showing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PromptReady {
+    /// The output since the last input was read, up to `PromptDetector::MAX_PROMPT` bytes.
+    pub text: String,
+    /// The position of the `in` waiting for input.
+    pub pos: u16,
+    pub steps: u64,
+}
+
+/// Detects when a prompt is showing and publishes a `PromptReady` for it, the point where bots,
+/// user interfaces and remote clients should send the next command.
+pub struct PromptDetector {
+    sender: WatchSender<Option<PromptReady>>,
+    /// The output since the last input was read.
+    output: VecDeque<u8>,
+    /// The last prompt detected.
+    pub last: Option<PromptReady>,
+}
+
+impl PromptDetector {
+    /// How many bytes of output are kept for a prompt.
+    pub const MAX_PROMPT: usize = 4096;
+
+    /// Creates a detector and a receiver for its events.
+    pub fn new() -> (Self, WatchReceiver<Option<PromptReady>>) {
+        let (sender, receiver) = watch_channel(None);
+        let detector = Self {
+            sender,
+            output: VecDeque::new(),
+            last: None,
+        };
+        (detector, receiver)
+    }
+
+    pub fn record_output(&mut self, byte: u8) {
+        if self.output.len() == Self::MAX_PROMPT {
+            self.output.pop_front();
+        }
+        self.output.push_back(byte);
+    }
+
+    pub fn record_input(&mut self) {
+        self.output.clear();
+    }
+
+    /// Called when the `in` at `pos` is about to wait for input. Fires once per prompt, so an `in`
+    /// that is retried until input arrives doesn't show the same prompt again.
+    pub fn waiting(&mut self, pos: u16, steps: u64) {
+        if self.output.is_empty() {
+            return;
+        }
+        let (front, back) = self.output.as_slices();
+        let prompt = PromptReady {
+            text: String::from_utf8_lossy(&[front, back].concat()).into_owned(),
+            pos,
+            steps,
+        };
+        self.output.clear();
+        self.sender.send(Some(prompt.clone()));
+        self.last = Some(prompt);
+    }
+}
+
+impl Clone for PromptDetector {
+    /// Clones publish to a channel of their own, whose receiver is dropped.
+    fn clone(&self) -> Self {
+        let (mut detector, _) = Self::new();
+        detector.output = self.output.clone();
+        detector.last = self.last.clone();
+        detector
+    }
+}
+
+impl std::fmt::Debug for PromptDetector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PromptDetector")
+            .field("last", &self.last)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for PromptDetector {
+    fn eq(&self, other: &Self) -> bool {
+        self.output == other.output && self.last == other.last
+    }
+}
+
+impl Eq for PromptDetector {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MachineBuilder, RunOutcome};
+
+    #[test]
+    fn detects_prompts() {
+        // 0: out '>'
+        // 2: in r0
+        // 4: in r0
+        // 6: out '>'
+        // 8: in r0
+        let mut machine = MachineBuilder::new()
+            .program(&[19, 62, 20, 32768, 20, 32768, 19, 62, 20, 32768])
+            .input(b"")
+            .build();
+        let (detector, mut receiver) = PromptDetector::new();
+        machine.prompt = Some(detector);
+
+        assert_eq!(machine.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(
+            receiver.changed(),
+            Some(PromptReady {
+                text: ">".to_string(),
+                pos: 2,
+                steps: 1,
+            })
+        );
+        // retrying without input doesn't fire again
+        assert_eq!(machine.run(), Ok(RunOutcome::NeedsInput));
+        assert!(!receiver.has_changed());
+
+        // input that is already there shows no prompt
+        machine.push_input(b"ab");
+        assert_eq!(machine.run(), Ok(RunOutcome::NeedsInput));
+        let prompt = receiver.changed().unwrap();
+        assert_eq!(
+            (prompt.text.as_str(), prompt.pos, prompt.steps),
+            (">", 8, 4)
+        );
+        assert_eq!(machine.prompt.unwrap().last, Some(prompt));
+    }
+}
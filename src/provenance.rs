@@ -0,0 +1,85 @@
+use std::collections::{BTreeMap, VecDeque};
+
+/// Remembers, for every memory address, the positions of the last few instructions that wrote to
+/// it, to trace corrupted data and game state back to the code responsible.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WriteProvenance {
+    /// How many writers are kept per address.
+    pub depth: usize,
+    /// The instruction being executed.
+    current: u16,
+    writers: BTreeMap<u16, VecDeque<u16>>,
+}
+
+impl WriteProvenance {
+    pub const DEFAULT_DEPTH: usize = 4;
+
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            current: 0,
+            writers: BTreeMap::new(),
+        }
+    }
+
+    /// Attributes the following writes to the instruction at `pos`.
+    pub fn begin(&mut self, pos: u16) {
+        self.current = pos;
+    }
+
+    /// Records a write to `addr` by the current instruction.
+    pub fn record(&mut self, addr: u16) {
+        let writers = self.writers.entry(addr).or_default();
+        if writers.len() == self.depth {
+            writers.pop_front();
+        }
+        writers.push_back(self.current);
+    }
+
+    /// The positions of the last instructions that wrote to `addr`, most recent first.
+    pub fn writers(&self, addr: u16) -> impl Iterator<Item = u16> + '_ {
+        self.writers
+            .get(&addr)
+            .into_iter()
+            .flat_map(|writers| writers.iter().rev().copied())
+    }
+
+    /// Every address written to, with its writers, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &VecDeque<u16>)> {
+        self.writers.iter().map(|(&addr, writers)| (addr, writers))
+    }
+}
+
+impl Default for WriteProvenance {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_DEPTH)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::setup, RunOutcome};
+
+    #[test]
+    fn keeps_last_writers() {
+        let mut provenance = WriteProvenance::new(2);
+        for pos in [1, 2, 3] {
+            provenance.begin(pos);
+            provenance.record(100);
+        }
+        assert_eq!(provenance.writers(100).collect::<Vec<_>>(), vec![3, 2]);
+        assert_eq!(provenance.writers(101).count(), 0);
+    }
+
+    #[test]
+    fn records_machine_writes() {
+        // 0: wmem 10 1
+        // 3: add 10 10 1
+        let mut machine = setup(vec![16, 10, 1, 9, 10, 10, 1, 0]);
+        machine.provenance = Some(WriteProvenance::default());
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        let provenance = machine.provenance.unwrap();
+        assert_eq!(provenance.writers(10).collect::<Vec<_>>(), vec![3, 0]);
+    }
+}
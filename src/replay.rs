@@ -0,0 +1,289 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::{
+    io::Io,
+    project,
+    timer::{TimerEvent, Timers},
+    MachineState, RunResult,
+};
+
+/// How many output bytes go on an `out` line.
+const OUTPUT_LINE_LEN: usize = 32;
+
+/// A session as recorded by `run --record`: every byte `in` read, with the number of instructions
+/// executed when it read it, and everything the program printed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub inputs: Vec<(u64, u8)>,
+    pub output: Vec<u8>,
+}
+
+impl Recording {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        Self::parse(&text).map_err(|err| format!("{}: {err}", path.display()))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    /// Parses the line-based format written by `Display`:
+    /// ```text
+    /// # input bytes, with the step count of the `in` that read them
+    /// in 1466 108
+    /// # the output, in hex
+    /// out 57656c636f6d6520746f2074686520
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut recording = Self::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |msg: String| format!("{msg} on line {}", i + 1);
+            match project::split_word(line) {
+                ("in", rest) => {
+                    let (steps, byte) = project::split_word(rest);
+                    let steps = steps
+                        .parse()
+                        .map_err(|_| err(format!("invalid step count `{steps}`")))?;
+                    let byte = byte
+                        .parse()
+                        .map_err(|_| err(format!("invalid byte `{byte}`")))?;
+                    recording.inputs.push((steps, byte));
+                }
+                ("out", hex) => {
+                    if hex.len() % 2 != 0 {
+                        return Err(err(format!("invalid output `{hex}`")));
+                    }
+                    for i in (0..hex.len()).step_by(2) {
+                        let byte = hex
+                            .get(i..i + 2)
+                            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                            .ok_or_else(|| err(format!("invalid output `{hex}`")))?;
+                        recording.output.push(byte);
+                    }
+                }
+                (other, _) => return Err(err(format!("unknown directive `{other}`"))),
+            }
+        }
+        Ok(recording)
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "# input bytes, with the step count of the `in` that read them"
+        )?;
+        for (steps, byte) in &self.inputs {
+            writeln!(f, "in {steps} {byte}")?;
+        }
+        writeln!(f, "# the output, in hex")?;
+        for chunk in self.output.chunks(OUTPUT_LINE_LEN) {
+            let hex = chunk
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>();
+            writeln!(f, "out {hex}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The first difference between a recording and its replay.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The `index`th input byte was read at another step count, or only by one of them.
+    Input {
+        index: usize,
+        expected: Option<(u64, u8)>,
+        actual: Option<(u64, u8)>,
+    },
+    /// The output differs at `offset`, or only one of them goes on to it.
+    Output {
+        offset: usize,
+        expected: Option<u8>,
+        actual: Option<u8>,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn input(read: Option<(u64, u8)>) -> String {
+            match read {
+                Some((steps, byte)) => format!(
+                    "`{}` read after {steps} steps",
+                    (byte as char).escape_default()
+                ),
+                None => "nothing".to_string(),
+            }
+        }
+        fn output(byte: Option<u8>) -> String {
+            match byte {
+                Some(byte) => format!("`{}`", (byte as char).escape_default()),
+                None => "the end of the output".to_string(),
+            }
+        }
+
+        match *self {
+            Mismatch::Input {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "input byte {index}: expected {}, got {}",
+                input(expected),
+                input(actual)
+            ),
+            Mismatch::Output {
+                offset,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "output byte {offset}: expected {}, got {}",
+                output(expected),
+                output(actual)
+            ),
+        }
+    }
+}
+
+/// The result of replaying a recording.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    pub result: RunResult,
+    /// What the replay read and printed.
+    pub recording: Recording,
+    pub mismatch: Option<Mismatch>,
+}
+
+impl Replay {
+    pub fn passed(&self) -> bool {
+        self.result.is_ok() && self.mismatch.is_none()
+    }
+}
+
+/// Replays `recording` on `machine` for at most `fuel` instructions, feeding every input byte
+/// right before the instruction that read it, and compares what the replay reads and prints with
+/// the recording.
+pub fn replay(mut machine: MachineState, recording: &Recording, fuel: u64) -> Replay {
+    let mut timers = Timers::new();
+    for &(steps, byte) in &recording.inputs {
+        // `in` counts itself before it reads
+        timers.at(steps.saturating_sub(1), TimerEvent::Input(vec![byte]));
+    }
+    machine.timers = Some(timers);
+    machine.io = Io::buffered();
+    machine.recording = Some(Recording::new());
+    let result = machine.run_for(fuel);
+    let replayed = machine.recording.take().unwrap_or_default();
+
+    let inputs =
+        first_difference(&recording.inputs, &replayed.inputs).map(|(index, expected, actual)| {
+            Mismatch::Input {
+                index,
+                expected,
+                actual,
+            }
+        });
+    let output =
+        first_difference(&recording.output, &replayed.output).map(|(offset, expected, actual)| {
+            Mismatch::Output {
+                offset,
+                expected,
+                actual,
+            }
+        });
+    Replay {
+        result,
+        recording: replayed,
+        mismatch: inputs.or(output),
+    }
+}
+
+/// The first index at which `expected` and `actual` differ, with their items there.
+fn first_difference<T: Copy + PartialEq>(
+    expected: &[T],
+    actual: &[T],
+) -> Option<(usize, Option<T>, Option<T>)> {
+    (0..expected.len().max(actual.len()))
+        .map(|i| (i, expected.get(i).copied(), actual.get(i).copied()))
+        .find(|(_, expected, actual)| expected != actual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MachineBuilder, RunOutcome};
+
+    // 0: out '>'
+    // 2: in r0
+    // 4: out r0
+    // 6: jmp 0
+    const ECHO: [u16; 8] = [19, 62, 20, 32768, 19, 32768, 6, 0];
+
+    fn record(program: &[u16], input: &[u8]) -> Recording {
+        let mut machine = MachineBuilder::new().program(program).input(input).build();
+        machine.recording = Some(Recording::new());
+        assert_eq!(machine.run(), Ok(RunOutcome::NeedsInput));
+        machine.recording.unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let recording = record(&ECHO, b"hi");
+        assert_eq!(recording.inputs, [(2, b'h'), (6, b'i')]);
+        assert_eq!(recording.output, b">h>i>");
+        assert_eq!(Recording::parse(&recording.to_string()), Ok(recording));
+        assert!(Recording::parse("in 2").is_err());
+        assert!(Recording::parse("out 6").is_err());
+        assert!(Recording::parse("out zz").is_err());
+    }
+
+    #[test]
+    fn replays_sessions() {
+        let recording = record(&ECHO, b"hi");
+        let replayed = replay(MachineState::new(ECHO.to_vec()), &recording, 1000);
+        assert_eq!(replayed.result, Ok(RunOutcome::NeedsInput));
+        assert_eq!(replayed.mismatch, None);
+
+        // printing `<` instead
+        let mut changed = ECHO;
+        changed[1] = 60;
+        let replayed = replay(MachineState::new(changed.to_vec()), &recording, 1000);
+        assert_eq!(
+            replayed.mismatch,
+            Some(Mismatch::Output {
+                offset: 0,
+                expected: Some(b'>'),
+                actual: Some(b'<')
+            })
+        );
+
+        // reading a step later
+        let mut changed = vec![21];
+        changed.extend(ECHO.iter().map(|&word| if word == 0 { 1 } else { word }));
+        let replayed = replay(MachineState::new(changed), &recording, 1000);
+        assert_eq!(
+            replayed.mismatch,
+            Some(Mismatch::Input {
+                index: 0,
+                expected: Some((2, b'h')),
+                actual: Some((3, b'h'))
+            })
+        );
+    }
+}
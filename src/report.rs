@@ -0,0 +1,285 @@
+//! Turns the transcript of a playthrough into a self-contained HTML write-up, with a map of the
+//! rooms visited and a timeline of the commands entered.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::profile::xml_escape;
+
+/// What the challenge prints before reading each command.
+pub const PROMPT: &str = "What do you do?";
+
+const CELL_WIDTH: i32 = 150;
+const CELL_HEIGHT: i32 = 70;
+const ROOM_WIDTH: i32 = 130;
+const ROOM_HEIGHT: i32 = 36;
+
+/// A command and what the program printed in response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// `None` for the output before the first command.
+    pub command: Option<String>,
+    /// The room the player is in after the command.
+    pub room: Option<String>,
+    pub output: String,
+    /// The codes that first appeared in this output.
+    pub codes: Vec<String>,
+}
+
+/// A playthrough, split into steps at every prompt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    pub steps: Vec<Step>,
+}
+
+impl Session {
+    /// Lines up `transcript`, the output of a run, with `input`, the commands it read, one per
+    /// line, and finds where each of `codes` was printed.
+    pub fn parse(transcript: &str, input: &str, codes: &[String]) -> Self {
+        let mut commands = input.lines().map(|line| line.trim().to_string());
+        let mut room = None;
+        let mut found: Vec<String> = Vec::new();
+        let mut steps = Vec::new();
+        for (i, output) in transcript.split(PROMPT).enumerate() {
+            let command = if i == 0 { None } else { commands.next() };
+            if let Some(name) = output.lines().rev().find_map(room_name) {
+                room = Some(name);
+            }
+            let codes = codes
+                .iter()
+                .filter(|&code| !found.contains(code) && output.contains(code.as_str()))
+                .cloned()
+                .collect::<Vec<_>>();
+            found.extend(codes.iter().cloned());
+            steps.push(Step {
+                command,
+                room: room.clone(),
+                output: output.to_string(),
+                codes,
+            });
+        }
+        Self { steps }
+    }
+
+    /// Every move from one room to another, with the command that made it.
+    pub fn moves(&self) -> Vec<(String, String, String)> {
+        let mut moves = Vec::new();
+        for pair in self.steps.windows(2) {
+            if let ([Some(from), Some(to)], Some(command)) =
+                ([&pair[0].room, &pair[1].room], &pair[1].command)
+            {
+                let edge = (from.clone(), command.clone(), to.clone());
+                if from != to && !moves.contains(&edge) {
+                    moves.push(edge);
+                }
+            }
+        }
+        moves
+    }
+}
+
+/// The name in a room heading such as `== Foothills ==`.
+fn room_name(line: &str) -> Option<String> {
+    let name = line.trim().strip_prefix("==")?.strip_suffix("==")?.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Where going `command` leads on the map, relative to the room it is entered in.
+fn direction(command: &str) -> (i32, i32) {
+    match command.trim_start_matches("go ") {
+        "north" => (0, -1),
+        "south" => (0, 1),
+        "east" => (1, 0),
+        "west" => (-1, 0),
+        "up" | "ladder" => (1, -1),
+        "down" => (-1, 1),
+        _ => (1, 1),
+    }
+}
+
+/// Lays out the rooms on a grid following the directions taken, moving rooms that would overlap
+/// to the nearest free cell.
+fn layout(session: &Session) -> BTreeMap<String, (i32, i32)> {
+    let mut cells = BTreeMap::new();
+    let Some(first) = session.steps.iter().find_map(|step| step.room.clone()) else {
+        return cells;
+    };
+    cells.insert(first, (0, 0));
+    for (from, command, to) in session.moves() {
+        if cells.contains_key(&to) {
+            continue;
+        }
+        let (x, y) = cells[&from];
+        let (dx, dy) = direction(&command);
+        let mut cell = (x + dx, y + dy);
+        let mut distance = 1;
+        while cells.values().any(|&taken| taken == cell) {
+            distance += 1;
+            cell = (
+                x + dx * distance,
+                y + dy * distance + (distance - 1) * dx.abs(),
+            );
+        }
+        cells.insert(to, cell);
+    }
+    cells
+}
+
+/// Draws the rooms visited and the moves between them as an SVG image.
+pub fn map_svg(session: &Session) -> String {
+    let cells = layout(session);
+    let min_x = cells.values().map(|&(x, _)| x).min().unwrap_or(0);
+    let min_y = cells.values().map(|&(_, y)| y).min().unwrap_or(0);
+    let max_x = cells.values().map(|&(x, _)| x).max().unwrap_or(0);
+    let max_y = cells.values().map(|&(_, y)| y).max().unwrap_or(0);
+    let center = |(x, y): (i32, i32)| {
+        (
+            (x - min_x) * CELL_WIDTH + CELL_WIDTH / 2,
+            (y - min_y) * CELL_HEIGHT + CELL_HEIGHT / 2,
+        )
+    };
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         font-family=\"sans-serif\" font-size=\"11\">",
+        (max_x - min_x + 1) * CELL_WIDTH,
+        (max_y - min_y + 1) * CELL_HEIGHT
+    );
+    for (from, command, to) in session.moves() {
+        let (x1, y1) = center(cells[&from]);
+        let (x2, y2) = center(cells[&to]);
+        let _ = writeln!(
+            out,
+            "<line x1=\"{x1}\" y1=\"{y1}\" x2=\"{x2}\" y2=\"{y2}\" stroke=\"#999\">\
+             <title>{}</title></line>",
+            xml_escape(&command)
+        );
+    }
+    for (name, &cell) in &cells {
+        let (x, y) = center(cell);
+        let _ = writeln!(
+            out,
+            "<g><title>{0}</title><rect x=\"{1}\" y=\"{2}\" width=\"{ROOM_WIDTH}\" \
+             height=\"{ROOM_HEIGHT}\" rx=\"4\" fill=\"#eef\" stroke=\"#336\"/>\
+             <text x=\"{x}\" y=\"{3}\" text-anchor=\"middle\">{0}</text></g>",
+            xml_escape(name),
+            x - ROOM_WIDTH / 2,
+            y - ROOM_HEIGHT / 2,
+            y + 4,
+        );
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Renders the session as an HTML page that needs no other files.
+pub fn html(session: &Session) -> String {
+    let mut out = String::new();
+    out.push_str(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Synacor session report</title>\n\
+         <style>body{font-family:sans-serif;max-width:1100px;margin:auto}\
+         td,th{padding:2px 8px;text-align:left;vertical-align:top}\
+         tr:nth-child(even){background:#f4f4f4}.code{font-weight:bold;color:#a00}\
+         pre{white-space:pre-wrap}</style></head><body>\n<h1>Synacor session report</h1>\n",
+    );
+
+    let codes = session
+        .steps
+        .iter()
+        .flat_map(|step| &step.codes)
+        .collect::<Vec<_>>();
+    let _ = writeln!(
+        out,
+        "<p>{} commands, {} rooms, {} codes.</p>",
+        session.steps.len().saturating_sub(1),
+        layout(session).len(),
+        codes.len()
+    );
+    if !codes.is_empty() {
+        out.push_str("<h2>Codes</h2>\n<ol>\n");
+        for code in codes {
+            let _ = writeln!(out, "<li class=\"code\">{}</li>", xml_escape(code));
+        }
+        out.push_str("</ol>\n");
+    }
+
+    out.push_str("<h2>Map</h2>\n");
+    out.push_str(&map_svg(session));
+
+    out.push_str("<h2>Timeline</h2>\n<table>\n<tr><th>#</th><th>command</th><th>room</th><th>codes</th></tr>\n");
+    for (i, step) in session.steps.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "<tr><td>{i}</td><td>{}</td><td>{}</td><td class=\"code\">{}</td></tr>",
+            xml_escape(step.command.as_deref().unwrap_or("(start)")),
+            xml_escape(step.room.as_deref().unwrap_or("")),
+            xml_escape(&step.codes.join(" ")),
+        );
+    }
+    out.push_str("</table>\n");
+
+    out.push_str("<h2>Transcript</h2>\n<details><summary>Show</summary>\n<pre>");
+    for step in &session.steps {
+        if let Some(command) = &step.command {
+            let _ = write!(out, "{PROMPT}\n<b>&gt; {}</b>", xml_escape(command));
+        }
+        out.push_str(&xml_escape(&step.output));
+    }
+    out.push_str("</pre></details>\n</body></html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        let transcript = "Welcome!\n\n== Foothills ==\nA path.\n\nWhat do you do?\
+                          \n\n== Dark cave ==\nIt is dark. abcDEF123\n\nWhat do you do?\
+                          \n\nI don't understand.\n\nWhat do you do?\
+                          \n\n== Foothills ==\nA path.\n\nWhat do you do?";
+        let codes = vec!["abcDEF123".to_string(), "neverSeen".to_string()];
+        Session::parse(transcript, "go north\nfly\nsouth\n", &codes)
+    }
+
+    #[test]
+    fn splits_steps() {
+        let session = session();
+        let rooms = session
+            .steps
+            .iter()
+            .map(|step| (step.command.as_deref(), step.room.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rooms[..4],
+            [
+                (None, Some("Foothills")),
+                (Some("go north"), Some("Dark cave")),
+                (Some("fly"), Some("Dark cave")),
+                (Some("south"), Some("Foothills")),
+            ]
+        );
+        assert_eq!(session.steps[1].codes, vec!["abcDEF123"]);
+        assert_eq!(
+            session.moves(),
+            vec![
+                ("Foothills".into(), "go north".into(), "Dark cave".into()),
+                ("Dark cave".into(), "south".into(), "Foothills".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lays_out_map() {
+        let cells = layout(&session());
+        assert_eq!(cells["Foothills"], (0, 0));
+        assert_eq!(cells["Dark cave"], (0, -1));
+
+        let html = html(&session());
+        assert!(html.contains("<li class=\"code\">abcDEF123</li>"));
+        assert!(html.contains("<svg "));
+        assert!(html.contains("<b>&gt; go north</b>"));
+    }
+}
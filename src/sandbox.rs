@@ -0,0 +1,167 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// A resource capped by a `Sandbox`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resource {
+    Instructions,
+    Output,
+    StackDepth,
+    WallTime,
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resource::Instructions => write!(f, "instructions"),
+            Resource::Output => write!(f, "output bytes"),
+            Resource::StackDepth => write!(f, "stack depth"),
+            Resource::WallTime => write!(f, "wall time"),
+        }
+    }
+}
+
+/// Caps on the resources an untrusted program may use over its whole run, unlike `RunLimits`,
+/// which only apply to a single call to `run_with`. A program that goes over a cap stops with
+/// `RunOutcome::ResourceLimit` right after the instruction that did, and stays stopped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Sandbox {
+    pub max_steps: Option<u64>,
+    pub max_output: Option<u64>,
+    pub max_stack: Option<usize>,
+    pub max_time: Option<Duration>,
+    /// The number of bytes written by `out` so far.
+    pub output: u64,
+    /// When the program first ran in the sandbox.
+    started: Option<Instant>,
+}
+
+impl Sandbox {
+    /// No caps at all, to set only some of them.
+    pub fn unlimited() -> Self {
+        Self {
+            max_steps: None,
+            max_output: None,
+            max_stack: None,
+            max_time: None,
+            output: 0,
+            started: None,
+        }
+    }
+
+    /// The caps for programs uploaded by users: they are far beyond what the challenge needs.
+    pub fn untrusted() -> Self {
+        Self {
+            max_steps: Some(1_000_000_000),
+            max_output: Some(1 << 20),
+            max_stack: Some(1 << 16),
+            max_time: Some(Duration::from_secs(60)),
+            ..Self::unlimited()
+        }
+    }
+
+    /// Checks the caps before the next instruction runs. The wall time is only checked when
+    /// `check_time` is set, since reading the clock is comparatively slow.
+    pub fn check(&mut self, steps: u64, stack_depth: usize, check_time: bool) -> Option<Resource> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        if self.max_steps.is_some_and(|max| steps >= max) {
+            Some(Resource::Instructions)
+        } else if self.max_output.is_some_and(|max| self.output > max) {
+            Some(Resource::Output)
+        } else if self.max_stack.is_some_and(|max| stack_depth > max) {
+            Some(Resource::StackDepth)
+        } else if check_time && self.max_time.is_some_and(|max| started.elapsed() >= max) {
+            Some(Resource::WallTime)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self::untrusted()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MachineBuilder, ExecutionError, RunOutcome};
+
+    fn run(program: &[u16], sandbox: Sandbox) -> RunOutcome {
+        let mut machine = MachineBuilder::new().program(program).input(b"").build();
+        machine.sandbox = Some(sandbox);
+        machine.run().unwrap()
+    }
+
+    #[test]
+    fn caps_resources() {
+        // jmp 0
+        let sandbox = Sandbox {
+            max_steps: Some(10),
+            ..Sandbox::unlimited()
+        };
+        assert_eq!(
+            run(&[6, 0], sandbox),
+            RunOutcome::ResourceLimit(Resource::Instructions)
+        );
+
+        // out 'a', jmp 0
+        let sandbox = Sandbox {
+            max_output: Some(3),
+            ..Sandbox::unlimited()
+        };
+        assert_eq!(
+            run(&[19, 97, 6, 0], sandbox),
+            RunOutcome::ResourceLimit(Resource::Output)
+        );
+
+        // push 1, jmp 0
+        let sandbox = Sandbox {
+            max_stack: Some(5),
+            ..Sandbox::unlimited()
+        };
+        assert_eq!(
+            run(&[2, 1, 6, 0], sandbox),
+            RunOutcome::ResourceLimit(Resource::StackDepth)
+        );
+
+        let sandbox = Sandbox {
+            max_time: Some(Duration::ZERO),
+            ..Sandbox::unlimited()
+        };
+        assert_eq!(
+            run(&[6, 0], sandbox),
+            RunOutcome::ResourceLimit(Resource::WallTime)
+        );
+
+        // halt
+        assert_eq!(run(&[0], Sandbox::untrusted()), RunOutcome::Halted);
+    }
+
+    #[test]
+    fn survives_division_by_zero() {
+        // mod r0 r1 0
+        let mut machine = MachineBuilder::new()
+            .program(&[11, 32768, 32769, 0])
+            .input(b"")
+            .build();
+        machine.sandbox = Some(Sandbox::untrusted());
+        assert_eq!(machine.run(), Err(ExecutionError::DivisionByZero(0)));
+    }
+
+    #[test]
+    fn stays_stopped() {
+        let mut machine = MachineBuilder::new().program(&[2, 1, 6, 0]).build();
+        machine.sandbox = Some(Sandbox {
+            max_stack: Some(1),
+            ..Sandbox::unlimited()
+        });
+        let outcome = RunOutcome::ResourceLimit(Resource::StackDepth);
+        assert_eq!(machine.run(), Ok(outcome));
+        let steps = machine.steps;
+        assert_eq!(machine.run(), Ok(outcome));
+        assert_eq!(machine.steps, steps);
+    }
+}
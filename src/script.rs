@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+
+use crate::MachineState;
+
+/// Commands from a file, fed to `in` before any other input, to replay a walkthrough up to where
+/// play continues from the keyboard.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Script {
+    pending: VecDeque<u8>,
+    /// Whether the commands are written to the output as they are read, so they show up among the
+    /// responses the way typed ones do on a terminal.
+    pub echo: bool,
+}
+
+impl Script {
+    /// A script of `commands`, given a final newline if it lacks one so the last command is read
+    /// whole.
+    pub fn new(commands: &[u8]) -> Self {
+        let mut pending = commands.iter().copied().collect::<VecDeque<_>>();
+        if pending.back().is_some_and(|&byte| byte != b'\n') {
+            pending.push_back(b'\n');
+        }
+        Self {
+            pending,
+            echo: false,
+        }
+    }
+
+    /// How many bytes of it are left to read.
+    pub fn remaining(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+impl MachineState {
+    /// Takes the next input byte of the script.
+    pub(crate) fn script_input(&mut self) -> Option<u8> {
+        let script = self.script.as_mut()?;
+        let byte = script.pending.pop_front()?;
+        if script.echo {
+            self.io.write_byte(byte);
+        }
+        Some(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MachineBuilder, RunOutcome};
+
+    #[test]
+    fn feeds_the_script_first() {
+        // 0: in r0
+        // 2: out r0
+        // 4: jmp 0
+        let mut machine = MachineBuilder::new()
+            .program(&[20, 32768, 19, 32768, 6, 0])
+            .input(b"c\n")
+            .build();
+        let mut script = Script::new(b"a\nb");
+        script.echo = true;
+        machine.script = Some(script);
+        assert_eq!(machine.run(), Ok(RunOutcome::NeedsInput));
+        // echoed, then printed by the program
+        assert_eq!(machine.drain_output(), b"aa\n\nbb\n\nc\n");
+        assert_eq!(machine.script.unwrap().remaining(), 0);
+    }
+}
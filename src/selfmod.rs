@@ -0,0 +1,84 @@
+use std::fmt::Write as _;
+use std::ops::Range;
+
+use crate::instruction::disassemble;
+
+/// Changed words separated by at most this many unchanged ones are reported as one range.
+const MERGE_GAP: usize = 2;
+/// How many instructions of each side of a range are shown in a report.
+const MAX_LINES: usize = 12;
+
+/// The ranges of addresses whose words differ between `before` and `after`.
+pub fn changed_ranges(before: &[u16], after: &[u16]) -> Vec<Range<usize>> {
+    let len = before.len().max(after.len());
+    let differs = |i: usize| before.get(i) != after.get(i);
+
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for i in (0..len).filter(|&i| differs(i)) {
+        match ranges.last_mut() {
+            Some(last) if i - last.end <= MERGE_GAP => last.end = i + 1,
+            _ => ranges.push(i..i + 1),
+        }
+    }
+    ranges
+}
+
+/// Summarizes how a program rewrote itself: every changed range of memory, with its disassembly
+/// before and after.
+pub fn report(before: &[u16], after: &[u16]) -> String {
+    let ranges = changed_ranges(before, after);
+    let total = ranges.iter().map(|range| range.len()).sum::<usize>();
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{} range(s) of memory changed, spanning {total} word(s)",
+        ranges.len()
+    );
+    for range in ranges {
+        let _ = writeln!(
+            out,
+            "\n{}..{} ({} words)",
+            range.start,
+            range.end,
+            range.len()
+        );
+        for (label, mem) in [("before", before), ("after", after)] {
+            let _ = writeln!(out, "  {label}:");
+            let mut addr = range.start;
+            let mut lines = 0;
+            while addr < range.end && addr < mem.len() {
+                if lines == MAX_LINES {
+                    let _ = writeln!(out, "    ...");
+                    break;
+                }
+                let (text, len) = disassemble(mem, addr);
+                let _ = writeln!(out, "    {addr:5}: {text}");
+                addr += len;
+                lines += 1;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        let before = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let after = [1, 0, 0, 1, 0, 0, 0, 0, 1, 1];
+        assert_eq!(changed_ranges(&before, &after), vec![0..4, 8..10]);
+        assert!(changed_ranges(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn report_shows_both_sides() {
+        let report = report(&[21, 21, 0], &[19, 97, 0]);
+        assert!(report.starts_with("1 range(s) of memory changed, spanning 2 word(s)"));
+        assert!(report.contains("before:\n        0: noop\n        1: noop\n"));
+        assert!(report.contains("after:\n        0: out 'a'\n"));
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+
+use crate::{MachineState, RunOutcome, RunResult, REGISTER_COUNT};
+
+/// A copy of the machine's state, published by the worker of a `SharedMachine`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MachineSnapshot {
+    pub mem: Vec<u16>,
+    pub cur: u16,
+    pub registers: [u16; REGISTER_COUNT],
+    pub stack: Vec<u16>,
+    pub steps: u64,
+    /// Why the worker last stopped, or `None` while it is running.
+    pub outcome: Option<RunResult>,
+}
+
+impl MachineSnapshot {
+    fn of(machine: &MachineState, outcome: Option<RunResult>) -> Self {
+        Self {
+            mem: machine.mem.clone(),
+            cur: machine.cur,
+            registers: machine.registers,
+            stack: machine.stack.as_slice().to_vec(),
+            steps: machine.steps,
+            outcome,
+        }
+    }
+}
+
+enum Command {
+    Input(Vec<u8>),
+    Pause,
+    Resume,
+    Stop,
+}
+
+/// Runs a machine on a worker thread while other threads inspect it.
+///
+/// The worker executes `chunk` instructions at a time with `poll_step`, publishing a snapshot
+/// after every chunk. Readers only ever clone an `Arc` to the latest snapshot, so repainting a UI
+/// never pauses execution.
+pub struct SharedMachine {
+    snapshot: Arc<RwLock<Arc<MachineSnapshot>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+    commands: Sender<Command>,
+    worker: JoinHandle<MachineState>,
+}
+
+impl SharedMachine {
+    pub fn spawn(machine: MachineState, chunk: u64) -> Self {
+        let snapshot = Arc::new(RwLock::new(Arc::new(MachineSnapshot::of(&machine, None))));
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let (commands, receiver) = mpsc::channel();
+
+        let worker = {
+            let snapshot = Arc::clone(&snapshot);
+            let output = Arc::clone(&output);
+            thread::spawn(move || work(machine, chunk.max(1), receiver, snapshot, output))
+        };
+
+        Self {
+            snapshot,
+            output,
+            commands,
+            worker,
+        }
+    }
+
+    /// The most recently published state of the machine.
+    pub fn snapshot(&self) -> Arc<MachineSnapshot> {
+        Arc::clone(&self.snapshot.read().unwrap())
+    }
+
+    /// Queues input for the machine, resuming it if it was waiting for input.
+    pub fn push_input(&self, bytes: &[u8]) {
+        let _ = self.commands.send(Command::Input(bytes.to_vec()));
+    }
+
+    /// Takes everything the machine has written since the last call.
+    pub fn drain_output(&self) -> Vec<u8> {
+        std::mem::take(&mut self.output.lock().unwrap())
+    }
+
+    pub fn pause(&self) {
+        let _ = self.commands.send(Command::Pause);
+    }
+
+    pub fn resume(&self) {
+        let _ = self.commands.send(Command::Resume);
+    }
+
+    /// Stops the worker and hands back the machine.
+    pub fn join(self) -> MachineState {
+        let _ = self.commands.send(Command::Stop);
+        self.worker.join().expect("machine worker panicked")
+    }
+}
+
+fn work(
+    mut machine: MachineState,
+    chunk: u64,
+    commands: Receiver<Command>,
+    snapshot: Arc<RwLock<Arc<MachineSnapshot>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+) -> MachineState {
+    let mut running = true;
+    loop {
+        // only block on commands while there is nothing to execute
+        let command = if running {
+            match commands.try_recv() {
+                Ok(command) => Some(command),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return machine,
+            }
+        } else {
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => return machine,
+            }
+        };
+
+        match command {
+            Some(Command::Input(bytes)) => {
+                machine.push_input(&bytes);
+                running = !machine.halted;
+            }
+            Some(Command::Pause) => running = false,
+            Some(Command::Resume) => running = !machine.halted,
+            Some(Command::Stop) => return machine,
+            None => {}
+        }
+        if !running {
+            continue;
+        }
+
+        let outcome = machine.poll_step(chunk);
+        output.lock().unwrap().extend(machine.drain_output());
+        running = matches!(outcome, Ok(RunOutcome::FuelExhausted));
+        let outcome = (!running).then_some(outcome);
+        *snapshot.write().unwrap() = Arc::new(MachineSnapshot::of(&machine, outcome));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{setup, MachineBuilder};
+
+    #[test]
+    fn runs_in_background() {
+        // echo characters forever
+        let machine = MachineBuilder::new()
+            .program(&[20, 32768, 19, 32768, 6, 0])
+            .input(b"hi")
+            .build();
+        let shared = SharedMachine::spawn(machine, 100);
+
+        let snapshot = loop {
+            let snapshot = shared.snapshot();
+            if snapshot.outcome.is_some() {
+                break snapshot;
+            }
+            thread::yield_now();
+        };
+        assert_eq!(snapshot.outcome, Some(Ok(RunOutcome::NeedsInput)));
+        assert_eq!(shared.drain_output(), b"hi");
+
+        let machine = shared.join();
+        assert_eq!(machine.registers[0], b'i' as u16);
+    }
+
+    #[test]
+    fn input_resumes() {
+        let shared = SharedMachine::spawn(setup(vec![20, 32768, 0]), 100);
+        shared.push_input(b"x");
+        while shared.snapshot().outcome != Some(Ok(RunOutcome::Halted)) {
+            thread::yield_now();
+        }
+        assert_eq!(shared.join().registers[0], b'x' as u16);
+    }
+}
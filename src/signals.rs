@@ -0,0 +1,68 @@
+//! Pausing and resuming long runs from outside: `SIGUSR1` pauses, `SIGUSR2` resumes.
+//!
+//! The handlers only set flags, which the run loop polls between chunks of instructions.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How many instructions run between checks for a pause request.
+pub const CHECK_INTERVAL: u64 = 1 << 16;
+
+const RESUME_POLL: Duration = Duration::from_millis(100);
+
+static PAUSE: AtomicBool = AtomicBool::new(false);
+static RESUME: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_pause(_: libc::c_int) {
+    PAUSE.store(true, Ordering::SeqCst);
+}
+
+extern "C" fn on_resume(_: libc::c_int) {
+    RESUME.store(true, Ordering::SeqCst);
+}
+
+/// Installs the `SIGUSR1` and `SIGUSR2` handlers.
+pub fn install() {
+    let pause: extern "C" fn(libc::c_int) = on_pause;
+    let resume: extern "C" fn(libc::c_int) = on_resume;
+    // SAFETY: the handlers only store to atomics, which is async-signal-safe
+    unsafe {
+        libc::signal(libc::SIGUSR1, pause as libc::sighandler_t);
+        libc::signal(libc::SIGUSR2, resume as libc::sighandler_t);
+    }
+}
+
+/// Whether a pause was requested since the last call.
+pub fn take_pause() -> bool {
+    PAUSE.swap(false, Ordering::SeqCst)
+}
+
+/// Blocks until a resume is requested. Resume requests sent before the pause are ignored.
+pub fn wait_for_resume() {
+    RESUME.store(false, Ordering::SeqCst);
+    while !RESUME.swap(false, Ordering::SeqCst) {
+        std::thread::sleep(RESUME_POLL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags() {
+        install();
+        assert!(!take_pause());
+        // SAFETY: raising a signal whose handler was just installed
+        unsafe { libc::raise(libc::SIGUSR1) };
+        assert!(take_pause());
+        assert!(!take_pause());
+
+        // the waiter ignores resumes sent before it starts, so keep sending them
+        let waiter = std::thread::spawn(wait_for_resume);
+        while !waiter.is_finished() {
+            unsafe { libc::raise(libc::SIGUSR2) };
+            std::thread::sleep(RESUME_POLL);
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! Named save slots kept next to an image, and autosaving to one of them while playing.
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    crash::{CrashDump, SNAPSHOT_EXTENSION},
+    MachineState,
+};
+
+/// The slot autosaves go to by default.
+pub const AUTOSAVE_SLOT: &str = "autosave";
+
+/// Checks that `name` can name a slot. Names are used in file names, so they may only hold
+/// letters, digits, `_` and `-`.
+pub fn check_name(name: &str) -> Result<(), String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    if name.is_empty() || !name.chars().all(valid) {
+        return Err(format!("invalid slot name `{name}`"));
+    }
+    Ok(())
+}
+
+/// A saved slot, as listed by `Slots::list`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Slot {
+    pub name: String,
+    pub steps: u64,
+    pub cur: u16,
+    /// Why it was saved.
+    pub reason: String,
+    /// The slot the machine was restored from before it was saved.
+    pub parent: Option<String>,
+}
+
+/// The save slots of an image: the snapshots named `<image>.<name>.snapshot` next to it, which
+/// include the checkpoints of scripts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Slots {
+    pub image: PathBuf,
+}
+
+impl Slots {
+    pub fn new(image: impl Into<PathBuf>) -> Self {
+        Self {
+            image: image.into(),
+        }
+    }
+
+    pub fn path(&self, name: &str) -> Result<PathBuf, String> {
+        check_name(name)?;
+        Ok(self
+            .image
+            .with_extension(format!("{name}.{SNAPSHOT_EXTENSION}")))
+    }
+
+    fn dir(&self) -> &Path {
+        match self.image.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        }
+    }
+
+    /// The name of the slot saved at `path`, if it is one of these slots.
+    pub fn name_of(&self, path: &Path) -> Option<String> {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+        if dir.unwrap_or(Path::new(".")) != self.dir() {
+            return None;
+        }
+        let stem = self.image.file_stem()?.to_string_lossy().into_owned();
+        let file_name = path.file_name()?.to_string_lossy().into_owned();
+        let name = file_name
+            .strip_prefix(&format!("{stem}."))?
+            .strip_suffix(&format!(".{SNAPSHOT_EXTENSION}"))?;
+        check_name(name).ok()?;
+        Some(name.to_string())
+    }
+
+    /// The saved slots, by name.
+    pub fn list(&self) -> Result<Vec<Slot>, String> {
+        let dir = self.dir();
+        let entries = std::fs::read_dir(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+
+        let mut slots = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|err| format!("{}: {err}", dir.display()))?;
+            let Some(name) = self.name_of(&dir.join(entry.file_name())) else {
+                continue;
+            };
+            let dump = CrashDump::load(entry.path())?;
+            slots.push(Slot {
+                name,
+                steps: dump.steps,
+                cur: dump.cur,
+                reason: dump.reason,
+                parent: dump.parent,
+            });
+        }
+        slots.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(slots)
+    }
+
+    /// Saves `machine` as the slot `name`, replacing it, and returns where it was written. The slot
+    /// becomes the parent of what the machine saves next.
+    pub fn save(&self, name: &str, machine: &mut MachineState) -> Result<PathBuf, String> {
+        let path = self.path(name)?;
+        machine
+            .save(&path)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        machine.parent = Some(name.to_string());
+        Ok(path)
+    }
+
+    /// Loads the slot `name`, which becomes the parent of what the machine saves.
+    pub fn load(&self, name: &str) -> Result<MachineState, String> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            return Err(format!("there is no slot `{name}`"));
+        }
+        let mut machine = MachineState::load(path)?;
+        machine.parent = Some(name.to_string());
+        Ok(machine)
+    }
+
+    /// Draws the slots as a tree, each under the slot it was saved from. Slots saved from a slot
+    /// that is gone are drawn at the top.
+    pub fn tree(&self) -> Result<String, String> {
+        let slots = self.list()?;
+        let names = slots
+            .iter()
+            .map(|slot| slot.name.as_str())
+            .collect::<BTreeSet<_>>();
+        let mut out = String::new();
+        let mut drawn = BTreeSet::new();
+        let roots = slots.iter().filter(|slot| {
+            slot.parent
+                .as_deref()
+                .is_none_or(|parent| !names.contains(parent))
+        });
+        for root in roots {
+            draw_tree(&slots, root, 0, &mut drawn, &mut out);
+        }
+        // the slots of a cycle, left by saving a slot over one of its ancestors
+        for slot in &slots {
+            if !drawn.contains(slot.name.as_str()) {
+                draw_tree(&slots, slot, 0, &mut drawn, &mut out);
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<(), String> {
+        let path = self.path(name)?;
+        std::fs::remove_file(&path).map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => format!("there is no slot `{name}`"),
+            _ => format!("{}: {err}", path.display()),
+        })
+    }
+}
+
+fn draw_tree<'a>(
+    slots: &'a [Slot],
+    slot: &'a Slot,
+    depth: usize,
+    drawn: &mut BTreeSet<&'a str>,
+    out: &mut String,
+) {
+    if !drawn.insert(&slot.name) {
+        return;
+    }
+    let _ = writeln!(
+        out,
+        "{:indent$}{}  {} steps at {}",
+        "",
+        slot.name,
+        slot.steps,
+        slot.cur,
+        indent = depth * 2
+    );
+    let children = slots
+        .iter()
+        .filter(|child| child.parent.as_deref() == Some(slot.name.as_str()));
+    for child in children {
+        draw_tree(slots, child, depth + 1, drawn, out);
+    }
+}
+
+/// Saves the machine to a snapshot as it runs, so a crash of the host or of the game loses little
+/// play: every `every` instructions, and at every prompt, when `in` is about to read a new line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Autosave {
+    pub path: PathBuf,
+    pub every: Option<u64>,
+    pub at_prompts: bool,
+    /// How many times it saved.
+    pub saves: usize,
+    /// Why the last save failed, if it did. Saving is retried at the next chance.
+    pub error: Option<String>,
+    /// The step count of the last save.
+    last: u64,
+    /// Whether the next input byte starts a line.
+    at_line_start: bool,
+}
+
+impl Autosave {
+    pub fn new(path: impl Into<PathBuf>, every: Option<u64>, at_prompts: bool) -> Self {
+        Self {
+            path: path.into(),
+            every,
+            at_prompts,
+            saves: 0,
+            error: None,
+            last: 0,
+            at_line_start: true,
+        }
+    }
+
+    /// Parses when to save, `prompt`, a number of instructions, or both separated by `,`.
+    pub fn parse(spec: &str, path: impl Into<PathBuf>) -> Result<Self, String> {
+        let mut autosave = Self::new(path, None, false);
+        for part in spec.split(',').map(str::trim) {
+            match part {
+                "prompt" => autosave.at_prompts = true,
+                steps => {
+                    let steps = steps
+                        .parse::<u64>()
+                        .ok()
+                        .filter(|&steps| steps > 0)
+                        .ok_or_else(|| {
+                            format!("invalid autosave `{part}`, expected `prompt` or a step count")
+                        })?;
+                    autosave.every = Some(steps);
+                }
+            }
+        }
+        Ok(autosave)
+    }
+
+    fn write(&mut self, dump: &CrashDump, steps: u64) {
+        self.last = steps;
+        match dump.save(&self.path) {
+            Ok(()) => {
+                self.saves += 1;
+                self.error = None;
+            }
+            Err(err) => self.error = Some(format!("{}: {err}", self.path.display())),
+        }
+    }
+}
+
+impl MachineState {
+    /// Called after every instruction, to save every `every` instructions.
+    pub(crate) fn autosave_on_steps(&mut self) {
+        let Some(autosave) = &self.autosave else {
+            return;
+        };
+        if autosave
+            .every
+            .is_none_or(|every| self.steps < autosave.last + every)
+        {
+            return;
+        }
+        let dump = CrashDump::of(self, "autosaved");
+        if let Some(autosave) = &mut self.autosave {
+            autosave.write(&dump, self.steps);
+        }
+    }
+
+    /// Called by the `in` at `pos` before it reads, to save at prompts.
+    pub(crate) fn autosave_at_prompt(&mut self, pos: u16) {
+        let Some(autosave) = &self.autosave else {
+            return;
+        };
+        if !autosave.at_prompts || !autosave.at_line_start {
+            return;
+        }
+        let dump = CrashDump::before(self, pos, "autosaved at a prompt");
+        if let Some(autosave) = &mut self.autosave {
+            // a prompt retried once input comes in isn't saved again
+            autosave.at_line_start = false;
+            autosave.write(&dump, self.steps);
+        }
+    }
+
+    /// Called by `in` after it read `byte`.
+    pub(crate) fn autosave_read(&mut self, byte: u8) {
+        if let Some(autosave) = &mut self.autosave {
+            autosave.at_line_start = byte == b'\n';
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MachineBuilder, RunOutcome};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("synacor-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn manages_slots() {
+        let dir = temp_dir("slots");
+        let slots = Slots::new(dir.join("game.bin"));
+        let mut machine = MachineState::new(vec![21, 21, 0]);
+        machine.run_for(1).unwrap();
+
+        slots.save("maze", &mut machine).unwrap();
+        machine.run_for(1).unwrap();
+        slots.save("after-maze", &mut machine).unwrap();
+        std::fs::write(dir.join("other.x.snapshot"), "").unwrap();
+        let listed = slots
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|slot| (slot.name, slot.steps))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            listed,
+            [("after-maze".to_string(), 2), ("maze".to_string(), 1)]
+        );
+
+        assert_eq!(slots.load("maze").unwrap().cur, 1);
+        slots.delete("maze").unwrap();
+        assert!(slots.load("maze").is_err());
+        assert!(slots.delete("maze").is_err());
+        assert!(slots.save("../up", &mut machine).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn draws_the_tree_of_saves() {
+        let dir = temp_dir("tree");
+        let slots = Slots::new(dir.join("game.bin"));
+        let mut machine = MachineState::new(vec![21, 21, 21, 0]);
+        slots.save("start", &mut machine).unwrap();
+        let mut machine = slots.load("start").unwrap();
+        machine.run_for(1).unwrap();
+        slots.save("left", &mut machine).unwrap();
+        machine.run_for(1).unwrap();
+        slots.save("left-deeper", &mut machine).unwrap();
+        machine = slots.load("start").unwrap();
+        machine.run_for(2).unwrap();
+        slots.save("right", &mut machine).unwrap();
+        // `left` comes first by name
+        assert_eq!(slots.list().unwrap()[0].parent.as_deref(), Some("start"));
+        assert_eq!(
+            slots.tree().unwrap(),
+            "start  0 steps at 0\n  left  1 steps at 1\n    left-deeper  2 steps at 2\n  right  2 steps at 2\n"
+        );
+
+        // saving over an ancestor leaves a cycle, which is still drawn
+        machine = slots.load("left").unwrap();
+        slots.save("start", &mut machine).unwrap();
+        let tree = slots.tree().unwrap();
+        assert_eq!(tree.lines().count(), 4, "{tree}");
+        assert_eq!(
+            slots.name_of(&dir.join("game.right.snapshot")).as_deref(),
+            Some("right")
+        );
+        assert_eq!(slots.name_of(&dir.join("other.right.snapshot")), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn autosaves() {
+        // 0: in r0
+        // 2: jmp 0
+        let dir = temp_dir("autosave");
+        let path = dir.join("game.autosave.snapshot");
+        let mut machine = MachineBuilder::new()
+            .program(&[20, 32768, 6, 0])
+            .input(b"ab\n")
+            .build();
+        machine.autosave = Some(Autosave::new(&path, None, true));
+        assert_eq!(machine.run(), Ok(RunOutcome::NeedsInput));
+        // once before the first line, once before the next
+        assert_eq!(machine.autosave.as_ref().unwrap().saves, 2);
+        let saved = CrashDump::load(&path).unwrap();
+        assert_eq!((saved.cur, saved.steps), (0, 6));
+
+        machine.autosave = Some(Autosave::new(&path, Some(4), false));
+        machine.push_input(b"abcdef");
+        assert_eq!(machine.run(), Ok(RunOutcome::NeedsInput));
+        assert_eq!(machine.autosave.as_ref().unwrap().saves, 3);
+        assert_eq!(CrashDump::load(&path).unwrap().steps, 15);
+        std::fs::remove_dir_all(dir).unwrap();
+
+        let parsed = Autosave::parse("prompt, 1000", "x").unwrap();
+        assert_eq!((parsed.every, parsed.at_prompts), (Some(1000), true));
+        assert!(Autosave::parse("0", "x").is_err());
+        assert!(Autosave::parse("often", "x").is_err());
+    }
+}
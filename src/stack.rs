@@ -0,0 +1,85 @@
+/// The machine's stack. Only the top is accessible, so values always come off in the reverse
+/// order they went on.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Stack {
+    values: Vec<u16>,
+    /// Prints every push and pop to stderr.
+    pub trace: bool,
+}
+
+impl Stack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, value: u16) {
+        self.values.push(value);
+        if self.trace {
+            eprintln!("stack: push {value} (depth {})", self.values.len());
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<u16> {
+        let value = self.values.pop();
+        if self.trace {
+            match value {
+                Some(value) => eprintln!("stack: pop {value} (depth {})", self.values.len()),
+                None => eprintln!("stack: pop from an empty stack"),
+            }
+        }
+        value
+    }
+
+    /// The value `pop` would return, without removing it.
+    pub fn peek(&self) -> Option<u16> {
+        self.values.last().copied()
+    }
+
+    /// Iterates over the values from the top of the stack down.
+    pub fn iter_top_down(&self) -> impl Iterator<Item = u16> + '_ {
+        self.values.iter().rev().copied()
+    }
+
+    /// The values from the bottom of the stack up, the order they were pushed in.
+    pub fn as_slice(&self) -> &[u16] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// Builds a stack from values ordered bottom first.
+impl FromIterator<u16> for Stack {
+    fn from_iter<I: IntoIterator<Item = u16>>(iter: I) -> Self {
+        Self {
+            values: iter.into_iter().collect(),
+            trace: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lifo() {
+        let mut stack = [1, 2].into_iter().collect::<Stack>();
+        stack.push(3);
+        assert_eq!(stack.peek(), Some(3));
+        assert_eq!(stack.iter_top_down().collect::<Vec<_>>(), vec![3, 2, 1]);
+        assert_eq!(stack.as_slice(), [1, 2, 3]);
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.len(), 1);
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+    }
+}
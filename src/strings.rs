@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::{
+    instruction,
+    project::{Project, RegionKind},
+};
+
+/// The shortest string reported outside of regions marked as strings in the project.
+pub const MIN_LEN: usize = 4;
+
+/// A string found in memory, and the instructions that refer to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StringEntry {
+    /// The address of the length word.
+    pub addr: u16,
+    pub text: String,
+    pub xrefs: Vec<u16>,
+}
+
+/// Finds the strings in `mem`, stored the way the challenge stores them: a length word followed by
+/// that many characters. Inside regions marked as strings in `project` every such string is
+/// reported, elsewhere only those at least `MIN_LEN` characters long.
+///
+/// Strings the program decrypts at runtime only show up in a dump of memory taken after that.
+pub fn strings(mem: &[u16], project: &Project) -> Vec<StringEntry> {
+    let xrefs = xrefs(mem);
+    let mut found = Vec::new();
+
+    let mut addr = 0;
+    while addr < mem.len() {
+        let len = mem[addr] as usize;
+        let in_region =
+            project.region_at(addr as u16).map(|region| region.kind) == Some(RegionKind::Strings);
+        let min_len = if in_region { 1 } else { MIN_LEN };
+
+        let chars = mem.get(addr + 1..addr + 1 + len);
+        match chars {
+            Some(chars) if len >= min_len && chars.iter().all(|&c| is_text(c)) => {
+                found.push(StringEntry {
+                    addr: addr as u16,
+                    text: chars.iter().map(|&c| c as u8 as char).collect(),
+                    xrefs: xrefs.get(&(addr as u16)).cloned().unwrap_or_default(),
+                });
+                addr += 1 + len;
+            }
+            _ => addr += 1,
+        }
+    }
+    found
+}
+
+/// How far back `containing` looks for the length word of a string.
+const MAX_STRING: usize = 256;
+
+/// Finds the string whose characters include `addr`, returning the address of its length word and
+/// its text. Unlike `strings`, this has no minimum length.
+pub fn containing(mem: &[u16], addr: u16) -> Option<(u16, String)> {
+    let addr = addr as usize;
+    (addr.saturating_sub(MAX_STRING)..addr)
+        .rev()
+        .find_map(|start| {
+            let len = mem[start] as usize;
+            let chars = mem.get(start + 1..start + 1 + len)?;
+            (addr <= start + len && chars.iter().all(|&c| is_text(c))).then(|| {
+                (
+                    start as u16,
+                    chars.iter().map(|&c| c as u8 as char).collect(),
+                )
+            })
+        })
+}
+
+/// The string whose length word is at `addr`, if the words there look like one.
+pub fn at(mem: &[u16], addr: u16) -> Option<String> {
+    let start = addr as usize + 1;
+    let chars = mem.get(start..start + *mem.get(addr as usize)? as usize)?;
+    (!chars.is_empty() && chars.iter().all(|&c| is_text(c)))
+        .then(|| chars.iter().map(|&c| c as u8 as char).collect())
+}
+
+fn is_text(word: u16) -> bool {
+    word == b'\n' as u16 || (b' ' as u16..0x7f).contains(&word)
+}
+
+/// Maps every literal operand to the instructions using it, found with a linear sweep.
+fn xrefs(mem: &[u16]) -> BTreeMap<u16, Vec<u16>> {
+    let mut xrefs = BTreeMap::<u16, Vec<u16>>::new();
+    let mut addr = 0;
+    while addr < mem.len() {
+        let Some(info) = instruction::info(mem[addr]) else {
+            addr += 1;
+            continue;
+        };
+        let Some(operands) = mem.get(addr + 1..addr + 1 + info.arity) else {
+            break;
+        };
+        for &operand in operands {
+            xrefs.entry(operand).or_default().push(addr as u16);
+        }
+        addr += 1 + info.arity;
+    }
+    xrefs
+}
+
+/// Renders `entries` whose text contains `query`, ignoring case, one per line.
+pub fn render(entries: &[StringEntry], query: Option<&str>, project: &Project) -> String {
+    let query = query.map(str::to_lowercase);
+    let mut out = String::new();
+    for entry in entries {
+        if query
+            .as_ref()
+            .is_some_and(|query| !entry.text.to_lowercase().contains(query))
+        {
+            continue;
+        }
+        let _ = write!(out, "{}: {:?}", project.describe(entry.addr), entry.text);
+        if !entry.xrefs.is_empty() {
+            let xrefs = entry
+                .xrefs
+                .iter()
+                .map(|&xref| project.describe(xref))
+                .collect::<Vec<_>>();
+            let _ = write!(out, "  ; xrefs: {}", xrefs.join(", "));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0: set r0 4, 3: halt, 4: "hello", 10: "hi", 13: "no\x01"
+    const MEM: [u16; 17] = [
+        1, 32768, 4, 0, 5, 104, 101, 108, 108, 111, 2, 104, 105, 3, 110, 111, 1,
+    ];
+
+    #[test]
+    fn finds_strings() {
+        let found = strings(&MEM, &Project::default());
+        assert_eq!(
+            found,
+            vec![StringEntry {
+                addr: 4,
+                text: "hello".to_string(),
+                xrefs: vec![0],
+            }]
+        );
+
+        // short strings count inside string regions
+        let project = Project::parse("region 10 13 strings short").unwrap();
+        let found = strings(&MEM, &project);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].text, "hi");
+    }
+
+    #[test]
+    fn finds_containing_string() {
+        assert_eq!(containing(&MEM, 12), Some((10, "hi".to_string())));
+        assert_eq!(containing(&MEM, 5), Some((4, "hello".to_string())));
+        assert_eq!(containing(&MEM, 4), None);
+    }
+
+    #[test]
+    fn renders_matches() {
+        let project = Project::parse("symbol 0 greet").unwrap();
+        let found = strings(&MEM, &project);
+        assert_eq!(
+            render(&found, Some("ELL"), &project),
+            "4 <greet+4>: \"hello\"  ; xrefs: 0 <greet>\n"
+        );
+        assert_eq!(render(&found, Some("bye"), &project), "");
+    }
+}
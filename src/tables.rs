@@ -0,0 +1,78 @@
+use std::fmt::Write as _;
+
+use crate::{
+    project::{Project, VarType},
+    strings,
+};
+
+/// Renders the region called `name` as a table, with the columns declared for it in `project`:
+/// one row per record, every cell shown according to the type of its column. Addresses that hold
+/// a string are followed by its text, so a room table shows room names rather than pointers.
+pub fn render(project: &Project, name: &str, mem: &[u16]) -> Result<String, String> {
+    let region = project
+        .regions
+        .iter()
+        .find(|region| region.name == name)
+        .ok_or_else(|| format!("no region called `{name}`"))?;
+    let columns = project
+        .tables
+        .get(&region.start)
+        .ok_or_else(|| format!("no columns declared for `{name}`"))?;
+
+    let mut rows = vec![std::iter::once("addr".to_string())
+        .chain(columns.iter().map(|column| column.name.clone()))
+        .collect::<Vec<_>>()];
+    let end = (region.end as usize).min(mem.len());
+    for start in (region.start as usize..end).step_by(columns.len()) {
+        let mut row = vec![start.to_string()];
+        for (column, &word) in columns.iter().zip(&mem[start..end]) {
+            let mut cell = column.ty.value(word).to_string();
+            if column.ty == VarType::Addr {
+                if let Some(text) = strings::at(mem, word) {
+                    let _ = write!(cell, " {text:?}");
+                }
+            }
+            row.push(cell);
+        }
+        rows.push(row);
+    }
+
+    let widths = (0..=columns.len())
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .map(String::len)
+                .max()
+        })
+        .collect::<Vec<_>>();
+    let mut out = String::new();
+    for row in &rows {
+        let cells = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}", width = width.unwrap_or(0)))
+            .collect::<Vec<_>>();
+        let _ = writeln!(out, "{}", cells.join("  ").trim_end());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_tables() {
+        let project = Project::parse(
+            "region 0 4 data rooms\ncolumns 0 name:addr lit:bool\nregion 4 6 data plain",
+        )
+        .unwrap();
+        let mem = [4, 1, 6, 0, 1, 104, 2, 105, 106];
+        assert_eq!(
+            render(&project, "rooms", &mem).unwrap(),
+            "addr  name         lit\n0     0x0004 \"h\"   true\n2     0x0006 \"ij\"  false\n"
+        );
+        assert!(render(&project, "plain", &mem).is_err());
+        assert!(render(&project, "items", &mem).is_err());
+    }
+}
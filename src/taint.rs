@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+
+use crate::{instruction::disassemble_registers, project::Project, MAX_ADDR, REGISTER_COUNT};
+
+/// Tracks which values are derived from bytes read by `in`, to find the code that depends on
+/// the player's input, such as the command parser.
+///
+/// Taint flows from the sources of an instruction to its destination, through the stack, and
+/// through memory accessed by `rmem` and `wmem`, including when the address itself is tainted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Taint {
+    mem: Vec<bool>,
+    registers: [bool; REGISTER_COUNT],
+    stack: Vec<bool>,
+    /// The memory address each register was last loaded from by `rmem`, if it wasn't overwritten since.
+    origins: [Option<u16>; REGISTER_COUNT],
+    /// How many times each jump was decided by a tainted value, by position.
+    pub branches: BTreeMap<u16, u64>,
+    /// Addresses whose values were compared with tainted values by `eq` or `gt`.
+    pub compared: BTreeSet<u16>,
+}
+
+/// How executing an instruction changes the taint, computed before it runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Effect {
+    /// A register or memory address, and whether the value written to it is tainted.
+    write: Option<(u16, bool)>,
+    push: Option<bool>,
+    /// Pops the stack, writing the popped taint to a register or memory address if given.
+    pop: Option<Option<u16>>,
+    /// Where the value written to a register was loaded from.
+    origin: Option<u16>,
+    branch: Option<u16>,
+    compared: Option<u16>,
+}
+
+impl Taint {
+    pub fn new() -> Self {
+        Self {
+            mem: vec![false; MAX_ADDR],
+            registers: [false; REGISTER_COUNT],
+            stack: Vec::new(),
+            origins: [None; REGISTER_COUNT],
+            branches: BTreeMap::new(),
+            compared: BTreeSet::new(),
+        }
+    }
+
+    /// Whether the value of an operand is tainted. Literals never are.
+    fn operand(&self, operand: u16) -> bool {
+        (operand as usize)
+            .checked_sub(MAX_ADDR)
+            .and_then(|register| self.registers.get(register))
+            .is_some_and(|&tainted| tainted)
+    }
+
+    /// Computes the effect of the instruction at `pos`, given the state right before it runs.
+    pub fn effect(&self, pos: u16, mem: &[u16], registers: &[u16; REGISTER_COUNT]) -> Effect {
+        let pos = pos as usize;
+        let op = |n: usize| mem.get(pos + 1 + n).copied().unwrap_or(0);
+        let tainted = |n: usize| self.operand(op(n));
+        let value = |n: usize| match op(n) {
+            val if (val as usize) < MAX_ADDR => val,
+            val => registers.get(val as usize - MAX_ADDR).copied().unwrap_or(0),
+        };
+        let branch = |n: usize| tainted(n).then_some(pos as u16);
+        let origin = |n: usize| {
+            (op(n) as usize)
+                .checked_sub(MAX_ADDR)
+                .and_then(|register| self.origins.get(register).copied().flatten())
+        };
+
+        let mut effect = Effect::default();
+        match mem.get(pos).copied().unwrap_or(0) {
+            1 => {
+                effect.write = Some((op(0), tainted(1)));
+                effect.origin = origin(1);
+            }
+            14 => effect.write = Some((op(0), tainted(1))),
+            4 | 5 => {
+                effect.write = Some((op(0), tainted(1) || tainted(2)));
+                effect.compared = match (tainted(1), tainted(2)) {
+                    (true, false) => origin(2),
+                    (false, true) => origin(1),
+                    _ => None,
+                };
+            }
+            9..=13 => effect.write = Some((op(0), tainted(1) || tainted(2))),
+            2 => effect.push = Some(tainted(0)),
+            3 => effect.pop = Some(Some(op(0))),
+            6..=8 => effect.branch = branch(0),
+            15 => {
+                let addr = value(1) as usize;
+                let from = self.mem.get(addr).is_some_and(|&tainted| tainted);
+                effect.write = Some((op(0), from || tainted(1)));
+                effect.origin = Some(addr as u16);
+            }
+            16 => effect.write = Some((value(0), tainted(0) || tainted(1))),
+            17 => {
+                effect.push = Some(false);
+                effect.branch = branch(0);
+            }
+            18 => effect.pop = Some(None),
+            20 => effect.write = Some((op(0), true)),
+            _ => {}
+        }
+        effect
+    }
+
+    /// Applies the effect of an instruction that completed.
+    pub fn apply(&mut self, effect: Effect) {
+        if let Some(tainted) = effect.push {
+            self.stack.push(tainted);
+        }
+        if let Some(dest) = effect.pop {
+            let tainted = self.stack.pop().unwrap_or(false);
+            if let Some(dest) = dest {
+                self.set(dest, tainted);
+            }
+        }
+        if let Some((dest, tainted)) = effect.write {
+            self.set(dest, tainted);
+            let register = (dest as usize).checked_sub(MAX_ADDR);
+            if let Some(slot) = register.and_then(|register| self.origins.get_mut(register)) {
+                *slot = effect.origin;
+            }
+        }
+        if let Some(addr) = effect.compared {
+            self.compared.insert(addr);
+        }
+        if let Some(pos) = effect.branch {
+            *self.branches.entry(pos).or_default() += 1;
+        }
+    }
+
+    fn set(&mut self, dest: u16, tainted: bool) {
+        let slot = match (dest as usize).checked_sub(MAX_ADDR) {
+            None => self.mem.get_mut(dest as usize),
+            Some(register) => {
+                if let Some(origin) = self.origins.get_mut(register) {
+                    *origin = None;
+                }
+                self.registers.get_mut(register)
+            }
+        };
+        if let Some(slot) = slot {
+            *slot = tainted;
+        }
+    }
+
+    /// Whether the word at `addr` holds a value derived from input.
+    pub fn is_tainted(&self, addr: u16) -> bool {
+        self.mem.get(addr as usize).is_some_and(|&tainted| tainted)
+    }
+
+    /// Lists the jumps that depended on input, with how often they did.
+    pub fn report(&self, mem: &[u16], project: &Project) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{} jump(s) depended on input", self.branches.len());
+        for (&pos, count) in &self.branches {
+            let (text, _) = disassemble_registers(mem, pos as usize, project);
+            let _ = writeln!(out, "  {}: {text} ({count}x)", project.describe(pos));
+        }
+        out
+    }
+}
+
+impl Default for Taint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::MachineBuilder, RunOutcome};
+
+    #[test]
+    fn finds_input_dependent_branches() {
+        // 0: in r0
+        // 2: add r1 r0 1
+        // 6: wmem 100 r1
+        // 9: rmem r2 100
+        // 12: jt r2 15
+        // 15: jf r3 18
+        // 18: halt
+        let mut machine = MachineBuilder::new()
+            .program(&[
+                20, 32768, 9, 32769, 32768, 1, 16, 100, 32769, 15, 32770, 100, 7, 32770, 15, 8,
+                32771, 18, 0,
+            ])
+            .input(b"x")
+            .build();
+        machine.taint = Some(Taint::new());
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+
+        let taint = machine.taint.unwrap();
+        assert!(taint.is_tainted(100));
+        assert_eq!(taint.branches.keys().copied().collect::<Vec<_>>(), vec![12]);
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::MAX_ADDR;
+
+/// What the confirmation routine must return for the teleporter to accept `r7`.
+pub const EXPECTED: u16 = 6;
+
+/// How many values of `r7` a worker claims at a time.
+const CHUNK: u32 = 256;
+
+/// How often progress is reported.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The confirmation routine the teleporter runs before using `r7`, extracted from the binary: a
+/// variant of the Ackermann function over 15-bit numbers, where `f(a, 0)` is `f(a - 1, r7)`,
+/// called with `a = 4` and `b = 1`. Naively it takes longer than the universe has, so it is
+/// computed bottom-up a row of `a` at a time, in `memo`, which workers reuse between calls.
+pub fn check(r7: u16, memo: &mut Vec<u16>) -> u16 {
+    memo.resize(MAX_ADDR * 2, 0);
+    let (prev, row) = memo.split_at_mut(MAX_ADDR);
+    for (b, val) in prev.iter_mut().enumerate() {
+        *val = ((b + 1) % MAX_ADDR) as u16;
+    }
+    for _ in 1..4 {
+        row[0] = prev[r7 as usize];
+        for b in 1..MAX_ADDR {
+            row[b] = prev[row[b - 1] as usize];
+        }
+        prev.copy_from_slice(row);
+    }
+    // only f(4, 1) is needed of the last row
+    prev[prev[r7 as usize] as usize]
+}
+
+/// The confirmation routine for any `a` and `b`, computed the same way as `check`.
+pub fn confirm(a: u16, b: u16, r7: u16) -> u16 {
+    let mut row = (1..=MAX_ADDR as u16)
+        .map(|b| b % MAX_ADDR as u16)
+        .collect::<Vec<_>>();
+    for _ in 0..a {
+        let mut next = vec![row[r7 as usize]; MAX_ADDR];
+        for b in 1..MAX_ADDR {
+            next[b] = row[next[b - 1] as usize];
+        }
+        row = next;
+    }
+    row[b as usize % MAX_ADDR]
+}
+
+/// The result of a search as JSON: `{"r7": 25734}`, or `{"r7": null}` if no value confirms.
+pub fn to_json(found: Option<u16>) -> String {
+    match found {
+        Some(r7) => format!("{{\"r7\": {r7}}}\n"),
+        None => "{\"r7\": null}\n".to_string(),
+    }
+}
+
+/// A search for the `r7` that makes the teleporter confirm, over all cores.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Search {
+    /// The first value to try, unless the checkpoint is further along.
+    pub start: u16,
+    /// The value after the last to try.
+    pub end: u32,
+    pub threads: usize,
+    /// Where to record the first value not yet tried, so a search can resume after it is stopped.
+    pub checkpoint: Option<PathBuf>,
+    /// Whether to report the values tried, the rate and the time left on stderr.
+    pub progress: bool,
+}
+
+/// How far a search is: the chunks finished out of order, and the first value not yet tried
+/// before all of them.
+struct Progress {
+    done: BTreeSet<u32>,
+    tried: u32,
+    /// Where this run started, maybe from a checkpoint, and when.
+    resumed: u32,
+    started: Instant,
+    reported: Option<Instant>,
+}
+
+impl Search {
+    /// Searches every value but 0, which the teleporter rejects before checking.
+    pub fn new(threads: usize, checkpoint: Option<PathBuf>) -> Self {
+        Self {
+            start: 1,
+            end: MAX_ADDR as u32,
+            threads,
+            checkpoint,
+            progress: false,
+        }
+    }
+
+    /// Tries values in chunks until a worker finds one that confirms, which stops the others.
+    pub fn run(&self) -> std::io::Result<Option<u16>> {
+        let start = match &self.checkpoint {
+            Some(path) if path.exists() => std::fs::read_to_string(path)?
+                .trim()
+                .parse::<u32>()
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?,
+            _ => self.start as u32,
+        };
+
+        let next = AtomicU32::new(start);
+        let stop = AtomicBool::new(false);
+        let found = Mutex::new(None);
+        let progress = Mutex::new(Progress {
+            done: BTreeSet::new(),
+            tried: start,
+            resumed: start,
+            started: Instant::now(),
+            reported: None,
+        });
+        let result = std::thread::scope(|scope| {
+            let workers = (0..self.threads.max(1))
+                .map(|_| {
+                    scope.spawn(|| -> std::io::Result<()> {
+                        let mut memo = Vec::new();
+                        while !stop.load(Ordering::Relaxed) {
+                            let from = next.fetch_add(CHUNK, Ordering::Relaxed);
+                            if from >= self.end {
+                                return Ok(());
+                            }
+                            let to = (from + CHUNK).min(self.end);
+                            for r7 in from..to {
+                                if stop.load(Ordering::Relaxed) {
+                                    return Ok(());
+                                }
+                                if check(r7 as u16, &mut memo) == EXPECTED {
+                                    stop.store(true, Ordering::Relaxed);
+                                    *found.lock().unwrap() = Some(r7 as u16);
+                                    return Ok(());
+                                }
+                            }
+                            self.finished(&progress, from)?;
+                        }
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+            workers
+                .into_iter()
+                .try_for_each(|worker| worker.join().unwrap())
+        });
+        result?;
+        if progress.into_inner().unwrap().reported.is_some() {
+            eprintln!();
+        }
+        let found = found.into_inner().unwrap();
+        Ok(found)
+    }
+
+    /// Records that the chunk starting at `from` was tried, moves the checkpoint past every chunk
+    /// tried without a gap before it, and reports the progress if it is time to.
+    fn finished(&self, progress: &Mutex<Progress>, from: u32) -> std::io::Result<()> {
+        let mut progress = progress.lock().unwrap();
+        let Progress { done, tried, .. } = &mut *progress;
+        done.insert(from);
+        let before = *tried;
+        while done.remove(tried) {
+            *tried = (*tried + CHUNK).min(self.end);
+        }
+        if self.progress
+            && progress
+                .reported
+                .is_none_or(|time| time.elapsed() >= PROGRESS_INTERVAL)
+        {
+            let total = self.end - self.start as u32;
+            let this_run = progress.tried - progress.resumed + progress.done.len() as u32 * CHUNK;
+            let tried = (progress.resumed - self.start as u32 + this_run).min(total);
+            let elapsed = progress.started.elapsed();
+            eprint!("\r{}", report(tried, total, this_run, elapsed));
+            progress.reported = Some(Instant::now());
+        }
+        match &self.checkpoint {
+            Some(path) if progress.tried != before => {
+                std::fs::write(path, format!("{}\n", progress.tried))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A line of progress: `tried` values out of `total`, and the rate and time left from trying
+/// `this_run` of them in `elapsed`.
+fn report(tried: u32, total: u32, this_run: u32, elapsed: Duration) -> String {
+    let rate = this_run as f64 / elapsed.as_secs_f64().max(0.001);
+    let left = match this_run {
+        0 => "?".to_string(),
+        _ => {
+            let secs = (total.saturating_sub(tried) as f64 / rate).round() as u64;
+            format!("{}:{:02}", secs / 60, secs % 60)
+        }
+    };
+    format!("tried {tried}/{total} values of r7, {rate:.0}/s, {left} left")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_r7() {
+        let mut memo = Vec::new();
+        assert_eq!(check(25734, &mut memo), EXPECTED);
+        assert_ne!(check(1, &mut memo), EXPECTED);
+        assert_eq!(confirm(4, 1, 25734), EXPECTED);
+        assert_eq!(confirm(1, 2, 1), 4);
+        assert_eq!(confirm(0, 32767, 1), 0);
+    }
+
+    #[test]
+    fn searches_with_checkpoints() {
+        let checkpoint = std::env::temp_dir().join(format!("teleporter-{}", std::process::id()));
+        let _ = std::fs::remove_file(&checkpoint);
+        let mut search = Search {
+            start: 25000,
+            end: 26000,
+            threads: 2,
+            checkpoint: Some(checkpoint.clone()),
+            progress: false,
+        };
+        assert_eq!(search.run().unwrap(), Some(25734));
+        // the chunk with the answer is never finished, so neither is any after it
+        if let Ok(tried) = std::fs::read_to_string(&checkpoint) {
+            assert!(tried.trim().parse::<u32>().unwrap() <= 25734);
+        }
+
+        // resuming past the answer finds nothing
+        std::fs::write(&checkpoint, "25800\n").unwrap();
+        assert_eq!(search.run().unwrap(), None);
+        assert_eq!(std::fs::read_to_string(&checkpoint).unwrap(), "26000\n");
+
+        search.checkpoint = None;
+        search.start = 25734;
+        assert_eq!(search.run().unwrap(), Some(25734));
+        std::fs::remove_file(&checkpoint).unwrap();
+    }
+
+    #[test]
+    fn writes_json() {
+        assert_eq!(to_json(Some(25734)), "{\"r7\": 25734}\n");
+        assert_eq!(to_json(None), "{\"r7\": null}\n");
+    }
+
+    #[test]
+    fn reports_progress() {
+        assert_eq!(
+            report(8192, 32767, 4096, Duration::from_secs(4)),
+            "tried 8192/32767 values of r7, 1024/s, 0:24 left"
+        );
+        assert_eq!(
+            report(0, 32767, 0, Duration::ZERO),
+            "tried 0/32767 values of r7, 0/s, ? left"
+        );
+    }
+}
@@ -0,0 +1,391 @@
+//! The terminal under the TUI: raw mode on the alternate screen, its size, and the keys typed and
+//! the mouse events reported.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A key pressed in the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+    Char(char),
+    /// A letter typed with Ctrl held, in lowercase.
+    Ctrl(char),
+    Enter,
+    Tab,
+    BackTab,
+    Backspace,
+    Delete,
+    Esc,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    /// `F1` to `F12`.
+    F(u8),
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Char(' ') => write!(f, "Space"),
+            Key::Char(ch) => write!(f, "{ch}"),
+            Key::Ctrl(ch) => write!(f, "Ctrl-{ch}"),
+            Key::F(n) => write!(f, "F{n}"),
+            key => write!(f, "{key:?}"),
+        }
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    /// Parses a key as it is shown: `a`, `Space`, `Ctrl-c`, `F1` or a name such as `PageUp`.
+    fn from_str(name: &str) -> Result<Self, String> {
+        const NAMED: [Key; 14] = [
+            Key::Enter,
+            Key::Tab,
+            Key::BackTab,
+            Key::Backspace,
+            Key::Delete,
+            Key::Esc,
+            Key::Up,
+            Key::Down,
+            Key::Left,
+            Key::Right,
+            Key::Home,
+            Key::End,
+            Key::PageUp,
+            Key::PageDown,
+        ];
+        let mut chars = name.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(ch), None) if !ch.is_control() => Some(Key::Char(ch)),
+            _ if name == "Space" => Some(Key::Char(' ')),
+            _ => match name.strip_prefix("Ctrl-").map(str::chars) {
+                Some(mut chars) => match (chars.next(), chars.next()) {
+                    (Some(ch @ 'a'..='z'), None) => Some(Key::Ctrl(ch)),
+                    _ => None,
+                },
+                None => name
+                    .strip_prefix('F')
+                    .and_then(|n| n.parse().ok())
+                    .filter(|n| (1..=12).contains(n))
+                    .map(Key::F)
+                    .or_else(|| NAMED.into_iter().find(|key| key.to_string() == name)),
+            },
+        };
+        key.ok_or_else(|| format!("unknown key `{name}`"))
+    }
+}
+
+/// What was done with the mouse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mouse {
+    /// A press of the left button.
+    Click,
+    ScrollUp,
+    ScrollDown,
+}
+
+/// Something read from the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Key(Key),
+    /// A mouse event at a cell, counted from 0 at the top left.
+    Mouse {
+        mouse: Mouse,
+        x: usize,
+        y: usize,
+    },
+}
+
+/// Puts the terminal in raw mode on the alternate screen, with the cursor hidden and mouse events
+/// reported in the SGR encoding, until dropped.
+pub struct Terminal {
+    original: libc::termios,
+}
+
+impl Terminal {
+    pub fn enter() -> io::Result<Self> {
+        // SAFETY: termios is plain data, and tcgetattr fills it in before it is read
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        // SAFETY: the pointer is to a live termios
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = original;
+        // SAFETY: the pointers are to live termios
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let terminal = Self { original };
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l\x1b[?1000h\x1b[?1006h\x1b[2J")?;
+        stdout.flush()?;
+        Ok(terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x1b[0m\x1b[?1006l\x1b[?1000l\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+        // SAFETY: the pointer is to a live termios
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// The width and height of the terminal, if stdout is one.
+pub fn size() -> Option<(usize, usize)> {
+    // SAFETY: winsize is plain data, and TIOCGWINSZ fills it in
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    (ok && size.ws_col > 0 && size.ws_row > 0)
+        .then_some((size.ws_col as usize, size.ws_row as usize))
+}
+
+/// Waits at most `timeout` for input and reads what is available, which is nothing if the time
+/// ran out.
+pub fn read(timeout: Duration) -> io::Result<Vec<u8>> {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let millis = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+    // SAFETY: the pointer is to a single live pollfd
+    match unsafe { libc::poll(&mut fd, 1, millis) } {
+        0 => return Ok(Vec::new()),
+        -1 => {
+            let err = io::Error::last_os_error();
+            // a resize interrupts the wait, and only needs a redraw
+            return match err.kind() {
+                io::ErrorKind::Interrupted => Ok(Vec::new()),
+                _ => Err(err),
+            };
+        }
+        _ => {}
+    }
+    let mut buf = [0; 256];
+    // SAFETY: read writes at most `buf.len()` bytes to `buf`
+    let len = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+    match len {
+        0 => Err(io::ErrorKind::UnexpectedEof.into()),
+        len if len < 0 => Err(io::Error::last_os_error()),
+        len => Ok(buf[..len as usize].to_vec()),
+    }
+}
+
+/// Decodes the bytes read from a terminal into events, skipping sequences that are neither keys
+/// nor the mouse events in `Mouse`.
+pub fn decode(bytes: &[u8]) -> Vec<Event> {
+    let mut events = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let (event, len) = decode_one(rest);
+        events.extend(event);
+        rest = &rest[len.min(rest.len())..];
+    }
+    events
+}
+
+/// Decodes the event at the start of `bytes`, returning it and the number of bytes it took.
+fn decode_one(bytes: &[u8]) -> (Option<Event>, usize) {
+    if let Some(rest) = bytes.strip_prefix(b"\x1b[<") {
+        let (event, len) = decode_mouse(rest);
+        return (event, len + 3);
+    }
+    let (key, len) = decode_key(bytes);
+    (key.map(Event::Key), len)
+}
+
+/// Decodes a mouse event following `ESC [ <`: the button, column and row counted from 1, then
+/// `M` for a press or `m` for a release.
+fn decode_mouse(bytes: &[u8]) -> (Option<Event>, usize) {
+    let Some(end) = bytes.iter().position(|byte| !(0x30..=0x3f).contains(byte)) else {
+        return (None, bytes.len());
+    };
+    let params = std::str::from_utf8(&bytes[..end]).unwrap_or_default();
+    let params = params
+        .split(';')
+        .map(|n| n.parse::<usize>().ok())
+        .collect::<Vec<_>>();
+    let [Some(button), Some(x @ 1..), Some(y @ 1..)] = params[..] else {
+        return (None, end + 1);
+    };
+    // without the bits of Shift, Alt and Ctrl
+    let mouse = match (button & !0b11100, bytes[end]) {
+        (0, b'M') => Mouse::Click,
+        (64, b'M') => Mouse::ScrollUp,
+        (65, b'M') => Mouse::ScrollDown,
+        _ => return (None, end + 1),
+    };
+    let event = Event::Mouse {
+        mouse,
+        x: x - 1,
+        y: y - 1,
+    };
+    (Some(event), end + 1)
+}
+
+/// Decodes the key at the start of `bytes`, returning it and the number of bytes it took.
+fn decode_key(bytes: &[u8]) -> (Option<Key>, usize) {
+    match bytes[0] {
+        0x1b => match bytes.get(1) {
+            Some(b'[') => {
+                let (key, len) = decode_csi(&bytes[2..]);
+                (key, len + 2)
+            }
+            Some(b'O') => {
+                let key = match bytes.get(2) {
+                    Some(&code @ b'P'..=b'S') => Key::F(code - b'P' + 1),
+                    Some(b'A') => Key::Up,
+                    Some(b'B') => Key::Down,
+                    Some(b'C') => Key::Right,
+                    Some(b'D') => Key::Left,
+                    Some(b'H') => Key::Home,
+                    Some(b'F') => Key::End,
+                    _ => return (Some(Key::Esc), 1),
+                };
+                (Some(key), 3)
+            }
+            _ => (Some(Key::Esc), 1),
+        },
+        b'\r' | b'\n' => (Some(Key::Enter), 1),
+        b'\t' => (Some(Key::Tab), 1),
+        0x7f | 0x08 => (Some(Key::Backspace), 1),
+        byte @ 1..=26 => (Some(Key::Ctrl((b'a' + byte - 1) as char)), 1),
+        byte if byte < 0x20 => (None, 1),
+        byte => {
+            let len = match byte {
+                0xf0.. => 4,
+                0xe0.. => 3,
+                0xc0.. => 2,
+                _ => 1,
+            };
+            let len = len.min(bytes.len());
+            let ch = std::str::from_utf8(&bytes[..len])
+                .ok()
+                .and_then(|s| s.chars().next());
+            (ch.map(Key::Char), len)
+        }
+    }
+}
+
+/// Decodes a control sequence following `ESC [`: parameter bytes, then a final byte.
+fn decode_csi(bytes: &[u8]) -> (Option<Key>, usize) {
+    let Some(end) = bytes.iter().position(|byte| !(0x30..=0x3f).contains(byte)) else {
+        return (None, bytes.len());
+    };
+    let params = std::str::from_utf8(&bytes[..end]).unwrap_or_default();
+    let first = params.split(';').next().and_then(|n| n.parse::<u8>().ok());
+    let key = match (bytes[end], first) {
+        (b'A', _) => Some(Key::Up),
+        (b'B', _) => Some(Key::Down),
+        (b'C', _) => Some(Key::Right),
+        (b'D', _) => Some(Key::Left),
+        (b'H', _) => Some(Key::Home),
+        (b'F', _) => Some(Key::End),
+        (b'Z', _) => Some(Key::BackTab),
+        (b'~', Some(1 | 7)) => Some(Key::Home),
+        (b'~', Some(4 | 8)) => Some(Key::End),
+        (b'~', Some(3)) => Some(Key::Delete),
+        (b'~', Some(5)) => Some(Key::PageUp),
+        (b'~', Some(6)) => Some(Key::PageDown),
+        (b'~', Some(n @ 11..=15)) => Some(Key::F(n - 10)),
+        (b'~', Some(n @ 17..=21)) => Some(Key::F(n - 11)),
+        (b'~', Some(n @ 23..=24)) => Some(Key::F(n - 12)),
+        _ => None,
+    };
+    (key, end + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(bytes: &[u8]) -> Vec<Key> {
+        decode(bytes)
+            .into_iter()
+            .map(|event| match event {
+                Event::Key(key) => key,
+                event => panic!("{event:?} isn't a key"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_keys() {
+        assert_eq!(
+            keys(b"ab\r\x7f\x03\t\x1b[Z\x1b"),
+            [
+                Key::Char('a'),
+                Key::Char('b'),
+                Key::Enter,
+                Key::Backspace,
+                Key::Ctrl('c'),
+                Key::Tab,
+                Key::BackTab,
+                Key::Esc
+            ]
+        );
+        assert_eq!(
+            keys(b"\x1b[A\x1bOB\x1b[5~\x1b[6~\x1b[1;5C\x1bOP\x1b[24~"),
+            [
+                Key::Up,
+                Key::Down,
+                Key::PageUp,
+                Key::PageDown,
+                Key::Right,
+                Key::F(1),
+                Key::F(12)
+            ]
+        );
+        assert_eq!(keys("é".as_bytes()), [Key::Char('é')]);
+        // unknown sequences are skipped whole
+        assert_eq!(keys(b"\x1b[200~x\x1b[9"), [Key::Char('x')]);
+    }
+
+    #[test]
+    fn parses_keys() {
+        for key in [
+            Key::Char('a'),
+            Key::Char(' '),
+            Key::Char('F'),
+            Key::Ctrl('c'),
+            Key::F(12),
+            Key::PageUp,
+            Key::BackTab,
+        ] {
+            assert_eq!(key.to_string().parse(), Ok(key));
+        }
+        for name in ["", "Ctrl-", "Ctrl-C", "F13", "Pageup", "ab"] {
+            assert!(name.parse::<Key>().is_err(), "{name}");
+        }
+    }
+
+    #[test]
+    fn decodes_the_mouse() {
+        let at = |mouse, x, y| Event::Mouse { mouse, x, y };
+        assert_eq!(
+            decode(b"\x1b[<0;5;2M\x1b[<0;5;2m\x1b[<65;1;1Mq\x1b[<68;3;4M\x1b[<2;1;1M\x1b[<0;0;0M"),
+            [
+                at(Mouse::Click, 4, 1),
+                at(Mouse::ScrollDown, 0, 0),
+                Event::Key(Key::Char('q')),
+                // with Shift held
+                at(Mouse::ScrollUp, 2, 3),
+            ]
+        );
+    }
+}
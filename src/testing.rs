@@ -0,0 +1,250 @@
+//! Scaffolding for tests of the machine and of tools built on it.
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    prompt::PromptDetector, MachineState, RunLimits, RunOutcome, RunResult, MAX_ADDR,
+    REGISTER_COUNT,
+};
+
+/// Creates a machine whose memory starts with `overrides` and is zeroed everywhere else.
+pub fn setup(overrides: Vec<u16>) -> MachineState {
+    MachineBuilder::new().program(&overrides).build()
+}
+
+/// Builds machines in a specific state. Memory is zeroed unless set otherwise.
+#[derive(Clone, Debug)]
+pub struct MachineBuilder {
+    mem: Vec<u16>,
+    cur: u16,
+    registers: [u16; REGISTER_COUNT],
+    stack: Vec<u16>,
+    input: Option<Vec<u8>>,
+}
+
+impl MachineBuilder {
+    pub fn new() -> Self {
+        Self {
+            mem: vec![0; MAX_ADDR],
+            cur: 0,
+            registers: [0; REGISTER_COUNT],
+            stack: Vec::new(),
+            input: None,
+        }
+    }
+
+    /// Writes `words` to the start of memory.
+    pub fn program(self, words: &[u16]) -> Self {
+        self.at(0, words)
+    }
+
+    /// Writes `words` to memory starting at `addr`.
+    pub fn at(mut self, addr: u16, words: &[u16]) -> Self {
+        let addr = addr as usize;
+        self.mem[addr..addr + words.len()].copy_from_slice(words);
+        self
+    }
+
+    pub fn cur(mut self, cur: u16) -> Self {
+        self.cur = cur;
+        self
+    }
+
+    pub fn register(mut self, register: usize, val: u16) -> Self {
+        self.registers[register] = val;
+        self
+    }
+
+    /// Sets the stack, bottom first.
+    pub fn stack(mut self, values: &[u16]) -> Self {
+        self.stack = values.to_vec();
+        self
+    }
+
+    /// Switches the machine to buffered I/O with `bytes` as its input, so tests never touch stdio.
+    pub fn input(mut self, bytes: &[u8]) -> Self {
+        self.input = Some(bytes.to_vec());
+        self
+    }
+
+    pub fn build(self) -> MachineState {
+        let mut machine = MachineState::new(self.mem);
+        machine.cur = self.cur;
+        machine.registers = self.registers;
+        machine.stack = self.stack.into_iter().collect();
+        if let Some(input) = self.input {
+            machine.push_input(&input);
+        }
+        machine
+    }
+}
+
+impl Default for MachineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The program asked for more input than the script had.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptExhausted {
+    /// What the program printed since it last read input, usually the prompt it is waiting on.
+    pub prompt: String,
+    /// The position of the `in` waiting for input.
+    pub pos: u16,
+    pub steps: u64,
+}
+
+impl ScriptExhausted {
+    /// Describes `machine`, stopped for input. The prompt is only known if it has a
+    /// `PromptDetector`.
+    pub fn of(machine: &MachineState) -> Self {
+        let prompt = machine
+            .prompt
+            .as_ref()
+            .and_then(|detector| detector.last.as_ref())
+            // an earlier prompt, if the program printed nothing before this `in`
+            .filter(|prompt| prompt.pos == machine.cur && prompt.steps == machine.steps)
+            .map(|prompt| prompt.text.clone())
+            .unwrap_or_default();
+        Self {
+            prompt,
+            pos: machine.cur,
+            steps: machine.steps,
+        }
+    }
+}
+
+impl std::fmt::Display for ScriptExhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The script ran out of input at `{}` after {} steps",
+            self.pos, self.steps
+        )?;
+        match self.prompt.trim_end() {
+            "" => Ok(()),
+            prompt => write!(f, ", waiting on:\n{prompt}"),
+        }
+    }
+}
+
+/// The result of `run_script`.
+#[derive(Clone, Debug)]
+pub struct ScriptRun {
+    pub result: RunResult,
+    pub output: String,
+    /// Set when the run stopped because the program wanted input the script didn't have.
+    pub exhausted: Option<ScriptExhausted>,
+    pub machine: MachineState,
+}
+
+/// Runs `machine` on `input` with buffered I/O for at most `fuel` instructions, collecting its output.
+pub fn run_script(mut machine: MachineState, input: &[u8], fuel: u64) -> ScriptRun {
+    // a detector of our own, if the machine has none, to tell what it was waiting on
+    let detached = machine.prompt.is_none();
+    if detached {
+        machine.prompt = Some(PromptDetector::new().0);
+    }
+    machine.push_input(input);
+    let result = machine.run_with(RunLimits {
+        fuel: Some(fuel),
+        ..RunLimits::default()
+    });
+    let output = String::from_utf8_lossy(&machine.drain_output()).into_owned();
+
+    let exhausted = (result == Ok(RunOutcome::NeedsInput)).then(|| ScriptExhausted::of(&machine));
+    if detached {
+        machine.prompt = None;
+    }
+    ScriptRun {
+        result,
+        output,
+        exhausted,
+        machine,
+    }
+}
+
+/// Asserts the values of the given registers, naming the first one that differs.
+/// A sink whose clones share what is written to them, to check what traces and transcripts wrote.
+#[derive(Clone, Debug, Default)]
+pub struct SharedSink(pub Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[track_caller]
+pub fn assert_registers(machine: &MachineState, expected: &[(usize, u16)]) {
+    for &(register, val) in expected {
+        assert_eq!(
+            machine.registers[register], val,
+            "register r{register} differs"
+        );
+    }
+}
+
+/// Asserts that memory starting at `addr` holds `expected`.
+#[track_caller]
+pub fn assert_mem(machine: &MachineState, addr: u16, expected: &[u16]) {
+    let addr = addr as usize;
+    assert_eq!(
+        &machine.mem[addr..addr + expected.len()],
+        expected,
+        "memory at {addr} differs"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder() {
+        let machine = MachineBuilder::new()
+            .program(&[21])
+            .at(100, &[1, 2])
+            .cur(5)
+            .register(7, 3)
+            .stack(&[1, 2])
+            .build();
+        assert_mem(&machine, 99, &[0, 1, 2]);
+        assert_registers(&machine, &[(7, 3), (0, 0)]);
+        assert_eq!(machine.cur, 5);
+        assert_eq!(machine.stack.peek(), Some(2));
+    }
+
+    #[test]
+    fn script() {
+        // in r0, out r0, jmp 0
+        let machine = setup(vec![20, 32768, 19, 32768, 6, 0]);
+        let run = run_script(machine, b"hello", 1000);
+        assert_eq!(run.result, Ok(RunOutcome::NeedsInput));
+        assert_eq!(run.output, "hello");
+        let exhausted = run.exhausted.unwrap();
+        assert_eq!((exhausted.pos, exhausted.steps), (0, 15));
+        assert_eq!(
+            exhausted.to_string(),
+            "The script ran out of input at `0` after 15 steps, waiting on:\no"
+        );
+        assert_eq!(run.machine.prompt, None);
+
+        // out '>', in r0, halt
+        let machine = setup(vec![19, 62, 20, 32768, 0]);
+        let run = run_script(machine, b"x", 1000);
+        assert_eq!(run.result, Ok(RunOutcome::Halted));
+        assert_eq!(run.exhausted, None);
+
+        // out '>', in r0, in r0: nothing printed between the two
+        let machine = setup(vec![19, 62, 20, 32768, 20, 32768]);
+        let run = run_script(machine, b"x", 1000);
+        assert_eq!(run.exhausted.unwrap().prompt, "");
+    }
+}
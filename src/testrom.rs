@@ -0,0 +1,142 @@
+//! Runs guest test ROMs: programs that check the machine, or code injected into the challenge,
+//! from the inside and report to the host with the extension opcodes.
+//!
+//! A ROM passes when it halts and every `assert` it executed held. Its `dbg` and `dump` lines and
+//! its output are shown when it fails. A ROM `name.bin` is fed the contents of `name.in`, if there
+//! is such a file, as input.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::{extensions::Extensions, words_from_bytes, MachineState, RunOutcome, RunResult};
+
+/// The extension of test ROMs.
+pub const ROM_EXTENSION: &str = "bin";
+/// The extension of the input file fed to the ROM of the same name.
+pub const INPUT_EXTENSION: &str = "in";
+
+/// The result of running one test ROM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomResult {
+    pub name: String,
+    pub result: RunResult,
+    pub extensions: Extensions,
+    pub output: String,
+}
+
+impl RomResult {
+    pub fn passed(&self) -> bool {
+        self.result == Ok(RunOutcome::Halted) && self.extensions.passed()
+    }
+}
+
+/// Runs the test ROM `program` with extensions enabled for at most `fuel` instructions.
+pub fn run(name: impl Into<String>, program: Vec<u16>, input: &[u8], fuel: u64) -> RomResult {
+    let mut machine = MachineState::new(program);
+    machine.extensions = Some(Extensions::new(false));
+    machine.push_input(input);
+    let result = machine.run_for(fuel);
+    RomResult {
+        name: name.into(),
+        result,
+        output: String::from_utf8_lossy(&machine.drain_output()).into_owned(),
+        extensions: machine.extensions.unwrap_or_default(),
+    }
+}
+
+/// Runs every test ROM in `dir`, in the order of their names.
+pub fn run_dir(dir: impl AsRef<Path>, fuel: u64) -> std::io::Result<Vec<RomResult>> {
+    let mut roms = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<PathBuf>>>()?;
+    roms.retain(|path| path.extension().is_some_and(|ext| ext == ROM_EXTENSION));
+    roms.sort();
+
+    roms.iter()
+        .map(|path| {
+            let program = words_from_bytes(&std::fs::read(path)?);
+            let input = match std::fs::read(path.with_extension(INPUT_EXTENSION)) {
+                Ok(input) => input,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(err) => return Err(err),
+            };
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            Ok(run(name, program, &input, fuel))
+        })
+        .collect()
+}
+
+/// Reports `results` the way `cargo test` does: a line per ROM, the details of the failures and
+/// a summary.
+pub fn report(results: &[RomResult]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "running {} test ROM(s)", results.len());
+    for rom in results {
+        let status = if rom.passed() { "ok" } else { "FAILED" };
+        let _ = writeln!(out, "test {} ... {status}", rom.name);
+    }
+
+    let failed = results
+        .iter()
+        .filter(|rom| !rom.passed())
+        .collect::<Vec<_>>();
+    for rom in &failed {
+        let _ = writeln!(out, "\n---- {} ----", rom.name);
+        match &rom.result {
+            Ok(RunOutcome::Halted) => {}
+            Ok(outcome) => {
+                let _ = writeln!(out, "did not halt: {outcome}");
+            }
+            Err(err) => {
+                let _ = writeln!(out, "failed: {err}");
+            }
+        }
+        for line in &rom.extensions.log {
+            let _ = writeln!(out, "{line}");
+        }
+        if !rom.output.is_empty() {
+            let _ = writeln!(out, "output:\n{}", rom.output.trim_end());
+        }
+    }
+
+    let status = if failed.is_empty() { "ok" } else { "FAILED" };
+    let _ = writeln!(
+        out,
+        "\ntest result: {status}. {} passed; {} failed",
+        results.len() - failed.len(),
+        failed.len()
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExecutionError;
+
+    #[test]
+    fn reports_results() {
+        // assert 1 5, halt, "x"
+        let pass = run("pass", vec![25, 1, 5, 0, 0, 1, 120], b"", 100);
+        // out 'a', assert 0 6, halt, "no"
+        let fail = run("fail", vec![19, 97, 25, 0, 6, 0, 2, 110, 111], b"", 100);
+        let invalid = run("invalid", vec![9999], b"", 100);
+        assert!(pass.passed());
+        assert!(!fail.passed());
+        assert_eq!(invalid.result, Err(ExecutionError::InvalidOpcode(9999, 0)));
+
+        assert_eq!(
+            report(&[pass, fail]),
+            "running 2 test ROM(s)\n\
+             test pass ... ok\n\
+             test fail ... FAILED\n\
+             \n\
+             ---- fail ----\n\
+             assertion failed at 2: no\n\
+             output:\n\
+             a\n\
+             \n\
+             test result: FAILED. 1 passed; 1 failed\n"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! How the TUI draws each kind of cell: a base palette, with the SGR parameters of any style
+//! replaced from the config.
+
+use std::collections::BTreeMap;
+
+/// What a cell of the screen is, which decides how it is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Style {
+    Normal,
+    /// The title bar of a pane.
+    Title,
+    /// The title bar of the pane with the focus.
+    Focused,
+    /// The line or cell the keys act on.
+    Cursor,
+    /// The next instruction to execute.
+    Current,
+    Breakpoint,
+    /// A value that changed at the last stop.
+    Changed,
+    Error,
+}
+
+impl Style {
+    pub const ALL: [Style; 8] = [
+        Style::Normal,
+        Style::Title,
+        Style::Focused,
+        Style::Cursor,
+        Style::Current,
+        Style::Breakpoint,
+        Style::Changed,
+        Style::Error,
+    ];
+
+    /// The name of the style in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            Style::Normal => "normal",
+            Style::Title => "title",
+            Style::Focused => "focused",
+            Style::Cursor => "cursor",
+            Style::Current => "current",
+            Style::Breakpoint => "breakpoint",
+            Style::Changed => "changed",
+            Style::Error => "error",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Style::ALL
+            .into_iter()
+            .find(|style| style.name() == name)
+            .ok_or_else(|| format!("unknown style `{name}`"))
+    }
+}
+
+/// The palette a theme starts from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Base {
+    /// The 256 colors of most terminals.
+    #[default]
+    Default,
+    /// The 16 colors every color terminal has.
+    Colors16,
+    /// Only bold, underlined and reversed text, which doesn't depend on telling colors apart.
+    NoColor,
+}
+
+impl Base {
+    pub fn name(self) -> &'static str {
+        match self {
+            Base::Default => "default",
+            Base::Colors16 => "16",
+            Base::NoColor => "none",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        [Base::Default, Base::Colors16, Base::NoColor]
+            .into_iter()
+            .find(|base| base.name() == name)
+            .ok_or_else(|| format!("unknown theme `{name}`, try `default`, `16` or `none`"))
+    }
+
+    /// The SGR parameters of `style`.
+    fn params(self, style: Style) -> &'static str {
+        match (self, style) {
+            (_, Style::Normal) => "0",
+            (Base::Default, Style::Title) => "0;38;5;252;48;5;238",
+            (Base::Default, Style::Focused) => "0;1;38;5;16;48;5;80",
+            (Base::Default, Style::Cursor) => "0;38;5;16;48;5;152",
+            (Base::Default, Style::Current) => "0;1;38;5;220",
+            (Base::Default, Style::Breakpoint) => "0;1;38;5;203",
+            (Base::Default, Style::Changed) => "0;1;38;5;213",
+            (Base::Default, Style::Error) => "0;38;5;203",
+            (Base::Colors16, Style::Title) => "0;7",
+            (Base::Colors16, Style::Focused) => "0;1;7;36",
+            (Base::Colors16, Style::Cursor) => "0;30;46",
+            (Base::Colors16, Style::Current) => "0;1;33",
+            (Base::Colors16, Style::Breakpoint) => "0;1;31",
+            (Base::Colors16, Style::Changed) => "0;1;35",
+            (Base::Colors16, Style::Error) => "0;31",
+            (Base::NoColor, Style::Title) => "0;7",
+            (Base::NoColor, Style::Focused) => "0;1;4;7",
+            (Base::NoColor, Style::Cursor) => "0;7",
+            (Base::NoColor, Style::Current) => "0;1",
+            (Base::NoColor, Style::Breakpoint) => "0;1",
+            (Base::NoColor, Style::Changed) => "0;4",
+            (Base::NoColor, Style::Error) => "0;1",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Theme {
+    pub base: Base,
+    /// SGR parameters replacing those of the base, such as `1;33` for bold yellow.
+    pub styles: BTreeMap<Style, String>,
+}
+
+impl Theme {
+    /// The base that suits the terminal, without colors if `no_color` is set, as the `NO_COLOR`
+    /// convention asks, or the terminal is dumb.
+    pub fn for_terminal(term: Option<&str>, no_color: bool) -> Self {
+        let base = match term {
+            _ if no_color => Base::NoColor,
+            None | Some("dumb") => Base::NoColor,
+            Some(term) if term.contains("256color") || term.contains("direct") => Base::Default,
+            Some(_) => Base::Colors16,
+        };
+        Self {
+            base,
+            styles: BTreeMap::new(),
+        }
+    }
+
+    /// Checks that `params` are SGR parameters: numbers separated by `;`.
+    pub fn check_params(params: &str) -> Result<(), String> {
+        match params.split(';').all(|param| param.parse::<u8>().is_ok()) {
+            true => Ok(()),
+            false => Err(format!(
+                "invalid style `{params}`, expected SGR parameters such as `1;33`"
+            )),
+        }
+    }
+
+    /// The SGR sequence drawing `style`.
+    pub fn sgr(&self, style: Style) -> String {
+        let params = match self.styles.get(&style) {
+            Some(params) => params,
+            None => self.base.params(style),
+        };
+        format!("\x1b[{params}m")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_styles() {
+        let mut theme = Theme::for_terminal(Some("xterm"), false);
+        assert_eq!(theme.base, Base::Colors16);
+        assert_eq!(theme.sgr(Style::Breakpoint), "\x1b[0;1;31m");
+        theme.styles.insert(Style::Breakpoint, "4".to_string());
+        assert_eq!(theme.sgr(Style::Breakpoint), "\x1b[4m");
+
+        assert_eq!(
+            Theme::for_terminal(Some("xterm-256color"), false).base,
+            Base::Default
+        );
+        assert_eq!(
+            Theme::for_terminal(Some("xterm-256color"), true).base,
+            Base::NoColor
+        );
+        assert_eq!(Theme::for_terminal(None, false).base, Base::NoColor);
+        // no color theme draws with attributes alone
+        for style in Style::ALL {
+            let params = Base::NoColor.params(style);
+            assert!(params.split(';').all(|p| p.len() == 1), "{params}");
+        }
+        assert!(Theme::check_params("0;1;38;5;220").is_ok());
+        assert!(Theme::check_params("bold").is_err());
+        assert!(Theme::check_params("").is_err());
+    }
+}
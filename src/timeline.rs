@@ -0,0 +1,192 @@
+use std::fmt::Write as _;
+
+use crate::{calls::CallEvent, project::Project};
+
+/// Something that happened during a run, besides calls, which come from a `CallTrace`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimelineEvent {
+    /// The instruction at `pos` wrote `value` to memory at `addr`.
+    Write {
+        addr: u16,
+        value: u16,
+        pos: u16,
+    },
+    Input(u8),
+    Output(u8),
+}
+
+/// An optional record of memory writes, input and output, by the number of instructions executed
+/// when they happened.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timeline {
+    pub events: Vec<(u64, TimelineEvent)>,
+}
+
+impl Timeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, steps: u64, event: TimelineEvent) {
+        self.events.push((steps, event));
+    }
+}
+
+/// Exports `calls` and `timeline` in the Chrome trace-event format, which timeline viewers such as
+/// Perfetto and `chrome://tracing` open directly. One instruction is shown as one microsecond.
+pub fn chrome_trace(calls: &[CallEvent], timeline: &Timeline, project: &Project) -> String {
+    let mut events = Vec::new();
+
+    let mut open = 0;
+    for event in calls {
+        match *event {
+            CallEvent::Call {
+                pos,
+                target,
+                args,
+                steps,
+                ..
+            } => {
+                open += 1;
+                let args = args
+                    .iter()
+                    .enumerate()
+                    .map(|(i, val)| format!("\"r{i}\":{val}"))
+                    .collect::<Vec<_>>();
+                events.push((
+                    steps,
+                    format!(
+                        "{{\"name\":{},\"cat\":\"call\",\"ph\":\"B\",\"ts\":{steps},\"pid\":1,\"tid\":1,\"args\":{{\"from\":{pos},{}}}}}",
+                        json_string(&project.describe(target)),
+                        args.join(",")
+                    ),
+                ));
+            }
+            CallEvent::Return {
+                result,
+                steps,
+                depth,
+                ..
+            } => {
+                // unwinding past calls that never returned closes them too
+                for _ in depth..open {
+                    events.push((
+                        steps,
+                        format!(
+                            "{{\"ph\":\"E\",\"ts\":{steps},\"pid\":1,\"tid\":1,\"args\":{{\"r0\":{result}}}}}"
+                        ),
+                    ));
+                }
+                open = open.min(depth);
+            }
+        }
+    }
+
+    for &(steps, event) in &timeline.events {
+        let (name, cat, args) = match event {
+            TimelineEvent::Write { addr, value, pos } => {
+                let mut args = format!("\"addr\":{addr},\"value\":{value},\"pos\":{pos}");
+                if let Some(var) = project.variables.get(&addr) {
+                    let _ = write!(
+                        args,
+                        ",\"variable\":{},\"typed\":{}",
+                        json_string(&var.name),
+                        json_string(&var.ty.value(value).to_string())
+                    );
+                }
+                (format!("write {}", project.describe(addr)), "write", args)
+            }
+            TimelineEvent::Input(byte) => (
+                format!("in {}", byte as char),
+                "io",
+                format!("\"byte\":{byte}"),
+            ),
+            TimelineEvent::Output(byte) => (
+                format!("out {}", byte as char),
+                "io",
+                format!("\"byte\":{byte}"),
+            ),
+        };
+        events.push((
+            steps,
+            format!(
+                "{{\"name\":{},\"cat\":\"{cat}\",\"ph\":\"i\",\"s\":\"t\",\"ts\":{steps},\"pid\":1,\"tid\":1,\"args\":{{{args}}}}}",
+                json_string(&name)
+            ),
+        ));
+    }
+
+    // stable, so calls stay ahead of what happens at the same step
+    events.sort_by_key(|&(steps, _)| steps);
+    let mut out = String::from("{\"traceEvents\":[\n");
+    for (i, (_, event)) in events.iter().enumerate() {
+        let separator = if i + 1 == events.len() { "" } else { "," };
+        let _ = writeln!(out, "{event}{separator}");
+    }
+    out.push_str("]}\n");
+    out
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{calls::CallTrace, testing::MachineBuilder, RunOutcome};
+
+    #[test]
+    fn exports_trace_events() {
+        // 0: call 3
+        // 2: halt
+        // 3: out 'a'
+        // 5: wmem 100 1
+        // 8: ret
+        let mut machine = MachineBuilder::new()
+            .program(&[17, 3, 0, 19, 97, 16, 100, 1, 18])
+            .input(b"")
+            .build();
+        machine.call_trace = Some(CallTrace::new());
+        machine.timeline = Some(Timeline::new());
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+
+        let project = Project::parse("symbol 3 greet\nvariable 100 done bool").unwrap();
+        let trace = chrome_trace(
+            &machine.call_trace.unwrap().log,
+            &machine.timeline.unwrap(),
+            &project,
+        );
+        let lines = trace.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 6);
+        assert!(
+            lines[1].starts_with("{\"name\":\"3 <greet>\",\"cat\":\"call\",\"ph\":\"B\",\"ts\":1,")
+        );
+        assert!(lines[2]
+            .starts_with("{\"name\":\"out a\",\"cat\":\"io\",\"ph\":\"i\",\"s\":\"t\",\"ts\":2,"));
+        assert!(lines[3].starts_with("{\"name\":\"write 100 <done>\","));
+        assert!(lines[3].contains(
+            "\"args\":{\"addr\":100,\"value\":1,\"pos\":5,\"variable\":\"done\",\"typed\":\"true\"}},"
+        ));
+        assert!(lines[4].starts_with("{\"ph\":\"E\",\"ts\":4,"));
+        assert!(!lines[4].ends_with(','));
+        assert_eq!(lines[5], "]}");
+    }
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(json_string("a\"b\\\n"), "\"a\\\"b\\\\\\u000a\"");
+    }
+}
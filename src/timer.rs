@@ -0,0 +1,119 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::{MachineState, RunOutcome};
+
+/// Something to do once the machine has executed a given number of instructions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TimerEvent {
+    /// Feed the bytes to `in` before any other input.
+    Input(Vec<u8>),
+    /// Stop the run with `RunOutcome::Timer`, for the host to do whatever it needs to.
+    Stop,
+}
+
+/// Events scheduled on the count of executed instructions rather than on time, so that races
+/// between output and input play out the same way on every run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timers {
+    /// The events still to fire, by the step count they fire at.
+    pub events: BTreeMap<u64, Vec<TimerEvent>>,
+    /// Input injected by events and not read yet.
+    pending: VecDeque<u8>,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Schedules `event` for right after the `steps`th instruction, or before the first one for 0.
+    /// Events scheduled for a count already passed fire before the next instruction.
+    pub fn at(&mut self, steps: u64, event: TimerEvent) {
+        self.events.entry(steps).or_default().push(event);
+    }
+
+    /// Parses an event such as `1500 input look` or `1500 stop`. Input gets a newline appended.
+    pub fn parse(spec: &str) -> Result<(u64, TimerEvent), String> {
+        let (steps, event) = spec.trim().split_once(' ').unwrap_or((spec.trim(), ""));
+        let steps = steps
+            .parse()
+            .map_err(|_| format!("invalid step count `{steps}`"))?;
+        let event = match event.trim_start().split_once(' ') {
+            Some(("input", text)) => TimerEvent::Input(format!("{text}\n").into_bytes()),
+            None if event.trim() == "stop" => TimerEvent::Stop,
+            _ => {
+                return Err(format!(
+                    "invalid event `{event}`, expected `input <text>` or `stop`"
+                ))
+            }
+        };
+        Ok((steps, event))
+    }
+}
+
+impl MachineState {
+    /// Fires the events due before the next instruction, returning the outcome to stop with if
+    /// one of them stops the run.
+    pub(crate) fn fire_timers(&mut self) -> Option<RunOutcome> {
+        let timers = self.timers.as_mut()?;
+        let mut stop = None;
+        while let Some(entry) = timers.events.first_entry() {
+            if *entry.key() > self.steps {
+                break;
+            }
+            for event in entry.remove() {
+                match event {
+                    TimerEvent::Input(bytes) => timers.pending.extend(bytes),
+                    TimerEvent::Stop => stop = Some(RunOutcome::Timer(self.steps)),
+                }
+            }
+        }
+        stop
+    }
+
+    /// Takes the next input byte injected by an event.
+    pub(crate) fn timer_input(&mut self) -> Option<u8> {
+        self.timers.as_mut()?.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MachineBuilder;
+
+    #[test]
+    fn parses_events() {
+        assert_eq!(
+            Timers::parse("1500 input look north"),
+            Ok((1500, TimerEvent::Input(b"look north\n".to_vec())))
+        );
+        assert_eq!(Timers::parse(" 7 stop "), Ok((7, TimerEvent::Stop)));
+        for spec in ["stop", "7", "7 halt", "x input a"] {
+            assert!(Timers::parse(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn fires_on_step_counts() {
+        // 0: noop
+        // 1: noop
+        // 2: in r0
+        // 4: halt
+        let mut machine = MachineBuilder::new()
+            .program(&[21, 21, 20, 32768, 0])
+            .input(b"b")
+            .build();
+        let mut timers = Timers::new();
+        timers.at(1, TimerEvent::Stop);
+        timers.at(2, TimerEvent::Input(b"a".to_vec()));
+        machine.timers = Some(timers);
+
+        assert_eq!(machine.run(), Ok(RunOutcome::Timer(1)));
+        assert_eq!(machine.cur, 1);
+        // resuming doesn't fire the event again, and the injected input comes first
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.registers[0], b'a' as u16);
+        assert!(machine.timers.unwrap().events.is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::BTreeMap;
+
+use crate::instruction;
+
+/// What a disabled opcode does instead of running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Disabled {
+    /// Fail with `ExecutionError::DisabledOpcode`.
+    Error,
+    /// Skip the instruction, operands included.
+    Nop,
+}
+
+/// Parses a comma-separated list of mnemonics to disable, each optionally followed by `:nop` to
+/// skip the instruction instead of failing, e.g. `out:nop,wmem`.
+pub fn parse(spec: &str) -> Result<BTreeMap<u16, Disabled>, String> {
+    spec.split(',')
+        .map(|entry| {
+            let (mnemonic, mode) = entry.trim().split_once(':').unwrap_or((entry.trim(), ""));
+            let info = instruction::by_mnemonic(mnemonic)
+                .ok_or_else(|| format!("unknown mnemonic `{mnemonic}`"))?;
+            let mode = match mode {
+                "" | "error" => Disabled::Error,
+                "nop" => Disabled::Nop,
+                other => return Err(format!("unknown mode `{other}`, expected `error` or `nop`")),
+            };
+            Ok((info.code, mode))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::setup, ExecutionError, RunOutcome};
+
+    #[test]
+    fn parses_spec() {
+        assert_eq!(
+            parse("out:nop, wmem"),
+            Ok(BTreeMap::from([(16, Disabled::Error), (19, Disabled::Nop)]))
+        );
+        assert!(parse("frobnicate").is_err());
+        assert!(parse("out:maybe").is_err());
+    }
+
+    #[test]
+    fn disables_opcodes() {
+        // set r0 1, wmem 10 r0, halt
+        let program = vec![1, 32768, 1, 16, 10, 32768, 0];
+
+        let mut machine = setup(program.clone());
+        machine.disabled = parse("wmem:nop").unwrap();
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        assert_eq!(machine.mem[10], 0);
+        assert_eq!(machine.registers[0], 1);
+
+        let mut machine = setup(program);
+        machine.disabled = parse("wmem").unwrap();
+        assert_eq!(machine.run(), Err(ExecutionError::DisabledOpcode(16, 3)));
+        assert_eq!(machine.cur, 3);
+    }
+}
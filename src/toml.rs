@@ -0,0 +1,262 @@
+//! The small subset of TOML read by patch scripts and the TUI's config: `[table]` and
+//! `[[array.table]]` headers, and `key = value` entries whose values are numbers, basic strings
+//! and arrays of them, which may span several lines. Comments start with `#`.
+
+use std::fmt::Write as _;
+
+use crate::project::parse_number;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Value {
+    Int(u16),
+    Str(String),
+    Array(Vec<Value>),
+}
+
+/// A line of a document, or several for an array.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Item {
+    /// `[name]`
+    Table(String),
+    /// `[[name]]`
+    ArrayTable(String),
+    Entry(String, Value),
+}
+
+/// Parses `text` into its items, each with the number of the line it starts on. Errors come with
+/// the number of the line they are on.
+pub(crate) fn parse(text: &str) -> Result<Vec<(usize, Item)>, (String, usize)> {
+    let mut items = Vec::new();
+    let mut lines = text.lines().enumerate();
+
+    while let Some((i, line)) = lines.next() {
+        let line_no = i + 1;
+        let err = |msg: String| (msg, line_no);
+        let mut line = strip_comment(line).trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("[[").and_then(|s| s.strip_suffix("]]")) {
+            items.push((line_no, Item::ArrayTable(name.trim().to_string())));
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            items.push((line_no, Item::Table(name.trim().to_string())));
+            continue;
+        }
+
+        let (key, _) = line
+            .split_once('=')
+            .ok_or_else(|| err(format!("expected `key = value`, got `{line}`")))?;
+        let key = key.trim().to_string();
+        // arrays may span several lines
+        while open_brackets(&line) > 0 {
+            let (_, next) = lines
+                .next()
+                .ok_or_else(|| err("unterminated array".to_string()))?;
+            line.push(' ');
+            line.push_str(strip_comment(next).trim());
+        }
+        let (_, value) = line.split_once('=').unwrap();
+        let value = parse_value(value.trim()).map_err(err)?;
+        items.push((line_no, Item::Entry(key, value)));
+    }
+    Ok(items)
+}
+
+/// Escapes `s` to go between the quotes of a basic TOML string.
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            ch if ch.is_control() => {
+                let _ = write!(out, "\\u{:04X}", ch as u32);
+            }
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Reverses `escape`, for the inside of a basic TOML string.
+fn unescape(s: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => out.push('"'),
+            Some('\\') => out.push('\\'),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('u') => {
+                let hex = chars.by_ref().take(4).collect::<String>();
+                let ch = u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("invalid escape `\\u{hex}`"))?;
+                out.push(ch);
+            }
+            other => {
+                let other = other.map(String::from).unwrap_or_default();
+                return Err(format!("invalid escape `\\{other}`"));
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, ch) in line.char_indices() {
+        if std::mem::take(&mut escaped) {
+            continue;
+        }
+        match ch {
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// How many more `[` than `]` there are in `line`, outside of strings.
+fn open_brackets(line: &str) -> isize {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for ch in line.chars() {
+        if std::mem::take(&mut escaped) {
+            continue;
+        }
+        match ch {
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '[' if !in_string => depth += 1,
+            ']' if !in_string => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+fn parse_value(s: &str) -> Result<Value, String> {
+    if let Some(inner) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+        let mut items = Vec::new();
+        for item in split_items(inner) {
+            let item = item.trim();
+            if !item.is_empty() {
+                items.push(parse_value(item)?);
+            }
+        }
+        return Ok(Value::Array(items));
+    }
+    if let Some(inner) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        return unescape(inner).map(Value::Str);
+    }
+    parse_number(&s.replace('_', "")).map(Value::Int)
+}
+
+/// Splits array items on commas that are not inside a string.
+fn split_items(s: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, ch) in s.char_indices() {
+        if std::mem::take(&mut escaped) {
+            continue;
+        }
+        match ch {
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                items.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&s[start..]);
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_items() {
+        let items = parse(
+            r#"
+            top = "a # b"  # a comment
+            [table]
+            list = [
+                1, 0x2,
+                "three, \"3\"",
+            ]
+            [[array.table]]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            items,
+            [
+                (
+                    2,
+                    Item::Entry("top".to_string(), Value::Str("a # b".to_string()))
+                ),
+                (3, Item::Table("table".to_string())),
+                (
+                    4,
+                    Item::Entry(
+                        "list".to_string(),
+                        Value::Array(vec![
+                            Value::Int(1),
+                            Value::Int(2),
+                            Value::Str("three, \"3\"".to_string())
+                        ])
+                    )
+                ),
+                (8, Item::ArrayTable("array.table".to_string())),
+            ]
+        );
+        assert_eq!(
+            parse("a = 1\nb"),
+            Err(("expected `key = value`, got `b`".to_string(), 2))
+        );
+        assert_eq!(
+            parse("a = [1,\n2"),
+            Err(("unterminated array".to_string(), 1))
+        );
+        // brackets in strings don't open or close arrays
+        assert_eq!(
+            parse("a = \"[\"\nb = [\"]\",\n\"\\\"[\"]"),
+            Ok(vec![
+                (1, Item::Entry("a".to_string(), Value::Str("[".to_string()))),
+                (
+                    2,
+                    Item::Entry(
+                        "b".to_string(),
+                        Value::Array(vec![
+                            Value::Str("]".to_string()),
+                            Value::Str("\"[".to_string())
+                        ])
+                    )
+                ),
+            ])
+        );
+    }
+}
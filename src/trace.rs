@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::{instruction::OpcodeInfo, MachineState, MAX_ADDR, REGISTER_COUNT};
+
+/// Writes a line for every instruction executed, for diffing runs against other implementations:
+/// its position, mnemonic and operands, with registers resolved to their values, followed by the
+/// registers before it ran, e.g. `1531 add r0 r1=42 5 | 0 42 0 0 0 0 0 0`.
+///
+/// Registers given a name in `registers` show it instead, e.g. `key=42` for `r7=42`, which makes
+/// the trace easier to read but no longer comparable with traces of other implementations.
+///
+/// The first write error stops the trace, and is returned by `flush`.
+pub struct InstructionTrace {
+    sink: Arc<Mutex<dyn Write + Send>>,
+    error: Option<String>,
+    /// How many lines were written.
+    pub lines: u64,
+    pub registers: BTreeMap<usize, String>,
+}
+
+impl InstructionTrace {
+    pub fn new(sink: impl Write + Send + 'static) -> Self {
+        Self {
+            sink: Arc::new(Mutex::new(sink)),
+            error: None,
+            lines: 0,
+            registers: BTreeMap::new(),
+        }
+    }
+
+    /// Traces to a new file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn record(&mut self, line: &str) {
+        if self.error.is_some() {
+            return;
+        }
+        let mut sink = self.sink.lock().unwrap_or_else(|err| err.into_inner());
+        match writeln!(sink, "{line}") {
+            Ok(()) => self.lines += 1,
+            Err(err) => self.error = Some(err.to_string()),
+        }
+    }
+
+    /// Flushes the trace, failing if any write failed.
+    pub fn flush(&mut self) -> Result<(), String> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+        let mut sink = self.sink.lock().unwrap_or_else(|err| err.into_inner());
+        sink.flush().map_err(|err| err.to_string())
+    }
+}
+
+impl Clone for InstructionTrace {
+    /// Clones write to the same sink.
+    fn clone(&self) -> Self {
+        Self {
+            sink: Arc::clone(&self.sink),
+            error: self.error.clone(),
+            lines: self.lines,
+            registers: self.registers.clone(),
+        }
+    }
+}
+
+impl std::fmt::Debug for InstructionTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstructionTrace")
+            .field("lines", &self.lines)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for InstructionTrace {
+    /// Traces are equal when they write to the same sink.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.sink, &other.sink) && self.lines == other.lines
+    }
+}
+
+impl Eq for InstructionTrace {}
+
+/// The trace line of the instruction at `pos`, described by `info`, before it runs.
+pub fn line(machine: &MachineState, pos: u16, info: &OpcodeInfo) -> String {
+    let mut line = format!("{pos} {}", info.mnemonic);
+    for n in 0..info.arity {
+        let Some(&word) = machine.mem.get(pos as usize + 1 + n) else {
+            line.push_str(" ?");
+            continue;
+        };
+        let operand = match word as usize {
+            val if val < MAX_ADDR => val.to_string(),
+            val if val < MAX_ADDR + REGISTER_COUNT => {
+                let reg = val - MAX_ADDR;
+                let name = machine
+                    .instruction_trace
+                    .as_ref()
+                    .and_then(|trace| trace.registers.get(&reg));
+                match name {
+                    Some(name) => format!("{name}={}", machine.registers[reg]),
+                    None => format!("r{reg}={}", machine.registers[reg]),
+                }
+            }
+            val => format!("<invalid {val}>"),
+        };
+        line.push(' ');
+        line.push_str(&operand);
+    }
+    line.push_str(" |");
+    for reg in machine.registers {
+        line.push_str(&format!(" {reg}"));
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{setup, SharedSink},
+        RunOutcome,
+    };
+
+    #[test]
+    fn traces_instructions() {
+        // 0: set r1 42
+        // 3: add r0 r1 5
+        // 7: halt
+        let mut machine = setup(vec![1, 32769, 42, 9, 32768, 32769, 5, 0]);
+        let sink = SharedSink::default();
+        machine.instruction_trace = Some(InstructionTrace::new(sink.clone()));
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+
+        let mut trace = machine.instruction_trace.unwrap();
+        assert_eq!(trace.flush(), Ok(()));
+        assert_eq!(trace.lines, 3);
+        assert_eq!(
+            String::from_utf8(sink.0.lock().unwrap().clone()).unwrap(),
+            "0 set r1=0 42 | 0 0 0 0 0 0 0 0\n\
+             3 add r0=0 r1=42 5 | 0 42 0 0 0 0 0 0\n\
+             7 halt | 47 42 0 0 0 0 0 0\n"
+        );
+
+        let mut machine = setup(vec![1, 32769, 42, 0]);
+        let sink = SharedSink::default();
+        let mut trace = InstructionTrace::new(sink.clone());
+        trace.registers.insert(1, "key".to_string());
+        machine.instruction_trace = Some(trace);
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+        let text = String::from_utf8(sink.0.lock().unwrap().clone()).unwrap();
+        assert!(text.starts_with("0 set key=0 42 |"), "{text}");
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::MachineState;
+
+/// A copy of everything the program prints, and optionally of the input it reads, so a session
+/// can be searched for codes and clues afterwards. Without the input, it is the transcript
+/// `report` reads.
+///
+/// The first write error stops the transcript, and is returned by `flush`.
+pub struct Transcript {
+    sink: Arc<Mutex<dyn Write + Send>>,
+    error: Option<String>,
+    /// Whether the input is written too, where the program read it.
+    pub input: bool,
+}
+
+impl Transcript {
+    pub fn new(sink: impl Write + Send + 'static, input: bool) -> Self {
+        Self {
+            sink: Arc::new(Mutex::new(sink)),
+            error: None,
+            input,
+        }
+    }
+
+    /// Writes the transcript to a new file at `path`.
+    pub fn create(path: impl AsRef<Path>, input: bool) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), input))
+    }
+
+    fn write(&mut self, byte: u8) {
+        if self.error.is_some() {
+            return;
+        }
+        let mut sink = self.sink.lock().unwrap_or_else(|err| err.into_inner());
+        if let Err(err) = sink.write_all(&[byte]) {
+            self.error = Some(err.to_string());
+        }
+    }
+
+    /// Flushes the transcript, failing if any write failed.
+    pub fn flush(&mut self) -> Result<(), String> {
+        if let Some(err) = &self.error {
+            return Err(err.clone());
+        }
+        let mut sink = self.sink.lock().unwrap_or_else(|err| err.into_inner());
+        sink.flush().map_err(|err| err.to_string())
+    }
+}
+
+impl Clone for Transcript {
+    /// Clones write to the same sink.
+    fn clone(&self) -> Self {
+        Self {
+            sink: Arc::clone(&self.sink),
+            error: self.error.clone(),
+            input: self.input,
+        }
+    }
+}
+
+impl std::fmt::Debug for Transcript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Transcript")
+            .field("error", &self.error)
+            .field("input", &self.input)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for Transcript {
+    /// Transcripts are equal when they write to the same sink.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.sink, &other.sink) && self.input == other.input
+    }
+}
+
+impl Eq for Transcript {}
+
+impl MachineState {
+    /// Called by `out` after it printed `byte`.
+    pub(crate) fn transcribe_output(&mut self, byte: u8) {
+        if let Some(transcript) = &mut self.transcript {
+            transcript.write(byte);
+        }
+    }
+
+    /// Called by `in` after it read `byte`.
+    pub(crate) fn transcribe_input(&mut self, byte: u8) {
+        if let Some(transcript) = self.transcript.as_mut().filter(|t| t.input) {
+            transcript.write(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        testing::{MachineBuilder, SharedSink},
+        RunOutcome,
+    };
+
+    #[test]
+    fn copies_the_session() {
+        // 0: out '>'
+        // 2: in r0
+        // 4: out r0
+        // 6: halt
+        let program = [19, 62, 20, 32768, 19, 32768, 0];
+        for (input, expected) in [(false, ">a"), (true, ">aa")] {
+            let mut machine = MachineBuilder::new().program(&program).input(b"a").build();
+            let sink = SharedSink::default();
+            machine.transcript = Some(Transcript::new(sink.clone(), input));
+            assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+            assert_eq!(machine.transcript.unwrap().flush(), Ok(()));
+            assert_eq!(*sink.0.lock().unwrap(), expected.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,1849 @@
+//! A full-screen debugger: the disassembly, registers, stack, memory, watched expressions and the
+//! program's output in panes, above a command line taking every command of `Debugger`. The program
+//! can be played in the output pane, while the other panes follow the machine, and the panes can
+//! be scrubbed back through the instructions the journal can undo.
+//!
+//! Frames are drawn into a `Screen`, a grid of styled characters, which is turned into escape
+//! sequences only to show it, so everything but the terminal itself can be tested.
+
+use std::collections::{btree_map::Entry, BTreeSet};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crate::{
+    condition::Condition,
+    config::Config,
+    debugger::Debugger,
+    instruction::{self, disassemble_with},
+    layout::{Layout, Pane, Rect},
+    project,
+    terminal::{self, Event, Key, Mouse, Terminal},
+    theme::{Base, Style, Theme},
+    MachineState, RunOutcome, RunResult, REGISTER_COUNT,
+};
+
+/// How many instructions run between checks for keys while the machine runs.
+const CHUNK: u64 = 1 << 14;
+
+/// How often the screen is redrawn while the machine runs.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long to wait for a key before redrawing anyway, which follows resizes.
+const IDLE_POLL: Duration = Duration::from_millis(250);
+
+/// How many lines a turn of the mouse wheel scrolls.
+const WHEEL_LINES: isize = 3;
+
+/// The status line while the keys go to the program.
+const PLAYING: &str = "Playing, press Esc for the debugger's keys.";
+
+/// How many bytes of the program's output are kept for the output pane.
+const OUTPUT_LIMIT: usize = 1 << 16;
+
+/// How many lines of commands and responses are kept for the log pane.
+const LOG_LIMIT: usize = 1000;
+
+/// How many instructions Page Up and Page Down move the scrubber.
+const SCRUB_PAGE: usize = 100;
+
+/// How many columns the scrubber's slider takes in the status line.
+const SLIDER_WIDTH: usize = 20;
+
+/// A frame: a grid of characters, each with its style.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<(char, Style)>,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![(' ', Style::Normal); width * height],
+        }
+    }
+
+    /// Writes `text` from column `x` of row `y`, cut off after `width` columns or at the edge.
+    pub fn print(&mut self, x: usize, y: usize, width: usize, text: &str, style: Style) {
+        if y >= self.height {
+            return;
+        }
+        let end = (x + width).min(self.width);
+        for (x, ch) in (x..end).zip(text.chars()) {
+            let ch = if ch.is_control() { ' ' } else { ch };
+            self.cells[y * self.width + x] = (ch, style);
+        }
+    }
+
+    /// Sets the style of `width` columns from column `x` of row `y`, keeping their text.
+    pub fn paint(&mut self, x: usize, y: usize, width: usize, style: Style) {
+        if y >= self.height {
+            return;
+        }
+        for x in x..(x + width).min(self.width) {
+            self.cells[y * self.width + x].1 = style;
+        }
+    }
+
+    /// The text on the screen, without styles or trailing spaces.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        for row in self.cells.chunks(self.width.max(1)) {
+            let line = row.iter().map(|&(ch, _)| ch).collect::<String>();
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The escape sequences drawing the screen over the whole terminal in `theme`.
+    pub fn to_ansi(&self, theme: &Theme) -> String {
+        let mut out = String::new();
+        let mut style = None;
+        for (y, row) in self.cells.chunks(self.width.max(1)).enumerate() {
+            let _ = write!(out, "\x1b[{};1H", y + 1);
+            for &(ch, cell_style) in row {
+                if style != Some(cell_style) {
+                    out.push_str(&theme.sgr(cell_style));
+                    style = Some(cell_style);
+                }
+                out.push(ch);
+            }
+        }
+        out.push_str(&theme.sgr(Style::Normal));
+        out
+    }
+}
+
+/// Something a key does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    NextPane,
+    PreviousPane,
+    /// Give the focused pane more of its column's height.
+    Grow,
+    Shrink,
+    /// Give the focused pane's column more of the width.
+    Widen,
+    Narrow,
+    /// Move the focused pane up or down its column, or to the column to its left or right.
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Step,
+    Back,
+    Continue,
+    Pause,
+    /// Continue until the instruction under the cursor.
+    RunToCursor,
+    ToggleBreakpoint,
+    /// Move the cursor to the next instruction to execute.
+    FollowPc,
+    Command,
+    /// Type to the program in the output pane.
+    Play,
+    /// Slide the panes back through the instructions the journal can undo.
+    Scrub,
+    Help,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 26] = [
+        Action::Up,
+        Action::Down,
+        Action::PageUp,
+        Action::PageDown,
+        Action::NextPane,
+        Action::PreviousPane,
+        Action::Grow,
+        Action::Shrink,
+        Action::Widen,
+        Action::Narrow,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Step,
+        Action::Back,
+        Action::Continue,
+        Action::Pause,
+        Action::RunToCursor,
+        Action::ToggleBreakpoint,
+        Action::FollowPc,
+        Action::Command,
+        Action::Play,
+        Action::Scrub,
+        Action::Help,
+        Action::Quit,
+    ];
+
+    /// The name of the action in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::PageUp => "page-up",
+            Action::PageDown => "page-down",
+            Action::NextPane => "next-pane",
+            Action::PreviousPane => "previous-pane",
+            Action::Grow => "grow",
+            Action::Shrink => "shrink",
+            Action::Widen => "widen",
+            Action::Narrow => "narrow",
+            Action::MoveUp => "move-up",
+            Action::MoveDown => "move-down",
+            Action::MoveLeft => "move-left",
+            Action::MoveRight => "move-right",
+            Action::Step => "step",
+            Action::Back => "back",
+            Action::Continue => "continue",
+            Action::Pause => "pause",
+            Action::RunToCursor => "run-to-cursor",
+            Action::ToggleBreakpoint => "toggle-breakpoint",
+            Action::FollowPc => "follow-pc",
+            Action::Command => "command",
+            Action::Play => "play",
+            Action::Scrub => "scrub",
+            Action::Help => "help",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Self, String> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.name() == name)
+            .ok_or_else(|| format!("unknown action `{name}`"))
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Action::Up => "move up",
+            Action::Down => "move down",
+            Action::PageUp => "move up a page",
+            Action::PageDown => "move down a page",
+            Action::NextPane => "focus the next pane",
+            Action::PreviousPane => "focus the previous pane",
+            Action::Grow => "make the pane taller",
+            Action::Shrink => "make the pane shorter",
+            Action::Widen => "make the pane's column wider",
+            Action::Narrow => "make the pane's column narrower",
+            Action::MoveUp => "move the pane up",
+            Action::MoveDown => "move the pane down",
+            Action::MoveLeft => "move the pane to the column on the left",
+            Action::MoveRight => "move the pane to the column on the right",
+            Action::Step => "execute an instruction",
+            Action::Back => "undo an instruction",
+            Action::Continue => "run until the program stops",
+            Action::Pause => "stop the run",
+            Action::RunToCursor => "run until the cursor's instruction",
+            Action::ToggleBreakpoint => "set or delete a breakpoint at the cursor",
+            Action::FollowPc => "move the cursor to the next instruction",
+            Action::Command => "enter a debugger command",
+            Action::Play => "play: type to the program until Esc",
+            Action::Scrub => {
+                "scrub back through time: ← → Home End, Enter resumes there, Esc returns"
+            }
+            Action::Help => "list the keys",
+            Action::Quit => "quit",
+        }
+    }
+}
+
+/// The keys and what they do, unless the config binds others.
+pub const KEYS: &[(Key, Action)] = &[
+    (Key::Up, Action::Up),
+    (Key::Char('k'), Action::Up),
+    (Key::Down, Action::Down),
+    (Key::Char('j'), Action::Down),
+    (Key::PageUp, Action::PageUp),
+    (Key::PageDown, Action::PageDown),
+    (Key::Tab, Action::NextPane),
+    (Key::BackTab, Action::PreviousPane),
+    (Key::Char('+'), Action::Grow),
+    (Key::Char('-'), Action::Shrink),
+    (Key::Char('>'), Action::Widen),
+    (Key::Char('<'), Action::Narrow),
+    (Key::Char('K'), Action::MoveUp),
+    (Key::Char('J'), Action::MoveDown),
+    (Key::Char('H'), Action::MoveLeft),
+    (Key::Char('L'), Action::MoveRight),
+    (Key::Char('s'), Action::Step),
+    (Key::Char('u'), Action::Back),
+    (Key::Char('c'), Action::Continue),
+    (Key::Esc, Action::Pause),
+    (Key::Ctrl('c'), Action::Pause),
+    (Key::Char('r'), Action::RunToCursor),
+    (Key::Char('b'), Action::ToggleBreakpoint),
+    (Key::Char('g'), Action::FollowPc),
+    (Key::Char(':'), Action::Command),
+    (Key::Char('i'), Action::Play),
+    (Key::Char('t'), Action::Scrub),
+    (Key::Char('?'), Action::Help),
+    (Key::Char('q'), Action::Quit),
+];
+
+/// The commands of the TUI besides those of `Debugger`.
+const COMMANDS: &[&str] = &[
+    "goto <addr>             move the cursor there, or the memory pane if it has the focus",
+    "display <expression>    watch an expression, e.g. `mem[r1] + 1`",
+    "display words <n> <e>   watch the `n` words from the address `e`",
+    "display str <e>         watch the string whose length word is at `e`",
+    "display list <n> <e>    watch the linked list from `e`, each node's next pointer `n` words in",
+    "undisplay [n]           stop watching the `n`th expression, or all of them",
+    "layout [name]           switch to a layout of the config, or list them",
+    "layout save <name>      save the panes as they are as a layout of the config",
+    "theme [base]            switch to the `default`, `16` or `none` colors, or show which",
+    "poke <addr> <value>     write a word to memory, as clicking a word of the memory pane does",
+    "hide <pane>             hide a pane of the layout",
+    "show <pane>             show a hidden pane, or add one to the layout",
+    "keys                    list the keys and these commands",
+    "quit                    quit",
+];
+
+/// How many nodes of a linked list the watches pane follows.
+const LIST_LIMIT: usize = 16;
+
+/// How a watched expression is shown: as a number, or as the address of a structure in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum View {
+    Value,
+    /// The given number of words.
+    Words(u16),
+    /// A string stored the way the challenge stores them: a length word, then the characters.
+    Str,
+    /// The addresses of the nodes of a linked list, each with its next pointer at the given
+    /// offset, up to a null pointer.
+    List(u16),
+}
+
+impl View {
+    /// Splits the view off the front of the arguments of `display`, such as `words 8 r1`.
+    fn parse(args: &str) -> Result<(Self, &str), String> {
+        let (first, rest) = args.trim().split_once(' ').unwrap_or((args.trim(), ""));
+        let view = match first {
+            "str" => return Ok((View::Str, rest)),
+            "words" => View::Words,
+            "list" => View::List,
+            _ => return Ok((View::Value, args)),
+        };
+        let (n, rest) = rest
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| format!("expected a number and an expression after `{first}`"))?;
+        Ok((view(project::parse_number(n)?), rest))
+    }
+
+    /// Shows `value` this way, reading the structure it points to from `mem`. `None` if `value`
+    /// is `None` or the structure runs outside memory.
+    fn render(self, value: Option<i64>, mem: &[u16]) -> Option<String> {
+        let value = value?;
+        if self == View::Value {
+            return Some(value.to_string());
+        }
+        let word = |addr: usize| mem.get(addr).map(|&word| word as usize);
+        let addr = usize::try_from(value)
+            .ok()
+            .filter(|&addr| addr < mem.len())?;
+        match self {
+            View::Value => unreachable!(),
+            View::Words(n) => {
+                let words = mem.get(addr..addr + n as usize)?;
+                let words = words.iter().map(u16::to_string).collect::<Vec<_>>();
+                Some(format!("[{}]", words.join(" ")))
+            }
+            View::Str => {
+                let len = word(addr)?;
+                let chars = mem.get(addr + 1..addr + 1 + len)?;
+                let text = chars.iter().map(|&c| c as u8 as char).collect::<String>();
+                Some(format!("{text:?}"))
+            }
+            View::List(offset) => {
+                let mut nodes = Vec::new();
+                let mut node = addr;
+                while node != 0 && nodes.len() < LIST_LIMIT && !nodes.contains(&node) {
+                    nodes.push(node);
+                    node = word(node + offset as usize)?;
+                }
+                let mut shown = nodes.iter().map(usize::to_string).collect::<Vec<_>>();
+                if node != 0 {
+                    // a cycle, or longer than shown
+                    shown.push("...".to_string());
+                }
+                Some(format!("[{}]", shown.join(" ")))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for View {
+    /// The view as written before the expression in `display`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            View::Value => Ok(()),
+            View::Words(n) => write!(f, "words {n} "),
+            View::Str => write!(f, "str "),
+            View::List(offset) => write!(f, "list {offset} "),
+        }
+    }
+}
+
+/// An expression in the watches pane, with its value at the last stop.
+#[derive(Clone, Debug)]
+pub struct Watch {
+    pub expr: Condition,
+    pub view: View,
+    /// `None` if the expression has no value, e.g. because it divides by zero.
+    pub value: Option<i64>,
+    /// The value shown through the view, `None` if it has none.
+    pub shown: Option<String>,
+    /// Whether what is shown changed at the last stop.
+    pub changed: bool,
+}
+
+/// The live machine, kept aside while the panes show it as it was `back` instructions ago.
+#[derive(Clone, Debug)]
+struct Scrub {
+    present: MachineState,
+    back: usize,
+}
+
+/// The full-screen debugger.
+pub struct Tui {
+    pub debugger: Debugger,
+    /// The address highlighted in the disassembly.
+    pub cursor: u16,
+    /// The first address shown in the disassembly.
+    disasm_top: u16,
+    /// The first address shown in the memory pane.
+    pub memory_top: u16,
+    pub focus: Pane,
+    /// The last `OUTPUT_LIMIT` bytes the program printed.
+    pub output: Vec<u8>,
+    /// The last `LOG_LIMIT` lines of commands and responses.
+    pub log: Vec<String>,
+    pub watches: Vec<Watch>,
+    /// How many lines the output and the log are scrolled up from their ends.
+    output_scroll: usize,
+    log_scroll: usize,
+    /// The command being typed after `:`.
+    pub command: Option<String>,
+    /// Why the machine last stopped, or another message for the status line.
+    pub status: String,
+    pub running: bool,
+    /// The breakpoint set by run-to-cursor, deleted when the run stops.
+    temporary: Option<u16>,
+    /// Set while scrubbing through the journal.
+    scrub: Option<Scrub>,
+    /// The panes as last drawn.
+    areas: Vec<(Pane, Rect)>,
+    pub quit: bool,
+    pub config: Config,
+    /// Where `layout save` writes the config, if anywhere.
+    pub config_path: Option<String>,
+    /// The panes as arranged from the layout named `layout_name`.
+    pub layout: Layout,
+    pub layout_name: String,
+    /// The panes of the layout not shown.
+    pub hidden: BTreeSet<Pane>,
+    pub theme: Theme,
+    /// The word of the memory pane clicked to edit.
+    pub selected: Option<u16>,
+    /// The line being typed to the program, while the keys go to it.
+    pub playing: Option<String>,
+    /// The first line shown of the keys and commands drawn over the panes, while they are.
+    pub help: Option<usize>,
+}
+
+impl Tui {
+    /// Starts in the config's layout, drawing in `theme`.
+    pub fn new(debugger: Debugger, config: Config, theme: Theme) -> Self {
+        let cur = debugger.machine.cur;
+        let layout_name = config.layout.clone();
+        let layout = config.layouts.get(&layout_name).cloned();
+        Self {
+            debugger,
+            cursor: cur,
+            disasm_top: cur,
+            memory_top: 0,
+            focus: Pane::Disassembly,
+            output: Vec::new(),
+            log: Vec::new(),
+            watches: Vec::new(),
+            output_scroll: 0,
+            log_scroll: 0,
+            command: None,
+            status: "Press ? for the keys.".to_string(),
+            running: false,
+            temporary: None,
+            scrub: None,
+            areas: Vec::new(),
+            quit: false,
+            config,
+            config_path: None,
+            layout: layout.unwrap_or_else(Layout::debug),
+            layout_name,
+            hidden: BTreeSet::new(),
+            theme,
+            selected: None,
+            playing: None,
+            help: None,
+        }
+    }
+
+    /// Takes over the terminal until `q` is pressed.
+    pub fn run(&mut self) -> io::Result<()> {
+        let _terminal = Terminal::enter()?;
+        let mut stdout = io::stdout();
+        let mut last_draw: Option<Instant> = None;
+        while !self.quit {
+            self.tick();
+            if !self.running || last_draw.is_none_or(|time| time.elapsed() >= REDRAW_INTERVAL) {
+                let (width, height) = terminal::size().unwrap_or((80, 24));
+                stdout.write_all(self.draw(width, height).to_ansi(&self.theme).as_bytes())?;
+                stdout.flush()?;
+                last_draw = Some(Instant::now());
+            }
+            let timeout = if self.running {
+                Duration::ZERO
+            } else {
+                IDLE_POLL
+            };
+            for event in terminal::decode(&terminal::read(timeout)?) {
+                match event {
+                    Event::Key(key) => self.handle(key),
+                    Event::Mouse { mouse, x, y } => self.handle_mouse(mouse, x, y),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the next chunk of instructions, if the machine is running.
+    pub fn tick(&mut self) {
+        if !self.running {
+            return;
+        }
+        match self.debugger.machine.run_for(CHUNK) {
+            Ok(RunOutcome::FuelExhausted) => self.collect_output(),
+            result => self.stopped(result),
+        }
+    }
+
+    pub fn handle(&mut self, key: Key) {
+        if let Some(command) = &mut self.command {
+            match key {
+                Key::Enter => {
+                    let line = std::mem::take(command);
+                    self.command = None;
+                    self.execute(&line);
+                }
+                Key::Esc | Key::Ctrl('c') => self.command = None,
+                Key::Backspace if command.pop().is_none() => self.command = None,
+                Key::Char(ch) => command.push(ch),
+                _ => {}
+            }
+            if self.command.is_none() {
+                self.selected = None;
+            }
+            return;
+        }
+        if let Some(line) = &mut self.playing {
+            match key {
+                Key::Enter => {
+                    let line = std::mem::take(line) + "\n";
+                    // as the terminal would echo it
+                    self.output.extend(line.as_bytes());
+                    self.output_scroll = 0;
+                    self.debugger.machine.push_input(line.as_bytes());
+                    if !self.running {
+                        self.resume();
+                    }
+                }
+                Key::Esc => {
+                    self.playing = None;
+                    if self.status == PLAYING {
+                        self.status.clear();
+                    }
+                }
+                Key::Ctrl('c') => self.perform(Action::Pause),
+                Key::Backspace => {
+                    line.pop();
+                }
+                Key::Char(ch) => line.push(ch),
+                Key::PageUp => self.perform(Action::PageUp),
+                Key::PageDown => self.perform(Action::PageDown),
+                _ => {}
+            }
+            return;
+        }
+        if let Some(scrub) = &self.scrub {
+            let back = scrub.back;
+            match key {
+                Key::Left | Key::Char('h') => self.scrub_to(back + 1),
+                Key::Right | Key::Char('l') => self.scrub_to(back.saturating_sub(1)),
+                Key::PageUp => self.scrub_to(back + SCRUB_PAGE),
+                Key::PageDown => self.scrub_to(back.saturating_sub(SCRUB_PAGE)),
+                Key::Home => self.scrub_to(usize::MAX),
+                Key::End => self.scrub_to(0),
+                Key::Enter => self.end_scrub(true),
+                Key::Esc | Key::Ctrl('c') => self.end_scrub(false),
+                _ => {}
+            }
+            return;
+        }
+        let action = self.config.keys.iter().find(|&&(bound, _)| bound == key);
+        let action = action.map(|&(_, action)| action);
+        if let Some(top) = &mut self.help {
+            // scroll the help, and close it with any other key
+            match action {
+                Some(Action::Up) => *top = top.saturating_sub(1),
+                Some(Action::Down) => *top += 1,
+                Some(Action::PageUp) => *top = top.saturating_sub(10),
+                Some(Action::PageDown) => *top += 10,
+                _ => self.help = None,
+            }
+            return;
+        }
+        let Some(action) = action else {
+            return;
+        };
+        // only a pause can interrupt a run
+        if self.running && !matches!(action, Action::Pause | Action::Quit) {
+            return;
+        }
+        self.perform(action);
+    }
+
+    /// Clicking focuses a pane, and in the disassembly moves the cursor, setting or deleting a
+    /// breakpoint if on the address, and in memory starts a `poke` of the word. The wheel scrolls
+    /// the pane under the pointer.
+    pub fn handle_mouse(&mut self, mouse: Mouse, x: usize, y: usize) {
+        let Some((pane, area)) = self.areas.iter().copied().find(|(_, area)| {
+            (area.x..area.x + area.width).contains(&x)
+                && (area.y..area.y + area.height).contains(&y)
+        }) else {
+            return;
+        };
+        if pane.scrolls() {
+            self.focus = pane;
+        }
+        let (column, row) = (x - area.x, y.wrapping_sub(area.y + 1));
+        match mouse {
+            Mouse::ScrollUp => self.scroll(-WHEEL_LINES),
+            Mouse::ScrollDown => self.scroll(WHEEL_LINES),
+            // only the wheel works during a run, while typing a command or while scrubbing
+            Mouse::Click if self.running || self.command.is_some() || self.scrub.is_some() => {}
+            // the title bar
+            Mouse::Click if y == area.y => {}
+            Mouse::Click => match pane {
+                Pane::Disassembly => {
+                    let addrs = self.instructions(self.disasm_top, area.inner().height);
+                    if let Some(&addr) = addrs.get(row) {
+                        self.cursor = addr;
+                        // the marker, the breakpoint and the address
+                        if column < 8 {
+                            self.perform(Action::ToggleBreakpoint);
+                        }
+                    }
+                }
+                Pane::Memory => {
+                    let columns = self.memory_columns();
+                    let word = column.checked_sub(7).filter(|x| x % 6 < 5).map(|x| x / 6);
+                    let addr = word
+                        .filter(|&word| word < columns)
+                        .map(|word| self.memory_top as usize + row * columns + word)
+                        .filter(|&addr| addr < self.debugger.machine.mem.len());
+                    if let Some(addr) = addr {
+                        self.selected = Some(addr as u16);
+                        self.command = Some(format!("poke {addr} "));
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+
+    fn perform(&mut self, action: Action) {
+        let machine = &mut self.debugger.machine;
+        match action {
+            Action::Up => self.scroll(-1),
+            Action::Down => self.scroll(1),
+            Action::PageUp => self.scroll(-(self.page() as isize)),
+            Action::PageDown => self.scroll(self.page() as isize),
+            Action::NextPane => self.cycle_focus(1),
+            Action::PreviousPane => self.cycle_focus(-1),
+            Action::Grow => self.layout.resize(self.focus, 1, 0),
+            Action::Shrink => self.layout.resize(self.focus, -1, 0),
+            Action::Widen => self.layout.resize(self.focus, 0, 1),
+            Action::Narrow => self.layout.resize(self.focus, 0, -1),
+            Action::MoveUp => self.layout.move_within(self.focus, -1),
+            Action::MoveDown => self.layout.move_within(self.focus, 1),
+            Action::MoveLeft => self.layout.move_across(self.focus, false),
+            Action::MoveRight => self.layout.move_across(self.focus, true),
+            Action::Step => {
+                let result = machine.run_for(1);
+                self.stopped(result);
+            }
+            Action::Back => {
+                self.status = match machine.step_back() {
+                    true => String::new(),
+                    false => "There is nothing left to undo.".to_string(),
+                };
+                self.cursor = machine.cur;
+                self.refresh_watches();
+            }
+            Action::Continue => self.resume(),
+            Action::Pause if self.running => {
+                self.stopped(Ok(RunOutcome::FuelExhausted));
+                self.status = format!(
+                    "Paused at {}.",
+                    self.debugger.project.describe(self.debugger.machine.cur)
+                );
+            }
+            Action::Pause => {}
+            Action::RunToCursor => {
+                if let Entry::Vacant(entry) = machine.breakpoints.entry(self.cursor) {
+                    entry.insert(None);
+                    self.temporary = Some(self.cursor);
+                }
+                self.resume();
+            }
+            Action::ToggleBreakpoint => {
+                let described = self.debugger.project.describe(self.cursor);
+                self.status = match machine.breakpoints.remove(&self.cursor) {
+                    Some(_) => format!("Deleted the breakpoint at {described}."),
+                    None => {
+                        machine.breakpoints.insert(self.cursor, None);
+                        format!("Break at {described}.")
+                    }
+                };
+            }
+            Action::FollowPc => {
+                self.cursor = machine.cur;
+                self.focus = Pane::Disassembly;
+            }
+            Action::Command => self.command = Some(String::new()),
+            Action::Play => {
+                self.hidden.remove(&Pane::Output);
+                self.layout.add(Pane::Output);
+                self.focus = Pane::Output;
+                self.playing = Some(String::new());
+                // run until the program asks for input
+                self.resume();
+            }
+            Action::Scrub if machine.journal.as_ref().is_none_or(|j| j.is_empty()) => {
+                self.status = "There is nothing to scrub through yet.".to_string();
+            }
+            Action::Scrub => {
+                self.scrub = Some(Scrub {
+                    present: machine.clone(),
+                    back: 0,
+                });
+                self.scrub_to(1);
+            }
+            Action::Help => self.help = Some(0),
+            Action::Quit => self.quit = true,
+        }
+    }
+
+    /// Shows the machine as it was `back` instructions ago, or as far back as the journal goes,
+    /// undoing them on a copy of the live machine.
+    fn scrub_to(&mut self, back: usize) {
+        let Some(scrub) = &mut self.scrub else {
+            return;
+        };
+        let mut past = scrub.present.clone();
+        scrub.back = (0..back).take_while(|_| past.step_back()).count();
+        let total = scrub.present.journal.as_ref().map_or(0, |j| j.len());
+        let left = past.journal.as_ref().map_or(0, |j| j.len());
+        let at = left * (SLIDER_WIDTH - 1) / total.max(1);
+        let slider = (0..SLIDER_WIDTH)
+            .map(|i| if i == at { '|' } else { '-' })
+            .collect::<String>();
+        self.status = format!(
+            "[{slider}] step {}, {} back. ← → Home End move, Enter resumes here, Esc returns.",
+            past.steps, scrub.back
+        );
+        self.debugger.machine = past;
+        self.cursor = self.debugger.machine.cur;
+        self.refresh_watches();
+    }
+
+    /// Stops scrubbing, going on from the instruction shown if `here`, which drops those after
+    /// it, and back to the live machine otherwise.
+    fn end_scrub(&mut self, here: bool) {
+        let Some(scrub) = self.scrub.take() else {
+            return;
+        };
+        self.status = match here {
+            true => format!(
+                "Went back {} instruction(s), to step {}.",
+                scrub.back, self.debugger.machine.steps
+            ),
+            false => {
+                self.debugger.machine = scrub.present;
+                String::new()
+            }
+        };
+        self.cursor = self.debugger.machine.cur;
+        self.refresh_watches();
+    }
+
+    fn resume(&mut self) {
+        self.running = true;
+        self.status = match self.playing {
+            Some(_) => PLAYING.to_string(),
+            None => "Running, press Esc to pause.".to_string(),
+        };
+    }
+
+    /// Ends a run or a step with `result`, deleting the breakpoint of run-to-cursor and moving the
+    /// cursor to where the machine stopped.
+    fn stopped(&mut self, result: RunResult) {
+        // a single step stopping at a breakpoint is no news
+        if let (true, Ok(outcome)) = (std::mem::take(&mut self.running), &result) {
+            self.debugger.notify_stop(*outcome);
+        }
+        if let Some(addr) = self.temporary.take() {
+            self.debugger.machine.breakpoints.remove(&addr);
+        }
+        self.collect_output();
+        self.status = match result {
+            // waiting for the next line of the game
+            Ok(RunOutcome::NeedsInput | RunOutcome::FuelExhausted) if self.playing.is_some() => {
+                PLAYING.to_string()
+            }
+            Ok(RunOutcome::FuelExhausted) => String::new(),
+            Ok(outcome) => self.debugger.describe_outcome(outcome),
+            Err(err) => format!("error: {err}"),
+        };
+        if self.status != PLAYING {
+            // the program stopped for the debugger
+            self.playing = None;
+        }
+        self.cursor = self.debugger.machine.cur;
+        self.refresh_watches();
+    }
+
+    /// Evaluates the watched expressions again, noting which changed.
+    fn refresh_watches(&mut self) {
+        let machine = &self.debugger.machine;
+        for watch in &mut self.watches {
+            watch.value = watch.expr.eval(machine);
+            let shown = watch.view.render(watch.value, &machine.mem);
+            watch.changed = shown != watch.shown;
+            watch.shown = shown;
+        }
+    }
+
+    fn collect_output(&mut self) {
+        let printed = self.debugger.machine.drain_output();
+        if printed.is_empty() {
+            return;
+        }
+        self.output.extend(printed);
+        let excess = self.output.len().saturating_sub(OUTPUT_LIMIT);
+        self.output.drain(..excess);
+        self.output_scroll = 0;
+    }
+
+    fn trim_log(&mut self) {
+        let excess = self.log.len().saturating_sub(LOG_LIMIT);
+        self.log.drain(..excess);
+    }
+
+    /// Runs a command entered after `:`, one of `COMMANDS` or of `Debugger`.
+    fn execute(&mut self, line: &str) {
+        let (command, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        if command.is_empty() {
+            return;
+        }
+        self.log.push(format!(":{line}"));
+        let machine = &self.debugger.machine;
+        let position = (machine.cur, machine.steps);
+        let result = match command {
+            "q" | "quit" => {
+                self.quit = true;
+                return;
+            }
+            "goto" => self.goto(rest.trim()),
+            "display" => self.display(rest),
+            "undisplay" => self.undisplay(rest.trim()),
+            "keys" => {
+                self.help = Some(0);
+                Ok(String::new())
+            }
+            "layout" => self.switch_layout(rest.trim()),
+            "theme" if rest.trim().is_empty() => Ok(format!("{}\n", self.theme.base.name())),
+            "theme" => Base::parse(rest.trim()).map(|base| {
+                self.theme.base = base;
+                String::new()
+            }),
+            "poke" => self.poke(rest.trim()),
+            "hide" => Pane::parse(rest.trim()).map(|pane| {
+                self.hidden.insert(pane);
+                String::new()
+            }),
+            "show" => Pane::parse(rest.trim()).map(|pane| {
+                self.hidden.remove(&pane);
+                self.layout.add(pane);
+                String::new()
+            }),
+            _ => self.debugger.execute(line),
+        };
+        match result {
+            Ok(text) => self.log.extend(text.lines().map(String::from)),
+            Err(err) => {
+                self.log.push(format!("error: {err}"));
+                self.status = format!("error: {err}");
+            }
+        }
+        self.log_scroll = 0;
+        self.trim_log();
+        let machine = &self.debugger.machine;
+        if (machine.cur, machine.steps) != position {
+            self.cursor = machine.cur;
+        }
+        // the command may have run the machine or changed memory
+        self.refresh_watches();
+    }
+
+    fn display(&mut self, args: &str) -> Result<String, String> {
+        let (view, expr) = View::parse(args)?;
+        let expr = Condition::parse(expr)?;
+        let machine = &self.debugger.machine;
+        let value = expr.eval(machine);
+        self.watches.push(Watch {
+            expr,
+            view,
+            value,
+            shown: view.render(value, &machine.mem),
+            changed: false,
+        });
+        Ok(String::new())
+    }
+
+    fn undisplay(&mut self, n: &str) -> Result<String, String> {
+        if n.is_empty() {
+            self.watches.clear();
+            return Ok(String::new());
+        }
+        match n.parse::<usize>() {
+            Ok(n) if (1..=self.watches.len()).contains(&n) => {
+                self.watches.remove(n - 1);
+                Ok(String::new())
+            }
+            _ => Err(format!("no expression `{n}`, they are numbered from 1")),
+        }
+    }
+
+    /// Lists the layouts with no `name`, or switches to the one called `name`, showing all of its
+    /// panes. `save <name>` saves the current one instead.
+    fn switch_layout(&mut self, name: &str) -> Result<String, String> {
+        if name.is_empty() {
+            let names = self
+                .config
+                .layouts
+                .keys()
+                .map(|name| match *name == self.layout_name {
+                    true => format!("{name} (current)\n"),
+                    false => format!("{name}\n"),
+                });
+            return Ok(names.collect());
+        }
+        if let Some(name) = name.strip_prefix("save ") {
+            return self.save_layout(name.trim());
+        }
+        let layout = self
+            .config
+            .layouts
+            .get(name)
+            .ok_or_else(|| format!("no layout `{name}`, try `layout`"))?;
+        self.layout = layout.clone();
+        self.layout_name = name.to_string();
+        self.hidden.clear();
+        Ok(String::new())
+    }
+
+    /// Saves the panes as they are shown as the layout `name`, writing the config file if there
+    /// is one.
+    fn save_layout(&mut self, name: &str) -> Result<String, String> {
+        let mut layout = self.layout.clone();
+        for column in &mut layout.columns {
+            column.panes.retain(|(pane, _)| !self.hidden.contains(pane));
+        }
+        layout.columns.retain(|column| !column.panes.is_empty());
+        if layout.columns.is_empty() {
+            return Err("there are no panes to save".to_string());
+        }
+        self.config.layouts.insert(name.to_string(), layout.clone());
+        self.layout = layout;
+        self.layout_name = name.to_string();
+        self.hidden.clear();
+        match &self.config_path {
+            Some(path) => {
+                std::fs::write(path, self.config.to_toml()).map_err(|err| err.to_string())?;
+                Ok(format!("saved layout `{name}` to {path}\n"))
+            }
+            None => Ok(format!(
+                "saved layout `{name}` until quitting, as there is no --config file\n"
+            )),
+        }
+    }
+
+    fn poke(&mut self, args: &str) -> Result<String, String> {
+        let (addr, value) = args
+            .split_once(' ')
+            .ok_or("expected an address and a value")?;
+        let addr = self.debugger.address(addr)?;
+        let value = project::parse_number(value.trim())?;
+        let machine = &mut self.debugger.machine;
+        let cur = machine.cur;
+        machine
+            .write(addr, value, cur)
+            .map_err(|err| err.to_string())?;
+        Ok(String::new())
+    }
+
+    fn goto(&mut self, addr: &str) -> Result<String, String> {
+        let addr = self.debugger.address(addr)?;
+        if addr as usize >= self.debugger.machine.mem.len() {
+            return Err(format!("`{addr}` is outside memory"));
+        }
+        match self.focus {
+            Pane::Memory => self.memory_top = addr,
+            _ => {
+                self.cursor = addr;
+                self.focus = Pane::Disassembly;
+            }
+        }
+        Ok(String::new())
+    }
+
+    /// Moves the focused pane `lines` down, or up if negative.
+    fn scroll(&mut self, lines: isize) {
+        let up = lines < 0;
+        let count = lines.unsigned_abs();
+        match self.focus {
+            Pane::Disassembly => {
+                for _ in 0..count {
+                    self.cursor = match up {
+                        true => self.previous(self.cursor),
+                        false => self.next(self.cursor),
+                    };
+                }
+                let last = self.debugger.machine.mem.len().saturating_sub(1);
+                self.cursor = self.cursor.min(last as u16);
+            }
+            Pane::Memory => {
+                let step = count * self.memory_columns();
+                let top = self.memory_top as usize;
+                let len = self.debugger.machine.mem.len();
+                self.memory_top = match up {
+                    true => top.saturating_sub(step),
+                    false if top + step < len => top + step,
+                    false => top,
+                } as u16;
+            }
+            Pane::Output => self.output_scroll = shift(self.output_scroll, count, up),
+            Pane::Log => self.log_scroll = shift(self.log_scroll, count, up),
+            Pane::Registers | Pane::Stack | Pane::Watches => {}
+        }
+    }
+
+    fn cycle_focus(&mut self, by: isize) {
+        let panes = self
+            .areas
+            .iter()
+            .map(|&(pane, _)| pane)
+            .filter(|pane| pane.scrolls())
+            .collect::<Vec<_>>();
+        if panes.is_empty() {
+            return;
+        }
+        let i = panes
+            .iter()
+            .position(|&pane| pane == self.focus)
+            .unwrap_or(0);
+        let len = panes.len() as isize;
+        self.focus = panes[(i as isize + by).rem_euclid(len) as usize];
+    }
+
+    fn area(&self, pane: Pane) -> Option<Rect> {
+        self.areas
+            .iter()
+            .find(|&&(shown, _)| shown == pane)
+            .map(|&(_, area)| area)
+    }
+
+    /// How many lines a page of the focused pane is.
+    fn page(&self) -> usize {
+        let rows = self.area(self.focus).map_or(0, |area| area.inner().height);
+        rows.saturating_sub(1).max(1)
+    }
+
+    /// How many words go on a row of the memory pane.
+    fn memory_columns(&self) -> usize {
+        let width = self.area(Pane::Memory).map_or(0, |area| area.width);
+        (width.saturating_sub(7) / 6).clamp(1, 8)
+    }
+
+    /// The address of the instruction after the one at `addr`. An instruction that would run
+    /// over the next instruction to execute or the cursor is cut short, so that both are shown.
+    fn next(&self, addr: u16) -> u16 {
+        let machine = &self.debugger.machine;
+        let addr = addr as usize;
+        let next = addr + instruction::length(&machine.mem, addr).max(1);
+        [machine.cur as usize, self.cursor as usize]
+            .into_iter()
+            .filter(|&sync| addr < sync && sync < next)
+            .min()
+            .unwrap_or(next) as u16
+    }
+
+    /// The address of the instruction before the one at `addr`, found by disassembling from the
+    /// start of memory.
+    fn previous(&self, addr: u16) -> u16 {
+        let (mut pos, mut last) = (0, 0);
+        while pos < addr {
+            last = pos;
+            pos = self.next(pos);
+        }
+        last
+    }
+
+    /// The addresses of `count` instructions from `top`.
+    fn instructions(&self, top: u16, count: usize) -> Vec<u16> {
+        let len = self.debugger.machine.mem.len();
+        let mut addrs = Vec::new();
+        let mut addr = top;
+        while addrs.len() < count && (addr as usize) < len {
+            addrs.push(addr);
+            addr = self.next(addr);
+        }
+        addrs
+    }
+
+    /// Draws a frame of `width` by `height`.
+    pub fn draw(&mut self, width: usize, height: usize) -> Screen {
+        let mut screen = Screen::new(width, height);
+        let area = Rect {
+            x: 0,
+            y: 0,
+            width,
+            height: height.saturating_sub(1),
+        };
+        self.areas = self.layout.rects(area, &self.hidden);
+        if !self.areas.iter().any(|&(pane, _)| pane == self.focus) {
+            let shown = self.areas.iter().map(|&(pane, _)| pane);
+            if let Some(pane) = shown.clone().find(|pane| pane.scrolls()) {
+                self.focus = pane;
+            }
+        }
+        for (pane, area) in self.areas.clone() {
+            if area.width == 0 || area.height == 0 {
+                continue;
+            }
+            let style = match pane == self.focus {
+                true => Style::Focused,
+                false => Style::Title,
+            };
+            screen.paint(area.x, area.y, area.width, style);
+            let machine = &self.debugger.machine;
+            let title = match (pane, &machine.return_stack) {
+                (Pane::Stack, Some(returns)) => {
+                    format!("Stack ({}, {} returns)", machine.stack.len(), returns.len())
+                }
+                (Pane::Stack, None) => format!("Stack ({})", machine.stack.len()),
+                (Pane::Output, _) if self.playing.is_some() => "Output (playing)".to_string(),
+                _ => pane.title().to_string(),
+            };
+            screen.print(area.x, area.y, area.width, &format!(" {title} "), style);
+            let inner = area.inner();
+            match pane {
+                Pane::Disassembly => self.draw_disassembly(inner, &mut screen),
+                Pane::Registers => self.draw_registers(inner, &mut screen),
+                Pane::Stack => self.draw_stack(inner, &mut screen),
+                Pane::Watches => self.draw_watches(inner, &mut screen),
+                Pane::Memory => self.draw_memory(inner, &mut screen),
+                Pane::Output => {
+                    let mut text = String::from_utf8_lossy(&self.output).into_owned();
+                    if let Some(line) = &self.playing {
+                        text.push_str(line);
+                        text.push('█');
+                    }
+                    self.output_scroll = draw_tail(&text, self.output_scroll, inner, &mut screen);
+                }
+                Pane::Log => {
+                    let text = self.log.join("\n");
+                    self.log_scroll = draw_tail(&text, self.log_scroll, inner, &mut screen);
+                }
+            }
+        }
+        // the columns are separated by a line
+        let rows = height.saturating_sub(1);
+        for (_, area) in &self.areas {
+            if area.x > 0 {
+                for y in area.y..area.y + area.height {
+                    screen.print(area.x - 1, y, 1, "│", Style::Normal);
+                }
+            }
+        }
+        if let Some(top) = self.help {
+            self.help = Some(self.draw_help(top, &mut screen));
+        }
+        match &self.command {
+            Some(command) => {
+                let line = format!(":{command}");
+                screen.print(0, rows, width, &line, Style::Normal);
+                screen.paint(line.chars().count(), rows, 1, Style::Cursor);
+            }
+            None => {
+                let style = match self.status.starts_with("error") {
+                    true => Style::Error,
+                    false => Style::Normal,
+                };
+                screen.print(0, rows, width, &self.status, style);
+            }
+        }
+        screen
+    }
+
+    /// The lines of the help: each action's keys, then the commands.
+    fn help_lines(&self) -> Vec<String> {
+        let mut lines = vec!["keys:".to_string()];
+        for action in Action::ALL {
+            let keys = self
+                .config
+                .keys
+                .iter()
+                .filter(|&&(_, bound)| bound == action);
+            let keys = keys.map(|(key, _)| key.to_string()).collect::<Vec<_>>();
+            if !keys.is_empty() {
+                lines.push(format!("  {:<14} {}", keys.join(", "), action.describe()));
+            }
+        }
+        lines.push("commands, after `:`, besides those listed by `help`:".to_string());
+        lines.extend(COMMANDS.iter().map(|command| format!("  {command}")));
+        lines
+    }
+
+    /// Draws the help over the middle of the screen from line `top`. Returns the top line, limited
+    /// to the lines there are.
+    fn draw_help(&self, top: usize, screen: &mut Screen) -> usize {
+        let lines = self.help_lines();
+        let width = lines
+            .iter()
+            .map(|line| line.chars().count() + 2)
+            .max()
+            .unwrap_or(0)
+            .min(screen.width.saturating_sub(2));
+        let height = (lines.len() + 1).min(screen.height.saturating_sub(3));
+        let top = top.min((lines.len() + 1).saturating_sub(height));
+        let x = (screen.width - width) / 2;
+        let y = (screen.height - height) / 2;
+        let title = " Keys, any other key to close";
+        screen.print(x, y, width, &format!("{title:<width$}"), Style::Focused);
+        let shown = lines
+            .iter()
+            .skip(top)
+            .map(String::as_str)
+            .chain(std::iter::repeat(""));
+        for (y, line) in (y + 1..y + height).zip(shown) {
+            screen.print(x, y, width, &format!(" {line:<width$}"), Style::Normal);
+        }
+        top
+    }
+
+    fn draw_disassembly(&mut self, area: Rect, screen: &mut Screen) {
+        let mut addrs = self.instructions(self.disasm_top, area.height);
+        if !addrs.contains(&self.cursor) {
+            // show some of what comes before the cursor
+            self.disasm_top = self.cursor;
+            for _ in 0..area.height / 3 {
+                self.disasm_top = self.previous(self.disasm_top);
+            }
+            addrs = self.instructions(self.disasm_top, area.height);
+        }
+        let machine = &self.debugger.machine;
+        let project = &self.debugger.project;
+        for (y, &addr) in (area.y..).zip(&addrs) {
+            let len = self.next(addr) - addr;
+            let text = match instruction::length(&machine.mem, addr as usize) == len as usize {
+                true => disassemble_with(&machine.mem, addr as usize, project).0,
+                // cut short by `next`
+                false => format!("dw {}", machine.mem[addr as usize]),
+            };
+            let marker = if addr == machine.cur { "=>" } else { "  " };
+            let label = match project.symbols.get(&addr) {
+                Some(name) => format!(" <{name}>"),
+                None => String::new(),
+            };
+            let style = match addr {
+                _ if addr == self.cursor => Style::Cursor,
+                _ if addr == machine.cur => Style::Current,
+                _ => Style::Normal,
+            };
+            screen.paint(area.x, y, area.width, style);
+            let line = format!("{marker} {addr:>5}{label}: {text}");
+            screen.print(area.x, y, area.width, &line, style);
+            if machine.breakpoints.contains_key(&addr) {
+                screen.print(area.x + 2, y, 1, "●", Style::Breakpoint);
+            }
+        }
+    }
+
+    fn draw_registers(&self, area: Rect, screen: &mut Screen) {
+        let machine = &self.debugger.machine;
+        let half = REGISTER_COUNT / 2;
+        let mut lines = (0..half)
+            .map(|i| {
+                let (low, high) = (machine.registers[i], machine.registers[i + half]);
+                format!("r{i} {low:5}  r{} {high:5}", i + half)
+            })
+            .collect::<Vec<_>>();
+        lines.push(format!("pc {:5}  steps {}", machine.cur, machine.steps));
+        for (y, line) in (area.y..area.y + area.height).zip(&lines) {
+            screen.print(area.x, y, area.width, line, Style::Normal);
+        }
+    }
+
+    fn draw_stack(&self, area: Rect, screen: &mut Screen) {
+        let machine = &self.debugger.machine;
+        let stack = machine.stack.iter_top_down();
+        let indices = (0..machine.stack.len()).rev();
+        for (y, (value, i)) in (area.y..area.y + area.height).zip(stack.zip(indices)) {
+            let slot = machine
+                .call_stack
+                .as_ref()
+                .and_then(|calls| calls.describe_slot(i, &self.debugger.project));
+            let line = match slot {
+                Some(slot) => format!("{value:5}  {slot}"),
+                None => format!("{value:5}"),
+            };
+            screen.print(area.x, y, area.width, &line, Style::Normal);
+        }
+    }
+
+    fn draw_watches(&self, area: Rect, screen: &mut Screen) {
+        for (y, (i, watch)) in (area.y..area.y + area.height).zip(self.watches.iter().enumerate()) {
+            let shown = watch.shown.as_deref().unwrap_or("?");
+            let style = match watch.changed {
+                true => Style::Changed,
+                false => Style::Normal,
+            };
+            let line = format!("{} {}{} = {shown}", i + 1, watch.view, watch.expr);
+            screen.print(area.x, y, area.width, &line, style);
+        }
+    }
+
+    fn draw_memory(&self, area: Rect, screen: &mut Screen) {
+        let mem = &self.debugger.machine.mem;
+        let columns = self.memory_columns();
+        let rows = (self.memory_top as usize..mem.len()).step_by(columns);
+        for (y, start) in (area.y..area.y + area.height).zip(rows) {
+            let words = mem[start..(start + columns).min(mem.len())]
+                .iter()
+                .map(|word| format!("{word:5}"))
+                .collect::<Vec<_>>();
+            let line = format!("{start:5}: {}", words.join(" "));
+            screen.print(area.x, y, area.width, &line, Style::Normal);
+            if let Some(selected) = self.selected.map(usize::from) {
+                if (start..start + columns).contains(&selected) {
+                    let x = area.x + 7 + (selected - start) * 6;
+                    screen.paint(
+                        x,
+                        y,
+                        5.min((area.x + area.width).saturating_sub(x)),
+                        Style::Cursor,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Moves a scroll position `count` lines up or down from the end.
+fn shift(scroll: usize, count: usize, up: bool) -> usize {
+    match up {
+        true => scroll.saturating_add(count),
+        false => scroll.saturating_sub(count),
+    }
+}
+
+/// Draws the end of `text`, wrapped to the width of `area`, scrolled `scroll` lines up. Returns
+/// the scroll position, limited to the lines there are.
+fn draw_tail(text: &str, scroll: usize, area: Rect, screen: &mut Screen) -> usize {
+    let lines = wrap(text, area.width);
+    let scroll = scroll.min(lines.len().saturating_sub(area.height));
+    let end = lines.len() - scroll;
+    let start = end.saturating_sub(area.height);
+    for (y, line) in (area.y..).zip(&lines[start..end]) {
+        screen.print(area.x, y, area.width, line, Style::Normal);
+    }
+    scroll
+}
+
+/// Breaks `text` into lines of at most `width` characters.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    for line in text.split('\n') {
+        let chars = line.chars().collect::<Vec<_>>();
+        if chars.is_empty() {
+            lines.push(String::new());
+        }
+        lines.extend(
+            chars
+                .chunks(width.max(1))
+                .map(|chunk| chunk.iter().collect()),
+        );
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{project::Project, MachineState};
+
+    fn tui() -> Tui {
+        // 0: in r0
+        // 2: out r0
+        // 4: set r1 7
+        // 7: halt
+        let mut mem = vec![20, 32768, 19, 32768, 1, 32769, 7, 0];
+        mem.resize(100, 0);
+        let project = Project::parse("symbol 4 later").unwrap();
+        Tui::new(
+            Debugger::new(MachineState::new(mem), project),
+            Config::default(),
+            Theme::default(),
+        )
+    }
+
+    fn press(tui: &mut Tui, keys: &str) {
+        for ch in keys.chars() {
+            tui.handle(match ch {
+                '\n' => Key::Enter,
+                ch => Key::Char(ch),
+            });
+        }
+    }
+
+    fn finish(tui: &mut Tui) {
+        while tui.running {
+            tui.tick();
+        }
+    }
+
+    #[test]
+    fn draws_the_panes() {
+        let mut tui = tui();
+        let text = tui.draw(80, 24).text();
+        for expected in [
+            " Disassembly ",
+            "=>     0: in r0",
+            "       4 <later>: set r1 7",
+            " Registers",
+            "r1     0  r5     0",
+            "pc     0  steps 0",
+            " Stack (0)",
+            "    0:    20 32768    19 32768",
+            "Press ? for the keys.",
+        ] {
+            assert!(text.contains(expected), "{expected:?} in\n{text}");
+        }
+        assert_eq!(text.lines().count(), 24);
+    }
+
+    #[test]
+    fn names_the_stack_slots() {
+        // 0: call 10
+        // 2: halt
+        // 10: push r0
+        // 12: ret
+        let mut mem = vec![17, 10, 0];
+        mem.resize(10, 21);
+        mem.extend([2, 32768, 18]);
+        let project = Project::parse("symbol 10 routine").unwrap();
+        let mut tui = Tui::new(
+            Debugger::new(MachineState::new(mem), project),
+            Config::default(),
+            Theme::default(),
+        );
+        press(&mut tui, "ss");
+        let text = tui.draw(160, 40).text();
+        for expected in ["    0  pushed r0 in 10 <routine>", "    2  return to 2"] {
+            assert!(text.contains(expected), "{expected:?} in\n{text}");
+        }
+    }
+
+    #[test]
+    fn scrubs_through_the_journal() {
+        let mut tui = tui();
+        press(&mut tui, "t");
+        assert_eq!(tui.status, "There is nothing to scrub through yet.");
+        press(&mut tui, ":input a\nsss");
+        assert_eq!(tui.debugger.machine.cur, 7);
+
+        press(&mut tui, "t");
+        assert_eq!(tui.debugger.machine.cur, 4);
+        assert!(tui.status.contains("step 2, 1 back"), "{}", tui.status);
+        // stepping is off while scrubbing
+        press(&mut tui, "sh");
+        assert_eq!(tui.debugger.machine.cur, 2);
+        tui.handle(Key::Home);
+        assert_eq!(tui.debugger.machine.cur, 0);
+        assert!(tui.status.starts_with("[|---"), "{}", tui.status);
+        assert!(tui.draw(80, 24).text().contains("=>     0: in r0"));
+        tui.handle(Key::End);
+        assert_eq!(tui.debugger.machine.cur, 7);
+        press(&mut tui, "hh");
+        tui.handle(Key::Esc);
+        assert_eq!(
+            (tui.debugger.machine.cur, tui.debugger.machine.steps),
+            (7, 3)
+        );
+
+        press(&mut tui, "th");
+        tui.handle(Key::Enter);
+        assert_eq!(tui.status, "Went back 2 instruction(s), to step 1.");
+        press(&mut tui, "s");
+        assert_eq!(
+            (tui.debugger.machine.cur, tui.debugger.machine.steps),
+            (4, 2)
+        );
+    }
+
+    #[test]
+    fn runs_to_the_cursor() {
+        let mut tui = tui();
+        press(&mut tui, ":input hi\n");
+        tui.handle(Key::Down);
+        tui.handle(Key::Down);
+        assert_eq!(tui.cursor, 4);
+        press(&mut tui, "r");
+        assert!(tui.running);
+        finish(&mut tui);
+        assert_eq!(tui.debugger.machine.cur, 4);
+        assert_eq!(tui.output, b"h");
+        assert_eq!(tui.status, "Hit a breakpoint at `4`.");
+        // the breakpoint was only for the run
+        assert!(tui.debugger.machine.breakpoints.is_empty());
+
+        // a breakpoint of the user's stays
+        press(&mut tui, "jb");
+        assert_eq!(tui.cursor, 7);
+        press(&mut tui, "r");
+        finish(&mut tui);
+        assert_eq!(tui.debugger.machine.cur, 7);
+        assert!(tui.debugger.machine.breakpoints.contains_key(&7));
+        assert!(tui.draw(80, 24).text().contains("=>●    7: halt"));
+    }
+
+    #[test]
+    fn steps_and_pauses() {
+        let mut tui = tui();
+        press(&mut tui, "s");
+        assert_eq!(tui.status, "The machine is waiting for input.");
+        press(&mut tui, ":input x\nc");
+        tui.handle(Key::Esc);
+        assert!(!tui.running);
+        assert_eq!(tui.status, "Paused at 0.");
+        press(&mut tui, "ss");
+        assert_eq!((tui.debugger.machine.cur, tui.cursor), (4, 4));
+        press(&mut tui, "u");
+        assert_eq!((tui.debugger.machine.cur, tui.cursor), (2, 2));
+    }
+
+    #[test]
+    fn runs_commands() {
+        let mut tui = tui();
+        press(&mut tui, ":break later\n");
+        assert!(tui.debugger.machine.breakpoints.contains_key(&4));
+        assert_eq!(tui.log, [":break later", "break at 4 <later>"]);
+        press(&mut tui, ":goto 7\n:frobnicate\n");
+        assert_eq!(tui.cursor, 7);
+        assert!(tui.status.starts_with("error: unknown command"));
+
+        // typing a command doesn't trigger keys
+        press(&mut tui, ":q");
+        assert!(!tui.quit);
+        assert!(tui.draw(80, 24).text().ends_with(":q\n"));
+        tui.handle(Key::Esc);
+        press(&mut tui, "q");
+        assert!(tui.quit);
+    }
+
+    #[test]
+    fn watches_expressions() {
+        let mut tui = tui();
+        press(
+            &mut tui,
+            ":display r0\n:display stack.len()\n:display mem[r1 * 5000]\n",
+        );
+        press(&mut tui, ":display r0 +\n");
+        assert!(tui.status.starts_with("error: expected a value"));
+        assert_eq!(tui.watches.len(), 3);
+        press(&mut tui, ":input a\ns");
+        let changed = |tui: &Tui| {
+            tui.watches
+                .iter()
+                .map(|watch| watch.changed)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(changed(&tui), [true, false, false]);
+        let text = tui.draw(80, 40).text();
+        for expected in ["1 r0 = 97", "2 stack.len() = 0", "3 mem[r1 * 5000] = 20"] {
+            assert!(text.contains(expected), "{expected:?} in\n{text}");
+        }
+        press(&mut tui, "ss");
+        assert_eq!(changed(&tui), [false, false, true]);
+        assert!(tui.draw(80, 40).text().contains("3 mem[r1 * 5000] = ?"));
+
+        press(&mut tui, ":undisplay 2\n");
+        assert_eq!(tui.watches.len(), 2);
+        press(&mut tui, ":undisplay 3\n");
+        assert!(tui.status.starts_with("error: no expression"));
+        press(&mut tui, ":undisplay\n");
+        assert!(tui.watches.is_empty());
+    }
+
+    #[test]
+    fn watches_typed_views() {
+        let mut mem = vec![0; 40];
+        // a string at 10
+        mem[10..13].copy_from_slice(&[2, 'h' as u16, 'i' as u16]);
+        // a list 20 -> 30 -> null, the next pointer one word into each node
+        mem[21] = 30;
+        assert_eq!(
+            View::Words(3).render(Some(10), &mem).unwrap(),
+            "[2 104 105]"
+        );
+        assert_eq!(View::Words(3).render(Some(38), &mem), None);
+        assert_eq!(View::Str.render(Some(10), &mem).unwrap(), "\"hi\"");
+        assert_eq!(View::List(1).render(Some(20), &mem).unwrap(), "[20 30]");
+        mem[31] = 20;
+        assert_eq!(View::List(1).render(Some(20), &mem).unwrap(), "[20 30 ...]");
+        assert_eq!(View::Str.render(None, &mem), None);
+        assert_eq!(View::Value.render(Some(-1), &mem).unwrap(), "-1");
+
+        let mut tui = tui();
+        press(
+            &mut tui,
+            ":display words 3 r1 - 3
+:display list 1 4
+",
+        );
+        press(
+            &mut tui,
+            ":display words r1
+",
+        );
+        assert!(tui.status.starts_with("error: expected a number"));
+        assert_eq!(tui.watches.len(), 2);
+        assert_eq!(tui.watches[1].view, View::List(1));
+        press(
+            &mut tui,
+            ":input a
+sss",
+        );
+        assert_eq!(tui.watches[0].shown.as_deref(), Some("[1 32769 7]"));
+        assert!(tui.watches[0].changed);
+        let text = tui.draw(80, 40).text();
+        for expected in ["1 words 3 r1 - 3 = [1 32769 7]", "2 list 1 4 = ?"] {
+            assert!(text.contains(expected), "{expected:?} in\n{text}");
+        }
+    }
+
+    #[test]
+    fn scrolls_the_focused_pane() {
+        let mut tui = tui();
+        tui.draw(80, 24);
+        tui.handle(Key::Tab);
+        assert_eq!(tui.focus, Pane::Output);
+        tui.handle(Key::Tab);
+        assert_eq!(tui.focus, Pane::Memory);
+        tui.handle(Key::PageDown);
+        // a page is a row less than the pane shows, of 4 words each
+        let top = tui.memory_top;
+        assert!(top > 0 && top.is_multiple_of(4), "{top}");
+        assert!(tui.draw(80, 24).text().contains(&format!("{top:5}:     0")));
+        tui.handle(Key::BackTab);
+        tui.handle(Key::BackTab);
+        assert_eq!(tui.focus, Pane::Disassembly);
+    }
+
+    #[test]
+    fn arranges_the_panes() {
+        let mut tui = tui();
+        press(&mut tui, ":layout play\n");
+        let text = tui.draw(40, 10).text();
+        assert!(text.starts_with(" Output\n"), "{text}");
+        assert_eq!(tui.focus, Pane::Output);
+        press(&mut tui, ":layout\n");
+        assert!(tui.log.ends_with(&[
+            "debug".to_string(),
+            "play (current)".to_string(),
+            "split".to_string()
+        ]));
+
+        press(&mut tui, ":show memory\n:show stack\n:hide output\n");
+        let text = tui.draw(40, 10).text();
+        assert!(text.starts_with(" Memory\n"), "{text}");
+        assert!(text.contains(" Stack (0)"), "{text}");
+        assert_eq!(tui.focus, Pane::Memory);
+        press(&mut tui, "H+++");
+        let text = tui.draw(41, 10).text();
+        assert!(
+            text.starts_with(" Memory             │ Stack (0)\n"),
+            "{text}"
+        );
+
+        press(&mut tui, ":layout save peek\n");
+        assert_eq!(
+            tui.config.layouts["peek"].to_columns().0,
+            ["memory:4", "stack"]
+        );
+        press(&mut tui, ":hide frobnicator\n");
+        assert_eq!(tui.status, "error: unknown pane `frobnicator`");
+        press(&mut tui, ":layout debug\n");
+        assert!(tui.hidden.is_empty());
+        assert!(tui.draw(80, 24).text().contains(" Disassembly "));
+    }
+
+    #[test]
+    fn draws_in_the_theme() {
+        let mut tui = tui();
+        let screen = tui.draw(80, 24);
+        let focused = format!("{} Disassembly", tui.theme.sgr(Style::Focused));
+        assert!(screen.to_ansi(&tui.theme).contains(&focused));
+        assert!(focused.contains(";5;"));
+        press(&mut tui, ":theme none\n:theme\n");
+        assert_eq!(tui.log.last().unwrap(), "none");
+        let ansi = tui.draw(80, 24).to_ansi(&tui.theme);
+        assert!(ansi.contains("\x1b[0;1;4;7m Disassembly"));
+        assert!(!ansi.contains(";5;"));
+        press(&mut tui, ":theme sepia\n");
+        assert!(tui.status.starts_with("error: unknown theme"));
+    }
+
+    #[test]
+    fn follows_the_mouse() {
+        let mut tui = tui();
+        let text = tui.draw(80, 24).text();
+        let row = text
+            .lines()
+            .position(|line| line.contains("4 <later>"))
+            .unwrap();
+        // clicking the address sets a breakpoint, clicking the instruction only moves there
+        tui.handle_mouse(Mouse::Click, 5, row);
+        assert_eq!(tui.cursor, 4);
+        assert!(tui.debugger.machine.breakpoints.contains_key(&4));
+        tui.handle_mouse(Mouse::Click, 20, row + 1);
+        assert_eq!(tui.cursor, 7);
+        assert_eq!(tui.debugger.machine.breakpoints.len(), 1);
+
+        let memory = tui.area(Pane::Memory).unwrap();
+        tui.handle_mouse(Mouse::ScrollDown, memory.x, memory.y + 2);
+        assert_eq!(tui.focus, Pane::Memory);
+        assert_eq!(tui.memory_top, 12);
+        // the second word of the second row
+        tui.draw(80, 24);
+        tui.handle_mouse(Mouse::Click, memory.x + 14, memory.y + 2);
+        assert_eq!(tui.selected, Some(17));
+        assert_eq!(tui.command.as_deref(), Some("poke 17 "));
+        press(&mut tui, "42\n");
+        assert_eq!(tui.debugger.machine.mem[17], 42);
+        assert_eq!(tui.selected, None);
+        // between words
+        tui.handle_mouse(Mouse::Click, memory.x + 12, memory.y + 2);
+        assert_eq!(tui.command, None);
+    }
+
+    #[test]
+    fn plays_alongside_the_panes() {
+        // 0: in r0
+        // 2: out r0
+        // 4: jmp 0
+        let mut mem = vec![20, 32768, 19, 32768, 6, 0];
+        mem.resize(100, 0);
+        let debugger = Debugger::new(MachineState::new(mem), Project::default());
+        let mut tui = Tui::new(debugger, Config::default(), Theme::default());
+        press(&mut tui, ":layout split\n:display r0\ni");
+        finish(&mut tui);
+        assert_eq!(tui.status, PLAYING);
+        // the keys go to the program
+        press(&mut tui, "sq\n");
+        assert!(!tui.quit);
+        finish(&mut tui);
+        assert_eq!(tui.output, b"sq\nsq\n");
+        assert_eq!(tui.watches[0].value, Some(10));
+        press(&mut tui, "lo");
+        let text = tui.draw(80, 24).text();
+        assert!(text.starts_with(" Output (playing)"), "{text}");
+        assert!(text.contains("lo█"), "{text}");
+        assert!(text.contains("1 r0 = 10"), "{text}");
+
+        // a breakpoint hands the keys back to the debugger
+        tui.debugger.machine.breakpoints.insert(2, None);
+        press(&mut tui, "\n");
+        finish(&mut tui);
+        assert_eq!(tui.playing, None);
+        assert_eq!(tui.status, "Hit a breakpoint at `2`.");
+        press(&mut tui, "i");
+        tui.handle(Key::Esc);
+        assert_eq!(tui.playing, None);
+        finish(&mut tui);
+    }
+
+    #[test]
+    fn shows_the_keys() {
+        let mut tui = tui();
+        tui.config = Config::parse("[keys]\nstep = [\"F10\", \"n\"]\nhelp = \"F1\"").unwrap();
+        press(&mut tui, "s");
+        assert_eq!(tui.debugger.machine.steps, 0);
+        press(&mut tui, "n");
+        assert_eq!(tui.status, "The machine is waiting for input.");
+
+        tui.handle(Key::F(1));
+        let text = tui.draw(100, 50).text();
+        assert!(
+            text.contains("F10, n         execute an instruction"),
+            "{text}"
+        );
+        assert!(text.contains("F1             list the keys"), "{text}");
+        assert!(
+            text.contains("keys                    list the keys"),
+            "{text}"
+        );
+        // the help scrolls, and any other key only closes it
+        tui.handle(Key::Down);
+        assert_eq!(tui.help, Some(1));
+        press(&mut tui, "q");
+        assert_eq!(tui.help, None);
+        assert!(!tui.quit);
+        press(&mut tui, ":keys\n");
+        assert_eq!(tui.help, Some(0));
+        assert!(tui
+            .draw(100, 40)
+            .text()
+            .contains(" Keys, any other key to close"));
+    }
+
+    #[test]
+    fn wraps_text() {
+        assert_eq!(wrap("abcde\n\nf", 2), ["ab", "cd", "e", "", "f"]);
+    }
+}
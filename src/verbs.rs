@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use crate::{strings, taint::Taint, MachineState, RunLimits};
+
+/// Feeds `input` to `program` with taint tracking and returns the strings the program compared it
+/// against, by address: with input the game doesn't understand, that is its whole vocabulary of
+/// verbs and nouns, including undocumented ones.
+///
+/// Only comparisons of characters are seen, so a parser that checks lengths first only reveals
+/// the words as long as the ones typed; probing with words of several lengths finds the rest.
+pub fn discover(program: &[u16], input: &[u8], fuel: u64) -> BTreeMap<u16, String> {
+    let mut machine = MachineState::new(program.to_vec());
+    machine.taint = Some(Taint::new());
+    machine.push_input(input);
+    // whatever stopped the run, the comparisons made until then are what matters
+    let _ = machine.run_with(RunLimits {
+        fuel: Some(fuel),
+        ..RunLimits::default()
+    });
+
+    let compared = machine.taint.as_ref().map(|taint| &taint.compared);
+    compared
+        .into_iter()
+        .flatten()
+        .filter_map(|&addr| strings::containing(&machine.mem, addr))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_compared_words() {
+        // 0: set r3 101
+        // 3: in r0
+        // 5: rmem r1 r3
+        // 8: eq r2 r0 r1
+        // 12: add r3 r3 3
+        // 16: jf r2 5
+        // 19: halt
+        // 100: "go", "up", "ox"
+        let mut program = vec![
+            1, 32771, 101, 20, 32768, 15, 32769, 32771, 4, 32770, 32768, 32769, 9, 32771, 32771, 3,
+            8, 32770, 5, 0,
+        ];
+        program.resize(crate::MAX_ADDR, 0);
+        program[100..109].copy_from_slice(&[2, 103, 111, 2, 117, 112, 2, 111, 120]);
+
+        // the search stops at "up", so "ox" is never compared
+        let words = discover(&program, b"u", 1000);
+        assert_eq!(
+            words,
+            BTreeMap::from([(100, "go".to_string()), (103, "up".to_string())])
+        );
+    }
+}
@@ -0,0 +1,355 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::{
+    oracle::AccessOracle, patch::PatchScript, project, words_from_bytes, MachineState, RunOutcome,
+    RunResult, MAX_ADDR, REGISTER_COUNT,
+};
+
+/// A complete solution, ready to be replayed:
+/// - `mem` is the program image with all patches applied
+/// - `input` is the walkthrough fed to the program
+/// - `r7` optionally overrides the eighth register before the run starts
+/// - `expect` are the codes the program must print
+/// - `hashes` are state hashes recorded by earlier runs, by the number of instructions executed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bundle {
+    pub mem: Vec<u16>,
+    pub input: Vec<u8>,
+    pub r7: Option<u16>,
+    pub expect: Vec<String>,
+    pub hashes: Vec<(u64, u64)>,
+}
+
+impl Bundle {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, VerifyError> {
+        let path = path.as_ref();
+        let text = read_to_string(path)?;
+        Self::parse(&text, path.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Parses the line-based bundle format, reading the files it names relative to `base`:
+    /// ```text
+    /// # the image defaults to challenge.bin
+    /// image challenge.bin
+    /// input walkthrough.txt
+    /// patch teleporter.toml
+    /// r7 25734
+    /// expect LDOb7UGhTi
+    /// hash 1000000 8c41a21d3f1e0b77
+    /// ```
+    /// Several `input` files are concatenated, and patches are applied in order.
+    pub fn parse(text: &str, base: &Path) -> Result<Self, VerifyError> {
+        let mut image = None;
+        let mut input = Vec::new();
+        let mut patches = Vec::new();
+        let mut r7 = None;
+        let mut expect = Vec::new();
+        let mut hashes = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let err = |msg: String| VerifyError::Parse(msg, line_no);
+            let (directive, rest) = project::split_word(line);
+            if rest.is_empty() {
+                return Err(err(format!("`{directive}` expects a value")));
+            }
+            match directive {
+                "image" => image = Some(base.join(rest)),
+                "input" => input.extend(read(&base.join(rest))?),
+                "patch" => {
+                    let script = PatchScript::parse(&read_to_string(&base.join(rest))?)
+                        .map_err(|e| err(format!("in `{rest}`: {e}")))?;
+                    patches.push(script);
+                }
+                "r7" => r7 = Some(project::parse_number(rest).map_err(err)?),
+                "expect" => expect.push(rest.to_string()),
+                "hash" => {
+                    let (steps, hash) = project::split_word(rest);
+                    let steps = steps
+                        .parse()
+                        .map_err(|_| err(format!("invalid step count `{steps}`")))?;
+                    let hash = u64::from_str_radix(hash, 16)
+                        .map_err(|_| err(format!("invalid hash `{hash}`")))?;
+                    hashes.push((steps, hash));
+                }
+                other => return Err(err(format!("unknown directive `{other}`"))),
+            }
+        }
+
+        let image = image.unwrap_or_else(|| base.join(crate::BINARY_PATH));
+        let mut mem = words_from_bytes(&read(&image)?);
+        mem.resize(MAX_ADDR, 0);
+        for script in patches {
+            script
+                .apply(&mut mem)
+                .map_err(|e| VerifyError::Patch(e.to_string()))?;
+        }
+
+        Ok(Self {
+            mem,
+            input,
+            r7,
+            expect,
+            hashes,
+        })
+    }
+}
+
+/// Hashes memory, registers, the stack, the current position and in dual-stack mode the return
+/// stack with FNV-1a, which unlike the standard library's hasher is stable across builds, so hashes
+/// can be stored in bundles.
+pub fn checkpoint_hash(machine: &MachineState) -> u64 {
+    // the length keeps the return stack from hashing like more of the data stack; without one,
+    // hashes stay those recorded before dual-stack mode existed
+    let (len, return_stack) = match &machine.return_stack {
+        Some(stack) => (Some(stack.len() as u16), stack.as_slice()),
+        None => (None, &[][..]),
+    };
+    fnv1a(
+        machine
+            .mem
+            .iter()
+            .chain(&machine.registers)
+            .chain(machine.stack.as_slice())
+            .chain([&machine.cur])
+            .chain(&len)
+            .chain(return_stack),
+    )
+}
+
+/// Hashes the little-endian bytes of `words` with FNV-1a.
+pub fn fnv1a<'a>(words: impl IntoIterator<Item = &'a u16>) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    words
+        .into_iter()
+        .flat_map(|word| word.to_le_bytes())
+        .fold(OFFSET, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(PRIME)
+        })
+}
+
+/// Replays `bundle` for at most `fuel` instructions, returning the state hash after every
+/// `interval` instructions, as `hash` lines to add to the bundle.
+pub fn record_hashes(bundle: &Bundle, fuel: u64, interval: u64) -> String {
+    let mut machine = start(bundle, false);
+    let mut out = String::new();
+    while machine.steps + interval <= fuel
+        && machine.run_for(interval) == Ok(RunOutcome::FuelExhausted)
+    {
+        out.push_str(&format!(
+            "hash {} {:016x}\n",
+            machine.steps,
+            checkpoint_hash(&machine)
+        ));
+    }
+    out
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, VerifyError> {
+    std::fs::read(path).map_err(|err| VerifyError::Io(format!("{}: {err}", path.display())))
+}
+
+fn read_to_string(path: &Path) -> Result<String, VerifyError> {
+    std::fs::read_to_string(path)
+        .map_err(|err| VerifyError::Io(format!("{}: {err}", path.display())))
+}
+
+/// The result of replaying a bundle.
+#[derive(Clone, Debug)]
+pub struct Verification {
+    pub result: RunResult,
+    pub output: String,
+    /// The expected codes that never appeared in the output.
+    pub missing: Vec<String>,
+    pub divergence: Option<Divergence>,
+}
+
+/// The first recorded hash that the replay didn't reproduce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub steps: u64,
+    pub expected: u64,
+    /// The hash of the replay after `steps` instructions, unless it stopped before.
+    pub actual: Option<u64>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.actual {
+            Some(actual) => write!(
+                f,
+                "diverged after {} steps: expected hash {:016x}, got {actual:016x}",
+                self.steps, self.expected
+            ),
+            None => write!(
+                f,
+                "stopped before step {}, which has a recorded hash",
+                self.steps
+            ),
+        }
+    }
+}
+
+impl Verification {
+    /// Whether the run finished without an error, reproduced every recorded hash and printed
+    /// every expected code.
+    pub fn passed(&self) -> bool {
+        self.result.is_ok() && self.missing.is_empty() && self.divergence.is_none()
+    }
+}
+
+fn start(bundle: &Bundle, access_oracle: bool) -> MachineState {
+    let mut machine = MachineState::new(bundle.mem.clone());
+    if access_oracle {
+        machine.access_oracle = Some(AccessOracle::new());
+    }
+    if let Some(r7) = bundle.r7 {
+        machine.registers[REGISTER_COUNT - 1] = r7;
+    }
+    machine.push_input(&bundle.input);
+    machine
+}
+
+/// Replays `bundle` non-interactively for at most `fuel` instructions, optionally checking every
+/// memory access with an `AccessOracle`. The state is compared with the recorded hashes on the
+/// way, to catch the first point where the replay no longer matches the recording.
+pub fn verify(bundle: &Bundle, fuel: u64, access_oracle: bool) -> Verification {
+    let mut machine = start(bundle, access_oracle);
+    let mut hashes = bundle.hashes.clone();
+    hashes.sort_unstable();
+    let mut divergence = None;
+    for &(steps, expected) in hashes.iter().filter(|&&(steps, _)| steps <= fuel) {
+        let reached = match machine.run_for(steps.saturating_sub(machine.steps)) {
+            Ok(RunOutcome::FuelExhausted) => machine.steps == steps,
+            _ => false,
+        };
+        let actual = reached.then(|| checkpoint_hash(&machine));
+        if actual != Some(expected) {
+            divergence = Some(Divergence {
+                steps,
+                expected,
+                actual,
+            });
+            break;
+        }
+    }
+    let result = machine.run_for(fuel.saturating_sub(machine.steps));
+
+    let output = String::from_utf8_lossy(&machine.drain_output()).into_owned();
+    let missing = bundle
+        .expect
+        .iter()
+        .filter(|code| !output.contains(code.as_str()))
+        .cloned()
+        .collect();
+    Verification {
+        result,
+        output,
+        missing,
+        divergence,
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    #[error("Could not read solution file: {0}")]
+    Io(String),
+    #[error("Invalid solution bundle: {0} on line `{1}`")]
+    Parse(String, usize),
+    #[error("Could not apply patch: {0}")]
+    Patch(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ExecutionError;
+
+    fn bundle(expect: &[&str]) -> Bundle {
+        // in r0, out r0, out r7, halt
+        let mut mem = vec![20, 32768, 19, 32768, 19, 32775, 0];
+        mem.resize(MAX_ADDR, 0);
+        Bundle {
+            mem,
+            input: b"a".to_vec(),
+            r7: Some(b'!' as u16),
+            expect: expect.iter().map(|code| code.to_string()).collect(),
+            hashes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn replays() {
+        let verification = verify(&bundle(&["a!"]), 100, true);
+        assert!(verification.passed());
+        assert_eq!(verification.output, "a!");
+
+        let verification = verify(&bundle(&["a!", "b"]), 100, false);
+        assert_eq!(verification.missing, vec!["b"]);
+        assert!(!verification.passed());
+    }
+
+    #[test]
+    fn checks_recorded_hashes() {
+        let mut bundle = bundle(&["a!"]);
+        let hashes = record_hashes(&bundle, 100, 2);
+        assert_eq!(hashes.lines().count(), 1);
+        assert!(hashes.starts_with("hash 2 "));
+        bundle.hashes = Bundle::parse(&hashes, Path::new("")).unwrap().hashes;
+        assert!(verify(&bundle, 100, false).divergence.is_none());
+
+        // a different input leads to a different state
+        bundle.input = b"b".to_vec();
+        let verification = verify(&bundle, 100, false);
+        assert_eq!(verification.divergence.unwrap().steps, 2);
+        assert!(!verification.passed());
+
+        bundle.hashes.push((50, 0));
+        bundle.input = b"a".to_vec();
+        let divergence = verify(&bundle, 100, false).divergence.unwrap();
+        assert_eq!(divergence.actual, None);
+    }
+
+    #[test]
+    fn hashes_the_return_stack() {
+        let mut machine = MachineState::new(vec![0]);
+        let single = checkpoint_hash(&machine);
+        machine.return_stack = Some(crate::stack::Stack::new());
+        let dual = checkpoint_hash(&machine);
+        assert_ne!(dual, single);
+        machine.return_stack.as_mut().unwrap().push(2);
+        assert_ne!(checkpoint_hash(&machine), dual);
+    }
+
+    #[test]
+    fn fails_on_errors() {
+        let mut bundle = bundle(&[]);
+        bundle.mem[6] = 9999;
+        let verification = verify(&bundle, 100, false);
+        assert_eq!(
+            verification.result,
+            Err(ExecutionError::InvalidOpcode(9999, 6))
+        );
+        assert!(!verification.passed());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            Bundle::parse("r7 1\nsolve it", Path::new("")),
+            Err(VerifyError::Parse("unknown directive `solve`".into(), 2))
+        );
+        assert_eq!(
+            Bundle::parse("expect", Path::new("")),
+            Err(VerifyError::Parse("`expect` expects a value".into(), 1))
+        );
+    }
+}
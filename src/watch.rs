@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{instruction, project::Project, MachineState, RunOutcome};
+
+/// What a watchpoint does when its location is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchAction {
+    /// Stop the run with `RunOutcome::Watchpoint`.
+    Pause,
+    /// Only record the write.
+    Log,
+}
+
+/// A write to a watched location.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    /// The memory address or register, encoded as an operand.
+    pub location: u16,
+    pub old: u16,
+    pub new: u16,
+    /// The position of the instruction that wrote it.
+    pub pos: u16,
+    pub steps: u64,
+}
+
+impl WatchHit {
+    /// Describes the hit, naming the register written by its name in `project` if it has one.
+    pub fn describe(&self, project: &Project) -> String {
+        format!(
+            "{} changed from {} to {} at index `{}` (step {})",
+            project.format_operand(self.location),
+            self.old,
+            self.new,
+            self.pos,
+            self.steps
+        )
+    }
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.describe(&Project::default()))
+    }
+}
+
+/// Watches memory cells and registers, reporting every write to them, even one that leaves the
+/// value unchanged, with the old and new values.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Watchpoints {
+    /// The watched locations, encoded as operands: addresses, or `32768..32776` for registers.
+    pub watched: BTreeMap<u16, WatchAction>,
+    /// Every hit so far, the last one being what stopped a paused run.
+    pub log: Vec<WatchHit>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a comma-separated list of addresses and registers to watch, each optionally followed
+    /// by `:log` to only record writes instead of pausing, e.g. `r7,2732:log`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let watched = spec
+            .split(',')
+            .map(|entry| {
+                let (location, action) = entry.trim().split_once(':').unwrap_or((entry.trim(), ""));
+                let action = match action {
+                    "" | "pause" => WatchAction::Pause,
+                    "log" => WatchAction::Log,
+                    other => {
+                        return Err(format!(
+                            "unknown action `{other}`, expected `pause` or `log`"
+                        ))
+                    }
+                };
+                Ok((parse_location(location)?, action))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            watched,
+            log: Vec::new(),
+        })
+    }
+}
+
+/// Parses a memory address or a register such as `r7`.
+pub fn parse_location(s: &str) -> Result<u16, String> {
+    if s.starts_with('\'') {
+        return Err(format!("invalid location `{s}`"));
+    }
+    instruction::parse_operand(s)
+}
+
+impl MachineState {
+    /// Called after `location` was written, with the value it held before.
+    pub(crate) fn watch_write(&mut self, location: u16, old: u16, new: u16) {
+        let Some(watchpoints) = &mut self.watchpoints else {
+            return;
+        };
+        let Some(&action) = watchpoints.watched.get(&location) else {
+            return;
+        };
+        watchpoints.log.push(WatchHit {
+            location,
+            old,
+            new,
+            pos: self.history.iter().next_back().unwrap_or(self.cur),
+            steps: self.steps,
+        });
+        if action == WatchAction::Pause && self.stop.is_none() {
+            self.stop = Some(RunOutcome::Watchpoint(location));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::setup;
+
+    #[test]
+    fn parses_spec() {
+        let watchpoints = Watchpoints::parse("r7, 0x10:log").unwrap();
+        assert_eq!(
+            watchpoints.watched,
+            BTreeMap::from([(16, WatchAction::Log), (32775, WatchAction::Pause)])
+        );
+        assert!(Watchpoints::parse("r8").is_err());
+        assert!(Watchpoints::parse("'a'").is_err());
+        assert!(Watchpoints::parse("10:maybe").is_err());
+    }
+
+    #[test]
+    fn watches_writes() {
+        // 0: set r0 5
+        // 3: wmem 20 r0
+        // 6: add r0 r0 1
+        // 10: wmem 20 r0
+        // 13: halt
+        let program = vec![
+            1, 32768, 5, 16, 20, 32768, 9, 32768, 32768, 1, 16, 20, 32768, 0,
+        ];
+        let mut machine = setup(program);
+        machine.watchpoints = Some(Watchpoints::parse("20,r0:log").unwrap());
+
+        assert_eq!(machine.run(), Ok(RunOutcome::Watchpoint(20)));
+        assert_eq!((machine.cur, machine.steps), (6, 2));
+        assert_eq!(machine.run(), Ok(RunOutcome::Watchpoint(20)));
+        assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+
+        let log = machine.watchpoints.unwrap().log;
+        assert_eq!(log.len(), 4);
+        assert_eq!(
+            log[0].to_string(),
+            "r0 changed from 0 to 5 at index `0` (step 1)"
+        );
+        assert_eq!(
+            log[3],
+            WatchHit {
+                location: 20,
+                old: 5,
+                new: 6,
+                pos: 10,
+                steps: 4,
+            }
+        );
+    }
+}
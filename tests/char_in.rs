@@ -0,0 +1,30 @@
+//! Input fed to a machine through the public API ends up in the registers `in` names.
+
+use synacor_challenge::{io::Io, MachineState, RunOutcome};
+
+#[test]
+fn reads_input_into_registers() {
+    // in r3, in r4, halt
+    let mut machine = MachineState::new(vec![20, 32771, 20, 32772, 0]);
+    machine.io = Io::buffered();
+    machine.push_input(b"hi");
+
+    assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+    assert_eq!(machine.registers[3], b'h' as u16);
+    assert_eq!(machine.registers[4], b'i' as u16);
+    assert_eq!(machine.steps, 3);
+}
+
+#[test]
+fn waits_for_more_input() {
+    let mut machine = MachineState::new(vec![20, 32771, 20, 32772, 0]);
+    machine.io = Io::buffered();
+    machine.push_input(b"h");
+
+    // the second `in` is retried once input arrives
+    assert_eq!(machine.run(), Ok(RunOutcome::NeedsInput));
+    assert_eq!(machine.cur, 2);
+    machine.push_input(b"i");
+    assert_eq!(machine.run(), Ok(RunOutcome::Halted));
+    assert_eq!(machine.registers[4], b'i' as u16);
+}